@@ -0,0 +1,4 @@
+-- Track the Discord channel each summary was produced from
+ALTER TABLE summaries ADD COLUMN channel_id INTEGER;
+
+CREATE INDEX idx_summaries_channel_id ON summaries (channel_id);
@@ -0,0 +1,12 @@
+-- Record what each summary and digest covers: where it came from, how many
+-- messages went into it and the time range of those messages
+ALTER TABLE summaries ADD COLUMN guild_id INTEGER;
+ALTER TABLE summaries ADD COLUMN message_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE summaries ADD COLUMN covers_from DATETIME;
+ALTER TABLE summaries ADD COLUMN covers_to DATETIME;
+
+ALTER TABLE daily_digests ADD COLUMN guild_id INTEGER;
+ALTER TABLE daily_digests ADD COLUMN channel_id INTEGER;
+ALTER TABLE daily_digests ADD COLUMN message_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE daily_digests ADD COLUMN covers_from DATETIME;
+ALTER TABLE daily_digests ADD COLUMN covers_to DATETIME;
@@ -0,0 +1,3 @@
+-- Summaries and digests are now looked up per guild
+CREATE INDEX idx_summaries_guild_id ON summaries (guild_id);
+CREATE INDEX idx_daily_digests_guild_id ON daily_digests (guild_id);
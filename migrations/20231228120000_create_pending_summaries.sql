@@ -0,0 +1,12 @@
+-- Message log files whose summarization failed, kept around to be retried
+CREATE TABLE pending_summaries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER,
+    channel_id INTEGER NOT NULL,
+    log_file_index INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_error TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    next_attempt_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (channel_id, log_file_index)
+);
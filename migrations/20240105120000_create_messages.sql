@@ -0,0 +1,24 @@
+-- Raw Discord messages, replacing the flat-file message logs. Messages are linked
+-- to the summary that covered them once they have been summarized.
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL,
+    guild_id INTEGER,
+    channel_id INTEGER NOT NULL,
+    author TEXT NOT NULL,
+    content TEXT NOT NULL,
+    timestamp DATETIME NOT NULL,
+    summary_id INTEGER,
+    FOREIGN KEY (summary_id) REFERENCES summaries(id)
+);
+
+CREATE INDEX idx_messages_channel_summary ON messages (channel_id, summary_id);
+
+-- Pending summaries now refer to a batch of stored messages instead of a log file.
+-- Pending log files are set aside until the bot imports them into `messages` when it
+-- starts, which moves their entries back, pointing at the imported messages
+CREATE TABLE pending_message_logs AS
+    SELECT guild_id, channel_id, log_file_index, attempts, last_error, created_at
+    FROM pending_summaries;
+DELETE FROM pending_summaries;
+ALTER TABLE pending_summaries RENAME COLUMN log_file_index TO up_to_message_id;
@@ -0,0 +1,4 @@
+-- Token count of each message as measured by the configured model's tokenizer
+ALTER TABLE messages ADD COLUMN token_count INTEGER NOT NULL DEFAULT 0;
+
+UPDATE messages SET token_count = LENGTH(content) / 4;
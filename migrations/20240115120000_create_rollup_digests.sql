@@ -0,0 +1,26 @@
+-- Weekly digests roll up daily digests, and monthly digests roll up weekly ones
+CREATE TABLE monthly_digests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    text TEXT NOT NULL,
+    timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    guild_id INTEGER,
+    channel_id INTEGER,
+    message_count INTEGER NOT NULL DEFAULT 0,
+    covers_from DATETIME,
+    covers_to DATETIME
+);
+
+CREATE TABLE weekly_digests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    monthly_digest_id INTEGER,
+    text TEXT NOT NULL,
+    timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    guild_id INTEGER,
+    channel_id INTEGER,
+    message_count INTEGER NOT NULL DEFAULT 0,
+    covers_from DATETIME,
+    covers_to DATETIME,
+    FOREIGN KEY (monthly_digest_id) REFERENCES monthly_digests(id)
+);
+
+ALTER TABLE daily_digests ADD COLUMN weekly_digest_id INTEGER REFERENCES weekly_digests(id);
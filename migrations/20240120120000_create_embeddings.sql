@@ -0,0 +1,10 @@
+-- Embeddings of summaries and digests, used to search them by meaning
+CREATE TABLE embeddings (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    content_kind TEXT NOT NULL,
+    content_id INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    vector BLOB NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (content_kind, content_id)
+);
@@ -0,0 +1,6 @@
+-- Structured breakdown of each summary, stored as JSON arrays. Summaries written
+-- before this migration have empty lists.
+ALTER TABLE summaries ADD COLUMN topics TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE summaries ADD COLUMN decisions TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE summaries ADD COLUMN action_items TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE summaries ADD COLUMN open_questions TEXT NOT NULL DEFAULT '[]';
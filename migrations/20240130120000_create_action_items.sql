@@ -0,0 +1,21 @@
+-- Action items extracted from summaries, tracked until someone resolves them
+CREATE TABLE action_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    summary_id INTEGER NOT NULL REFERENCES summaries(id),
+    guild_id INTEGER,
+    channel_id INTEGER,
+    description TEXT NOT NULL,
+    assignee TEXT,
+    status TEXT NOT NULL DEFAULT 'open',
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME
+);
+
+CREATE INDEX action_items_guild_status ON action_items (guild_id, status);
+
+INSERT INTO action_items (summary_id, guild_id, channel_id, description, assignee, created_at)
+SELECT summaries.id, summaries.guild_id, summaries.channel_id,
+    json_extract(item.value, '$.description'), json_extract(item.value, '$.owner'),
+    summaries.timestamp
+FROM summaries, json_each(summaries.action_items) AS item
+WHERE json_extract(item.value, '$.description') IS NOT NULL;
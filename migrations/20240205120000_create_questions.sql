@@ -0,0 +1,14 @@
+-- Discord ID of the message each message replies to, if any
+ALTER TABLE messages ADD COLUMN reply_to_message_id INTEGER;
+
+-- Questions found in the message stream and whether anyone answered them. Unanswered
+-- questions are listed in the first daily digest produced after they are found.
+CREATE TABLE questions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL UNIQUE REFERENCES messages(id),
+    answered BOOLEAN NOT NULL DEFAULT FALSE,
+    daily_digest_id INTEGER REFERENCES daily_digests(id),
+    detected_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_messages_channel_reply ON messages (channel_id, reply_to_message_id);
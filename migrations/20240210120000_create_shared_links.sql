@@ -0,0 +1,19 @@
+-- URLs posted in the listened to channels, with the title and description of the page
+-- when fetching them is enabled. Links are listed in the first daily digest produced
+-- after they are shared.
+CREATE TABLE shared_links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL REFERENCES messages(id),
+    guild_id INTEGER,
+    channel_id INTEGER NOT NULL,
+    author TEXT NOT NULL,
+    url TEXT NOT NULL,
+    title TEXT,
+    description TEXT,
+    shared_at DATETIME NOT NULL,
+    metadata_fetched_at DATETIME,
+    daily_digest_id INTEGER REFERENCES daily_digests(id),
+    UNIQUE (message_id, url)
+);
+
+CREATE INDEX idx_shared_links_guild ON shared_links (guild_id, shared_at);
@@ -0,0 +1,3 @@
+-- Summaries and daily digests can be fetched by the time range they were created in
+CREATE INDEX idx_summaries_timestamp ON summaries (timestamp);
+CREATE INDEX idx_daily_digests_timestamp ON daily_digests (timestamp);
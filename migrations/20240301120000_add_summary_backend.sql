@@ -0,0 +1,3 @@
+-- Provider and model that produced each summary, such as `openai/gpt-4`. Unknown for
+-- summaries written before this migration.
+ALTER TABLE summaries ADD COLUMN backend TEXT;
@@ -0,0 +1,8 @@
+-- Discord ID of each message's author. Messages stored before this migration have none
+ALTER TABLE messages ADD COLUMN author_id INTEGER;
+
+-- Members who asked for their messages not to be logged
+CREATE TABLE opted_out_users (
+    user_id INTEGER PRIMARY KEY,
+    opted_out_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- Replies are rendered along with the author of the message they reply to, looked up
+-- by its Discord ID
+CREATE INDEX idx_messages_message_id ON messages (message_id);
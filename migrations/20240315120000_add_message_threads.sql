@@ -0,0 +1,4 @@
+-- Messages posted in threads and forum posts are stored under the parent channel, along
+-- with the ID and title of their thread
+ALTER TABLE messages ADD COLUMN thread_id INTEGER;
+ALTER TABLE messages ADD COLUMN thread_name TEXT;
@@ -0,0 +1,3 @@
+-- Messages deleted on Discord are kept as a marker without their content, when
+-- configured to be marked rather than excluded
+ALTER TABLE messages ADD COLUMN deleted_at DATETIME;
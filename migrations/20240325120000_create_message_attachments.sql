@@ -0,0 +1,13 @@
+-- Files attached to logged messages, with a description of the images among them when
+-- they are run through a vision model
+CREATE TABLE message_attachments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL REFERENCES messages(id),
+    filename TEXT NOT NULL,
+    url TEXT NOT NULL,
+    content_type TEXT,
+    size INTEGER NOT NULL,
+    description TEXT
+);
+
+CREATE INDEX idx_message_attachments_message ON message_attachments (message_id);
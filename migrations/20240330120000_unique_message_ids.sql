@@ -0,0 +1,12 @@
+-- Gateway reconnects and backfills can deliver a message more than once. Only the first
+-- copy of each message is kept, and Discord IDs are unique from now on
+DELETE FROM questions WHERE message_id IN (
+    SELECT id FROM messages WHERE id NOT IN (SELECT MIN(id) FROM messages GROUP BY message_id));
+DELETE FROM shared_links WHERE message_id IN (
+    SELECT id FROM messages WHERE id NOT IN (SELECT MIN(id) FROM messages GROUP BY message_id));
+DELETE FROM message_attachments WHERE message_id IN (
+    SELECT id FROM messages WHERE id NOT IN (SELECT MIN(id) FROM messages GROUP BY message_id));
+DELETE FROM messages WHERE id NOT IN (SELECT MIN(id) FROM messages GROUP BY message_id);
+
+DROP INDEX idx_messages_message_id;
+CREATE UNIQUE INDEX idx_messages_message_id ON messages (message_id);
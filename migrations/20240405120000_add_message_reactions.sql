@@ -0,0 +1,5 @@
+-- How many reactions each logged message got, so that summaries can weigh the messages
+-- members engaged with the most
+ALTER TABLE messages ADD COLUMN reaction_count INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_messages_guild_timestamp ON messages (guild_id, timestamp);
@@ -0,0 +1,8 @@
+-- Messages members marked with one of the highlight emoji. Highlights are quoted in the
+-- first daily digest produced after they are marked.
+CREATE TABLE highlights (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL UNIQUE REFERENCES messages(id),
+    daily_digest_id INTEGER REFERENCES daily_digests(id),
+    highlighted_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- Participant statistics of the period each daily digest covers, as JSON, so that the
+-- API can return them along with the digest
+ALTER TABLE daily_digests ADD COLUMN stats TEXT;
@@ -0,0 +1,36 @@
+-- Hourly message volume of each channel, kept up to date as messages are logged so that
+-- activity analytics do not have to scan the message log
+CREATE TABLE channel_activity (
+    guild_id INTEGER,
+    channel_id INTEGER NOT NULL,
+    -- Start of the hour, in UTC
+    hour DATETIME NOT NULL,
+    message_count INTEGER NOT NULL DEFAULT 0,
+    token_count INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (channel_id, hour)
+);
+
+CREATE INDEX idx_channel_activity_hour ON channel_activity (hour);
+
+-- Who posted in each channel every hour, to count unique authors over any period.
+-- Authors are identified by their Discord ID, or by name for messages logged before
+-- IDs were recorded
+CREATE TABLE channel_activity_authors (
+    channel_id INTEGER NOT NULL,
+    hour DATETIME NOT NULL,
+    author TEXT NOT NULL,
+    PRIMARY KEY (channel_id, hour, author)
+);
+
+CREATE INDEX idx_channel_activity_authors_hour ON channel_activity_authors (hour);
+
+INSERT INTO channel_activity (guild_id, channel_id, hour, message_count, token_count)
+SELECT MAX(guild_id), channel_id, strftime('%Y-%m-%d %H:00:00', timestamp), COUNT(*),
+    SUM(token_count)
+FROM messages
+GROUP BY channel_id, strftime('%Y-%m-%d %H:00:00', timestamp);
+
+INSERT OR IGNORE INTO channel_activity_authors (channel_id, hour, author)
+SELECT channel_id, strftime('%Y-%m-%d %H:00:00', timestamp),
+    COALESCE(CAST(author_id AS TEXT), author)
+FROM messages;
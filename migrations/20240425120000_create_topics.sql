@@ -0,0 +1,20 @@
+-- Topic labels of each daily digest, normalized so that the same topic is counted
+-- across digests, with how many of the digest's summaries discussed them
+CREATE TABLE topics (
+    daily_digest_id INTEGER NOT NULL REFERENCES daily_digests(id),
+    guild_id INTEGER,
+    label TEXT NOT NULL,
+    mentions INTEGER NOT NULL,
+    timestamp DATETIME NOT NULL,
+    PRIMARY KEY (daily_digest_id, label)
+);
+
+CREATE INDEX idx_topics_guild_timestamp ON topics (guild_id, timestamp);
+
+-- Existing digests get the topics of their summaries, lowercased
+INSERT INTO topics (daily_digest_id, guild_id, label, mentions, timestamp)
+SELECT d.id, d.guild_id, lower(trim(t.value)), COUNT(DISTINCT s.id), d.timestamp
+FROM daily_digests d
+JOIN summaries s ON s.daily_digest_id = d.id, json_each(s.topics) t
+WHERE trim(t.value) != ''
+GROUP BY d.id, lower(trim(t.value));
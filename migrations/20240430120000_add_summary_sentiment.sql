@@ -0,0 +1,7 @@
+-- Overall sentiment of the messages each summary covers, from -1 for very negative to 1
+-- for very positive, and their tone in a few words. Only set when sentiment analysis is
+-- turned on
+ALTER TABLE summaries ADD COLUMN sentiment REAL;
+ALTER TABLE summaries ADD COLUMN tone TEXT;
+
+CREATE INDEX idx_summaries_channel_covers_to ON summaries (channel_id, covers_to);
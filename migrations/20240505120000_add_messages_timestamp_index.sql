@@ -0,0 +1,2 @@
+-- Anomaly detection counts the latest messages of every channel
+CREATE INDEX idx_messages_timestamp ON messages (timestamp);
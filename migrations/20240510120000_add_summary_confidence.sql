@@ -0,0 +1,5 @@
+-- How well the people and channels each summary mentions are backed by its messages,
+-- from 0 to 1, and the mentions that could not be found in them. Only set when
+-- summaries are verified
+ALTER TABLE summaries ADD COLUMN confidence REAL;
+ALTER TABLE summaries ADD COLUMN unverified_references TEXT;
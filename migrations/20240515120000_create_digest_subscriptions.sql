@@ -0,0 +1,22 @@
+-- Members who get daily digests in their DMs, per guild. Digests are sent once the
+-- hour of the day they chose comes, in the reporting timezone, or as soon as they are
+-- produced when they did not choose one. last_digest_id is the newest digest sent, or
+-- the newest one when they subscribed
+CREATE TABLE digest_subscriptions (
+    user_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+    delivery_hour INTEGER,
+    last_digest_id INTEGER,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, guild_id)
+);
+
+-- Channels a subscriber only wants the summaries of. Subscribers without any get the
+-- whole digest
+CREATE TABLE digest_subscription_channels (
+    user_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    PRIMARY KEY (user_id, guild_id, channel_id),
+    FOREIGN KEY (user_id, guild_id) REFERENCES digest_subscriptions(user_id, guild_id)
+);
@@ -0,0 +1,13 @@
+-- Emails of daily digests, one row per recipient. status is 'delivered' or 'failed',
+-- with the SMTP error in last_error
+CREATE TABLE email_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    daily_digest_id INTEGER NOT NULL,
+    recipient TEXT NOT NULL,
+    status TEXT NOT NULL,
+    last_error TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (daily_digest_id) REFERENCES daily_digests(id)
+);
+
+CREATE INDEX idx_email_deliveries_digest ON email_deliveries (daily_digest_id);
@@ -0,0 +1,7 @@
+-- Names of the channels messages were posted to POST /ingest for, by the ID derived
+-- from each name
+CREATE TABLE ingest_channels (
+    channel_id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
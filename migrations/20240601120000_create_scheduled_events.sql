@@ -0,0 +1,22 @@
+-- Scheduled events of guilds, as last seen through the gateway or the REST API. status
+-- is 'scheduled', 'active', 'completed' or 'canceled'. Events that concluded are
+-- summarized in the next daily digest, which is then recorded in daily_digest_id
+CREATE TABLE scheduled_events (
+    event_id INTEGER PRIMARY KEY,
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER,
+    name TEXT NOT NULL,
+    description TEXT,
+    location TEXT,
+    starts_at DATETIME NOT NULL,
+    ends_at DATETIME,
+    status TEXT NOT NULL,
+    user_count INTEGER,
+    started_at DATETIME,
+    concluded_at DATETIME,
+    daily_digest_id INTEGER,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (daily_digest_id) REFERENCES daily_digests(id)
+);
+
+CREATE INDEX idx_scheduled_events_concluded ON scheduled_events (guild_id, concluded_at);
@@ -0,0 +1,17 @@
+-- Standups the bot asked for in the standup channel. Replies posted until closes_at
+-- are summarized once it passes, per person, into entries (a JSON array of
+-- {person, yesterday, today, blockers}) and the posted summary
+CREATE TABLE standups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER,
+    channel_id INTEGER NOT NULL,
+    prompt_message_id INTEGER NOT NULL,
+    opened_at DATETIME NOT NULL,
+    closes_at DATETIME NOT NULL,
+    reply_count INTEGER NOT NULL DEFAULT 0,
+    entries TEXT NOT NULL DEFAULT '[]',
+    summary TEXT,
+    summarized_at DATETIME
+);
+
+CREATE INDEX idx_standups_open ON standups (summarized_at, closes_at);
@@ -0,0 +1,18 @@
+-- Frequently asked questions of each guild and their answers, mined from summaries.
+-- asked_count is how many batches of summaries the question came up in
+CREATE TABLE faq (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    asked_count INTEGER NOT NULL DEFAULT 1,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_faq_guild ON faq (guild_id, asked_count);
+
+-- When each summary was mined for questions, NULL until it is
+ALTER TABLE summaries ADD COLUMN faq_mined_at DATETIME;
+
+CREATE INDEX idx_summaries_faq_mined_at ON summaries (faq_mined_at);
@@ -0,0 +1,20 @@
+-- Knowledge base articles written on demand from every summary mentioning a topic
+CREATE TABLE kb_articles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER,
+    topic TEXT NOT NULL,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_kb_articles_guild ON kb_articles (guild_id, created_at);
+
+-- The summaries each article was written from, along with the daily digest each was
+-- rolled up into, if any yet
+CREATE TABLE kb_article_sources (
+    article_id INTEGER NOT NULL REFERENCES kb_articles(id),
+    summary_id INTEGER NOT NULL REFERENCES summaries(id),
+    daily_digest_id INTEGER REFERENCES daily_digests(id),
+    PRIMARY KEY (article_id, summary_id)
+);
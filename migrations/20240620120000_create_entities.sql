@@ -0,0 +1,16 @@
+-- People, projects, tools and tickets mentioned in each summary. normalized_name is the
+-- lowercased name with its whitespace collapsed, which mentions are looked up by
+CREATE TABLE entities (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    summary_id INTEGER NOT NULL REFERENCES summaries(id),
+    guild_id INTEGER,
+    channel_id INTEGER,
+    name TEXT NOT NULL,
+    normalized_name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_entities_name ON entities (normalized_name, timestamp);
+CREATE INDEX idx_entities_summary ON entities (summary_id);
+CREATE INDEX idx_entities_guild_timestamp ON entities (guild_id, timestamp);
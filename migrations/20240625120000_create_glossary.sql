@@ -0,0 +1,22 @@
+-- Jargon and acronyms of each guild with their definitions, mined from summaries.
+-- normalized_term is the lowercased term with its whitespace collapsed. mention_count is
+-- how many batches of summaries the term came up in, and weekly_digest_id the weekly
+-- digest that listed it as a new term
+CREATE TABLE glossary (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER,
+    term TEXT NOT NULL,
+    normalized_term TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    mention_count INTEGER NOT NULL DEFAULT 1,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    weekly_digest_id INTEGER REFERENCES weekly_digests(id)
+);
+
+CREATE INDEX idx_glossary_guild_term ON glossary (guild_id, normalized_term);
+
+-- When each summary was mined for jargon, NULL until it is
+ALTER TABLE summaries ADD COLUMN glossary_mined_at DATETIME;
+
+CREATE INDEX idx_summaries_glossary_mined_at ON summaries (glossary_mined_at);
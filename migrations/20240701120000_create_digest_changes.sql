@@ -0,0 +1,10 @@
+-- What changed in each daily digest compared to the previous daily digest of its guild,
+-- as JSON arrays of short items
+CREATE TABLE digest_changes (
+    daily_digest_id INTEGER PRIMARY KEY REFERENCES daily_digests(id),
+    previous_digest_id INTEGER NOT NULL REFERENCES daily_digests(id),
+    new_topics TEXT NOT NULL DEFAULT '[]',
+    resolved TEXT NOT NULL DEFAULT '[]',
+    continuing TEXT NOT NULL DEFAULT '[]',
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,4 @@
+-- One-paragraph TL;DR of each digest, generated alongside its full text
+ALTER TABLE daily_digests ADD COLUMN tldr TEXT;
+ALTER TABLE weekly_digests ADD COLUMN tldr TEXT;
+ALTER TABLE monthly_digests ADD COLUMN tldr TEXT;
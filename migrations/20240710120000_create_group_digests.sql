@@ -0,0 +1,16 @@
+-- Digests of the summaries of each channel group of [channel_groups], written along with
+-- the daily digest the summaries were rolled up into and sectioned by channel.
+-- channel_ids is a JSON array of the group's channels that were summarized
+CREATE TABLE group_digests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    daily_digest_id INTEGER NOT NULL REFERENCES daily_digests(id),
+    group_name TEXT NOT NULL,
+    guild_id INTEGER,
+    text TEXT NOT NULL,
+    message_count INTEGER NOT NULL,
+    channel_ids TEXT NOT NULL DEFAULT '[]',
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (daily_digest_id, group_name)
+);
+
+CREATE INDEX idx_group_digests_group ON group_digests (group_name, created_at);
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono_tz::Tz;
+use eyre::WrapErr;
+use serenity::http::Http;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+
+use crate::config::{AppConfig, DatabaseConfig};
+use crate::db::RollupTier;
+use crate::error::Error;
+use crate::gpt::{
+    self, Budget, Embedder, LlmPause, PromptGuard, RequestLayers, Summarizers, TokenCounter,
+    UsageRecorder,
+};
+use crate::names::DiscordNames;
+use crate::prompts::Prompts;
+use crate::schedule::Schedule;
+use crate::services::digests::RecapService;
+use crate::services::privacy::OptOuts;
+use crate::templates::DigestTemplates;
+
+/// What every command shares, set up from the config.
+pub struct App {
+    pub config: AppConfig,
+    pub timezone: Tz,
+    pub db: Arc<SqlitePool>,
+    pub opt_outs: OptOuts,
+    pub token_counter: Arc<dyn TokenCounter>,
+    /// What LLM requests made outside of summarizers go through.
+    pub layers: RequestLayers,
+    /// Used to talk to Discord outside of event handlers, before the client is created.
+    pub http: Arc<Http>,
+    pub names: DiscordNames,
+    pub prompts: Prompts,
+    pub templates: Arc<DigestTemplates>,
+    pub pause: LlmPause,
+    pub summarizers: Summarizers,
+    pub embedder: Arc<dyn Embedder>,
+    /// How many tokens of messages are summarized at once.
+    pub summary_tokens_threshold: usize,
+}
+
+impl App {
+    /// Opens the database and sets up the LLM clients. Without a Discord `token`,
+    /// guilds and channels are named by their IDs.
+    pub async fn setup(config: AppConfig, token: &str) -> eyre::Result<Self> {
+        let timezone = config.timezone()?;
+        let db = Arc::new(open_database(&config.database).await?);
+        let opt_outs = OptOuts::load(&db)
+            .await
+            .wrap_err("Could not load the members who opted out")?;
+        let token_counter = gpt::token_counter_from_config(&config.gpt);
+        let http = Arc::new(Http::new(token));
+        let names = DiscordNames::new(http.clone()).with_ingest_channels(db.clone());
+        let prompts = Prompts::load(&config.prompts)?;
+        let templates = Arc::new(DigestTemplates::load(&config.templates, timezone)?);
+        let budget = Budget::from_config(db.clone(), &config.gpt.budget, http.clone())?;
+        let pause = LlmPause::new(config.quiet_hours.quiet_hours(timezone)?);
+        let layers = RequestLayers {
+            usage: UsageRecorder::new(db.clone(), &config.gpt.prices),
+            budget,
+            guard: PromptGuard::from_config(&config.gpt.prompt_guard)?,
+            pause: pause.clone(),
+        };
+        let summarizers = gpt::summarizers_from_config(
+            &config.gpt,
+            token_counter.clone(),
+            config.service.max_gpt_request_tokens,
+            &layers,
+            &config.prompts.profile_models(),
+        )?;
+        let embedder = gpt::embedder_from_config(&config.gpt, &layers);
+        let summary_tokens_threshold = summarizers
+            .summaries
+            .max_input_tokens()
+            .map_or(config.service.max_gpt_request_tokens, |max| {
+                max.min(config.service.max_gpt_request_tokens)
+            });
+        Ok(Self {
+            config,
+            timezone,
+            db,
+            opt_outs,
+            token_counter,
+            layers,
+            http,
+            names,
+            prompts,
+            templates,
+            pause,
+            summarizers,
+            embedder,
+            summary_tokens_threshold,
+        })
+    }
+
+    /// A daily recap of calendar days, for commands that roll up digests once instead of
+    /// on a schedule. Its digests are neither delivered nor sent to webhooks.
+    pub fn daily_recap(&self) -> RecapService {
+        let config = &self.config;
+        let mut recap_srv = RecapService::new(
+            self.db.clone(),
+            RollupTier::Daily,
+            Schedule::Interval(Duration::from_secs(
+                config.service.produce_digest_interval_seconds,
+            )),
+            self.timezone,
+            self.summarizers.digests.clone(),
+        )
+        .with_embedder(self.embedder.clone())
+        .with_prompts(self.prompts.clone(), self.names.clone())
+        .with_templates(self.templates.clone())
+        .with_verbosity(config.digests.verbosity)
+        .with_calendar_days();
+        if config.digests.tldr {
+            recap_srv = recap_srv.with_tldr();
+        }
+        if config.citations.enabled {
+            recap_srv = recap_srv.with_citations();
+        }
+        if config.stats.digest_section {
+            recap_srv = recap_srv.with_participant_stats();
+        }
+        recap_srv
+    }
+}
+
+/// Initiates a connection to the database file, creating the file if required.
+pub async fn connect_database(config: &DatabaseConfig) -> eyre::Result<SqlitePool> {
+    SqlitePoolOptions::new()
+        .max_connections(4)
+        .connect_with(
+            SqliteConnectOptions::new()
+                .filename(&config.url)
+                .create_if_missing(true),
+        )
+        .await
+        .map_err(Error::from)
+        .wrap_err("Couldn't connect to database")
+}
+
+/// Connects to the database and migrates it to the latest schema.
+pub async fn open_database(config: &DatabaseConfig) -> eyre::Result<SqlitePool> {
+    let database = connect_database(config).await?;
+
+    // Run migrations, which updates the database's schema to the latest version.
+    sqlx::migrate!("./migrations")
+        .run(&database)
+        .await
+        .map_err(Error::from)?;
+    Ok(database)
+}
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, NaiveDate};
+use clap::{Parser, Subcommand, ValueEnum};
+use eyre::{bail, WrapErr};
+use serenity::all::ChannelId;
+use sqlx::migrate::Migrate;
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::app::{self, App};
+use crate::config::{AppConfig, DatabaseConfig};
+use crate::db::{self, ContentFilter, DateRange, RollupTier};
+use crate::error::Error;
+use crate::prompts::{format_prompt_time, PromptVars};
+use crate::redaction::Redactor;
+use crate::schedule::start_of_day;
+use crate::services::discord_export;
+use crate::services::discord_handler::MessageIntake;
+use crate::services::message_listener::MessageLogService;
+use crate::services::privacy::Pseudonyms;
+use crate::services::summarizer::{summarize_transcript, SummarizerService};
+use crate::services::webhooks::Webhooks;
+use crate::templates::{DigestTarget, DigestTemplates};
+
+/// Summarizes the conversations of Discord servers into daily digests.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Run without calling any LLM API, writing placeholder summaries instead.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// What to do, running the bot when left out.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Default)]
+pub enum Command {
+    /// Runs the bot, summarizing the messages it receives and rolling them up into
+    /// digests on schedule.
+    #[default]
+    Serve,
+    /// Imports DiscordChatExporter JSON exports, or the messages.json files of
+    /// Discord's data package, and summarizes them into a digest per day they cover.
+    Import {
+        /// Export files, or directories to import every JSON file of.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Summarizes a transcript and prints the summary, without storing it.
+    Summarize {
+        /// Text file of the transcript.
+        #[arg(long)]
+        file: PathBuf,
+        /// Channel whose prompt profile to summarize with.
+        #[arg(long)]
+        channel: Option<ChannelId>,
+        /// Print the summary's topics, decisions and action items as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rolls up the summaries of a day that are not part of a digest yet into a daily
+    /// digest per guild, and prints them.
+    Digest {
+        /// Day to roll up, in the reporting timezone, such as 2024-05-01.
+        #[arg(long)]
+        date: NaiveDate,
+    },
+    /// Exports the daily digests and the summaries they roll up.
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// First day to export the digests of, in the reporting timezone.
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last day to export the digests of, in the reporting timezone.
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Only export the digests of this guild.
+        #[arg(long)]
+        guild: Option<i64>,
+        /// File to write to instead of the standard output.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Checks the config, the Discord token and intents, the LLM API key, the database
+    /// and its storage, and prints what to do about every problem found.
+    Doctor,
+    /// Checks or applies the database migrations.
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Lists the migrations and whether each was applied.
+    Status,
+    /// Applies the migrations that were not applied yet.
+    Run,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ExportFormat {
+    /// The digests as the HTTP API returns them, summaries included.
+    Json,
+    /// The digests rendered with the API template, one after the other.
+    Markdown,
+}
+
+/// Imports channel history from export files, summarizes it and rolls it up into a
+/// digest per day.
+pub async fn import(app: App, paths: &[PathBuf]) -> eyre::Result<()> {
+    let config = &app.config;
+    let messages = discord_export::read_exports(paths)?;
+    let pseudonyms = config
+        .privacy
+        .anonymize_authors
+        .then(|| Pseudonyms::new(config.privacy.pseudonym_key.as_deref()));
+    let mut intake = MessageIntake::new(
+        config.discord.channel_filter()?,
+        config
+            .discord
+            .author_filter()?
+            .with_opt_outs(app.opt_outs.clone()),
+    );
+    if let Some(pseudonyms) = &pseudonyms {
+        intake = intake.with_pseudonyms(pseudonyms.clone());
+    }
+    let events = discord_export::replay_events(messages, &intake, app.timezone);
+    info!("Importing {} batches of channel history", events.len());
+
+    let (summarize_tx, summarize_rx) = mpsc::channel(100);
+    let (ingest_tx, ingest_rx) = mpsc::channel(100);
+    let mut message_log_srv = MessageLogService::new(
+        app.db.clone(),
+        summarize_tx,
+        ingest_rx,
+        app.token_counter.clone(),
+        app.summary_tokens_threshold,
+        None,
+    )
+    .with_redactor(Redactor::from_config(&config.privacy.redaction)?);
+    if let Some(pseudonyms) = pseudonyms {
+        message_log_srv = message_log_srv.with_pseudonyms(pseudonyms);
+    }
+    // Historical summaries are not sent to webhooks.
+    let summary_srv = SummarizerService::new(
+        summarize_rx,
+        app.db.clone(),
+        app.summarizers.summaries.clone(),
+        app.embedder.clone(),
+        app.token_counter.clone(),
+        config.service.pending_retry_interval_seconds,
+        Webhooks::new(app.db.clone(), vec![]),
+    )
+    .with_prompts(
+        app.prompts.clone(),
+        app.names.clone(),
+        app.summarizers.models.clone(),
+    );
+    let recap_srv = app.daily_recap();
+    discord_export::replay(events, ingest_tx, message_log_srv, summary_srv, recap_srv).await;
+    info!("Imported the channel history");
+    Ok(())
+}
+
+/// Summarizes a transcript file with the prompt of a channel, or the default prompt,
+/// and prints the summary.
+pub async fn summarize(
+    app: &App,
+    file: &Path,
+    channel_id: Option<ChannelId>,
+    json: bool,
+) -> eyre::Result<()> {
+    let transcript =
+        fs::read_to_string(file).wrap_err_with(|| format!("Could not read {}", file.display()))?;
+    if transcript.trim().is_empty() {
+        bail!("{} is empty, there is nothing to summarize", file.display());
+    }
+    let vars = PromptVars::lookup(
+        Some(&app.names),
+        None,
+        channel_id,
+        format_prompt_time(None),
+        format_prompt_time(None),
+    )
+    .await;
+    let prompt = match channel_id {
+        Some(channel_id) => app.prompts.summary(channel_id, &vars, &transcript),
+        None => app.prompts.default_summary(&vars, &transcript),
+    };
+    let summary = summarize_transcript(
+        &app.summarizers.summaries,
+        &app.summarizers.models,
+        &prompt,
+        &transcript,
+    )
+    .await
+    .wrap_err("Could not summarize the transcript")?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        println!("{}", summary.render());
+    }
+    Ok(())
+}
+
+/// Rolls up the summaries of a day into daily digests and prints them.
+pub async fn digest(app: &App, date: NaiveDate) -> eyre::Result<()> {
+    let digest_ids = app.daily_recap().recap_day(date).await;
+    if digest_ids.is_empty() {
+        bail!("No digest was written, either every summary of {date} is already part of a digest or it has none, see the logs for errors");
+    }
+    let mut rendered = vec![];
+    for digest_id in digest_ids {
+        let Some(digest) = db::fetch_daily_digest(&app.db, digest_id).await? else {
+            continue;
+        };
+        rendered.push(render_digest(&app.templates, &digest));
+    }
+    println!("{}", rendered.join(DIGEST_SEPARATOR));
+    Ok(())
+}
+
+/// Separates the digests printed or exported as Markdown.
+const DIGEST_SEPARATOR: &str = "\n\n---\n\n";
+
+fn render_digest(templates: &DigestTemplates, digest: &db::DailyDigest) -> String {
+    let view = templates.view(
+        RollupTier::Daily,
+        Some(digest.id),
+        digest.guild_id,
+        &digest.text,
+        digest.message_count,
+        digest.covers_from.zip(digest.covers_to),
+    );
+    templates.render(DigestTarget::Api, &view)
+}
+
+/// Exports the daily digests written between two days, both included. Needs nothing
+/// but the database, so it runs without any API key.
+pub async fn export(
+    config: &AppConfig,
+    format: ExportFormat,
+    (from, to): (Option<NaiveDate>, Option<NaiveDate>),
+    guild_id: Option<i64>,
+    output: Option<&Path>,
+) -> eyre::Result<()> {
+    let timezone = config.timezone()?;
+    let db = app::open_database(&config.database).await?;
+    let range = DateRange {
+        from: from.map(|day| start_of_day(day, timezone)),
+        to: to.map(|day| start_of_day(day + Duration::days(1), timezone)),
+    };
+    let filter = ContentFilter {
+        guild_id,
+        channel_id: None,
+    };
+    let digests = db::fetch_daily_digests(&db, &filter, &range, None).await?;
+    let exported = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&digests)?,
+        ExportFormat::Markdown => {
+            let templates = DigestTemplates::load(&config.templates, timezone)?;
+            digests
+                .iter()
+                .map(|digest| render_digest(&templates, digest))
+                .collect::<Vec<_>>()
+                .join(DIGEST_SEPARATOR)
+        }
+    };
+    match output {
+        Some(path) => {
+            fs::write(path, exported)
+                .wrap_err_with(|| format!("Could not write {}", path.display()))?;
+            info!(
+                "Exported {} daily digests to {}",
+                digests.len(),
+                path.display()
+            );
+        }
+        None => println!("{exported}"),
+    }
+    Ok(())
+}
+
+/// Whether a migration was applied to the database.
+#[derive(PartialEq)]
+pub enum MigrationState {
+    Applied,
+    /// Applied, but its file changed since.
+    Changed,
+    Pending,
+    /// Applied, but not among the migrations of this version of the bot.
+    Unknown,
+}
+
+pub struct MigrationStatus {
+    pub version: i64,
+    /// Empty for unknown migrations.
+    pub description: String,
+    pub state: MigrationState,
+}
+
+/// The state of every migration of this version of the bot, and of the unknown ones
+/// applied to the database, in the order they were created in.
+pub async fn migration_status(database: &SqlitePool) -> eyre::Result<Vec<MigrationStatus>> {
+    let mut conn = database.acquire().await.map_err(Error::from)?;
+    conn.ensure_migrations_table().await.map_err(Error::from)?;
+    let mut applied: HashMap<i64, Vec<u8>> = conn
+        .list_applied_migrations()
+        .await
+        .map_err(Error::from)?
+        .into_iter()
+        .map(|migration| (migration.version, migration.checksum.into_owned()))
+        .collect();
+    let mut statuses: Vec<MigrationStatus> = sqlx::migrate!("./migrations")
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            state: match applied.remove(&migration.version) {
+                Some(checksum) if checksum == *migration.checksum => MigrationState::Applied,
+                Some(_) => MigrationState::Changed,
+                None => MigrationState::Pending,
+            },
+        })
+        .collect();
+    statuses.extend(applied.into_keys().map(|version| MigrationStatus {
+        version,
+        description: String::new(),
+        state: MigrationState::Unknown,
+    }));
+    statuses.sort_by_key(|status| status.version);
+    Ok(statuses)
+}
+
+/// Lists or applies the database migrations.
+pub async fn migrate(config: &DatabaseConfig, command: MigrateCommand) -> eyre::Result<()> {
+    let database = app::connect_database(config).await?;
+    match command {
+        MigrateCommand::Run => {
+            sqlx::migrate!("./migrations")
+                .run(&database)
+                .await
+                .map_err(Error::from)?;
+            println!("The database is up to date");
+        }
+        MigrateCommand::Status => {
+            let statuses = migration_status(&database).await?;
+            for status in &statuses {
+                let state = match status.state {
+                    MigrationState::Applied => "applied",
+                    MigrationState::Changed => "applied, but changed since",
+                    MigrationState::Pending => "pending",
+                    MigrationState::Unknown => "applied, but unknown to this version of the bot",
+                };
+                if status.description.is_empty() {
+                    println!("{}: {state}", status.version);
+                } else {
+                    println!("{} {}: {state}", status.version, status.description);
+                }
+            }
+            let pending = statuses
+                .iter()
+                .filter(|status| status.state == MigrationState::Pending)
+                .count();
+            if pending > 0 {
+                println!("{pending} migrations are pending, they are applied by `migrate run` or when the bot starts");
+            } else {
+                println!("The database is up to date");
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,13 +1,134 @@
+use chrono::NaiveTime;
+use chrono_tz::Tz;
 use config::{Config, ConfigError};
-use serde::Deserialize;
+use eyre::{bail, eyre};
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, Timestamp, UserId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::path::PathBuf;
 
+use crate::db::RollupTier;
+use crate::gpt::QuietHours;
+use crate::schedule::{parse_timezone, Schedule};
+use crate::services::discord_handler::{AllowedChannels, AuthorFilter, ChannelFilter};
+
+/// Entry in `discord.channel_ids` that allows messages from every channel.
+pub const ALL_CHANNELS_WILDCARD: &str = "*";
+
 #[derive(Deserialize)]
 pub struct AppConfig {
     pub database: DatabaseConfig,
     pub service: ServiceConfig,
-    #[allow(unused)]
     pub discord: DiscordConfig,
+    #[serde(default)]
+    pub gpt: GptConfig,
+    #[serde(default)]
+    pub rollups: RollupsConfig,
+    #[serde(default)]
+    pub links: LinksConfig,
+    #[serde(default)]
+    pub highlights: HighlightsConfig,
+    #[serde(default)]
+    pub scheduled_events: ScheduledEventsConfig,
+    #[serde(default)]
+    pub standup: StandupConfig,
+    #[serde(default)]
+    pub faq: FaqConfig,
+    #[serde(default)]
+    pub knowledge_base: KnowledgeBaseConfig,
+    #[serde(default)]
+    pub glossary: GlossaryConfig,
+    #[serde(default)]
+    pub citations: CitationsConfig,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    #[serde(default)]
+    pub changes: ChangesConfig,
+    #[serde(default)]
+    pub digests: DigestsConfig,
+    #[serde(default)]
+    pub group_digests: GroupDigestsConfig,
+    #[serde(default)]
+    pub quiet_hours: QuietHoursConfig,
+    #[serde(default)]
+    pub sentiment: SentimentConfig,
+    #[serde(default)]
+    pub verification: VerificationConfig,
+    #[serde(default)]
+    pub anomalies: AnomaliesConfig,
+    #[serde(default)]
+    pub keyword_watch: KeywordWatchConfig,
+    #[serde(default)]
+    pub prompts: PromptsConfig,
+    #[serde(default)]
+    pub templates: TemplatesConfig,
+    #[serde(default)]
+    pub privacy: PrivacyConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub email: EmailConfig,
+    #[serde(default)]
+    pub telegram: TelegramConfig,
+    #[serde(default)]
+    pub matrix: MatrixConfig,
+    #[serde(default)]
+    pub ingest: IngestConfig,
+    #[serde(default)]
+    pub github: GithubConfig,
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+    /// Named groups of channel IDs, which sinks can be limited to and which can get
+    /// digests of their own.
+    #[serde(default)]
+    pub channel_groups: HashMap<String, Vec<String>>,
+    /// Settings of single channels, by channel ID.
+    #[serde(default)]
+    pub channels: HashMap<String, ChannelConfig>,
+}
+
+/// Settings of a channel that override those of every other channel, configured under
+/// `[channels.<channel ID>]`.
+#[derive(Deserialize, Clone, Default)]
+pub struct ChannelConfig {
+    /// Summarize the channel's messages once they reach this many tokens, instead of
+    /// `service.max_gpt_request_tokens`.
+    pub max_gpt_request_tokens: Option<usize>,
+    /// Summarize the channel's log once this long has passed since its last summary,
+    /// instead of after `service.summarize_after_seconds`.
+    pub summarize_after_seconds: Option<u64>,
+    /// Profile of `[prompts.profiles]` the channel is summarized with, as if it was
+    /// set in `[prompts.channels]`.
+    pub prompt_profile: Option<String>,
+    #[serde(default)]
+    pub priority: ChannelPriority,
+}
+
+impl ChannelConfig {
+    /// How many tokens of the channel's messages make a batch, when not `default`.
+    pub fn summary_tokens_threshold(&self, default: usize) -> Option<usize> {
+        match self.priority {
+            ChannelPriority::High => Some(self.max_gpt_request_tokens.unwrap_or(default / 2)),
+            _ => self.max_gpt_request_tokens,
+        }
+    }
+}
+
+/// How much a channel matters compared to the others.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelPriority {
+    /// Its summaries come last in daily digest prompts.
+    Low,
+    #[default]
+    Normal,
+    /// Summarized at half the token threshold unless it sets its own, and its
+    /// summaries come first in daily digest prompts, marked for the model to give them
+    /// more weight.
+    High,
 }
 
 #[derive(Deserialize)]
@@ -18,16 +139,1556 @@ pub struct DatabaseConfig {
 #[derive(Deserialize)]
 pub struct ServiceConfig {
     pub produce_digest_interval_seconds: u64,
-    pub message_log_directory: PathBuf,
+    /// Cron expression for when to produce daily digests, such as `0 8 * * *`. Takes
+    /// precedence over `produce_digest_interval_seconds`.
+    pub produce_digest_schedule: Option<String>,
+    /// IANA timezone that cron schedules are evaluated in and digest periods are
+    /// reported in.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Make each daily digest cover exactly one calendar day in `timezone`.
+    #[serde(default)]
+    pub calendar_day_digests: bool,
     pub port: u16,
     pub host: String,
+    /// Base URL the HTTP API is publicly reachable at, such as
+    /// `https://summaries.example.com`, used to link to it from the digest feed.
+    pub public_url: Option<String>,
     pub max_gpt_request_tokens: usize,
+    /// Summarize a channel's log once this long has passed since its last summary,
+    /// even if it has not reached `max_gpt_request_tokens`.
+    pub summarize_after_seconds: Option<u64>,
+    /// How often summarizations that failed are retried.
+    #[serde(default = "default_pending_retry_interval_seconds")]
+    pub pending_retry_interval_seconds: u64,
+    /// How long a question can go without a reply before daily digests list it as an
+    /// open question.
+    #[serde(default = "default_question_answer_window_seconds")]
+    pub question_answer_window_seconds: u64,
+    /// Requests each HTTP API client can make per minute on average, identified by API
+    /// key when keys are configured and by IP address otherwise. 0 disables rate limiting.
+    #[serde(default = "default_rate_limit_per_minute")]
+    pub rate_limit_per_minute: u32,
+    /// Requests a client can make in a quick burst before being limited.
+    #[serde(default = "default_rate_limit_burst")]
+    pub rate_limit_burst: u32,
+    /// How long to keep summarizing the collected messages after being asked to shut
+    /// down. Whatever is left is summarized on the next start.
+    #[serde(default = "default_shutdown_timeout_seconds")]
+    pub shutdown_timeout_seconds: u64,
+    /// Where versions of the bot that logged messages to files kept them. Logs left
+    /// there are imported into the database at startup.
+    #[serde(default = "default_message_log_directory")]
+    pub message_log_directory: PathBuf,
+}
+
+fn default_shutdown_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_message_log_directory() -> PathBuf {
+    PathBuf::from("messages")
+}
+
+fn default_rate_limit_per_minute() -> u32 {
+    120
+}
+
+fn default_rate_limit_burst() -> u32 {
+    30
+}
+
+fn default_question_answer_window_seconds() -> u64 {
+    4 * 60 * 60
+}
+
+fn default_pending_retry_interval_seconds() -> u64 {
+    300
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+/// How often daily digests are rolled up into weekly digests, and weekly digests into
+/// monthly ones. A cron schedule takes precedence over the interval, and a tier with
+/// neither is not produced.
+#[derive(Deserialize)]
+pub struct RollupsConfig {
+    pub weekly_interval_seconds: Option<u64>,
+    pub weekly_schedule: Option<String>,
+    pub monthly_interval_seconds: Option<u64>,
+    pub monthly_schedule: Option<String>,
+    /// Mention the topics that came up in several of the daily digests a weekly digest
+    /// rolls up.
+    #[serde(default = "default_recurring_topics")]
+    pub recurring_topics: bool,
+}
+
+impl Default for RollupsConfig {
+    fn default() -> Self {
+        Self {
+            weekly_interval_seconds: None,
+            weekly_schedule: None,
+            monthly_interval_seconds: None,
+            monthly_schedule: None,
+            recurring_topics: default_recurring_topics(),
+        }
+    }
+}
+
+fn default_recurring_topics() -> bool {
+    true
+}
+
+/// Access control for the HTTP API.
+#[derive(Deserialize, Default)]
+pub struct ApiConfig {
+    /// Keys that grant access to the HTTP API. Keys can also be given in the
+    /// comma-separated `API_KEYS` env var. Without any key, the API is open to anyone
+    /// who can reach it.
+    #[serde(default)]
+    pub keys: Vec<String>,
+}
+
+impl ApiConfig {
+    /// The configured API keys along with those from the `API_KEYS` env var.
+    pub fn api_keys(&self) -> Vec<String> {
+        let from_env = env::var("API_KEYS").unwrap_or_default();
+        self.keys
+            .iter()
+            .map(String::as_str)
+            .chain(from_env.split(','))
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// A URL that new summaries and digests are POSTed to, configured under `[[webhooks]]`.
+#[derive(Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// When set, requests are signed with an HMAC-SHA256 of their body using this secret.
+    pub secret: Option<String>,
+    /// Events to send, every event when empty.
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl WebhookConfig {
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// The SMTP server daily digests are emailed through and who they are sent to,
+/// configured under `[email]`. Digests are only emailed when recipients are listed.
+#[derive(Deserialize, Default)]
+pub struct EmailConfig {
+    pub smtp_host: Option<String>,
+    /// Defaults to the usual port of the connection security: 587 for STARTTLS, 465 for
+    /// TLS and 25 without any.
+    pub smtp_port: Option<u16>,
+    pub username: Option<String>,
+    /// Can also be given in the `SMTP_PASSWORD` env var, which takes precedence.
+    pub password: Option<String>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    /// Address emails are sent from, such as `Digests <digests@example.com>`.
+    pub from: Option<String>,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+}
+
+impl EmailConfig {
+    /// The SMTP password, from the `SMTP_PASSWORD` env var or the config.
+    pub fn password(&self) -> Option<String> {
+        env::var("SMTP_PASSWORD")
+            .ok()
+            .filter(|password| !password.is_empty())
+            .or_else(|| self.password.clone())
+    }
+}
+
+/// How connections to the SMTP server are secured.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS, which the server must support.
+    #[default]
+    #[serde(rename = "starttls")]
+    StartTls,
+    /// Connect over TLS from the start.
+    Tls,
+    /// Send in the clear, for local relays only.
+    None,
+}
+
+/// The Telegram group or channel a bot pushes digests to, configured under
+/// `[telegram]`.
+#[derive(Deserialize, Default)]
+pub struct TelegramConfig {
+    /// Token of the bot, as given by @BotFather. Can also be given in the
+    /// `TELEGRAM_BOT_TOKEN` env var, which takes precedence.
+    pub bot_token: Option<String>,
+    /// ID of the group or channel, or `@username` of a public channel. Digests are only
+    /// pushed when set.
+    pub chat_id: Option<String>,
+}
+
+impl TelegramConfig {
+    /// The bot token, from the `TELEGRAM_BOT_TOKEN` env var or the config.
+    pub fn bot_token(&self) -> Option<String> {
+        env::var("TELEGRAM_BOT_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .or_else(|| self.bot_token.clone())
+    }
+}
+
+/// The Matrix room digests are posted to, configured under `[matrix]`.
+#[derive(Deserialize, Default)]
+pub struct MatrixConfig {
+    /// Base URL of the homeserver the access token was issued by, such as
+    /// `https://matrix.example.org`.
+    pub homeserver_url: Option<String>,
+    /// Access token of the account digests are posted as. Can also be given in the
+    /// `MATRIX_ACCESS_TOKEN` env var, which takes precedence.
+    pub access_token: Option<String>,
+    /// ID of the room, such as `!abcdefg:example.org`, which the account must have
+    /// joined. Digests are only posted when set.
+    pub room_id: Option<String>,
+}
+
+impl MatrixConfig {
+    /// The access token, from the `MATRIX_ACCESS_TOKEN` env var or the config.
+    pub fn access_token(&self) -> Option<String> {
+        env::var("MATRIX_ACCESS_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .or_else(|| self.access_token.clone())
+    }
+}
+
+/// GitHub repositories whose activity is listed in daily digests, configured under
+/// `[github]`.
+#[derive(Deserialize)]
+pub struct GithubConfig {
+    /// Repositories as `owner/name`. Nothing is fetched when empty.
+    #[serde(default)]
+    pub repos: Vec<String>,
+    /// Guilds whose daily digests list the activity. Every guild when empty.
+    #[serde(default)]
+    pub guild_ids: Vec<String>,
+    /// Token to call the API with, needed for private repositories and higher rate
+    /// limits. Can also be given in the `GITHUB_TOKEN` env var, which takes precedence.
+    pub token: Option<String>,
+    /// Base URL of the REST API, to use GitHub Enterprise Server.
+    #[serde(default = "default_github_api_url")]
+    pub api_url: String,
+}
+
+impl Default for GithubConfig {
+    fn default() -> Self {
+        Self {
+            repos: vec![],
+            guild_ids: vec![],
+            token: None,
+            api_url: default_github_api_url(),
+        }
+    }
+}
+
+fn default_github_api_url() -> String {
+    "https://api.github.com".to_string()
+}
+
+impl GithubConfig {
+    /// The token, from the `GITHUB_TOKEN` env var or the config.
+    pub fn token(&self) -> Option<String> {
+        env::var("GITHUB_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .or_else(|| self.token.clone())
+    }
+
+    pub fn guild_ids(&self) -> eyre::Result<Vec<GuildId>> {
+        self.guild_ids
+            .iter()
+            .map(|id| parse_snowflake(id).map(GuildId::new))
+            .collect()
+    }
+}
+
+/// Messages of other systems posted to `POST /ingest`, configured under `[ingest]`.
+#[derive(Deserialize, Default)]
+pub struct IngestConfig {
+    /// Whether the endpoint is served.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Guild whose digests ingested messages are part of. They get digests of their own
+    /// when unset.
+    pub guild_id: Option<String>,
+}
+
+impl IngestConfig {
+    pub fn guild_id(&self) -> eyre::Result<Option<GuildId>> {
+        self.guild_id
+            .as_deref()
+            .map(|id| parse_snowflake(id).map(GuildId::new))
+            .transpose()
+    }
+}
+
+/// A destination digests are delivered to, configured as `[[sinks]]` entries, along
+/// with which digests it gets.
+#[derive(Deserialize)]
+pub struct SinkConfig {
+    #[serde(flatten)]
+    pub kind: SinkKind,
+    /// Guilds whose digests are delivered, every guild when empty.
+    #[serde(default)]
+    pub guild_ids: Vec<String>,
+    /// Group of `[channel_groups]` whose summaries are delivered. Sinks limited to a
+    /// group only get the daily digests that summarized any of its channels, as the
+    /// group's digest when `[group_digests]` is enabled, or else cut down to the
+    /// summaries of those channels.
+    pub channel_group: Option<String>,
+    /// Tiers of the digests delivered, every tier when empty.
+    #[serde(default)]
+    pub tiers: Vec<RollupTier>,
+}
+
+/// Where a sink delivers digests, and how.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkKind {
+    /// Posted to a Discord channel.
+    Discord { channel_id: String },
+    /// POSTed as JSON to a URL, signed like `[[webhooks]]` when a secret is set.
+    Webhook { url: String, secret: Option<String> },
+    /// Emailed through the SMTP server of `[email]`.
+    Email { recipients: Vec<String> },
+    /// Pushed to a Telegram chat by the bot of `[telegram]`.
+    Telegram { chat_id: String },
+    /// Posted to a Matrix room by the account of `[matrix]`.
+    Matrix { room_id: String },
+    /// Printed to standard output, to try out templates and filters.
+    Stdout,
+}
+
+impl SinkConfig {
+    pub fn guild_ids(&self) -> eyre::Result<Vec<GuildId>> {
+        self.guild_ids
+            .iter()
+            .map(|id| parse_snowflake(id).map(GuildId::new))
+            .collect()
+    }
+
+    /// The channels of the sink's channel group, if it is limited to one.
+    pub fn channel_ids(
+        &self,
+        channel_groups: &HashMap<String, Vec<String>>,
+    ) -> eyre::Result<Option<Vec<ChannelId>>> {
+        let Some(group) = &self.channel_group else {
+            return Ok(None);
+        };
+        let channel_ids = channel_groups.get(group).ok_or_else(|| {
+            eyre!("sinks refer to channel group {group:?}, which is not in channel_groups")
+        })?;
+        channel_ids
+            .iter()
+            .map(|id| parse_snowflake(id).map(ChannelId::new))
+            .collect::<eyre::Result<Vec<_>>>()
+            .map(Some)
+    }
+}
+
+impl SinkKind {
+    /// The channel posted to, for Discord sinks.
+    pub fn discord_channel(&self) -> eyre::Result<Option<ChannelId>> {
+        match self {
+            SinkKind::Discord { channel_id } => {
+                parse_snowflake(channel_id).map(|id| Some(ChannelId::new(id)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Something a webhook can be notified about.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Summary,
+    DailyDigest,
+    Anomaly,
+    KeywordMatch,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::Summary => "summary",
+            WebhookEvent::DailyDigest => "daily_digest",
+            WebhookEvent::Anomaly => "anomaly",
+            WebhookEvent::KeywordMatch => "keyword_match",
+        }
+    }
+}
+
+/// Templates of the instructions given to the model, configured under `[prompts]`.
+/// Each is either written inline or read from a file, the file taking precedence, and
+/// may use the `{guild}`, `{channel}`, `{from}` and `{to}` variables.
+#[derive(Deserialize, Default, Clone)]
+pub struct PromptsConfig {
+    /// Instructions for summarizing a channel's messages.
+    pub summary: Option<String>,
+    pub summary_file: Option<String>,
+    /// Instructions for rolling summaries or digests up into a digest.
+    pub digest: Option<String>,
+    pub digest_file: Option<String>,
+    /// Named profiles that channels can be summarized with instead.
+    #[serde(default)]
+    pub profiles: HashMap<String, PromptProfileConfig>,
+    /// The profile each channel is summarized with, by channel ID.
+    #[serde(default)]
+    pub channels: HashMap<String, String>,
+    /// Language summaries and digests are written in, such as "Spanish", or "auto" to
+    /// match the dominant language of the messages. Left to the model when unset.
+    pub language: Option<String>,
+}
+
+impl PromptsConfig {
+    /// Parses the profile of each channel, making sure every profile exists.
+    pub fn channel_profiles(&self) -> eyre::Result<HashMap<ChannelId, String>> {
+        self.channels
+            .iter()
+            .map(|(channel_id, profile)| {
+                if !self.profiles.contains_key(profile) {
+                    bail!(
+                        "prompts.channels maps channel {channel_id} to unknown profile {profile:?}"
+                    );
+                }
+                Ok((
+                    ChannelId::new(parse_snowflake(channel_id)?),
+                    profile.clone(),
+                ))
+            })
+            .collect()
+    }
+
+    /// The models the profiles use other than the provider's.
+    pub fn profile_models(&self) -> Vec<String> {
+        let mut models: Vec<String> = self
+            .profiles
+            .values()
+            .filter_map(|profile| profile.model.clone())
+            .collect();
+        models.sort();
+        models.dedup();
+        models
+    }
+}
+
+/// How channels of a prompt profile are summarized, configured under
+/// `[prompts.profiles.<name>]`.
+#[derive(Deserialize, Clone)]
+pub struct PromptProfileConfig {
+    /// Instructions for summarizing the channel, defaulting to `prompts.summary`.
+    pub summary: Option<String>,
+    pub summary_file: Option<String>,
+    /// Model of the selected provider to use instead of the configured one.
+    pub model: Option<String>,
+    #[serde(default)]
+    pub format: SummaryFormat,
+    /// Language to write the channel's summaries in, defaulting to `prompts.language`.
+    pub language: Option<String>,
+}
+
+/// The shape of a channel summary.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryFormat {
+    /// Prose along with the topics, decisions, action items and open questions.
+    #[default]
+    Structured,
+    /// Prose only.
+    Prose,
+    /// A bullet list of the key points.
+    Bullets,
+}
+
+/// Templates digests are rendered with, configured under `[templates]`. Each is either
+/// written inline or read from a file, the file taking precedence.
+#[derive(Deserialize, Default)]
+pub struct TemplatesConfig {
+    /// For digests posted to Discord.
+    pub discord: Option<String>,
+    pub discord_file: Option<String>,
+    /// For digests rendered by the HTTP API.
+    pub api: Option<String>,
+    pub api_file: Option<String>,
+    /// For the HTML body of digest emails.
+    pub email: Option<String>,
+    pub email_file: Option<String>,
+    /// For the subject of digest emails.
+    pub email_subject: Option<String>,
+    /// Strings templates look up with `{{t "key"}}`, replacing the built-in ones.
+    #[serde(default)]
+    pub strings: HashMap<String, String>,
+}
+
+/// How much of who said what is stored and sent to the LLM, configured under
+/// `[privacy]`.
+#[derive(Deserialize, Default)]
+pub struct PrivacyConfig {
+    /// Replace author names with pseudonyms derived from their Discord ID.
+    #[serde(default)]
+    pub anonymize_authors: bool,
+    /// Secret the pseudonyms are derived with, so that they cannot be traced back to
+    /// members by hashing their IDs.
+    pub pseudonym_key: Option<String>,
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+}
+
+/// What is scrubbed out of messages before they are stored, configured under
+/// `[privacy.redaction]`.
+#[derive(Deserialize)]
+pub struct RedactionConfig {
+    /// Built-in detectors to run, every one of them by default.
+    #[serde(default = "default_redaction_detectors")]
+    pub detectors: Vec<RedactionDetector>,
+    /// Extra patterns to redact, configured as `[[privacy.redaction.rules]]` entries.
+    #[serde(default)]
+    pub rules: Vec<RedactionRule>,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            detectors: default_redaction_detectors(),
+            rules: vec![],
+        }
+    }
+}
+
+fn default_redaction_detectors() -> Vec<RedactionDetector> {
+    vec![
+        RedactionDetector::ApiKeys,
+        RedactionDetector::Tokens,
+        RedactionDetector::Emails,
+        RedactionDetector::PhoneNumbers,
+    ]
+}
+
+/// A kind of secret or personal information that is recognized without configuration.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionDetector {
+    ApiKeys,
+    Tokens,
+    Emails,
+    PhoneNumbers,
+}
+
+/// A regular expression whose matches are redacted.
+#[derive(Deserialize)]
+pub struct RedactionRule {
+    pub name: String,
+    pub pattern: String,
+    /// What matches are replaced by, `[redacted <name>]` by default. Can refer to the
+    /// pattern's groups as `$1`, `$name` and so on.
+    pub replacement: Option<String>,
+}
+
+/// Messages members mark to be quoted in daily digests, configured under
+/// `[highlights]`.
+#[derive(Deserialize)]
+pub struct HighlightsConfig {
+    /// Reacting with any of these highlights a message. Unicode emoji, or the names of
+    /// custom emoji. Empty to turn highlights off.
+    #[serde(default = "default_highlight_emoji")]
+    pub emoji: Vec<String>,
+}
+
+impl Default for HighlightsConfig {
+    fn default() -> Self {
+        Self {
+            emoji: default_highlight_emoji(),
+        }
+    }
+}
+
+fn default_highlight_emoji() -> Vec<String> {
+    vec!["🔖".to_string(), "⭐".to_string()]
+}
+
+/// Links from daily digests back to the conversations they summarize, configured
+/// under `[citations]`.
+#[derive(Deserialize)]
+pub struct CitationsConfig {
+    /// Have the model cite the summaries behind each point, and end daily digests with
+    /// a "Sources" section linking to a message of each cited summary.
+    #[serde(default = "default_citations_enabled")]
+    pub enabled: bool,
+}
+
+impl Default for CitationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_citations_enabled(),
+        }
+    }
+}
+
+fn default_citations_enabled() -> bool {
+    true
+}
+
+/// What changed since the previous daily digest, configured under `[changes]`.
+#[derive(Deserialize, Default)]
+pub struct ChangesConfig {
+    /// Compare each daily digest to the previous one and list the new topics, resolved
+    /// issues and continuing discussions in it.
+    #[serde(default)]
+    pub digest_section: bool,
+}
+
+/// A daily period without LLM calls, configured under `[quiet_hours]`.
+#[derive(Deserialize, Default)]
+pub struct QuietHoursConfig {
+    /// Time of day quiet hours start at in the reporting timezone, as HH:MM.
+    pub start: Option<String>,
+    /// Time of day quiet hours end at, the next day when earlier than `start`.
+    pub end: Option<String>,
+}
+
+impl QuietHoursConfig {
+    /// Parses the quiet hours, if both their start and end are set.
+    pub fn quiet_hours(&self, timezone: Tz) -> eyre::Result<Option<QuietHours>> {
+        let (start, end) = match (&self.start, &self.end) {
+            (Some(start), Some(end)) => (start, end),
+            (None, None) => return Ok(None),
+            _ => bail!("quiet_hours must set both start and end"),
+        };
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| eyre!("invalid quiet hours time {time:?}, expected HH:MM"))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == end {
+            bail!("quiet_hours must start and end at different times");
+        }
+        Ok(Some(QuietHours {
+            start,
+            end,
+            timezone,
+        }))
+    }
+}
+
+/// Digests of each group of `[channel_groups]`, configured under `[group_digests]`.
+#[derive(Deserialize, Default)]
+pub struct GroupDigestsConfig {
+    /// Along with each daily digest, write a digest of the summaries of each channel
+    /// group, sectioned by channel, and deliver it to the sinks limited to the group.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// The length of digests, configured under `[digests]`.
+#[derive(Deserialize)]
+pub struct DigestsConfig {
+    /// How long the digests written by the model are.
+    #[serde(default)]
+    pub verbosity: Verbosity,
+    /// Also write a one-paragraph TL;DR of each digest, and store it along with it.
+    #[serde(default = "default_digests_tldr")]
+    pub tldr: bool,
+}
+
+impl Default for DigestsConfig {
+    fn default() -> Self {
+        Self {
+            verbosity: Verbosity::default(),
+            tldr: default_digests_tldr(),
+        }
+    }
+}
+
+fn default_digests_tldr() -> bool {
+    true
+}
+
+/// How long a digest is.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verbosity {
+    /// A single paragraph.
+    Tldr,
+    /// A digest of the main discussions.
+    #[default]
+    Standard,
+    /// A detailed report, covering each discussion in depth.
+    Detailed,
+}
+
+impl Verbosity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tldr" => Some(Self::Tldr),
+            "standard" => Some(Self::Standard),
+            "detailed" => Some(Self::Detailed),
+            _ => None,
+        }
+    }
+}
+
+/// Participant statistics, configured under `[stats]`.
+#[derive(Deserialize)]
+pub struct StatsConfig {
+    /// End daily digests with the message counts, active members, busiest hour and top
+    /// contributors of each channel.
+    #[serde(default = "default_stats_digest_section")]
+    pub digest_section: bool,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            digest_section: default_stats_digest_section(),
+        }
+    }
+}
+
+fn default_stats_digest_section() -> bool {
+    true
+}
+
+/// Sentiment analysis of summarized conversations, configured under `[sentiment]`.
+#[derive(Deserialize, Default)]
+pub struct SentimentConfig {
+    /// Score the sentiment and tone of every summarized batch of messages, with an
+    /// extra request to the summaries model.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Checks of the people and channels summaries mention against their messages,
+/// configured under `[verification]`.
+#[derive(Deserialize, Clone)]
+pub struct VerificationConfig {
+    #[serde(default = "default_verification_enabled")]
+    pub enabled: bool,
+    /// Summaries less confident than this are logged, and written again when
+    /// `regenerate` is set.
+    #[serde(default = "default_verification_min_confidence")]
+    pub min_confidence: f64,
+    /// Write summaries less confident than `min_confidence` again once, told which of
+    /// their mentions do not appear in the messages. The more confident attempt is kept.
+    /// Off by default, as each one costs another LLM request.
+    #[serde(default)]
+    pub regenerate: bool,
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_verification_enabled(),
+            min_confidence: default_verification_min_confidence(),
+            regenerate: false,
+        }
+    }
+}
+
+fn default_verification_enabled() -> bool {
+    true
+}
+
+fn default_verification_min_confidence() -> f64 {
+    0.5
+}
+
+/// Alerts about unusual activity in the listened to channels, configured under
+/// `[anomalies]`.
+#[derive(Deserialize, Clone)]
+pub struct AnomaliesConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Channel alerts are posted to. Alerts are also sent to the webhooks that want
+    /// `anomaly` events.
+    pub alert_channel_id: Option<String>,
+    #[serde(default = "default_anomaly_check_interval_seconds")]
+    pub check_interval_seconds: u64,
+    /// Length of the window the latest activity is counted over.
+    #[serde(default = "default_anomaly_window_minutes")]
+    pub window_minutes: u64,
+    /// How many days before the window a channel's usual activity is averaged over.
+    #[serde(default = "default_anomaly_baseline_days")]
+    pub baseline_days: u32,
+    /// How many times busier than usual a channel has to be to count as a spike.
+    #[serde(default = "default_anomaly_spike_factor")]
+    pub spike_factor: f64,
+    /// Fewest messages in the window for a spike, so that quiet channels do not
+    /// alert over a handful of messages.
+    #[serde(default = "default_anomaly_min_messages")]
+    pub min_messages: i64,
+    /// Messages a single member has to post in the window to flood a channel.
+    #[serde(default = "default_anomaly_flood_messages")]
+    pub flood_messages: i64,
+    /// How long to wait before alerting about the same channel again.
+    #[serde(default = "default_anomaly_cooldown_minutes")]
+    pub cooldown_minutes: u64,
+    /// Summarize what the channel is busy with in each alert.
+    #[serde(default = "default_anomaly_summarize")]
+    pub summarize: bool,
+}
+
+impl Default for AnomaliesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            alert_channel_id: None,
+            check_interval_seconds: default_anomaly_check_interval_seconds(),
+            window_minutes: default_anomaly_window_minutes(),
+            baseline_days: default_anomaly_baseline_days(),
+            spike_factor: default_anomaly_spike_factor(),
+            min_messages: default_anomaly_min_messages(),
+            flood_messages: default_anomaly_flood_messages(),
+            cooldown_minutes: default_anomaly_cooldown_minutes(),
+            summarize: default_anomaly_summarize(),
+        }
+    }
+}
+
+impl AnomaliesConfig {
+    pub fn alert_channel(&self) -> eyre::Result<Option<ChannelId>> {
+        self.alert_channel_id
+            .as_deref()
+            .map(|id| parse_snowflake(id).map(ChannelId::new))
+            .transpose()
+    }
+}
+
+/// Keywords and patterns that are alerted about as soon as a message mentions them,
+/// configured under `[keyword_watch]`.
+#[derive(Deserialize)]
+pub struct KeywordWatchConfig {
+    /// Channel alerts are posted to. Alerts are also sent to the webhooks that want
+    /// `keyword_match` events.
+    pub alert_channel_id: Option<String>,
+    /// Messages before the matching one included in alerts.
+    #[serde(default = "default_keyword_watch_context_messages")]
+    pub context_messages: i64,
+    /// What to watch for, configured as `[[keyword_watch.rules]]` entries. Empty to
+    /// turn the watch off.
+    #[serde(default)]
+    pub rules: Vec<KeywordWatchRule>,
+}
+
+impl Default for KeywordWatchConfig {
+    fn default() -> Self {
+        Self {
+            alert_channel_id: None,
+            context_messages: default_keyword_watch_context_messages(),
+            rules: vec![],
+        }
+    }
+}
+
+impl KeywordWatchConfig {
+    pub fn alert_channel(&self) -> eyre::Result<Option<ChannelId>> {
+        self.alert_channel_id
+            .as_deref()
+            .map(|id| parse_snowflake(id).map(ChannelId::new))
+            .transpose()
+    }
+}
+
+fn default_keyword_watch_context_messages() -> i64 {
+    5
+}
+
+/// A rule matching messages that contain any of its keywords, as whole words and
+/// regardless of case, or its regular expression.
+#[derive(Deserialize)]
+pub struct KeywordWatchRule {
+    pub name: String,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    pub pattern: Option<String>,
+}
+
+fn default_anomaly_check_interval_seconds() -> u64 {
+    60
+}
+
+fn default_anomaly_window_minutes() -> u64 {
+    15
+}
+
+fn default_anomaly_baseline_days() -> u32 {
+    7
+}
+
+fn default_anomaly_spike_factor() -> f64 {
+    5.0
+}
+
+fn default_anomaly_min_messages() -> i64 {
+    30
+}
+
+fn default_anomaly_flood_messages() -> i64 {
+    20
+}
+
+fn default_anomaly_cooldown_minutes() -> u64 {
+    60
+}
+
+fn default_anomaly_summarize() -> bool {
+    true
+}
+
+/// What to do with the URLs posted in listened to channels.
+#[derive(Deserialize)]
+pub struct LinksConfig {
+    /// Append the links shared since the previous daily digest to each one.
+    #[serde(default = "default_links_digest_section")]
+    pub digest_section: bool,
+    /// Fetch the pages of shared links to store their title and description.
+    #[serde(default)]
+    pub fetch_metadata: bool,
+    /// How often the pages of newly shared links are fetched.
+    #[serde(default = "default_link_fetch_interval_seconds")]
+    pub fetch_interval_seconds: u64,
+}
+
+impl Default for LinksConfig {
+    fn default() -> Self {
+        Self {
+            digest_section: default_links_digest_section(),
+            fetch_metadata: false,
+            fetch_interval_seconds: default_link_fetch_interval_seconds(),
+        }
+    }
+}
+
+fn default_links_digest_section() -> bool {
+    true
+}
+
+fn default_link_fetch_interval_seconds() -> u64 {
+    60
+}
+
+/// How the scheduled events of guilds appear in daily digests.
+#[derive(Deserialize)]
+pub struct ScheduledEventsConfig {
+    /// List upcoming events and summarize concluded ones in each daily digest.
+    #[serde(default = "default_scheduled_events_digest_section")]
+    pub digest_section: bool,
+    /// How many days ahead upcoming events are listed.
+    #[serde(default = "default_upcoming_event_days")]
+    pub upcoming_days: u32,
+}
+
+impl Default for ScheduledEventsConfig {
+    fn default() -> Self {
+        Self {
+            digest_section: default_scheduled_events_digest_section(),
+            upcoming_days: default_upcoming_event_days(),
+        }
+    }
+}
+
+fn default_scheduled_events_digest_section() -> bool {
+    true
+}
+
+fn default_upcoming_event_days() -> u32 {
+    7
+}
+
+/// Daily standups the bot runs in a channel, configured under `[standup]`.
+#[derive(Deserialize)]
+pub struct StandupConfig {
+    /// Channel the bot asks for standup updates in. Standups are off when unset.
+    pub channel_id: Option<String>,
+    /// Cron expression for when to ask, evaluated in `service.timezone`.
+    #[serde(default = "default_standup_schedule")]
+    pub schedule: String,
+    /// How long replies are collected before the standup is summarized.
+    #[serde(default = "default_standup_window_minutes")]
+    pub window_minutes: u64,
+    /// Message asking for updates.
+    #[serde(default = "default_standup_prompt")]
+    pub prompt: String,
+}
+
+impl Default for StandupConfig {
+    fn default() -> Self {
+        Self {
+            channel_id: None,
+            schedule: default_standup_schedule(),
+            window_minutes: default_standup_window_minutes(),
+            prompt: default_standup_prompt(),
+        }
+    }
+}
+
+impl StandupConfig {
+    pub fn channel_id(&self) -> eyre::Result<Option<ChannelId>> {
+        self.channel_id
+            .as_deref()
+            .map(|id| parse_snowflake(id).map(ChannelId::new))
+            .transpose()
+    }
+}
+
+fn default_standup_schedule() -> String {
+    "0 9 * * 1-5".to_string()
+}
+
+fn default_standup_window_minutes() -> u64 {
+    120
+}
+
+fn default_standup_prompt() -> String {
+    "Good morning! Time for standup: reply in this channel with what you did yesterday, what you are doing today and anything blocking you.".to_string()
+}
+
+/// Frequently asked questions mined from summaries, configured under `[faq]`.
+#[derive(Deserialize)]
+pub struct FaqConfig {
+    /// Whether summaries are mined for questions and `/faq` is registered.
+    #[serde(default)]
+    pub enabled: bool,
+    /// How often new summaries are mined.
+    #[serde(default = "default_faq_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+impl Default for FaqConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: default_faq_interval_seconds(),
+        }
+    }
+}
+
+fn default_faq_interval_seconds() -> u64 {
+    3600
+}
+
+/// The glossary of community jargon mined from summaries, configured under
+/// `[glossary]`.
+#[derive(Deserialize)]
+pub struct GlossaryConfig {
+    /// Whether summaries are mined for jargon.
+    #[serde(default)]
+    pub enabled: bool,
+    /// How often new summaries are mined.
+    #[serde(default = "default_glossary_interval_seconds")]
+    pub interval_seconds: u64,
+    /// List the terms added since the previous weekly digest in each weekly digest.
+    #[serde(default = "default_glossary_digest_section")]
+    pub digest_section: bool,
+}
+
+impl Default for GlossaryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: default_glossary_interval_seconds(),
+            digest_section: default_glossary_digest_section(),
+        }
+    }
+}
+
+fn default_glossary_interval_seconds() -> u64 {
+    86400
+}
+
+fn default_glossary_digest_section() -> bool {
+    true
+}
+
+/// Knowledge base articles written on demand, configured under `[knowledge_base]`.
+#[derive(Deserialize, Default)]
+pub struct KnowledgeBaseConfig {
+    /// Whether the `/kb` routes are served.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Default)]
+pub struct GptConfig {
+    /// Replaces every LLM call with a stub that echoes the start of its input, to run
+    /// the whole pipeline without spending anything. Also set by `--dry-run`.
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub provider: LlmProvider,
+    #[serde(default)]
+    pub openai: OpenAiConfig,
+    #[serde(default)]
+    pub anthropic: AnthropicConfig,
+    #[serde(default)]
+    pub ollama: OllamaConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub embeddings: EmbeddingsConfig,
+    #[serde(default)]
+    pub vision: VisionConfig,
+    #[serde(default)]
+    pub transcription: TranscriptionConfig,
+    /// Prices used to estimate the cost of LLM calls, on top of the built-in ones.
+    #[serde(default)]
+    pub prices: Vec<ModelPrice>,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub prompt_guard: PromptGuardConfig,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    /// Backends tried in order when the selected provider keeps failing.
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackConfig>,
+}
+
+/// A backend to fall back to, configured under `[[gpt.fallbacks]]`. It uses the
+/// settings of its provider's section, such as `[gpt.anthropic]`.
+#[derive(Deserialize)]
+pub struct FallbackConfig {
+    pub provider: LlmProvider,
+    /// Defaults to the model configured for the provider.
+    pub model: Option<String>,
+}
+
+/// Routes requests to cheaper or more capable models of the selected provider,
+/// configured under `[gpt.routing]`. The provider's own model is used for everything
+/// else.
+#[derive(Deserialize)]
+pub struct RoutingConfig {
+    /// Model for channel summaries of at most `small_input_tokens`.
+    pub small_model: Option<String>,
+    #[serde(default = "default_small_input_tokens")]
+    pub small_input_tokens: usize,
+    /// Model for digests, and for channel summaries larger than `large_input_tokens`.
+    pub premium_model: Option<String>,
+    /// Defaults to `max_gpt_request_tokens`, so that inputs that would otherwise be
+    /// split into chunks go to the premium model.
+    pub large_input_tokens: Option<usize>,
+    /// Largest request sent to the premium model, larger inputs are split into chunks.
+    /// Defaults to `max_gpt_request_tokens`.
+    pub premium_max_request_tokens: Option<usize>,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            small_model: None,
+            small_input_tokens: default_small_input_tokens(),
+            premium_model: None,
+            large_input_tokens: None,
+            premium_max_request_tokens: None,
+        }
+    }
+}
+
+fn default_small_input_tokens() -> usize {
+    2000
+}
+
+/// Daily limits on LLM usage, configured under `[gpt.budget]`. Days start at
+/// midnight UTC, and summaries are queued until then once a limit is reached.
+#[derive(Deserialize, Default)]
+pub struct BudgetConfig {
+    pub daily_tokens: Option<u64>,
+    /// Estimated cost, using the prices in `[[gpt.prices]]` and the built-in ones.
+    pub daily_cost_usd: Option<f64>,
+    /// Channel told when a limit is reached.
+    pub alert_channel_id: Option<String>,
+}
+
+impl BudgetConfig {
+    pub fn alert_channel(&self) -> eyre::Result<Option<ChannelId>> {
+        self.alert_channel_id
+            .as_deref()
+            .map(|id| parse_snowflake(id).map(ChannelId::new))
+            .transpose()
+    }
+}
+
+/// Defenses against prompt injection in the messages sent to the model, configured
+/// under `[gpt.prompt_guard]`.
+#[derive(Deserialize)]
+pub struct PromptGuardConfig {
+    /// Enclose the content of every request in delimiters the model is told to treat as
+    /// data, and strip known injection phrases out of it.
+    #[serde(default = "default_prompt_guard_enabled")]
+    pub enabled: bool,
+    /// Regular expressions of injection phrases to strip, on top of the built-in ones.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+impl Default for PromptGuardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_prompt_guard_enabled(),
+            patterns: vec![],
+        }
+    }
+}
+
+fn default_prompt_guard_enabled() -> bool {
+    true
+}
+
+/// Price of a model in US dollars, configured under `[[gpt.prices]]`.
+#[derive(Deserialize, Clone)]
+pub struct ModelPrice {
+    pub model: String,
+    pub input_per_million_tokens: f64,
+    #[serde(default)]
+    pub output_per_million_tokens: f64,
+}
+
+/// The large language model API used to produce summaries.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmProvider {
+    #[default]
+    OpenAi,
+    Anthropic,
+    Ollama,
+}
+
+/// Settings for the embeddings used to search stored summaries and digests, configured
+/// under `[gpt.embeddings]`. OpenAI embeddings go through `[gpt.openai]`'s `api_base`
+/// and Ollama embeddings through `[gpt.ollama]`'s `base_url`.
+#[derive(Deserialize)]
+pub struct EmbeddingsConfig {
+    #[serde(default = "default_embeddings_provider")]
+    pub provider: LlmProvider,
+    /// Defaults to `text-embedding-3-small` for OpenAI and `nomic-embed-text` for Ollama.
+    pub model: Option<String>,
+    /// How often summaries and digests without an embedding are embedded.
+    #[serde(default = "default_embeddings_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+impl Default for EmbeddingsConfig {
+    fn default() -> Self {
+        Self {
+            provider: default_embeddings_provider(),
+            model: None,
+            interval_seconds: default_embeddings_interval_seconds(),
+        }
+    }
+}
+
+fn default_embeddings_provider() -> LlmProvider {
+    LlmProvider::OpenAi
+}
+
+fn default_embeddings_interval_seconds() -> u64 {
+    300
+}
+
+/// Settings for describing images posted in channels, configured under `[gpt.vision]`.
+/// Requests go through the section of the provider, such as `[gpt.openai]`'s
+/// `api_base`.
+#[derive(Deserialize)]
+pub struct VisionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Defaults to the provider used for summaries.
+    pub provider: Option<LlmProvider>,
+    /// Must accept images. Defaults to the model configured for the provider.
+    pub model: Option<String>,
+    /// What the model is asked to do with each image.
+    pub prompt: Option<String>,
+    /// Larger images are only listed by their file name.
+    #[serde(default = "default_vision_max_image_bytes")]
+    pub max_image_bytes: u64,
+}
+
+impl Default for VisionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: None,
+            model: None,
+            prompt: None,
+            max_image_bytes: default_vision_max_image_bytes(),
+        }
+    }
+}
+
+fn default_vision_max_image_bytes() -> u64 {
+    5_000_000
+}
+
+/// Settings for transcribing meeting recordings with Whisper, configured under
+/// `[gpt.transcription]`.
+#[derive(Deserialize)]
+pub struct TranscriptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Base URL of an OpenAI-compatible transcription API, such as a local
+    /// whisper.cpp or faster-whisper server. Defaults to `[gpt.openai]`'s `api_base`.
+    pub api_base: Option<String>,
+    #[serde(default = "default_transcription_model")]
+    pub model: String,
+    /// ISO 639-1 code of the language spoken, which makes transcripts more accurate.
+    /// Detected when unset.
+    pub language: Option<String>,
+    /// Larger recordings are not transcribed. The OpenAI API takes at most 25 MB.
+    #[serde(default = "default_transcription_max_audio_bytes")]
+    pub max_audio_bytes: u64,
+    /// Whether `/meeting-start` and `/meeting-stop` record meetings held in voice
+    /// channels. Only available when the bot is built with the `voice` feature.
+    #[serde(default)]
+    pub record_voice: bool,
+}
+
+impl Default for TranscriptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            api_base: None,
+            model: default_transcription_model(),
+            language: None,
+            max_audio_bytes: default_transcription_max_audio_bytes(),
+            record_voice: false,
+        }
+    }
+}
+
+fn default_transcription_model() -> String {
+    "whisper-1".to_string()
+}
+
+fn default_transcription_max_audio_bytes() -> u64 {
+    25_000_000
+}
+
+/// Retry policy for failed LLM requests, configured under `[gpt.retry]`.
+#[derive(Deserialize)]
+pub struct RetryConfig {
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_retry_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_retry_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    #[serde(default = "default_retry_jitter")]
+    pub jitter: bool,
+    /// How long a single request may take before it is given up on and retried.
+    #[serde(default = "default_retry_request_timeout_seconds")]
+    pub request_timeout_seconds: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            initial_backoff_ms: default_retry_initial_backoff_ms(),
+            max_backoff_ms: default_retry_max_backoff_ms(),
+            jitter: default_retry_jitter(),
+            request_timeout_seconds: default_retry_request_timeout_seconds(),
+        }
+    }
+}
+
+fn default_retry_max_attempts() -> u32 {
+    5
+}
+
+fn default_retry_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_retry_max_backoff_ms() -> u64 {
+    60_000
+}
+
+fn default_retry_jitter() -> bool {
+    true
+}
+
+fn default_retry_request_timeout_seconds() -> u64 {
+    300
+}
+
+/// Settings for OpenAI or an OpenAI-compatible gateway, configured under `[gpt.openai]`.
+#[derive(Deserialize)]
+pub struct OpenAiConfig {
+    #[serde(default = "default_openai_api_base")]
+    pub api_base: String,
+    #[serde(default = "default_openai_model")]
+    pub model: String,
+    #[serde(default = "default_openai_max_tokens")]
+    pub max_tokens: u32,
+    pub temperature: Option<f32>,
+    /// Set when `api_base` points at an Azure OpenAI deployment.
+    pub azure_api_version: Option<String>,
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        Self {
+            api_base: default_openai_api_base(),
+            model: default_openai_model(),
+            max_tokens: default_openai_max_tokens(),
+            temperature: None,
+            azure_api_version: None,
+        }
+    }
+}
+
+fn default_openai_api_base() -> String {
+    "https://api.openai.com/v1".to_string()
+}
+
+fn default_openai_model() -> String {
+    "gpt-4".to_string()
+}
+
+fn default_openai_max_tokens() -> u32 {
+    4096
+}
+
+/// Settings for the Anthropic Messages API, configured under `[gpt.anthropic]`.
+#[derive(Deserialize)]
+pub struct AnthropicConfig {
+    #[serde(default = "default_anthropic_model")]
+    pub model: String,
+    #[serde(default = "default_anthropic_max_tokens")]
+    pub max_tokens: u32,
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        Self {
+            model: default_anthropic_model(),
+            max_tokens: default_anthropic_max_tokens(),
+        }
+    }
+}
+
+fn default_anthropic_model() -> String {
+    "claude-3-5-sonnet-20240620".to_string()
+}
+
+fn default_anthropic_max_tokens() -> u32 {
+    4096
+}
+
+/// Settings for a local Ollama server, configured under `[gpt.ollama]`.
+#[derive(Deserialize)]
+pub struct OllamaConfig {
+    #[serde(default = "default_ollama_base_url")]
+    pub base_url: String,
+    #[serde(default = "default_ollama_model")]
+    pub model: String,
+    /// Context window to request from the model, in tokens.
+    #[serde(default = "default_ollama_context_tokens")]
+    pub context_tokens: usize,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            base_url: default_ollama_base_url(),
+            model: default_ollama_model(),
+            context_tokens: default_ollama_context_tokens(),
+        }
+    }
+}
+
+fn default_ollama_base_url() -> String {
+    "http://localhost:11434".to_string()
+}
+
+fn default_ollama_model() -> String {
+    "llama3".to_string()
+}
+
+fn default_ollama_context_tokens() -> usize {
+    4096
 }
 
 #[derive(Deserialize)]
 pub struct DiscordConfig {
-    #[allow(unused)]
+    /// Channels to listen to in guilds that have no entry in `guilds`.
+    #[serde(default)]
+    pub channel_ids: Vec<String>,
+    #[serde(default)]
+    pub guilds: Vec<GuildConfig>,
+    /// Whether to skip messages from bot accounts, such as this one.
+    #[serde(default = "default_ignore_bots")]
+    pub ignore_bots: bool,
+    /// Whether to skip messages posted through webhooks.
+    #[serde(default = "default_ignore_webhooks")]
+    pub ignore_webhooks: bool,
+    /// Users whose messages are never logged.
+    #[serde(default)]
+    pub ignored_user_ids: Vec<String>,
+    /// Days of history to backfill in every listened to channel of the guilds the bot
+    /// joins. Nothing is backfilled when unset.
+    pub backfill_days_on_join: Option<u32>,
+    /// How many days back to fetch the messages missed while the bot was offline, when
+    /// it connects. 0 turns this off.
+    #[serde(default = "default_gap_recovery_days")]
+    pub gap_recovery_days: u32,
+    /// What happens to logged messages that are deleted on Discord.
+    #[serde(default)]
+    pub deleted_messages: DeletedMessagePolicy,
+}
+
+/// How messages deleted on Discord are handled, so that summaries never quote what was
+/// retracted.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletedMessagePolicy {
+    /// Remove the message, as if it had never been posted.
+    #[default]
+    Exclude,
+    /// Keep a marker without the content, so that summaries can tell a message was
+    /// deleted and replies to it still make sense.
+    Mark,
+}
+
+fn default_ignore_bots() -> bool {
+    true
+}
+
+fn default_ignore_webhooks() -> bool {
+    true
+}
+
+fn default_gap_recovery_days() -> u32 {
+    7
+}
+
+/// Per-guild overrides, configured as `[[discord.guilds]]` entries.
+#[derive(Deserialize)]
+pub struct GuildConfig {
+    pub id: String,
     pub channel_ids: Vec<String>,
+    /// Channel the guild's daily digests are posted to.
+    pub digest_channel_id: Option<String>,
 }
 
 impl AppConfig {
@@ -36,6 +1697,161 @@ impl AppConfig {
             .add_source(config::File::with_name(file_path))
             .build()?;
 
-        config.try_deserialize::<Self>()
+        let mut config = config.try_deserialize::<Self>()?;
+        for (channel_id, channel) in &config.channels {
+            if let Some(profile) = &channel.prompt_profile {
+                config
+                    .prompts
+                    .channels
+                    .insert(channel_id.clone(), profile.clone());
+            }
+        }
+        Ok(config)
+    }
+
+    /// The timezone digests are scheduled and reported in.
+    pub fn timezone(&self) -> eyre::Result<Tz> {
+        parse_timezone(&self.service.timezone)
+    }
+
+    /// Parses the schedule of every rollup tier that is enabled.
+    pub fn rollup_schedules(&self) -> eyre::Result<Vec<(RollupTier, Schedule)>> {
+        let timezone = self.timezone()?;
+        let tiers = [
+            (
+                RollupTier::Daily,
+                self.service.produce_digest_schedule.as_deref(),
+                Some(self.service.produce_digest_interval_seconds),
+            ),
+            (
+                RollupTier::Weekly,
+                self.rollups.weekly_schedule.as_deref(),
+                self.rollups.weekly_interval_seconds,
+            ),
+            (
+                RollupTier::Monthly,
+                self.rollups.monthly_schedule.as_deref(),
+                self.rollups.monthly_interval_seconds,
+            ),
+        ];
+        let mut schedules = vec![];
+        for (tier, cron_expression, interval_seconds) in tiers {
+            if let Some(schedule) =
+                Schedule::from_config(cron_expression, interval_seconds, timezone)?
+            {
+                schedules.push((tier, schedule));
+            }
+        }
+        Ok(schedules)
+    }
+
+    /// Parses the IDs of the channels with settings of their own.
+    pub fn channel_configs(&self) -> eyre::Result<HashMap<ChannelId, ChannelConfig>> {
+        self.channels
+            .iter()
+            .map(|(channel_id, channel)| {
+                Ok((
+                    ChannelId::new(parse_snowflake(channel_id)?),
+                    channel.clone(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Parses the channels of each channel group.
+    pub fn channel_group_ids(&self) -> eyre::Result<BTreeMap<String, Vec<ChannelId>>> {
+        self.channel_groups
+            .iter()
+            .map(|(group, channel_ids)| {
+                if channel_ids.is_empty() {
+                    bail!("channel group {group:?} must list at least one channel ID");
+                }
+                let channel_ids = channel_ids
+                    .iter()
+                    .map(|id| parse_snowflake(id).map(ChannelId::new))
+                    .collect::<eyre::Result<Vec<_>>>()?;
+                Ok((group.clone(), channel_ids))
+            })
+            .collect()
+    }
+}
+
+impl DiscordConfig {
+    /// Parses the configured guilds and channel IDs into the filter deciding which
+    /// messages the bot listens to.
+    pub fn channel_filter(&self) -> eyre::Result<ChannelFilter> {
+        let default_channels = parse_channel_ids(&self.channel_ids)?;
+        let mut guild_channels = HashMap::new();
+        for guild in &self.guilds {
+            let guild_id = GuildId::new(parse_snowflake(&guild.id)?);
+            let channels = parse_channel_ids(&guild.channel_ids)?;
+            if channels.is_empty() {
+                bail!("discord.guilds entry for guild {guild_id} must list at least one channel ID, or \"*\" for all channels");
+            }
+            if guild_channels.insert(guild_id, channels).is_some() {
+                bail!("guild {guild_id} is configured more than once in discord.guilds");
+            }
+        }
+        if default_channels.is_empty() && guild_channels.is_empty() {
+            bail!("discord.channel_ids or discord.guilds must list at least one channel ID, or \"*\" for all channels");
+        }
+        Ok(ChannelFilter::new(default_channels, guild_channels))
+    }
+
+    /// Parses the settings deciding whose messages the bot skips.
+    pub fn author_filter(&self) -> eyre::Result<AuthorFilter> {
+        let ignored_users = self
+            .ignored_user_ids
+            .iter()
+            .map(|id| parse_snowflake(id).map(UserId::new))
+            .collect::<eyre::Result<HashSet<_>>>()?;
+        Ok(AuthorFilter::new(
+            self.ignore_bots,
+            self.ignore_webhooks,
+            ignored_users,
+        ))
+    }
+
+    /// Parses the channels each guild's daily digests should be posted to.
+    pub fn digest_channels(&self) -> eyre::Result<HashMap<GuildId, ChannelId>> {
+        self.guilds
+            .iter()
+            .filter_map(|guild| {
+                let digest_channel_id = guild.digest_channel_id.as_ref()?;
+                Some(parse_snowflake(&guild.id).and_then(|guild_id| {
+                    let channel_id = parse_snowflake(digest_channel_id)?;
+                    Ok((GuildId::new(guild_id), ChannelId::new(channel_id)))
+                }))
+            })
+            .collect()
+    }
+}
+
+/// Parses a list of channel IDs, where a `"*"` entry allows every channel.
+fn parse_channel_ids(channel_ids: &[String]) -> eyre::Result<AllowedChannels> {
+    if channel_ids.iter().any(|id| id == ALL_CHANNELS_WILDCARD) {
+        return Ok(AllowedChannels::All);
+    }
+    let channels = channel_ids
+        .iter()
+        .map(|id| parse_snowflake(id).map(ChannelId::new))
+        .collect::<eyre::Result<HashSet<_>>>()?;
+    Ok(AllowedChannels::Only(channels))
+}
+
+/// Parses a Discord snowflake ID, rejecting values whose embedded creation
+/// timestamp could not belong to a real Discord object.
+fn parse_snowflake(raw: &str) -> eyre::Result<u64> {
+    let id: u64 = raw
+        .trim()
+        .parse()
+        .map_err(|_| eyre!("invalid Discord ID {raw:?}: expected a numeric snowflake"))?;
+    // The upper 42 bits hold milliseconds since the Discord epoch.
+    if id >> 22 == 0 {
+        bail!("invalid Discord ID {raw:?}: snowflake has no creation timestamp");
+    }
+    if ChannelId::new(id).created_at() > Timestamp::now() {
+        bail!("invalid Discord ID {raw:?}: snowflake was created in the future");
     }
+    Ok(id)
 }
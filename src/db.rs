@@ -1,55 +1,813 @@
-use chrono::NaiveDateTime;
-use futures::stream::{self, StreamExt};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
-use sqlx::{Error, SqlitePool};
+use sqlx::{types::Json, Error, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::gpt::{ActionItem, Entity, EntityKind, Sentiment, StructuredSummary};
+
 #[derive(Serialize, Deserialize)]
 pub struct Summary {
     pub id: i64,
     pub daily_digest_id: Option<i64>,
     pub text: String,
-    pub timestamp: NaiveDateTime,
+    pub timestamp: DateTime<Utc>,
+    pub channel_id: Option<i64>,
+    pub guild_id: Option<i64>,
+    pub message_count: i64,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
+    pub topics: Json<Vec<String>>,
+    pub decisions: Json<Vec<String>>,
+    pub action_items: Json<Vec<ActionItem>>,
+    pub open_questions: Json<Vec<String>>,
+    /// Provider and model that produced the summary, such as `openai/gpt-4`.
+    pub backend: Option<String>,
+    /// Overall sentiment of the summarized messages, from -1 for very negative to 1 for
+    /// very positive, when sentiment analysis is turned on.
+    pub sentiment: Option<f64>,
+    /// Tone of the summarized messages in a few words, such as `heated`.
+    pub tone: Option<String>,
+    /// Share of the people and channels the summary mentions that appear in its
+    /// messages, from 0 to 1, when summaries are verified.
+    pub confidence: Option<f64>,
+    /// Mentions of people and channels that do not appear in the summary's messages.
+    pub unverified_references: Option<Json<Vec<String>>>,
+}
+
+/// A summary that has not been written to the database yet.
+pub struct NewSummary<'a> {
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub summary: &'a StructuredSummary,
+    pub message_count: i64,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct DailyDigestData {
+pub struct DigestData {
     pub id: i64,
     pub text: String,
-    pub timestamp: NaiveDateTime,
+    pub timestamp: DateTime<Utc>,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub message_count: i64,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct DailyDigest {
     pub id: i64,
     pub text: String,
-    pub timestamp: NaiveDateTime,
+    pub timestamp: DateTime<Utc>,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub message_count: i64,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
     pub summaries: Vec<Summary>,
+    /// Who took part in the period the digest covers, for digests produced with
+    /// participant statistics.
+    pub stats: Option<DigestStats>,
+    /// Average sentiment of the digest's summaries, weighted by their message counts,
+    /// when they were analyzed.
+    pub sentiment: Option<f64>,
+    /// One-paragraph version of the digest, for digests produced with one.
+    pub tldr: Option<String>,
 }
 
-pub async fn fetch_summaries(pool: Arc<SqlitePool>) -> Vec<Summary> {
-    sqlx::query_as!(Summary, "SELECT * FROM summaries")
-        .fetch_all(&*pool)
-        .await
-        .unwrap_or_else(|_| vec![])
+/// Participant statistics of the period a daily digest covers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DigestStats {
+    pub message_count: i64,
+    pub active_users: i64,
+    /// Hour of the day, in the reporting timezone, most messages were sent in.
+    pub busiest_hour: Option<u32>,
+    /// Most active channel first.
+    pub channels: Vec<ChannelStats>,
 }
 
-pub async fn insert_summary(pool: &SqlitePool, text: &str) -> Result<i64, Error> {
-    let result = sqlx::query!(
-        "INSERT INTO summaries (daily_digest_id, text) VALUES (?, ?)",
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChannelStats {
+    pub channel_id: i64,
+    pub message_count: i64,
+    pub active_users: i64,
+    pub busiest_hour: Option<u32>,
+    /// Most active member first.
+    pub top_contributors: Vec<Contributor>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Contributor {
+    pub author: String,
+    pub message_count: i64,
+}
+
+/// A weekly or monthly digest along with the digests of the tier below it rolls up.
+#[derive(Serialize, Deserialize)]
+pub struct RollupDigest {
+    pub id: i64,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub message_count: i64,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
+    pub digests: Vec<DigestData>,
+}
+
+impl RollupDigest {
+    fn new(digest: DigestData, digests: Vec<DigestData>) -> Self {
+        Self {
+            id: digest.id,
+            text: digest.text,
+            timestamp: digest.timestamp,
+            guild_id: digest.guild_id,
+            channel_id: digest.channel_id,
+            message_count: digest.message_count,
+            covers_from: digest.covers_from,
+            covers_to: digest.covers_to,
+            digests,
+        }
+    }
+}
+
+/// A level of digest. Each tier rolls up the one below it: daily digests roll up
+/// summaries, weekly digests roll up daily digests and monthly digests roll up
+/// weekly digests.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RollupTier {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl RollupTier {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RollupTier::Daily => "daily",
+            RollupTier::Weekly => "weekly",
+            RollupTier::Monthly => "monthly",
+        }
+    }
+}
+
+/// A summary or digest that has not been rolled up into a digest of the tier above yet.
+pub struct RollupSource {
+    pub id: i64,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub message_count: i64,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
+}
+
+/// A digest that has not been written to the database yet. The guild and channel are
+/// only set when every source in the digest shares them.
+pub struct NewDigest {
+    pub text: String,
+    pub tldr: Option<String>,
+    /// Only stored for daily digests.
+    pub stats: Option<DigestStats>,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub message_count: i64,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
+}
+
+impl NewDigest {
+    /// Aggregates the coverage metadata of the summaries or digests that make up a digest.
+    pub fn from_sources(text: String, sources: &[RollupSource]) -> Self {
+        let shared = |ids: Vec<Option<i64>>| {
+            let first = *ids.first()?;
+            ids.iter().all(|id| *id == first).then_some(first).flatten()
+        };
+        Self {
+            text,
+            tldr: None,
+            stats: None,
+            guild_id: shared(sources.iter().map(|s| s.guild_id).collect()),
+            channel_id: shared(sources.iter().map(|s| s.channel_id).collect()),
+            message_count: sources.iter().map(|s| s.message_count).sum(),
+            covers_from: sources.iter().filter_map(|s| s.covers_from).min(),
+            covers_to: sources.iter().filter_map(|s| s.covers_to).max(),
+        }
+    }
+}
+
+/// Restricts fetched summaries and digests to a single guild and/or channel.
+#[derive(Deserialize, Default)]
+pub struct ContentFilter {
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+}
+
+/// Fetches all summaries matching the filter created within the date range, oldest first.
+pub async fn fetch_summaries(
+    pool: Arc<SqlitePool>,
+    filter: &ContentFilter,
+    range: &DateRange,
+) -> Vec<Summary> {
+    let (from, to) = range.bounds();
+    sqlx::query_as!(
+        Summary,
+        r#"SELECT id as "id!", daily_digest_id, text, timestamp as "timestamp: DateTime<Utc>", channel_id, guild_id,
+            message_count, covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>",
+            topics as "topics: Json<Vec<String>>", decisions as "decisions: Json<Vec<String>>",
+            action_items as "action_items: Json<Vec<ActionItem>>",
+            open_questions as "open_questions: Json<Vec<String>>", backend, sentiment, tone,
+            confidence, unverified_references as "unverified_references: Json<Vec<String>>"
+        FROM summaries
+        WHERE (?1 IS NULL OR guild_id = ?1) AND (?2 IS NULL OR channel_id = ?2)
+            AND timestamp >= ?3 AND timestamp < ?4
+        ORDER BY timestamp, id"#,
+        filter.guild_id,
+        filter.channel_id,
+        from,
+        to
+    )
+    .fetch_all(&*pool)
+    .await
+    .unwrap_or_else(|_| vec![])
+}
+
+pub async fn fetch_summary(pool: &SqlitePool, id: i64) -> Result<Option<Summary>, Error> {
+    sqlx::query_as!(
+        Summary,
+        r#"SELECT id, daily_digest_id, text, timestamp as "timestamp: DateTime<Utc>", channel_id, guild_id,
+            message_count, covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>",
+            topics as "topics: Json<Vec<String>>", decisions as "decisions: Json<Vec<String>>",
+            action_items as "action_items: Json<Vec<ActionItem>>",
+            open_questions as "open_questions: Json<Vec<String>>", backend, sentiment, tone,
+            confidence, unverified_references as "unverified_references: Json<Vec<String>>"
+        FROM summaries WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Inserts a summary along with its action items, and links every unsummarized message
+/// of its channel up to and including `up_to_message_id` to it.
+pub async fn insert_summary(
+    pool: &SqlitePool,
+    summary: NewSummary<'_>,
+    up_to_message_id: i64,
+) -> Result<i64, Error> {
+    let covers_from = summary.covers_from.map(|t| t.naive_utc());
+    let covers_to = summary.covers_to.map(|t| t.naive_utc());
+    let details = summary.summary;
+    let topics = Json(&details.topics);
+    let decisions = Json(&details.decisions);
+    let action_items = Json(&details.action_items);
+    let open_questions = Json(&details.open_questions);
+    let mut transaction = pool.begin().await?;
+    let summary_id = sqlx::query!(
+        "INSERT INTO summaries (daily_digest_id, text, channel_id, guild_id, message_count, covers_from, covers_to,
+            topics, decisions, action_items, open_questions, backend)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         None::<i64>,
-        text
+        details.summary,
+        summary.channel_id,
+        summary.guild_id,
+        summary.message_count,
+        covers_from,
+        covers_to,
+        topics,
+        decisions,
+        action_items,
+        open_questions,
+        details.backend
+    )
+    .execute(&mut *transaction)
+    .await?
+    .last_insert_rowid();
+
+    for item in &details.action_items {
+        sqlx::query!(
+            "INSERT INTO action_items (summary_id, guild_id, channel_id, description, assignee)
+            VALUES (?, ?, ?, ?, ?)",
+            summary_id,
+            summary.guild_id,
+            summary.channel_id,
+            item.description,
+            item.owner
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    insert_entities(
+        &mut transaction,
+        summary_id,
+        summary.guild_id,
+        summary.channel_id,
+        &details.entities,
     )
-    .execute(pool)
     .await?;
 
-    Ok(result.last_insert_rowid())
+    sqlx::query!(
+        "UPDATE messages SET summary_id = ?1
+        WHERE channel_id = ?2 AND id <= ?3 AND summary_id IS NULL",
+        summary_id,
+        summary.channel_id,
+        up_to_message_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    Ok(summary_id)
+}
+
+/// Restricts fetched content to what was created within a time range.
+#[derive(Deserialize, Default)]
+pub struct DateRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl DateRange {
+    /// The start and end of the range, bounded by dates no content can be outside of so
+    /// that queries can always use the timestamp indexes.
+    fn bounds(&self) -> (NaiveDateTime, NaiveDateTime) {
+        let from = self.from.unwrap_or(DateTime::UNIX_EPOCH).naive_utc();
+        // Timestamps are compared as text, in which far away years such as
+        // `NaiveDateTime::MAX` do not sort last.
+        let end_of_time = NaiveDate::from_ymd_opt(9999, 12, 31)
+            .and_then(|day| day.and_hms_opt(23, 59, 59))
+            .unwrap_or_default();
+        let to = self.to.map_or(end_of_time, |to| to.naive_utc());
+        (from, to)
+    }
+}
+
+/// Selects a page of results. Pages start at 1.
+pub struct Page {
+    pub count: u32,
+    pub page: u32,
+}
+
+impl Page {
+    fn limit_and_offset(page: Option<&Page>) -> (i64, i64) {
+        match page {
+            Some(page) => (
+                i64::from(page.count),
+                i64::from(page.count) * i64::from(page.page.saturating_sub(1)),
+            ),
+            // SQLite treats a negative limit as no limit.
+            None => (-1, 0),
+        }
+    }
+}
+
+/// Fetches the digests of the filtered guild created within the date range, oldest
+/// first, along with their summaries. When a channel is given, only that channel's
+/// summaries are included and digests without any of them are skipped. The summaries
+/// of every digest are fetched in a single query.
+pub async fn fetch_daily_digests(
+    pool: &SqlitePool,
+    filter: &ContentFilter,
+    range: &DateRange,
+    page: Option<&Page>,
+) -> Result<Vec<DailyDigest>, Error> {
+    let query = DigestQuery::new(filter, range, page);
+    stream_daily_digests(pool, &query).try_collect().await
+}
+
+/// How many daily digests are read before their summaries are fetched, when streaming
+/// them.
+const DIGEST_BATCH_SIZE: usize = 50;
+
+/// The arguments of the query of `fetch_daily_digests`, which the stream of its rows
+/// borrows.
+pub struct DigestQuery {
+    guild_id: Option<i64>,
+    channel_id: Option<i64>,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    limit: i64,
+    offset: i64,
+}
+
+impl DigestQuery {
+    pub fn new(filter: &ContentFilter, range: &DateRange, page: Option<&Page>) -> Self {
+        let (from, to) = range.bounds();
+        let (limit, offset) = Page::limit_and_offset(page);
+        Self {
+            guild_id: filter.guild_id,
+            channel_id: filter.channel_id,
+            from,
+            to,
+            limit,
+            offset,
+        }
+    }
+}
+
+/// Streams the daily digests `fetch_daily_digests` returns as their rows are read,
+/// fetching the summaries of a batch of digests at a time.
+pub fn stream_daily_digests<'a>(
+    pool: &'a SqlitePool,
+    query: &'a DigestQuery,
+) -> impl Stream<Item = Result<DailyDigest, Error>> + Send + 'a {
+    sqlx::query_as!(
+        DigestData,
+        r#"SELECT id as "id!", text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+            covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+        FROM daily_digests
+        WHERE (?1 IS NULL OR guild_id = ?1)
+            AND (?2 IS NULL OR EXISTS (
+                SELECT 1 FROM summaries s WHERE s.daily_digest_id = daily_digests.id AND s.channel_id = ?2
+            ))
+            AND timestamp >= ?3 AND timestamp < ?4
+        ORDER BY timestamp, id
+        LIMIT ?5 OFFSET ?6"#,
+        query.guild_id,
+        query.channel_id,
+        query.from,
+        query.to,
+        query.limit,
+        query.offset
+    )
+    .fetch(pool)
+    .try_chunks(DIGEST_BATCH_SIZE)
+    .map_err(|e| e.1)
+    .and_then(move |digests| with_summaries(pool, digests, query.channel_id))
+    .map_ok(|digests| stream::iter(digests.into_iter().map(Ok)))
+    .try_flatten()
+}
+
+/// Fetches the `count` most recent daily digests of a guild, most recent first.
+pub async fn fetch_recent_daily_digests(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    count: u32,
+) -> Result<Vec<DigestData>, Error> {
+    sqlx::query_as!(
+        DigestData,
+        r#"SELECT id as "id!", text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id,
+            message_count, covers_from as "covers_from: DateTime<Utc>",
+            covers_to as "covers_to: DateTime<Utc>"
+        FROM daily_digests WHERE ?1 IS NULL OR guild_id = ?1
+        ORDER BY timestamp DESC, id DESC LIMIT ?2"#,
+        guild_id,
+        count
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Fetches a daily digest along with all of its summaries.
+pub async fn fetch_daily_digest(pool: &SqlitePool, id: i64) -> Result<Option<DailyDigest>, Error> {
+    let digest = sqlx::query_as!(
+        DigestData,
+        r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+            covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+        FROM daily_digests WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(digest) = digest else {
+        return Ok(None);
+    };
+    Ok(with_summaries(pool, vec![digest], None).await?.pop())
+}
+
+/// Fetches a digest of any tier without the summaries or digests it rolls up.
+pub async fn fetch_digest(
+    pool: &SqlitePool,
+    tier: RollupTier,
+    id: i64,
+) -> Result<Option<DigestData>, Error> {
+    match tier {
+        RollupTier::Daily => {
+            sqlx::query_as!(
+                DigestData,
+                r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+                    covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+                FROM daily_digests WHERE id = ?"#,
+                id
+            )
+            .fetch_optional(pool)
+            .await
+        }
+        RollupTier::Weekly => {
+            sqlx::query_as!(
+                DigestData,
+                r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+                    covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+                FROM weekly_digests WHERE id = ?"#,
+                id
+            )
+            .fetch_optional(pool)
+            .await
+        }
+        RollupTier::Monthly => {
+            sqlx::query_as!(
+                DigestData,
+                r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+                    covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+                FROM monthly_digests WHERE id = ?"#,
+                id
+            )
+            .fetch_optional(pool)
+            .await
+        }
+    }
+}
+
+/// Fetches the summaries of the given daily digests in a single query, only those of a
+/// channel when given, and pairs each digest with its summaries.
+async fn with_summaries(
+    pool: &SqlitePool,
+    digests: Vec<DigestData>,
+    channel_id: Option<i64>,
+) -> Result<Vec<DailyDigest>, Error> {
+    let digest_ids = serde_json::to_string(&digests.iter().map(|d| d.id).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let summaries = sqlx::query_as!(
+        Summary,
+        r#"SELECT id as "id!", daily_digest_id, text as "text!", timestamp as "timestamp!: DateTime<Utc>",
+            channel_id, guild_id, message_count as "message_count!",
+            covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>",
+            topics as "topics!: Json<Vec<String>>", decisions as "decisions!: Json<Vec<String>>",
+            action_items as "action_items!: Json<Vec<ActionItem>>",
+            open_questions as "open_questions!: Json<Vec<String>>", backend, sentiment, tone,
+            confidence, unverified_references as "unverified_references: Json<Vec<String>>"
+        FROM summaries
+        WHERE daily_digest_id IN (SELECT value FROM json_each(?1))
+            AND (?2 IS NULL OR channel_id = ?2)
+        ORDER BY timestamp, id"#,
+        digest_ids,
+        channel_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let extras = sqlx::query!(
+        r#"SELECT id as "id!", stats as "stats: Json<DigestStats>", tldr
+        FROM daily_digests
+        WHERE id IN (SELECT value FROM json_each(?1))
+            AND (stats IS NOT NULL OR tldr IS NOT NULL)"#,
+        digest_ids
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut stats_by_digest: HashMap<i64, DigestStats> = HashMap::new();
+    let mut tldr_by_digest: HashMap<i64, String> = HashMap::new();
+    for row in extras {
+        if let Some(stats) = row.stats {
+            stats_by_digest.insert(row.id, stats.0);
+        }
+        if let Some(tldr) = row.tldr {
+            tldr_by_digest.insert(row.id, tldr);
+        }
+    }
+    let mut summaries_by_digest: HashMap<i64, Vec<Summary>> = HashMap::new();
+    for summary in summaries {
+        if let Some(digest_id) = summary.daily_digest_id {
+            summaries_by_digest
+                .entry(digest_id)
+                .or_default()
+                .push(summary);
+        }
+    }
+
+    Ok(digests
+        .into_iter()
+        .map(|digest| {
+            let summaries = summaries_by_digest.remove(&digest.id).unwrap_or_default();
+            DailyDigest {
+                sentiment: average_sentiment(&summaries),
+                summaries,
+                stats: stats_by_digest.remove(&digest.id),
+                tldr: tldr_by_digest.remove(&digest.id),
+                id: digest.id,
+                text: digest.text,
+                timestamp: digest.timestamp,
+                guild_id: digest.guild_id,
+                channel_id: digest.channel_id,
+                message_count: digest.message_count,
+                covers_from: digest.covers_from,
+                covers_to: digest.covers_to,
+            }
+        })
+        .collect())
+}
+
+/// Averages the sentiment of the analyzed summaries, weighted by their message counts.
+fn average_sentiment(summaries: &[Summary]) -> Option<f64> {
+    let (total, weight) = summaries
+        .iter()
+        .filter_map(|summary| {
+            let weight = summary.message_count.max(1) as f64;
+            Some((summary.sentiment? * weight, weight))
+        })
+        .fold((0.0, 0.0), |(total, weights), (score, weight)| {
+            (total + score, weights + weight)
+        });
+    (weight > 0.0).then(|| total / weight)
+}
+
+/// Fetches the most recent daily digest of a guild, or the most recent one covering part
+/// of the time range `during` when given.
+pub async fn fetch_latest_daily_digest(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    during: Option<(DateTime<Utc>, DateTime<Utc>)>,
+) -> Result<Option<DigestData>, Error> {
+    let from = during.map(|(from, _)| from.naive_utc());
+    let to = during.map(|(_, to)| to.naive_utc());
+    sqlx::query_as!(
+        DigestData,
+        r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+            covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+        FROM daily_digests
+        WHERE (?1 IS NULL OR guild_id = ?1)
+            AND (?2 IS NULL OR COALESCE(covers_to, timestamp) > ?2)
+            AND (?3 IS NULL OR COALESCE(covers_from, timestamp) < ?3)
+        ORDER BY COALESCE(covers_to, timestamp) DESC
+        LIMIT 1"#,
+        guild_id,
+        from,
+        to
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Fetches the summaries or digests that the given tier has not rolled up yet, oldest
+/// first, optionally only those created before a point in time.
+pub async fn fetch_rollup_sources(
+    pool: &SqlitePool,
+    tier: RollupTier,
+    before: Option<DateTime<Utc>>,
+) -> Result<Vec<RollupSource>, Error> {
+    let before = before.map(|before| before.naive_utc());
+    match tier {
+        RollupTier::Daily => {
+            sqlx::query_as!(
+                RollupSource,
+                r#"SELECT id as "id!", text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+                    covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+                FROM summaries WHERE daily_digest_id IS NULL AND (?1 IS NULL OR timestamp < ?1)
+                ORDER BY timestamp ASC"#,
+                before
+            )
+            .fetch_all(pool)
+            .await
+        }
+        RollupTier::Weekly => {
+            sqlx::query_as!(
+                RollupSource,
+                r#"SELECT id as "id!", text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+                    covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+                FROM daily_digests WHERE weekly_digest_id IS NULL AND (?1 IS NULL OR timestamp < ?1)
+                ORDER BY timestamp ASC"#,
+                before
+            )
+            .fetch_all(pool)
+            .await
+        }
+        RollupTier::Monthly => {
+            sqlx::query_as!(
+                RollupSource,
+                r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+                    covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+                FROM weekly_digests WHERE monthly_digest_id IS NULL AND (?1 IS NULL OR timestamp < ?1)
+                ORDER BY timestamp ASC"#,
+                before
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
 }
 
-pub async fn fetch_daily_digests(pool: Arc<SqlitePool>) -> Vec<DailyDigest> {
+/// Inserts a digest of the given tier, links the summaries or digests it rolls up to
+/// it and returns its ID.
+pub async fn insert_digest(
+    pool: &SqlitePool,
+    tier: RollupTier,
+    digest: NewDigest,
+    source_ids: Vec<i64>,
+) -> Result<i64, Error> {
+    let covers_from = digest.covers_from.map(|t| t.naive_utc());
+    let covers_to = digest.covers_to.map(|t| t.naive_utc());
+    let stats = digest.stats.map(Json);
+    let mut transaction = pool.begin().await?;
+
+    // Insert the new digest and get its ID
+    let digest_id: i64 = match tier {
+        RollupTier::Daily => sqlx::query!(
+            "INSERT INTO daily_digests (text, tldr, guild_id, channel_id, message_count, covers_from, covers_to, stats)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            digest.text,
+            digest.tldr,
+            digest.guild_id,
+            digest.channel_id,
+            digest.message_count,
+            covers_from,
+            covers_to,
+            stats
+        )
+        .execute(&mut *transaction)
+        .await?
+        .last_insert_rowid(),
+        RollupTier::Weekly => sqlx::query!(
+            "INSERT INTO weekly_digests (text, tldr, guild_id, channel_id, message_count, covers_from, covers_to)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+            digest.text,
+            digest.tldr,
+            digest.guild_id,
+            digest.channel_id,
+            digest.message_count,
+            covers_from,
+            covers_to
+        )
+        .execute(&mut *transaction)
+        .await?
+        .last_insert_rowid(),
+        RollupTier::Monthly => sqlx::query!(
+            "INSERT INTO monthly_digests (text, tldr, guild_id, channel_id, message_count, covers_from, covers_to)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+            digest.text,
+            digest.tldr,
+            digest.guild_id,
+            digest.channel_id,
+            digest.message_count,
+            covers_from,
+            covers_to
+        )
+        .execute(&mut *transaction)
+        .await?
+        .last_insert_rowid(),
+    };
+
+    // Update each source to link it to the new digest
+    for source_id in source_ids {
+        match tier {
+            RollupTier::Daily => {
+                sqlx::query!(
+                    "UPDATE summaries SET daily_digest_id = ? WHERE id = ?",
+                    digest_id,
+                    source_id
+                )
+                .execute(&mut *transaction)
+                .await?
+            }
+            RollupTier::Weekly => {
+                sqlx::query!(
+                    "UPDATE daily_digests SET weekly_digest_id = ? WHERE id = ?",
+                    digest_id,
+                    source_id
+                )
+                .execute(&mut *transaction)
+                .await?
+            }
+            RollupTier::Monthly => {
+                sqlx::query!(
+                    "UPDATE weekly_digests SET monthly_digest_id = ? WHERE id = ?",
+                    digest_id,
+                    source_id
+                )
+                .execute(&mut *transaction)
+                .await?
+            }
+        };
+    }
+
+    // Commit the transaction
+    transaction.commit().await?;
+    Ok(digest_id)
+}
+
+/// Fetches all weekly digests of the filtered guild along with the daily digests they
+/// roll up. When a channel is given, only digests covering it are included.
+pub async fn fetch_weekly_digests(
+    pool: Arc<SqlitePool>,
+    filter: &ContentFilter,
+) -> Vec<RollupDigest> {
     let digests = sqlx::query_as!(
-        DailyDigestData,
-        "SELECT id, text, timestamp FROM daily_digests"
+        DigestData,
+        r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+            covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+        FROM weekly_digests
+        WHERE (?1 IS NULL OR guild_id = ?1)
+            AND (?2 IS NULL OR EXISTS (
+                SELECT 1 FROM daily_digests d JOIN summaries s ON s.daily_digest_id = d.id
+                WHERE d.weekly_digest_id = weekly_digests.id AND s.channel_id = ?2
+            ))"#,
+        filter.guild_id,
+        filter.channel_id
     )
     .fetch_all(&*pool)
     .await
@@ -59,69 +817,3463 @@ pub async fn fetch_daily_digests(pool: Arc<SqlitePool>) -> Vec<DailyDigest> {
         .then(|digest| {
             let pool_clone = pool.clone();
             async move {
-                let summaries = sqlx::query_as!(
-                    Summary,
-                    "SELECT * FROM summaries WHERE daily_digest_id = ?",
+                let daily_digests = sqlx::query_as!(
+                    DigestData,
+                    r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+                        covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+                    FROM daily_digests WHERE weekly_digest_id = ?"#,
                     digest.id
                 )
                 .fetch_all(&*pool_clone)
                 .await
                 .unwrap_or_else(|_| vec![]);
+                RollupDigest::new(digest, daily_digests)
+            }
+        })
+        .collect::<Vec<RollupDigest>>()
+        .await
+}
+
+/// Fetches all monthly digests of the filtered guild along with the weekly digests they
+/// roll up. When a channel is given, only digests covering it are included.
+pub async fn fetch_monthly_digests(
+    pool: Arc<SqlitePool>,
+    filter: &ContentFilter,
+) -> Vec<RollupDigest> {
+    let digests = sqlx::query_as!(
+        DigestData,
+        r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+            covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+        FROM monthly_digests
+        WHERE (?1 IS NULL OR guild_id = ?1)
+            AND (?2 IS NULL OR EXISTS (
+                SELECT 1 FROM weekly_digests w
+                JOIN daily_digests d ON d.weekly_digest_id = w.id
+                JOIN summaries s ON s.daily_digest_id = d.id
+                WHERE w.monthly_digest_id = monthly_digests.id AND s.channel_id = ?2
+            ))"#,
+        filter.guild_id,
+        filter.channel_id
+    )
+    .fetch_all(&*pool)
+    .await
+    .unwrap_or_else(|_| vec![]);
 
-                DailyDigest {
-                    id: digest.id,
-                    text: digest.text,
-                    timestamp: digest.timestamp,
-                    summaries,
-                }
+    stream::iter(digests)
+        .then(|digest| {
+            let pool_clone = pool.clone();
+            async move {
+                let weekly_digests = sqlx::query_as!(
+                    DigestData,
+                    r#"SELECT id, text, timestamp as "timestamp: DateTime<Utc>", guild_id, channel_id, message_count,
+                        covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>"
+                    FROM weekly_digests WHERE monthly_digest_id = ?"#,
+                    digest.id
+                )
+                .fetch_all(&*pool_clone)
+                .await
+                .unwrap_or_else(|_| vec![]);
+                RollupDigest::new(digest, weekly_digests)
             }
         })
-        .collect::<Vec<DailyDigest>>()
+        .collect::<Vec<RollupDigest>>()
         .await
 }
 
-pub async fn insert_daily_digest(
+/// Fetches a page of `count` summaries matching the filter, most recent first. Pages
+/// start at 1.
+pub async fn fetch_latest_summaries(
+    pool: &SqlitePool,
+    filter: &ContentFilter,
+    count: u32,
+    page: u32,
+) -> Result<Vec<Summary>, Error> {
+    let limit = i64::from(count);
+    let offset = i64::from(count) * i64::from(page.saturating_sub(1));
+    sqlx::query_as!(
+        Summary,
+        r#"SELECT id as "id!", daily_digest_id, text, timestamp as "timestamp: DateTime<Utc>", channel_id, guild_id,
+            message_count, covers_from as "covers_from: DateTime<Utc>", covers_to as "covers_to: DateTime<Utc>",
+            topics as "topics: Json<Vec<String>>", decisions as "decisions: Json<Vec<String>>",
+            action_items as "action_items: Json<Vec<ActionItem>>",
+            open_questions as "open_questions: Json<Vec<String>>", backend, sentiment, tone,
+            confidence, unverified_references as "unverified_references: Json<Vec<String>>"
+        FROM summaries
+        WHERE (?1 IS NULL OR guild_id = ?1) AND (?2 IS NULL OR channel_id = ?2)
+        ORDER BY timestamp DESC, id DESC LIMIT ?3 OFFSET ?4"#,
+        filter.guild_id,
+        filter.channel_id,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn count_summaries(pool: &SqlitePool, filter: &ContentFilter) -> Result<i64, Error> {
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM summaries
+        WHERE (?1 IS NULL OR guild_id = ?1) AND (?2 IS NULL OR channel_id = ?2)"#,
+        filter.guild_id,
+        filter.channel_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PendingSummary {
+    pub id: i64,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub up_to_message_id: i64,
+    pub attempts: i64,
+    pub last_error: String,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+}
+
+/// Records a failed summarization of a batch of messages so it can be retried once
+/// `retry_after_seconds` have passed. Repeated failures bump the attempt count.
+pub async fn upsert_pending_summary(
     pool: &SqlitePool,
-    digest_text: String,
-    summary_ids: Vec<i64>,
+    guild_id: Option<i64>,
+    channel_id: i64,
+    up_to_message_id: i64,
+    error: &str,
+    retry_after_seconds: i64,
 ) -> Result<(), Error> {
-    let mut transaction = pool.begin().await?;
+    let retry_after = format!("+{retry_after_seconds} seconds");
+    sqlx::query!(
+        "INSERT INTO pending_summaries (guild_id, channel_id, up_to_message_id, last_error, next_attempt_at)
+        VALUES (?1, ?2, ?3, ?4, datetime('now', ?5))
+        ON CONFLICT (channel_id, up_to_message_id) DO UPDATE SET
+            attempts = attempts + 1,
+            last_error = excluded.last_error,
+            next_attempt_at = excluded.next_attempt_at",
+        guild_id,
+        channel_id,
+        up_to_message_id,
+        error,
+        retry_after
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
 
-    // Insert the new digest and get its ID
-    let digest_id: i64 = sqlx::query!("INSERT INTO daily_digests (text) VALUES (?)", digest_text)
-        .execute(&mut *transaction)
-        .await?
-        .last_insert_rowid();
+/// Removes the pending entry for a batch of messages, if there is one.
+pub async fn delete_pending_summary_for_batch(
+    pool: &SqlitePool,
+    channel_id: i64,
+    up_to_message_id: i64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM pending_summaries WHERE channel_id = ? AND up_to_message_id = ?",
+        channel_id,
+        up_to_message_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_pending_summaries(pool: Arc<SqlitePool>) -> Vec<PendingSummary> {
+    sqlx::query_as!(
+        PendingSummary,
+        r#"SELECT id, guild_id, channel_id, up_to_message_id, attempts, last_error,
+            created_at as "created_at: DateTime<Utc>", next_attempt_at as "next_attempt_at: DateTime<Utc>"
+        FROM pending_summaries ORDER BY next_attempt_at ASC"#
+    )
+    .fetch_all(&*pool)
+    .await
+    .unwrap_or_else(|_| vec![])
+}
 
-    // Update each summary to link it to the new digest
-    for summary_id in summary_ids {
+/// Fetches the pending entries that are due a retry and pushes their next attempt back
+/// by `retry_after_seconds`, so they are not picked up again while being retried.
+pub async fn claim_due_pending_summaries(
+    pool: &SqlitePool,
+    retry_after_seconds: i64,
+) -> Result<Vec<PendingSummary>, Error> {
+    let mut transaction = pool.begin().await?;
+    let due = sqlx::query_as!(
+        PendingSummary,
+        r#"SELECT id, guild_id, channel_id, up_to_message_id, attempts, last_error,
+            created_at as "created_at: DateTime<Utc>", next_attempt_at as "next_attempt_at: DateTime<Utc>"
+        FROM pending_summaries WHERE next_attempt_at <= datetime('now')"#
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let retry_after = format!("+{retry_after_seconds} seconds");
+    for pending in &due {
         sqlx::query!(
-            "UPDATE summaries SET daily_digest_id = ? WHERE id = ?",
-            digest_id,
-            summary_id
+            "UPDATE pending_summaries SET next_attempt_at = datetime('now', ?) WHERE id = ?",
+            retry_after,
+            pending.id
         )
         .execute(&mut *transaction)
         .await?;
     }
-
-    // Commit the transaction
     transaction.commit().await?;
-    Ok(())
+    Ok(due)
 }
 
-pub async fn fetch_latest_summaries(
-    pool: Arc<SqlitePool>,
-    count: usize,
-    page: usize,
-) -> Vec<Summary> {
-    let offset = count * (page - 1);
-    sqlx::query_as!(
-        Summary,
-        "SELECT * FROM summaries ORDER BY timestamp DESC LIMIT ? OFFSET ?",
-        count as i64,
-        offset as i64
+/// Makes a pending entry due for an immediate retry. Returns false if it does not exist.
+pub async fn redrive_pending_summary(pool: &SqlitePool, id: i64) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE pending_summaries SET next_attempt_at = datetime('now') WHERE id = ?",
+        id
     )
-    .fetch_all(&*pool)
-    .await
-    .unwrap_or_else(|_| vec![])
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Gives up on a pending entry. Returns false if it does not exist.
+pub async fn delete_pending_summary(pool: &SqlitePool, id: i64) -> Result<bool, Error> {
+    let result = sqlx::query!("DELETE FROM pending_summaries WHERE id = ?", id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// A Discord message stored for summarization.
+#[derive(Serialize, Deserialize)]
+pub struct LoggedMessage {
+    pub id: i64,
+    pub message_id: i64,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub author: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    pub summary_id: Option<i64>,
+    pub token_count: i64,
+    /// Discord ID and title of the thread or forum post the message was posted in.
+    pub thread_id: Option<i64>,
+    pub thread_name: Option<String>,
+    pub attachments: Json<Vec<LoggedAttachment>>,
+    /// How many reactions members left on the message.
+    pub reaction_count: i64,
+    /// Whether the message was deleted on Discord, in which case its content is empty.
+    pub deleted: bool,
+    /// Author and content of the message this one replies to, when it is stored.
+    pub reply_to_author: Option<String>,
+    pub reply_to_content: Option<String>,
+}
+
+/// A file attached to a logged message, as transcripts show it.
+#[derive(Serialize, Deserialize)]
+pub struct LoggedAttachment {
+    pub filename: String,
+    /// What a vision model saw in the image, when it was described.
+    pub description: Option<String>,
+}
+
+pub struct NewMessage<'a> {
+    pub message_id: i64,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    /// Discord ID of the author, unknown for messages imported from message logs.
+    pub author_id: Option<i64>,
+    pub author: &'a str,
+    pub content: &'a str,
+    pub timestamp: DateTime<Utc>,
+    pub token_count: i64,
+    /// Discord ID of the message this one replies to.
+    pub reply_to_message_id: Option<i64>,
+    /// Discord ID and title of the thread the message was posted in, if any.
+    pub thread_id: Option<i64>,
+    pub thread_name: Option<&'a str>,
+}
+
+/// Stores a message, unless a message with the same Discord ID is already stored, and
+/// counts it in the activity of its channel. Returns the row ID of the new message, or
+/// `None` if it was a duplicate.
+pub async fn insert_message(
+    pool: &SqlitePool,
+    message: NewMessage<'_>,
+) -> Result<Option<i64>, Error> {
+    let mut transaction = pool.begin().await?;
+    let id = insert_message_with(&mut transaction, message).await?;
+    if id.is_some() {
+        transaction.commit().await?;
+    }
+    Ok(id)
+}
+
+async fn insert_message_with(
+    connection: &mut SqliteConnection,
+    message: NewMessage<'_>,
+) -> Result<Option<i64>, Error> {
+    let timestamp = message.timestamp.naive_utc();
+    let result = sqlx::query!(
+        "INSERT INTO messages (message_id, guild_id, channel_id, author_id, author, content, timestamp,
+            token_count, reply_to_message_id, thread_id, thread_name)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (message_id) DO NOTHING",
+        message.message_id,
+        message.guild_id,
+        message.channel_id,
+        message.author_id,
+        message.author,
+        message.content,
+        timestamp,
+        message.token_count,
+        message.reply_to_message_id,
+        message.thread_id,
+        message.thread_name
+    )
+    .execute(&mut *connection)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+    sqlx::query!(
+        "INSERT INTO channel_activity (guild_id, channel_id, hour, message_count, token_count)
+        VALUES (?1, ?2, strftime('%Y-%m-%d %H:00:00', ?3), 1, ?4)
+        ON CONFLICT (channel_id, hour) DO UPDATE SET
+            message_count = message_count + 1,
+            token_count = token_count + excluded.token_count",
+        message.guild_id,
+        message.channel_id,
+        timestamp,
+        message.token_count
+    )
+    .execute(&mut *connection)
+    .await?;
+    let author = message
+        .author_id
+        .map_or_else(|| message.author.to_string(), |id| id.to_string());
+    sqlx::query!(
+        "INSERT OR IGNORE INTO channel_activity_authors (channel_id, hour, author)
+        VALUES (?1, strftime('%Y-%m-%d %H:00:00', ?2), ?3)",
+        message.channel_id,
+        timestamp,
+        author
+    )
+    .execute(&mut *connection)
+    .await?;
+    Ok(Some(result.last_insert_rowid()))
+}
+
+/// Stores the messages of a log file left behind by a version of the bot that logged
+/// messages to files, and points the pending summarization of the file, if it had one,
+/// at them. Returns the row ID of the last stored message.
+pub async fn import_message_log(
+    pool: &SqlitePool,
+    channel_id: i64,
+    log_file_index: i64,
+    messages: Vec<NewMessage<'_>>,
+) -> Result<Option<i64>, Error> {
+    let mut transaction = pool.begin().await?;
+    let mut last_id = None;
+    for message in messages {
+        last_id = insert_message_with(&mut transaction, message)
+            .await?
+            .or(last_id);
+    }
+    if let Some(last_id) = last_id {
+        sqlx::query!(
+            "INSERT INTO pending_summaries (guild_id, channel_id, up_to_message_id, attempts, last_error, created_at)
+            SELECT guild_id, channel_id, ?3, attempts, last_error, created_at
+            FROM pending_message_logs WHERE channel_id = ?1 AND log_file_index = ?2",
+            channel_id,
+            log_file_index,
+            last_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    sqlx::query!(
+        "DELETE FROM pending_message_logs WHERE channel_id = ? AND log_file_index = ?",
+        channel_id,
+        log_file_index
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(last_id)
+}
+
+pub struct NewAttachment<'a> {
+    pub message_id: i64,
+    pub filename: &'a str,
+    pub url: &'a str,
+    pub content_type: Option<&'a str>,
+    pub size: i64,
+    pub description: Option<&'a str>,
+}
+
+pub async fn insert_attachment(
+    pool: &SqlitePool,
+    attachment: NewAttachment<'_>,
+) -> Result<i64, Error> {
+    let result = sqlx::query!(
+        "INSERT INTO message_attachments (message_id, filename, url, content_type, size, description)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        attachment.message_id,
+        attachment.filename,
+        attachment.url,
+        attachment.content_type,
+        attachment.size,
+        attachment.description
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
+
+/// Adds what a vision model saw in an image to its stored attachment.
+pub async fn set_attachment_description(
+    pool: &SqlitePool,
+    id: i64,
+    description: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE message_attachments SET description = ? WHERE id = ?",
+        description,
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the messages of a channel that have not been summarized yet, up to and
+/// including `up_to_message_id`, in the order they were received.
+pub async fn fetch_unsummarized_messages(
+    pool: &SqlitePool,
+    channel_id: i64,
+    up_to_message_id: i64,
+) -> Result<Vec<LoggedMessage>, Error> {
+    sqlx::query_as!(
+        LoggedMessage,
+        r#"SELECT m.id as "id!", m.message_id as "message_id!", m.guild_id,
+            m.channel_id as "channel_id!", m.author as "author!", m.content as "content!",
+            m.timestamp as "timestamp!: DateTime<Utc>", m.summary_id,
+            m.token_count as "token_count!", m.thread_id, m.thread_name,
+            (SELECT json_group_array(json_object('filename', a.filename, 'description', a.description))
+                FROM message_attachments a WHERE a.message_id = m.id)
+                as "attachments!: Json<Vec<LoggedAttachment>>",
+            m.reaction_count as "reaction_count!",
+            m.deleted_at IS NOT NULL as "deleted!: bool",
+            parent.author as "reply_to_author?", parent.content as "reply_to_content?"
+        FROM messages m
+        LEFT JOIN messages parent ON parent.message_id = m.reply_to_message_id
+        WHERE m.channel_id = ? AND m.id <= ? AND m.summary_id IS NULL
+        ORDER BY m.id ASC"#,
+        channel_id,
+        up_to_message_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Fetches every stored message of a channel sent at or after `since`, summarized or
+/// not, in the order they were received.
+pub async fn fetch_channel_messages_since(
+    pool: &SqlitePool,
+    channel_id: i64,
+    since: DateTime<Utc>,
+) -> Result<Vec<LoggedMessage>, Error> {
+    let since = since.naive_utc();
+    sqlx::query_as!(
+        LoggedMessage,
+        r#"SELECT m.id as "id!", m.message_id as "message_id!", m.guild_id,
+            m.channel_id as "channel_id!", m.author as "author!", m.content as "content!",
+            m.timestamp as "timestamp!: DateTime<Utc>", m.summary_id,
+            m.token_count as "token_count!", m.thread_id, m.thread_name,
+            (SELECT json_group_array(json_object('filename', a.filename, 'description', a.description))
+                FROM message_attachments a WHERE a.message_id = m.id)
+                as "attachments!: Json<Vec<LoggedAttachment>>",
+            m.reaction_count as "reaction_count!",
+            m.deleted_at IS NOT NULL as "deleted!: bool",
+            parent.author as "reply_to_author?", parent.content as "reply_to_content?"
+        FROM messages m
+        LEFT JOIN messages parent ON parent.message_id = m.reply_to_message_id
+        WHERE m.channel_id = ? AND m.timestamp >= ?
+        ORDER BY m.id ASC"#,
+        channel_id,
+        since
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Fetches the messages a summary was written from, in the order they were received.
+pub async fn fetch_summary_messages(
+    pool: &SqlitePool,
+    summary_id: i64,
+) -> Result<Vec<LoggedMessage>, Error> {
+    sqlx::query_as!(
+        LoggedMessage,
+        r#"SELECT m.id as "id!", m.message_id as "message_id!", m.guild_id,
+            m.channel_id as "channel_id!", m.author as "author!", m.content as "content!",
+            m.timestamp as "timestamp!: DateTime<Utc>", m.summary_id,
+            m.token_count as "token_count!", m.thread_id, m.thread_name,
+            (SELECT json_group_array(json_object('filename', a.filename, 'description', a.description))
+                FROM message_attachments a WHERE a.message_id = m.id)
+                as "attachments!: Json<Vec<LoggedAttachment>>",
+            m.reaction_count as "reaction_count!",
+            m.deleted_at IS NOT NULL as "deleted!: bool",
+            parent.author as "reply_to_author?", parent.content as "reply_to_content?"
+        FROM messages m
+        LEFT JOIN messages parent ON parent.message_id = m.reply_to_message_id
+        WHERE m.summary_id = ?
+        ORDER BY m.id ASC"#,
+        summary_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Replaces the content of a message edited on Discord, unless it was already
+/// summarized. Returns false if there is no such message waiting to be summarized.
+pub async fn update_message_content(
+    pool: &SqlitePool,
+    message_id: i64,
+    content: &str,
+    token_count: i64,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE messages SET content = ?, token_count = ?
+        WHERE message_id = ? AND summary_id IS NULL AND deleted_at IS NULL",
+        content,
+        token_count,
+        message_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Adds `change` reactions to the count of a logged message, which can be negative for
+/// reactions that were removed.
+pub async fn add_message_reactions(
+    pool: &SqlitePool,
+    message_id: i64,
+    change: i64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE messages SET reaction_count = MAX(reaction_count + ?, 0) WHERE message_id = ?",
+        change,
+        message_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Sets the reaction count of a logged message, for when it is known in full.
+pub async fn set_message_reactions(
+    pool: &SqlitePool,
+    message_id: i64,
+    count: i64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE messages SET reaction_count = ? WHERE message_id = ?",
+        count,
+        message_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A message members reacted to, as digests show it.
+pub struct ReactedMessage {
+    pub channel_id: i64,
+    pub author: String,
+    pub content: String,
+    pub reaction_count: i64,
+}
+
+/// Fetches the messages of a guild sent in a period that got the most reactions, most
+/// reacted first.
+pub async fn fetch_most_reacted_messages(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<ReactedMessage>, Error> {
+    let from = from.naive_utc();
+    let to = to.naive_utc();
+    sqlx::query_as!(
+        ReactedMessage,
+        r#"SELECT channel_id as "channel_id!", author as "author!", content as "content!",
+            reaction_count as "reaction_count!"
+        FROM messages
+        WHERE guild_id IS ? AND timestamp >= ? AND timestamp <= ? AND reaction_count > 0
+            AND deleted_at IS NULL
+        ORDER BY reaction_count DESC, id ASC
+        LIMIT ?"#,
+        guild_id,
+        from,
+        to,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A message as activity statistics count it.
+pub struct MessageActivity {
+    pub channel_id: i64,
+    pub author: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Fetches who posted in which channel when, for the messages of a guild sent in a
+/// period.
+pub async fn fetch_message_activity(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<MessageActivity>, Error> {
+    let from = from.naive_utc();
+    let to = to.naive_utc();
+    sqlx::query_as!(
+        MessageActivity,
+        r#"SELECT channel_id as "channel_id!", author as "author!",
+            timestamp as "timestamp!: DateTime<Utc>"
+        FROM messages
+        WHERE guild_id IS ? AND timestamp >= ? AND timestamp < ? AND deleted_at IS NULL"#,
+        guild_id,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The message a digest links to as the source of one of its summaries.
+pub struct Citation {
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub thread_id: Option<i64>,
+    /// Discord ID of the message.
+    pub message_id: i64,
+}
+
+/// Fetches the message representing a summary in citations: its most reacted message,
+/// or its first one when nobody reacted. Returns `None` when no message of the summary
+/// is left with a Discord ID.
+pub async fn fetch_summary_citation(
+    pool: &SqlitePool,
+    summary_id: i64,
+) -> Result<Option<Citation>, Error> {
+    sqlx::query_as!(
+        Citation,
+        r#"SELECT guild_id, channel_id as "channel_id!", thread_id, message_id as "message_id!"
+        FROM messages
+        WHERE summary_id = ? AND message_id IS NOT NULL AND deleted_at IS NULL
+        ORDER BY reaction_count DESC, timestamp ASC, id ASC
+        LIMIT 1"#,
+        summary_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Removes a message deleted on Discord, along with its attachments, its highlight and
+/// the question and links found in it. With `keep_marker`, the message is kept without
+/// its content so that transcripts can tell it was deleted. Returns false if the
+/// message is not stored.
+pub async fn delete_message(
+    pool: &SqlitePool,
+    message_id: i64,
+    keep_marker: bool,
+) -> Result<bool, Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "DELETE FROM questions WHERE message_id IN (SELECT id FROM messages WHERE message_id = ?)",
+        message_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM shared_links WHERE message_id IN (SELECT id FROM messages WHERE message_id = ?)",
+        message_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM message_attachments
+        WHERE message_id IN (SELECT id FROM messages WHERE message_id = ?)",
+        message_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM highlights WHERE message_id IN (SELECT id FROM messages WHERE message_id = ?)",
+        message_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let result = if keep_marker {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "UPDATE messages SET content = '', token_count = 0, deleted_at = ?
+            WHERE message_id = ?",
+            now,
+            message_id
+        )
+        .execute(&mut *transaction)
+        .await?
+    } else {
+        sqlx::query!("DELETE FROM messages WHERE message_id = ?", message_id)
+            .execute(&mut *transaction)
+            .await?
+    };
+    transaction.commit().await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Discord ID of the oldest message logged in a channel itself, outside its threads.
+pub async fn fetch_first_message_id(
+    pool: &SqlitePool,
+    channel_id: i64,
+) -> Result<Option<i64>, Error> {
+    sqlx::query_scalar!(
+        r#"SELECT MIN(message_id) as "message_id?: i64" FROM messages
+        WHERE channel_id = ? AND thread_id IS NULL"#,
+        channel_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// The newest message logged in a channel.
+pub struct LastLoggedMessage {
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub message_id: i64,
+}
+
+/// Fetches the Discord ID of the newest message logged in each channel itself, outside
+/// its threads.
+pub async fn fetch_last_message_ids(pool: &SqlitePool) -> Result<Vec<LastLoggedMessage>, Error> {
+    sqlx::query_as!(
+        LastLoggedMessage,
+        r#"SELECT MAX(guild_id) as "guild_id?: i64", channel_id as "channel_id!",
+            MAX(message_id) as "message_id!: i64"
+        FROM messages
+        WHERE thread_id IS NULL
+        GROUP BY channel_id"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Unsummarized messages of a channel, as tracked by the message log service.
+pub struct UnsummarizedChannel {
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub token_count: i64,
+    pub last_message_id: i64,
+}
+
+/// Fetches every channel that has unsummarized messages, along with their total
+/// token count.
+pub async fn fetch_unsummarized_channels(
+    pool: &SqlitePool,
+) -> Result<Vec<UnsummarizedChannel>, Error> {
+    sqlx::query_as!(
+        UnsummarizedChannel,
+        r#"SELECT guild_id, channel_id as "channel_id!", SUM(token_count) as "token_count!: i64",
+            MAX(id) as "last_message_id!: i64"
+        FROM messages WHERE summary_id IS NULL GROUP BY channel_id"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Kinds of stored content that can be embedded and searched.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentKind {
+    Summary,
+    DailyDigest,
+    WeeklyDigest,
+    MonthlyDigest,
+}
+
+impl ContentKind {
+    /// The kind of the digests produced by a rollup tier.
+    pub fn digest_of(tier: RollupTier) -> Self {
+        match tier {
+            RollupTier::Daily => ContentKind::DailyDigest,
+            RollupTier::Weekly => ContentKind::WeeklyDigest,
+            RollupTier::Monthly => ContentKind::MonthlyDigest,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentKind::Summary => "summary",
+            ContentKind::DailyDigest => "daily_digest",
+            ContentKind::WeeklyDigest => "weekly_digest",
+            ContentKind::MonthlyDigest => "monthly_digest",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "summary" => Some(ContentKind::Summary),
+            "daily_digest" => Some(ContentKind::DailyDigest),
+            "weekly_digest" => Some(ContentKind::WeeklyDigest),
+            "monthly_digest" => Some(ContentKind::MonthlyDigest),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ContentKind::Summary => "Summary",
+            ContentKind::DailyDigest => "Daily digest",
+            ContentKind::WeeklyDigest => "Weekly digest",
+            ContentKind::MonthlyDigest => "Monthly digest",
+        }
+    }
+}
+
+/// A summary or digest that has no embedding from the current model yet.
+pub struct UnembeddedContent {
+    pub kind: String,
+    pub id: i64,
+    pub text: String,
+}
+
+/// Fetches up to `limit` summaries and digests without an embedding from `model`.
+pub async fn fetch_unembedded_content(
+    pool: &SqlitePool,
+    model: &str,
+    limit: i64,
+) -> Result<Vec<UnembeddedContent>, Error> {
+    sqlx::query_as!(
+        UnembeddedContent,
+        r#"SELECT kind as "kind!: String", id as "id!: i64", text as "text!: String" FROM (
+            SELECT 'summary' as kind, id, text FROM summaries
+            UNION ALL SELECT 'daily_digest', id, text FROM daily_digests
+            UNION ALL SELECT 'weekly_digest', id, text FROM weekly_digests
+            UNION ALL SELECT 'monthly_digest', id, text FROM monthly_digests
+        ) content
+        WHERE NOT EXISTS (
+            SELECT 1 FROM embeddings e
+            WHERE e.content_kind = content.kind AND e.content_id = content.id AND e.model = ?1
+        )
+        LIMIT ?2"#,
+        model,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Stores the embedding of a summary or digest, replacing any from another model.
+pub async fn upsert_embedding(
+    pool: &SqlitePool,
+    kind: ContentKind,
+    content_id: i64,
+    model: &str,
+    vector: &[f32],
+) -> Result<(), Error> {
+    let kind = kind.as_str();
+    let vector: Vec<u8> = vector.iter().flat_map(|x| x.to_le_bytes()).collect();
+    sqlx::query!(
+        "INSERT INTO embeddings (content_kind, content_id, model, vector)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT (content_kind, content_id) DO UPDATE SET
+            model = excluded.model,
+            vector = excluded.vector,
+            created_at = CURRENT_TIMESTAMP",
+        kind,
+        content_id,
+        model,
+        vector
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A summary or digest along with its embedding.
+pub struct EmbeddedContent {
+    pub kind: String,
+    pub id: i64,
+    pub text: String,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
+    pub vector: Vec<u8>,
+}
+
+impl EmbeddedContent {
+    pub fn vector(&self) -> Vec<f32> {
+        self.vector
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()
+    }
+}
+
+/// Fetches every summary and digest of the filtered guild that has an embedding from
+/// `model`.
+pub async fn fetch_embedded_content(
+    pool: &SqlitePool,
+    model: &str,
+    guild_id: Option<i64>,
+) -> Result<Vec<EmbeddedContent>, Error> {
+    sqlx::query_as!(
+        EmbeddedContent,
+        r#"SELECT content.kind as "kind!: String", content.id as "id!: i64",
+            content.text as "text!: String", content.guild_id as "guild_id: i64",
+            content.channel_id as "channel_id: i64",
+            content.covers_from as "covers_from: DateTime<Utc>",
+            content.covers_to as "covers_to: DateTime<Utc>", e.vector as "vector!: Vec<u8>"
+        FROM (
+            SELECT 'summary' as kind, id, text, guild_id, channel_id, covers_from, covers_to
+                FROM summaries
+            UNION ALL SELECT 'daily_digest', id, text, guild_id, channel_id, covers_from, covers_to
+                FROM daily_digests
+            UNION ALL SELECT 'weekly_digest', id, text, guild_id, channel_id, covers_from, covers_to
+                FROM weekly_digests
+            UNION ALL SELECT 'monthly_digest', id, text, guild_id, channel_id, covers_from, covers_to
+                FROM monthly_digests
+        ) content
+        JOIN embeddings e ON e.content_kind = content.kind AND e.content_id = content.id
+        WHERE e.model = ?1 AND (?2 IS NULL OR content.guild_id = ?2)"#,
+        model,
+        guild_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Whether an action item still has to be done.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum ActionItemStatus {
+    Open,
+    Resolved,
+}
+
+/// An action item extracted from a summary, along with where it came from.
+#[derive(Serialize, Deserialize)]
+pub struct TrackedActionItem {
+    pub id: i64,
+    pub summary_id: i64,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub description: String,
+    pub assignee: Option<String>,
+    pub status: ActionItemStatus,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Restricts fetched action items to a guild, channel and/or status.
+#[derive(Deserialize, Default)]
+pub struct ActionItemFilter {
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub status: Option<ActionItemStatus>,
+}
+
+/// Fetches the action items matching the filter, oldest first.
+pub async fn fetch_action_items(
+    pool: &SqlitePool,
+    filter: &ActionItemFilter,
+) -> Result<Vec<TrackedActionItem>, Error> {
+    sqlx::query_as!(
+        TrackedActionItem,
+        r#"SELECT id, summary_id, guild_id, channel_id, description, assignee,
+            status as "status: ActionItemStatus", created_at as "created_at: DateTime<Utc>",
+            resolved_at as "resolved_at: DateTime<Utc>"
+        FROM action_items
+        WHERE (?1 IS NULL OR guild_id = ?1) AND (?2 IS NULL OR channel_id = ?2)
+            AND (?3 IS NULL OR status = ?3)
+        ORDER BY created_at, id"#,
+        filter.guild_id,
+        filter.channel_id,
+        filter.status
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Marks an action item as resolved or open again. When a guild is given, only its
+/// action items can be updated. Returns whether the action item was found.
+pub async fn set_action_item_status(
+    pool: &SqlitePool,
+    id: i64,
+    guild_id: Option<i64>,
+    status: ActionItemStatus,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE action_items
+        SET status = ?1,
+            resolved_at = CASE WHEN ?1 = 'resolved' THEN COALESCE(resolved_at, datetime('now')) END
+        WHERE id = ?2 AND (?3 IS NULL OR guild_id = ?3)",
+        status,
+        id,
+        guild_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Records that a stored message asks a question.
+pub async fn insert_question(pool: &SqlitePool, message_id: i64) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT OR IGNORE INTO questions (message_id) VALUES (?)",
+        message_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Marks the questions not listed in a digest yet as answered when someone else replied
+/// to them, or when the next message in their channel came from someone else within
+/// `answer_window_seconds` without replying to another message.
+pub async fn mark_answered_questions(
+    pool: &SqlitePool,
+    answer_window_seconds: i64,
+) -> Result<u64, Error> {
+    let window = format!("+{answer_window_seconds} seconds");
+    let result = sqlx::query!(
+        r#"UPDATE questions SET answered = TRUE
+        WHERE NOT answered AND daily_digest_id IS NULL AND EXISTS (
+            SELECT 1 FROM messages question
+            WHERE question.id = questions.message_id AND (
+                EXISTS (
+                    SELECT 1 FROM messages reply
+                    WHERE reply.channel_id = question.channel_id
+                        AND reply.reply_to_message_id = question.message_id
+                        AND reply.author != question.author
+                )
+                OR EXISTS (
+                    SELECT 1 FROM messages next
+                    WHERE next.id = (
+                        SELECT MIN(id) FROM messages
+                        WHERE channel_id = question.channel_id AND id > question.id
+                    )
+                        AND next.author != question.author
+                        AND next.reply_to_message_id IS NULL
+                        AND next.timestamp <= datetime(question.timestamp, ?1)
+                )
+            )
+        )"#,
+        window
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// A question nobody answered.
+#[derive(Serialize, Deserialize)]
+pub struct UnansweredQuestion {
+    pub id: i64,
+    pub message_id: i64,
+    pub channel_id: i64,
+    pub author: String,
+    pub content: String,
+    pub asked_at: DateTime<Utc>,
+}
+
+/// Fetches the unanswered questions of a guild asked before `before` that no daily
+/// digest lists yet, oldest first.
+pub async fn fetch_unlisted_unanswered_questions(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    before: DateTime<Utc>,
+) -> Result<Vec<UnansweredQuestion>, Error> {
+    let before = before.naive_utc();
+    sqlx::query_as!(
+        UnansweredQuestion,
+        r#"SELECT questions.id as "id!", messages.message_id as "message_id!",
+            messages.channel_id as "channel_id!", messages.author as "author!",
+            messages.content as "content!", messages.timestamp as "asked_at!: DateTime<Utc>"
+        FROM questions JOIN messages ON messages.id = questions.message_id
+        WHERE NOT questions.answered AND questions.daily_digest_id IS NULL
+            AND messages.guild_id IS ?1 AND messages.timestamp <= ?2
+        ORDER BY messages.id"#,
+        guild_id,
+        before
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Records that a daily digest listed the given unanswered questions.
+pub async fn link_questions_to_digest(
+    pool: &SqlitePool,
+    question_ids: &[i64],
+    daily_digest_id: i64,
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    for question_id in question_ids {
+        sqlx::query!(
+            "UPDATE questions SET daily_digest_id = ? WHERE id = ?",
+            daily_digest_id,
+            question_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await
+}
+
+/// A URL posted in a listened to channel.
+#[derive(Serialize, Deserialize)]
+pub struct SharedLink {
+    pub id: i64,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub author: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub shared_at: DateTime<Utc>,
+}
+
+pub struct NewSharedLink<'a> {
+    pub message_id: i64,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub author: &'a str,
+    pub url: &'a str,
+    pub shared_at: DateTime<Utc>,
+}
+
+pub async fn insert_shared_link(pool: &SqlitePool, link: NewSharedLink<'_>) -> Result<(), Error> {
+    let shared_at = link.shared_at.naive_utc();
+    sqlx::query!(
+        "INSERT OR IGNORE INTO shared_links (message_id, guild_id, channel_id, author, url, shared_at)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        link.message_id,
+        link.guild_id,
+        link.channel_id,
+        link.author,
+        link.url,
+        shared_at
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the links matching the filter, most recently shared first.
+pub async fn fetch_shared_links(
+    pool: &SqlitePool,
+    filter: &ContentFilter,
+) -> Result<Vec<SharedLink>, Error> {
+    sqlx::query_as!(
+        SharedLink,
+        r#"SELECT id, guild_id, channel_id, author, url, title, description,
+            shared_at as "shared_at: DateTime<Utc>"
+        FROM shared_links
+        WHERE (?1 IS NULL OR guild_id = ?1) AND (?2 IS NULL OR channel_id = ?2)
+        ORDER BY shared_at DESC, id DESC"#,
+        filter.guild_id,
+        filter.channel_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A shared link whose page has not been looked at yet.
+pub struct UnfetchedLink {
+    pub id: i64,
+    pub url: String,
+}
+
+/// Fetches up to `limit` links whose title and description have not been fetched yet,
+/// oldest first.
+pub async fn fetch_links_without_metadata(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<UnfetchedLink>, Error> {
+    sqlx::query_as!(
+        UnfetchedLink,
+        "SELECT id, url FROM shared_links WHERE metadata_fetched_at IS NULL ORDER BY id LIMIT ?",
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Stores the title and description of a shared link's page. Either is `None` when the
+/// page could not be fetched or does not have one, so it is not fetched again.
+pub async fn update_link_metadata(
+    pool: &SqlitePool,
+    id: i64,
+    title: Option<&str>,
+    description: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE shared_links SET title = ?, description = ?, metadata_fetched_at = datetime('now')
+        WHERE id = ?",
+        title,
+        description,
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the links of a guild shared before `before` that no daily digest lists yet,
+/// in the order they were shared.
+pub async fn fetch_unlisted_shared_links(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    before: DateTime<Utc>,
+) -> Result<Vec<SharedLink>, Error> {
+    let before = before.naive_utc();
+    sqlx::query_as!(
+        SharedLink,
+        r#"SELECT id as "id!", guild_id, channel_id as "channel_id!", author as "author!",
+            url as "url!", title, description, shared_at as "shared_at!: DateTime<Utc>"
+        FROM shared_links
+        WHERE daily_digest_id IS NULL AND guild_id IS ?1 AND shared_at <= ?2
+        ORDER BY shared_at, id"#,
+        guild_id,
+        before
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Records that a daily digest listed the given links.
+pub async fn link_shared_links_to_digest(
+    pool: &SqlitePool,
+    link_ids: &[i64],
+    daily_digest_id: i64,
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    for link_id in link_ids {
+        sqlx::query!(
+            "UPDATE shared_links SET daily_digest_id = ? WHERE id = ?",
+            daily_digest_id,
+            link_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await
+}
+
+/// Highlights a logged message, unless it already is.
+pub async fn insert_highlight(pool: &SqlitePool, message_id: i64) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO highlights (message_id) SELECT id FROM messages WHERE message_id = ?
+        ON CONFLICT (message_id) DO NOTHING",
+        message_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Removes the highlight of a logged message, unless a digest already quoted it.
+pub async fn delete_unlisted_highlight(pool: &SqlitePool, message_id: i64) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM highlights WHERE daily_digest_id IS NULL
+            AND message_id IN (SELECT id FROM messages WHERE message_id = ?)",
+        message_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A highlighted message, as digests quote it.
+pub struct Highlight {
+    pub id: i64,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub thread_id: Option<i64>,
+    /// Discord ID of the message.
+    pub message_id: i64,
+    pub author: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Fetches the highlighted messages of a guild sent before `before` that no daily
+/// digest quotes yet, in the order they were sent.
+pub async fn fetch_unlisted_highlights(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    before: DateTime<Utc>,
+) -> Result<Vec<Highlight>, Error> {
+    let before = before.naive_utc();
+    sqlx::query_as!(
+        Highlight,
+        r#"SELECT h.id as "id!", m.guild_id, m.channel_id as "channel_id!", m.thread_id,
+            m.message_id as "message_id!", m.author as "author!", m.content as "content!",
+            m.timestamp as "timestamp!: DateTime<Utc>"
+        FROM highlights h
+        JOIN messages m ON m.id = h.message_id
+        WHERE h.daily_digest_id IS NULL AND m.guild_id IS ?1 AND m.timestamp <= ?2
+            AND m.deleted_at IS NULL
+        ORDER BY m.timestamp, m.id"#,
+        guild_id,
+        before
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Records that a daily digest quoted the given highlights.
+pub async fn link_highlights_to_digest(
+    pool: &SqlitePool,
+    highlight_ids: &[i64],
+    daily_digest_id: i64,
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    for highlight_id in highlight_ids {
+        sqlx::query!(
+            "UPDATE highlights SET daily_digest_id = ? WHERE id = ?",
+            daily_digest_id,
+            highlight_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await
+}
+
+/// Where a webhook delivery stands.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Not sent yet, or failed and waiting for its next attempt.
+    Pending,
+    Delivered,
+    /// Ran out of attempts.
+    Failed,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub url: String,
+    pub event: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub response_status: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Queues the delivery of an event's payload to each of the given webhook URLs.
+pub async fn insert_webhook_deliveries(
+    pool: &SqlitePool,
+    urls: &[&str],
+    event: &str,
+    payload: &str,
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    for url in urls {
+        sqlx::query!(
+            "INSERT INTO webhook_deliveries (url, event, payload) VALUES (?, ?, ?)",
+            url,
+            event,
+            payload
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await
+}
+
+/// Fetches the pending deliveries that are due to be sent, oldest first.
+pub async fn fetch_due_webhook_deliveries(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, Error> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"SELECT id as "id!", url, event, payload, status as "status!: DeliveryStatus", attempts,
+            last_error, response_status, created_at as "created_at!: DateTime<Utc>",
+            next_attempt_at as "next_attempt_at!: DateTime<Utc>",
+            delivered_at as "delivered_at: DateTime<Utc>"
+        FROM webhook_deliveries
+        WHERE status = 'pending' AND next_attempt_at <= datetime('now')
+        ORDER BY id LIMIT ?"#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Fetches the most recent webhook deliveries, only those with the given status when set.
+pub async fn fetch_webhook_deliveries(
+    pool: &SqlitePool,
+    status: Option<DeliveryStatus>,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, Error> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"SELECT id, url, event, payload, status as "status: DeliveryStatus", attempts, last_error,
+            response_status, created_at as "created_at: DateTime<Utc>",
+            next_attempt_at as "next_attempt_at: DateTime<Utc>",
+            delivered_at as "delivered_at: DateTime<Utc>"
+        FROM webhook_deliveries
+        WHERE ?1 IS NULL OR status = ?1
+        ORDER BY id DESC LIMIT ?2"#,
+        status,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn mark_webhook_delivered(
+    pool: &SqlitePool,
+    id: i64,
+    response_status: i64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE webhook_deliveries
+        SET status = 'delivered', attempts = attempts + 1, response_status = ?,
+            last_error = NULL, delivered_at = datetime('now')
+        WHERE id = ?",
+        response_status,
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Records a failed delivery attempt. The delivery is tried again after
+/// `retry_after_seconds`, or marked as failed when `retry_after_seconds` is `None`.
+pub async fn record_webhook_failure(
+    pool: &SqlitePool,
+    id: i64,
+    error: &str,
+    response_status: Option<i64>,
+    retry_after_seconds: Option<i64>,
+) -> Result<(), Error> {
+    let retry_after = retry_after_seconds.map(|seconds| format!("+{seconds} seconds"));
+    sqlx::query!(
+        "UPDATE webhook_deliveries
+        SET attempts = attempts + 1, last_error = ?1, response_status = ?2,
+            status = CASE WHEN ?3 IS NULL THEN 'failed' ELSE 'pending' END,
+            next_attempt_at = COALESCE(datetime('now', ?3), next_attempt_at)
+        WHERE id = ?4",
+        error,
+        response_status,
+        retry_after,
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Sends a failed or pending delivery again on the next run of the webhook service,
+/// with a fresh set of attempts. Returns whether the delivery was found.
+pub async fn redeliver_webhook(pool: &SqlitePool, id: i64) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE webhook_deliveries
+        SET status = 'pending', attempts = 0, next_attempt_at = datetime('now')
+        WHERE id = ? AND status != 'delivered'",
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// What an LLM API call was made for.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum UsageKind {
+    Completion,
+    Embedding,
+    Transcription,
+}
+
+/// Tokens used by an LLM API call that have not been written to the database yet.
+pub struct NewLlmUsage<'a> {
+    pub provider: &'a str,
+    pub model: &'a str,
+    pub kind: UsageKind,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost_usd: Option<f64>,
+}
+
+pub async fn insert_llm_usage(pool: &SqlitePool, usage: NewLlmUsage<'_>) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO llm_usage (provider, model, kind, prompt_tokens, completion_tokens, cost_usd)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        usage.provider,
+        usage.model,
+        usage.kind,
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.cost_usd
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Total tokens used and estimated cost in US dollars of LLM calls made since the
+/// given time.
+pub async fn fetch_usage_since(
+    pool: &SqlitePool,
+    since: DateTime<Utc>,
+) -> Result<(i64, f64), Error> {
+    let since = since.naive_utc();
+    let usage = sqlx::query!(
+        r#"SELECT COALESCE(SUM(prompt_tokens + completion_tokens), 0) as "tokens!: i64",
+            COALESCE(SUM(cost_usd), 0.0) as "cost_usd!: f64"
+        FROM llm_usage
+        WHERE created_at >= ?"#,
+        since
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok((usage.tokens, usage.cost_usd))
+}
+
+/// Length of the periods LLM usage is added up over.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsagePeriod {
+    #[default]
+    Daily,
+    Monthly,
+}
+
+/// LLM usage of a model over a day or month, in UTC.
+#[derive(Serialize)]
+pub struct UsageTotals {
+    /// `YYYY-MM-DD` for daily totals, `YYYY-MM` for monthly ones.
+    pub period: String,
+    pub provider: String,
+    pub model: String,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    /// Estimated cost of the requests whose model price is known.
+    pub cost_usd: f64,
+}
+
+/// Adds up the LLM usage within the date range per period and model, most recent
+/// period first.
+pub async fn fetch_usage_totals(
+    pool: &SqlitePool,
+    period: UsagePeriod,
+    range: &DateRange,
+) -> Result<Vec<UsageTotals>, Error> {
+    let (from, to) = range.bounds();
+    let format = match period {
+        UsagePeriod::Daily => "%Y-%m-%d",
+        UsagePeriod::Monthly => "%Y-%m",
+    };
+    sqlx::query_as!(
+        UsageTotals,
+        r#"SELECT strftime(?1, created_at) as "period!: String", provider, model,
+            COUNT(*) as "requests!: i64", SUM(prompt_tokens) as "prompt_tokens!: i64",
+            SUM(completion_tokens) as "completion_tokens!: i64",
+            COALESCE(SUM(cost_usd), 0.0) as "cost_usd!: f64"
+        FROM llm_usage
+        WHERE created_at >= ?2 AND created_at < ?3
+        GROUP BY 1, provider, model
+        ORDER BY 1 DESC, provider, model"#,
+        format,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Fetches the topics of each of the given summaries.
+pub async fn fetch_summary_topics(
+    pool: &SqlitePool,
+    summary_ids: &[i64],
+) -> Result<Vec<Vec<String>>, Error> {
+    let summary_ids = serde_json::to_string(summary_ids).unwrap_or_default();
+    let topics = sqlx::query_scalar!(
+        r#"SELECT topics as "topics!: Json<Vec<String>>"
+        FROM summaries WHERE id IN (SELECT value FROM json_each(?))"#,
+        summary_ids
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(topics.into_iter().map(|topics| topics.0).collect())
+}
+
+/// Stores the normalized topics of a daily digest along with how many of its summaries
+/// discussed each.
+pub async fn insert_digest_topics(
+    pool: &SqlitePool,
+    daily_digest_id: i64,
+    guild_id: Option<i64>,
+    topics: &[(String, i64)],
+) -> Result<(), Error> {
+    let timestamp = Utc::now().naive_utc();
+    let mut transaction = pool.begin().await?;
+    for (label, mentions) in topics {
+        sqlx::query!(
+            "INSERT INTO topics (daily_digest_id, guild_id, label, mentions, timestamp)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (daily_digest_id, label) DO UPDATE SET mentions = excluded.mentions",
+            daily_digest_id,
+            guild_id,
+            label,
+            mentions,
+            timestamp
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await
+}
+
+/// A topic of the daily digests of a period, along with how often it came up in the
+/// period of the same length before it.
+#[derive(Serialize)]
+pub struct TrendingTopic {
+    pub label: String,
+    /// How many summaries discussed the topic.
+    pub mentions: i64,
+    /// How many daily digests the topic came up in.
+    pub digest_count: i64,
+    pub previous_mentions: i64,
+}
+
+/// Fetches the topics of a guild's daily digests created since `since`, most mentioned
+/// first, along with their mentions in the period of the same length before it.
+pub async fn fetch_trending_topics(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    since: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<TrendingTopic>, Error> {
+    let previous_since = (since - (Utc::now() - since)).naive_utc();
+    let since = since.naive_utc();
+    sqlx::query_as!(
+        TrendingTopic,
+        r#"SELECT label as "label!",
+            SUM(CASE WHEN timestamp >= ?2 THEN mentions ELSE 0 END) as "mentions!: i64",
+            COUNT(DISTINCT CASE WHEN timestamp >= ?2 THEN daily_digest_id END) as "digest_count!: i64",
+            SUM(CASE WHEN timestamp < ?2 THEN mentions ELSE 0 END) as "previous_mentions!: i64"
+        FROM topics
+        WHERE (?1 IS NULL OR guild_id = ?1) AND timestamp >= ?3
+        GROUP BY label
+        HAVING SUM(CASE WHEN timestamp >= ?2 THEN mentions ELSE 0 END) > 0
+        ORDER BY 2 DESC, 3 DESC, label
+        LIMIT ?4"#,
+        guild_id,
+        since,
+        previous_since,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A topic that came up in several daily digests.
+pub struct RecurringTopic {
+    pub label: String,
+    pub digest_count: i64,
+}
+
+/// Fetches the topics that came up in at least `min_digests` of the given daily
+/// digests, those that came up the most first.
+pub async fn fetch_recurring_topics(
+    pool: &SqlitePool,
+    daily_digest_ids: &[i64],
+    min_digests: i64,
+    limit: i64,
+) -> Result<Vec<RecurringTopic>, Error> {
+    let daily_digest_ids = serde_json::to_string(daily_digest_ids).unwrap_or_default();
+    sqlx::query_as!(
+        RecurringTopic,
+        r#"SELECT label as "label!", COUNT(*) as "digest_count!: i64"
+        FROM topics
+        WHERE daily_digest_id IN (SELECT value FROM json_each(?1))
+        GROUP BY label
+        HAVING COUNT(*) >= ?2
+        ORDER BY 2 DESC, SUM(mentions) DESC, label
+        LIMIT ?3"#,
+        daily_digest_ids,
+        min_digests,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Stores how well a summary's mentions of people and channels are backed by its
+/// messages.
+pub async fn set_summary_confidence(
+    pool: &SqlitePool,
+    summary_id: i64,
+    confidence: f64,
+    unverified_references: &[String],
+) -> Result<(), Error> {
+    let unverified_references = Json(unverified_references);
+    sqlx::query!(
+        "UPDATE summaries SET confidence = ?, unverified_references = ? WHERE id = ?",
+        confidence,
+        unverified_references,
+        summary_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Stores the sentiment of the messages a summary covers.
+pub async fn set_summary_sentiment(
+    pool: &SqlitePool,
+    summary_id: i64,
+    sentiment: &Sentiment,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE summaries SET sentiment = ?, tone = ? WHERE id = ?",
+        sentiment.score,
+        sentiment.tone,
+        summary_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The mood of a channel over an hour or day, in UTC.
+#[derive(Serialize)]
+pub struct ChannelMood {
+    /// `YYYY-MM-DDTHH:00:00Z` for hourly moods, `YYYY-MM-DD` for daily moods.
+    pub period: String,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    /// Average sentiment of the period's summaries, weighted by their message counts.
+    pub sentiment: f64,
+    /// Sentiment of the period's most negative summary.
+    pub lowest_sentiment: f64,
+    /// Tone of the period's latest summary.
+    pub tone: Option<String>,
+    pub summary_count: i64,
+}
+
+/// Averages the sentiment of the filtered channels' summaries ending within the date
+/// range per period and channel, oldest period first.
+pub async fn fetch_channel_moods(
+    pool: &SqlitePool,
+    granularity: ActivityGranularity,
+    filter: &ContentFilter,
+    range: &DateRange,
+) -> Result<Vec<ChannelMood>, Error> {
+    let (from, to) = range.bounds();
+    let format = match granularity {
+        ActivityGranularity::Hour => "%Y-%m-%dT%H:00:00Z",
+        ActivityGranularity::Day => "%Y-%m-%d",
+    };
+    sqlx::query_as!(
+        ChannelMood,
+        r#"SELECT strftime(?1, COALESCE(covers_to, timestamp)) as "period!: String",
+            MAX(guild_id) as "guild_id: i64", channel_id as "channel_id!",
+            SUM(sentiment * MAX(message_count, 1)) / SUM(MAX(message_count, 1)) as "sentiment!: f64",
+            MIN(sentiment) as "lowest_sentiment!: f64",
+            (SELECT tone FROM summaries latest
+                WHERE latest.channel_id = summaries.channel_id AND latest.sentiment IS NOT NULL
+                    AND strftime(?1, COALESCE(latest.covers_to, latest.timestamp))
+                        = strftime(?1, COALESCE(summaries.covers_to, summaries.timestamp))
+                ORDER BY COALESCE(latest.covers_to, latest.timestamp) DESC, latest.id DESC
+                LIMIT 1) as "tone?: String",
+            COUNT(*) as "summary_count!: i64"
+        FROM summaries
+        WHERE sentiment IS NOT NULL AND COALESCE(covers_to, timestamp) >= ?2
+            AND COALESCE(covers_to, timestamp) < ?3 AND (?4 IS NULL OR guild_id = ?4)
+            AND (?5 IS NULL OR channel_id = ?5)
+        GROUP BY 1, channel_id
+        ORDER BY 1, channel_id"#,
+        format,
+        from,
+        to,
+        filter.guild_id,
+        filter.channel_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// How many messages a channel got over a period.
+pub struct ChannelMessageCount {
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub message_count: i64,
+}
+
+/// Counts the messages each channel got since `since`.
+pub async fn fetch_channel_message_counts(
+    pool: &SqlitePool,
+    since: DateTime<Utc>,
+) -> Result<Vec<ChannelMessageCount>, Error> {
+    let since = since.naive_utc();
+    sqlx::query_as!(
+        ChannelMessageCount,
+        r#"SELECT MAX(guild_id) as "guild_id: i64", channel_id as "channel_id!",
+            COUNT(*) as "message_count!: i64"
+        FROM messages
+        WHERE timestamp >= ?
+        GROUP BY channel_id"#,
+        since
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Adds up the messages each channel got over the hours from `from` to `to`, from the
+/// channel activity.
+pub async fn fetch_channel_activity_totals(
+    pool: &SqlitePool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<ChannelMessageCount>, Error> {
+    let from = from.naive_utc();
+    let to = to.naive_utc();
+    sqlx::query_as!(
+        ChannelMessageCount,
+        r#"SELECT MAX(guild_id) as "guild_id: i64", channel_id as "channel_id!",
+            SUM(message_count) as "message_count!: i64"
+        FROM channel_activity
+        WHERE hour >= ? AND hour < ?
+        GROUP BY channel_id"#,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// How many messages a member posted in a channel over a period.
+pub struct AuthorMessageCount {
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub author: String,
+    pub message_count: i64,
+}
+
+/// Fetches the members who posted at least `min_messages` messages in a channel since
+/// `since`, most prolific first.
+pub async fn fetch_prolific_authors(
+    pool: &SqlitePool,
+    since: DateTime<Utc>,
+    min_messages: i64,
+) -> Result<Vec<AuthorMessageCount>, Error> {
+    let since = since.naive_utc();
+    sqlx::query_as!(
+        AuthorMessageCount,
+        r#"SELECT MAX(guild_id) as "guild_id: i64", channel_id as "channel_id!",
+            MAX(author) as "author!: String", COUNT(*) as "message_count!: i64"
+        FROM messages
+        WHERE timestamp >= ?
+        GROUP BY channel_id, COALESCE(CAST(author_id AS TEXT), author)
+        HAVING COUNT(*) >= ?
+        ORDER BY 4 DESC"#,
+        since,
+        min_messages
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A message posted before another, quoted as its context.
+#[derive(Serialize)]
+pub struct ContextMessage {
+    pub message_id: i64,
+    pub author: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// The last `limit` messages logged before the message with row ID `before_id` in the
+/// same channel and thread, oldest first.
+pub async fn fetch_messages_before(
+    pool: &SqlitePool,
+    channel_id: i64,
+    thread_id: Option<i64>,
+    before_id: i64,
+    limit: i64,
+) -> Result<Vec<ContextMessage>, Error> {
+    let mut messages = sqlx::query_as!(
+        ContextMessage,
+        r#"SELECT message_id as "message_id!", author as "author!", content as "content!",
+            timestamp as "timestamp!: DateTime<Utc>"
+        FROM messages
+        WHERE channel_id = ? AND thread_id IS ? AND id < ? AND message_id IS NOT NULL
+            AND deleted_at IS NULL
+        ORDER BY id DESC
+        LIMIT ?"#,
+        channel_id,
+        thread_id,
+        before_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    messages.reverse();
+    Ok(messages)
+}
+
+/// Length of the periods channel activity is added up over.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityGranularity {
+    Hour,
+    #[default]
+    Day,
+}
+
+/// Activity of a channel over an hour or day, in UTC.
+#[derive(Serialize)]
+pub struct ChannelActivity {
+    /// `YYYY-MM-DDTHH:00:00Z` for hourly activity, `YYYY-MM-DD` for daily activity.
+    pub period: String,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub message_count: i64,
+    pub unique_authors: i64,
+    pub token_count: i64,
+}
+
+/// Adds up the activity of the filtered channels within the date range per period and
+/// channel, oldest period first.
+pub async fn fetch_channel_activity(
+    pool: &SqlitePool,
+    granularity: ActivityGranularity,
+    filter: &ContentFilter,
+    range: &DateRange,
+) -> Result<Vec<ChannelActivity>, Error> {
+    let (from, to) = range.bounds();
+    let format = match granularity {
+        ActivityGranularity::Hour => "%Y-%m-%dT%H:00:00Z",
+        ActivityGranularity::Day => "%Y-%m-%d",
+    };
+    sqlx::query_as!(
+        ChannelActivity,
+        r#"WITH volume AS (
+            SELECT strftime(?1, hour) AS period, MAX(guild_id) AS guild_id, channel_id,
+                SUM(message_count) AS message_count, SUM(token_count) AS token_count
+            FROM channel_activity
+            WHERE hour >= ?2 AND hour < ?3 AND (?4 IS NULL OR guild_id = ?4)
+                AND (?5 IS NULL OR channel_id = ?5)
+            GROUP BY 1, channel_id
+        ), authors AS (
+            SELECT strftime(?1, hour) AS period, channel_id,
+                COUNT(DISTINCT author) AS unique_authors
+            FROM channel_activity_authors
+            WHERE hour >= ?2 AND hour < ?3 AND (?5 IS NULL OR channel_id = ?5)
+            GROUP BY 1, channel_id
+        )
+        SELECT v.period as "period!: String", v.guild_id, v.channel_id as "channel_id!",
+            v.message_count as "message_count!: i64",
+            COALESCE(a.unique_authors, 0) as "unique_authors!: i64",
+            v.token_count as "token_count!: i64"
+        FROM volume v
+        LEFT JOIN authors a ON a.period = v.period AND a.channel_id = v.channel_id
+        ORDER BY v.period, v.channel_id"#,
+        format,
+        from,
+        to,
+        filter.guild_id,
+        filter.channel_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Fetches the Discord IDs of the members who opted out of logging.
+pub async fn fetch_opted_out_users(pool: &SqlitePool) -> Result<Vec<i64>, Error> {
+    sqlx::query_scalar!(r#"SELECT user_id as "user_id!" FROM opted_out_users"#)
+        .fetch_all(pool)
+        .await
+}
+
+/// Records that a member opted out of logging. Opting out again changes nothing.
+pub async fn insert_opted_out_user(pool: &SqlitePool, user_id: i64) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO opted_out_users (user_id) VALUES (?) ON CONFLICT (user_id) DO NOTHING",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// What deleting a member's messages removed.
+pub struct DeletedMessages {
+    pub count: u64,
+    /// Summaries that were written from some of the deleted messages.
+    pub summary_ids: Vec<i64>,
+}
+
+/// Deletes every message of a member, along with their attachments and highlights, the
+/// questions and links found in them and what channel activity knows of who they are.
+/// Messages stored before author IDs were recorded are matched by `author` name.
+pub async fn delete_user_messages(
+    pool: &SqlitePool,
+    user_id: i64,
+    author: Option<&str>,
+) -> Result<DeletedMessages, Error> {
+    let mut transaction = pool.begin().await?;
+    let summary_ids = sqlx::query_scalar!(
+        r#"SELECT DISTINCT summary_id as "summary_id!" FROM messages
+        WHERE (author_id = ?1 OR (author_id IS NULL AND author = ?2)) AND summary_id IS NOT NULL"#,
+        user_id,
+        author
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM questions WHERE message_id IN (
+            SELECT id FROM messages WHERE author_id = ?1 OR (author_id IS NULL AND author = ?2))",
+        user_id,
+        author
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM shared_links WHERE message_id IN (
+            SELECT id FROM messages WHERE author_id = ?1 OR (author_id IS NULL AND author = ?2))",
+        user_id,
+        author
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM message_attachments WHERE message_id IN (
+            SELECT id FROM messages WHERE author_id = ?1 OR (author_id IS NULL AND author = ?2))",
+        user_id,
+        author
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM highlights WHERE message_id IN (
+            SELECT id FROM messages WHERE author_id = ?1 OR (author_id IS NULL AND author = ?2))",
+        user_id,
+        author
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let user_key = user_id.to_string();
+    sqlx::query!(
+        "DELETE FROM channel_activity_authors WHERE author = ?1 OR author = ?2",
+        user_key,
+        author
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let count = sqlx::query!(
+        "DELETE FROM messages WHERE author_id = ?1 OR (author_id IS NULL AND author = ?2)",
+        user_id,
+        author
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    transaction.commit().await?;
+    Ok(DeletedMessages { count, summary_ids })
+}
+
+/// Replaces a summary with one written again, along with its action items. Its
+/// embedding is dropped so that the embedding service embeds the new text.
+pub async fn replace_summary(
+    pool: &SqlitePool,
+    summary_id: i64,
+    summary: NewSummary<'_>,
+) -> Result<(), Error> {
+    let covers_from = summary.covers_from.map(|t| t.naive_utc());
+    let covers_to = summary.covers_to.map(|t| t.naive_utc());
+    let details = summary.summary;
+    let topics = Json(&details.topics);
+    let decisions = Json(&details.decisions);
+    let action_items = Json(&details.action_items);
+    let open_questions = Json(&details.open_questions);
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "UPDATE summaries SET text = ?, message_count = ?, covers_from = ?, covers_to = ?,
+            topics = ?, decisions = ?, action_items = ?, open_questions = ?, backend = ?,
+            confidence = NULL, unverified_references = NULL
+        WHERE id = ?",
+        details.summary,
+        summary.message_count,
+        covers_from,
+        covers_to,
+        topics,
+        decisions,
+        action_items,
+        open_questions,
+        details.backend,
+        summary_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!("DELETE FROM action_items WHERE summary_id = ?", summary_id)
+        .execute(&mut *transaction)
+        .await?;
+    for item in &details.action_items {
+        sqlx::query!(
+            "INSERT INTO action_items (summary_id, guild_id, channel_id, description, assignee)
+            VALUES (?, ?, ?, ?, ?)",
+            summary_id,
+            summary.guild_id,
+            summary.channel_id,
+            item.description,
+            item.owner
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    sqlx::query!("DELETE FROM entities WHERE summary_id = ?", summary_id)
+        .execute(&mut *transaction)
+        .await?;
+    insert_entities(
+        &mut transaction,
+        summary_id,
+        summary.guild_id,
+        summary.channel_id,
+        &details.entities,
+    )
+    .await?;
+
+    let kind = ContentKind::Summary.as_str();
+    sqlx::query!(
+        "DELETE FROM embeddings WHERE content_kind = ? AND content_id = ?",
+        kind,
+        summary_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// A member's subscription to the daily digests of a guild.
+pub struct DigestSubscription {
+    pub user_id: i64,
+    pub guild_id: i64,
+    /// Hour of the day, in the reporting timezone, digests are sent at. As soon as
+    /// they are produced when `None`.
+    pub delivery_hour: Option<i64>,
+    /// Channels the member only wants the summaries of, every channel when empty.
+    pub channel_ids: Vec<i64>,
+}
+
+pub async fn fetch_digest_subscription(
+    pool: &SqlitePool,
+    user_id: i64,
+    guild_id: i64,
+) -> Result<Option<DigestSubscription>, Error> {
+    let subscription = sqlx::query!(
+        r#"SELECT delivery_hour,
+            (SELECT json_group_array(channel_id) FROM digest_subscription_channels c
+                WHERE c.user_id = s.user_id AND c.guild_id = s.guild_id)
+                as "channel_ids!: Json<Vec<i64>>"
+        FROM digest_subscriptions s
+        WHERE user_id = ? AND guild_id = ?"#,
+        user_id,
+        guild_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(subscription.map(|row| DigestSubscription {
+        user_id,
+        guild_id,
+        delivery_hour: row.delivery_hour,
+        channel_ids: row.channel_ids.0,
+    }))
+}
+
+/// Creates or updates a subscription. New subscriptions start after the guild's newest
+/// daily digest, so that only digests produced from then on are sent.
+pub async fn save_digest_subscription(
+    pool: &SqlitePool,
+    subscription: &DigestSubscription,
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "INSERT INTO digest_subscriptions (user_id, guild_id, delivery_hour, last_digest_id)
+        VALUES (?1, ?2, ?3, (SELECT MAX(id) FROM daily_digests WHERE guild_id = ?2))
+        ON CONFLICT (user_id, guild_id) DO UPDATE SET delivery_hour = excluded.delivery_hour",
+        subscription.user_id,
+        subscription.guild_id,
+        subscription.delivery_hour
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM digest_subscription_channels WHERE user_id = ? AND guild_id = ?",
+        subscription.user_id,
+        subscription.guild_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    for channel_id in &subscription.channel_ids {
+        sqlx::query!(
+            "INSERT INTO digest_subscription_channels (user_id, guild_id, channel_id)
+            VALUES (?, ?, ?)",
+            subscription.user_id,
+            subscription.guild_id,
+            channel_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Deletes a subscription, returning whether there was one.
+pub async fn delete_digest_subscription(
+    pool: &SqlitePool,
+    user_id: i64,
+    guild_id: i64,
+) -> Result<bool, Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "DELETE FROM digest_subscription_channels WHERE user_id = ? AND guild_id = ?",
+        user_id,
+        guild_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM digest_subscriptions WHERE user_id = ? AND guild_id = ?",
+        user_id,
+        guild_id
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    transaction.commit().await?;
+    Ok(deleted > 0)
+}
+
+/// A subscription whose guild has a daily digest it was not sent yet.
+pub struct PendingDelivery {
+    pub subscription: DigestSubscription,
+    /// The guild's newest daily digest, and when it was produced.
+    pub digest_id: i64,
+    pub digest_timestamp: DateTime<Utc>,
+}
+
+/// Subscriptions that were not sent their guild's newest daily digest yet, whether or
+/// not it is time to send it.
+pub async fn fetch_pending_deliveries(pool: &SqlitePool) -> Result<Vec<PendingDelivery>, Error> {
+    let rows = sqlx::query!(
+        r#"SELECT s.user_id as "user_id!", s.guild_id as "guild_id!", s.delivery_hour,
+            (SELECT json_group_array(channel_id) FROM digest_subscription_channels c
+                WHERE c.user_id = s.user_id AND c.guild_id = s.guild_id)
+                as "channel_ids!: Json<Vec<i64>>",
+            d.id as "digest_id!", d.timestamp as "digest_timestamp!: DateTime<Utc>"
+        FROM digest_subscriptions s
+        JOIN daily_digests d
+            ON d.id = (SELECT MAX(id) FROM daily_digests WHERE guild_id = s.guild_id)
+        WHERE d.id > COALESCE(s.last_digest_id, 0)"#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| PendingDelivery {
+            subscription: DigestSubscription {
+                user_id: row.user_id,
+                guild_id: row.guild_id,
+                delivery_hour: row.delivery_hour,
+                channel_ids: row.channel_ids.0,
+            },
+            digest_id: row.digest_id,
+            digest_timestamp: row.digest_timestamp,
+        })
+        .collect())
+}
+
+/// Records the newest digest a subscriber was sent, or gave up on sending them.
+pub async fn set_subscription_last_digest(
+    pool: &SqlitePool,
+    user_id: i64,
+    guild_id: i64,
+    digest_id: i64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE digest_subscriptions SET last_digest_id = ? WHERE user_id = ? AND guild_id = ?",
+        digest_id,
+        user_id,
+        guild_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Records that a daily digest was emailed to a recipient, or that sending it failed
+/// with `error`.
+pub async fn insert_email_delivery(
+    pool: &SqlitePool,
+    daily_digest_id: i64,
+    recipient: &str,
+    error: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO email_deliveries (daily_digest_id, recipient, status, last_error)
+        VALUES (?1, ?2, CASE WHEN ?3 IS NULL THEN 'delivered' ELSE 'failed' END, ?3)",
+        daily_digest_id,
+        recipient,
+        error
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn upsert_ingest_channel(
+    pool: &SqlitePool,
+    channel_id: i64,
+    name: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO ingest_channels (channel_id, name) VALUES (?1, ?2)
+        ON CONFLICT (channel_id) DO UPDATE SET name = excluded.name",
+        channel_id,
+        name
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_ingest_channel_name(
+    pool: &SqlitePool,
+    channel_id: i64,
+) -> Result<Option<String>, Error> {
+    let name = sqlx::query_scalar!(
+        "SELECT name FROM ingest_channels WHERE channel_id = ?1",
+        channel_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(name)
+}
+
+/// Where a scheduled event stands.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum ScheduledEventStatus {
+    Scheduled,
+    Active,
+    Completed,
+    Canceled,
+}
+
+/// A scheduled event of a guild, as Discord last reported it.
+pub struct NewScheduledEvent {
+    pub event_id: i64,
+    pub guild_id: i64,
+    pub channel_id: Option<i64>,
+    pub name: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub status: ScheduledEventStatus,
+    pub user_count: Option<i64>,
+}
+
+/// A scheduled event that concluded, as daily digests summarize it.
+pub struct ConcludedEvent {
+    pub event_id: i64,
+    pub channel_id: Option<i64>,
+    pub name: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    /// When it went live, or was scheduled to when that was missed.
+    pub started_at: DateTime<Utc>,
+    pub concluded_at: DateTime<Utc>,
+    pub user_count: Option<i64>,
+}
+
+/// Stores the latest state of a scheduled event, along with when it went live and
+/// concluded, the first time it is seen doing so.
+pub async fn upsert_scheduled_event(
+    pool: &SqlitePool,
+    event: &NewScheduledEvent,
+) -> Result<(), Error> {
+    let starts_at = event.starts_at.naive_utc();
+    let ends_at = event.ends_at.map(|ends_at| ends_at.naive_utc());
+    sqlx::query!(
+        "INSERT INTO scheduled_events (event_id, guild_id, channel_id, name, description,
+            location, starts_at, ends_at, status, user_count, started_at, concluded_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+            CASE WHEN ?9 = 'active' THEN CURRENT_TIMESTAMP END,
+            CASE WHEN ?9 = 'completed' THEN CURRENT_TIMESTAMP END)
+        ON CONFLICT (event_id) DO UPDATE SET
+            channel_id = excluded.channel_id,
+            name = excluded.name,
+            description = excluded.description,
+            location = excluded.location,
+            starts_at = excluded.starts_at,
+            ends_at = excluded.ends_at,
+            status = excluded.status,
+            user_count = COALESCE(excluded.user_count, scheduled_events.user_count),
+            started_at = COALESCE(scheduled_events.started_at, excluded.started_at),
+            concluded_at = COALESCE(scheduled_events.concluded_at, excluded.concluded_at),
+            updated_at = CURRENT_TIMESTAMP",
+        event.event_id,
+        event.guild_id,
+        event.channel_id,
+        event.name,
+        event.description,
+        event.location,
+        starts_at,
+        ends_at,
+        event.status,
+        event.user_count,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Marks a scheduled event as canceled, which Discord deletes events on.
+pub async fn cancel_scheduled_event(pool: &SqlitePool, event_id: i64) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE scheduled_events SET status = 'canceled', updated_at = CURRENT_TIMESTAMP
+        WHERE event_id = ?1 AND status != 'completed'",
+        event_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the scheduled events of a guild that concluded before `before` and that no
+/// daily digest summarized yet, in the order they concluded.
+pub async fn fetch_unlisted_concluded_events(
+    pool: &SqlitePool,
+    guild_id: i64,
+    before: DateTime<Utc>,
+) -> Result<Vec<ConcludedEvent>, Error> {
+    let before = before.naive_utc();
+    sqlx::query_as!(
+        ConcludedEvent,
+        r#"SELECT event_id as "event_id!", channel_id, name, description, location,
+            COALESCE(started_at, starts_at) as "started_at!: DateTime<Utc>",
+            concluded_at as "concluded_at!: DateTime<Utc>", user_count
+        FROM scheduled_events
+        WHERE guild_id = ?1 AND status = 'completed' AND daily_digest_id IS NULL
+            AND concluded_at <= ?2
+        ORDER BY concluded_at, event_id"#,
+        guild_id,
+        before
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Records that a daily digest summarized the given scheduled events.
+pub async fn link_scheduled_events_to_digest(
+    pool: &SqlitePool,
+    event_ids: &[i64],
+    daily_digest_id: i64,
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    for event_id in event_ids {
+        sqlx::query!(
+            "UPDATE scheduled_events SET daily_digest_id = ? WHERE event_id = ?",
+            daily_digest_id,
+            event_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await
+}
+
+/// Fetches the text of the summaries of a channel covering any part of the time from
+/// `from` to `to`, oldest first.
+pub async fn fetch_channel_summary_texts(
+    pool: &SqlitePool,
+    channel_id: i64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<String>, Error> {
+    let from = from.naive_utc();
+    let to = to.naive_utc();
+    sqlx::query_scalar!(
+        "SELECT text FROM summaries
+        WHERE channel_id = ?1 AND covers_to >= ?2 AND covers_from <= ?3
+        ORDER BY covers_from, id",
+        channel_id,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// What one person said in a standup.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StandupEntry {
+    pub person: String,
+    #[serde(default)]
+    pub yesterday: Vec<String>,
+    #[serde(default)]
+    pub today: Vec<String>,
+    #[serde(default)]
+    pub blockers: Vec<String>,
+}
+
+/// A standup whose replies are still being collected, or were not summarized yet.
+pub struct OpenStandup {
+    pub id: i64,
+    pub channel_id: i64,
+    pub prompt_message_id: i64,
+    pub opened_at: DateTime<Utc>,
+    pub closes_at: DateTime<Utc>,
+}
+
+pub async fn insert_standup(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    channel_id: i64,
+    prompt_message_id: i64,
+    opened_at: DateTime<Utc>,
+    closes_at: DateTime<Utc>,
+) -> Result<i64, Error> {
+    let opened_at = opened_at.naive_utc();
+    let closes_at = closes_at.naive_utc();
+    let id = sqlx::query!(
+        "INSERT INTO standups (guild_id, channel_id, prompt_message_id, opened_at, closes_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        guild_id,
+        channel_id,
+        prompt_message_id,
+        opened_at,
+        closes_at
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+    Ok(id)
+}
+
+/// Fetches the standups that were not summarized yet, the first to close first.
+pub async fn fetch_open_standups(pool: &SqlitePool) -> Result<Vec<OpenStandup>, Error> {
+    sqlx::query_as!(
+        OpenStandup,
+        r#"SELECT id as "id!", channel_id, prompt_message_id,
+            opened_at as "opened_at: DateTime<Utc>", closes_at as "closes_at: DateTime<Utc>"
+        FROM standups
+        WHERE summarized_at IS NULL
+        ORDER BY closes_at, id"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Stores the summary of a standup, which closes it.
+pub async fn complete_standup(
+    pool: &SqlitePool,
+    id: i64,
+    reply_count: i64,
+    entries: &[StandupEntry],
+    summary: &str,
+) -> Result<(), Error> {
+    let entries = Json(entries);
+    sqlx::query!(
+        "UPDATE standups
+        SET reply_count = ?2, entries = ?3, summary = ?4, summarized_at = CURRENT_TIMESTAMP
+        WHERE id = ?1",
+        id,
+        reply_count,
+        entries,
+        summary
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A summary that was not mined for frequently asked questions yet.
+pub struct FaqSource {
+    pub id: i64,
+    pub guild_id: Option<i64>,
+    pub text: String,
+}
+
+/// Fetches up to `limit` summaries that were not mined for frequently asked questions
+/// yet, oldest first.
+pub async fn fetch_unmined_faq_sources(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<FaqSource>, Error> {
+    sqlx::query_as!(
+        FaqSource,
+        r#"SELECT id as "id!", guild_id, text
+        FROM summaries
+        WHERE faq_mined_at IS NULL
+        ORDER BY id
+        LIMIT ?1"#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A frequently asked question and its answer.
+#[derive(Serialize)]
+pub struct FaqEntry {
+    pub id: i64,
+    pub guild_id: Option<i64>,
+    pub question: String,
+    pub answer: String,
+    /// How many batches of summaries the question came up in.
+    pub asked_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Restricts fetched frequently asked questions to a guild and/or those mentioning
+/// some text.
+#[derive(Deserialize, Default)]
+pub struct FaqFilter {
+    pub guild_id: Option<i64>,
+    pub search: Option<String>,
+}
+
+/// Fetches the frequently asked questions matching the filter, the most asked first.
+pub async fn fetch_faq(pool: &SqlitePool, filter: &FaqFilter) -> Result<Vec<FaqEntry>, Error> {
+    let search = filter
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    sqlx::query_as!(
+        FaqEntry,
+        r#"SELECT id as "id!", guild_id, question, answer, asked_count,
+            created_at as "created_at: DateTime<Utc>", updated_at as "updated_at: DateTime<Utc>"
+        FROM faq
+        WHERE (?1 IS NULL OR guild_id = ?1)
+            AND (?2 IS NULL OR question LIKE '%' || ?2 || '%' OR answer LIKE '%' || ?2 || '%')
+        ORDER BY asked_count DESC, updated_at DESC, id"#,
+        filter.guild_id,
+        search
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Fetches every frequently asked question of a guild, or of messages outside of any
+/// guild when `guild_id` is `None`, oldest first.
+pub async fn fetch_guild_faq(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+) -> Result<Vec<FaqEntry>, Error> {
+    sqlx::query_as!(
+        FaqEntry,
+        r#"SELECT id as "id!", guild_id, question, answer, asked_count,
+            created_at as "created_at: DateTime<Utc>", updated_at as "updated_at: DateTime<Utc>"
+        FROM faq
+        WHERE guild_id IS ?1
+        ORDER BY id"#,
+        guild_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A question found while mining summaries: a new one, or one already in the FAQ that
+/// was asked again, along with its latest answer.
+pub struct FaqUpdate {
+    /// ID of the question in the FAQ, when it was asked before.
+    pub id: Option<i64>,
+    pub question: String,
+    pub answer: String,
+}
+
+/// Stores the questions found in a guild's summaries and marks the summaries as mined,
+/// all at once. Updates of questions of other guilds are stored as new questions.
+pub async fn apply_faq_updates(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    updates: &[FaqUpdate],
+    summary_ids: &[i64],
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    for update in updates {
+        let updated = match update.id {
+            Some(id) => {
+                sqlx::query!(
+                    "UPDATE faq
+                SET question = ?1, answer = ?2, asked_count = asked_count + 1,
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?3 AND guild_id IS ?4",
+                    update.question,
+                    update.answer,
+                    id,
+                    guild_id
+                )
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected()
+                    > 0
+            }
+            None => false,
+        };
+        if !updated {
+            sqlx::query!(
+                "INSERT INTO faq (guild_id, question, answer) VALUES (?1, ?2, ?3)",
+                guild_id,
+                update.question,
+                update.answer
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    let summary_ids = serde_json::to_string(summary_ids).unwrap_or_default();
+    sqlx::query!(
+        "UPDATE summaries SET faq_mined_at = CURRENT_TIMESTAMP
+        WHERE id IN (SELECT value FROM json_each(?1))",
+        summary_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await
+}
+
+/// A summary a knowledge base article can be written from.
+pub struct TopicSummary {
+    pub id: i64,
+    pub daily_digest_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Fetches the `limit` most recent summaries whose text or topics mention `topic`, of a
+/// guild when given, oldest first.
+pub async fn fetch_topic_summaries(
+    pool: &SqlitePool,
+    topic: &str,
+    guild_id: Option<i64>,
+    limit: i64,
+) -> Result<Vec<TopicSummary>, Error> {
+    let mut summaries = sqlx::query_as!(
+        TopicSummary,
+        r#"SELECT id as "id!", daily_digest_id, channel_id, text,
+            timestamp as "timestamp: DateTime<Utc>"
+        FROM summaries
+        WHERE (?2 IS NULL OR guild_id = ?2)
+            AND (text LIKE '%' || ?1 || '%'
+                OR EXISTS (SELECT 1 FROM json_each(summaries.topics) t
+                    WHERE t.value LIKE '%' || ?1 || '%'))
+        ORDER BY id DESC
+        LIMIT ?3"#,
+        topic,
+        guild_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    summaries.reverse();
+    Ok(summaries)
+}
+
+/// A summary a knowledge base article was written from.
+#[derive(Serialize)]
+pub struct KbArticleSource {
+    pub summary_id: i64,
+    /// The daily digest the summary was rolled up into, if any yet.
+    pub daily_digest_id: Option<i64>,
+}
+
+/// An article of the knowledge base, along with the summaries it was written from.
+#[derive(Serialize)]
+pub struct KbArticle {
+    pub id: i64,
+    pub guild_id: Option<i64>,
+    pub topic: String,
+    pub title: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub sources: Vec<KbArticleSource>,
+}
+
+pub struct NewKbArticle<'a> {
+    pub guild_id: Option<i64>,
+    pub topic: &'a str,
+    pub title: &'a str,
+    pub content: &'a str,
+    pub sources: &'a [TopicSummary],
+}
+
+/// Stores an article along with the summaries it was written from, returning its ID.
+pub async fn insert_kb_article(
+    pool: &SqlitePool,
+    article: &NewKbArticle<'_>,
+) -> Result<i64, Error> {
+    let mut transaction = pool.begin().await?;
+    let article_id = sqlx::query!(
+        "INSERT INTO kb_articles (guild_id, topic, title, content) VALUES (?, ?, ?, ?)",
+        article.guild_id,
+        article.topic,
+        article.title,
+        article.content
+    )
+    .execute(&mut *transaction)
+    .await?
+    .last_insert_rowid();
+    for source in article.sources {
+        sqlx::query!(
+            "INSERT INTO kb_article_sources (article_id, summary_id, daily_digest_id)
+            VALUES (?, ?, ?)",
+            article_id,
+            source.id,
+            source.daily_digest_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(article_id)
+}
+
+struct KbArticleRow {
+    id: i64,
+    guild_id: Option<i64>,
+    topic: String,
+    title: String,
+    content: String,
+    created_at: DateTime<Utc>,
+}
+
+/// Fetches the articles of a guild when given, or of every guild, most recent first.
+pub async fn fetch_kb_articles(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+) -> Result<Vec<KbArticle>, Error> {
+    let rows = sqlx::query_as!(
+        KbArticleRow,
+        r#"SELECT id as "id!", guild_id, topic, title, content,
+            created_at as "created_at: DateTime<Utc>"
+        FROM kb_articles
+        WHERE ?1 IS NULL OR guild_id = ?1
+        ORDER BY created_at DESC, id DESC"#,
+        guild_id
+    )
+    .fetch_all(pool)
+    .await?;
+    with_kb_sources(pool, rows).await
+}
+
+pub async fn fetch_kb_article(pool: &SqlitePool, id: i64) -> Result<Option<KbArticle>, Error> {
+    let row = sqlx::query_as!(
+        KbArticleRow,
+        r#"SELECT id as "id!", guild_id, topic, title, content,
+            created_at as "created_at: DateTime<Utc>"
+        FROM kb_articles
+        WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(with_kb_sources(pool, row.into_iter().collect())
+        .await?
+        .pop())
+}
+
+/// Attaches their sources to articles.
+async fn with_kb_sources(
+    pool: &SqlitePool,
+    rows: Vec<KbArticleRow>,
+) -> Result<Vec<KbArticle>, Error> {
+    let article_ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+    let article_ids = serde_json::to_string(&article_ids).unwrap_or_default();
+    let sources = sqlx::query!(
+        r#"SELECT article_id, summary_id, daily_digest_id
+        FROM kb_article_sources
+        WHERE article_id IN (SELECT value FROM json_each(?1))
+        ORDER BY article_id, summary_id"#,
+        article_ids
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut sources_by_article: HashMap<i64, Vec<KbArticleSource>> = HashMap::new();
+    for source in sources {
+        sources_by_article
+            .entry(source.article_id)
+            .or_default()
+            .push(KbArticleSource {
+                summary_id: source.summary_id,
+                daily_digest_id: source.daily_digest_id,
+            });
+    }
+    Ok(rows
+        .into_iter()
+        .map(|row| KbArticle {
+            sources: sources_by_article.remove(&row.id).unwrap_or_default(),
+            id: row.id,
+            guild_id: row.guild_id,
+            topic: row.topic,
+            title: row.title,
+            content: row.content,
+            created_at: row.created_at,
+        })
+        .collect())
+}
+
+/// Lowercases the name of an entity or glossary term and collapses its whitespace, so
+/// that differently written mentions of the same one are found together.
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Stores the entities of a summary, dated like the summary.
+async fn insert_entities(
+    connection: &mut SqliteConnection,
+    summary_id: i64,
+    guild_id: Option<i64>,
+    channel_id: i64,
+    entities: &[Entity],
+) -> Result<(), Error> {
+    for entity in entities {
+        let normalized_name = normalize_name(&entity.name);
+        sqlx::query!(
+            "INSERT INTO entities (summary_id, guild_id, channel_id, name, normalized_name, kind, timestamp)
+            SELECT ?1, ?2, ?3, ?4, ?5, ?6, timestamp FROM summaries WHERE id = ?1",
+            summary_id,
+            guild_id,
+            channel_id,
+            entity.name,
+            normalized_name,
+            entity.kind
+        )
+        .execute(&mut *connection)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Restricts fetched entities and their mentions to a guild, a kind and/or a time range.
+#[derive(Deserialize, Default)]
+pub struct EntityFilter {
+    pub guild_id: Option<i64>,
+    pub kind: Option<EntityKind>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// An entity along with how often summaries mention it.
+#[derive(Serialize)]
+pub struct EntityCount {
+    /// The name the entity was last mentioned by.
+    pub name: String,
+    pub kind: EntityKind,
+    pub mention_count: i64,
+    pub last_mentioned_at: DateTime<Utc>,
+}
+
+/// Fetches the `limit` entities mentioned by the most summaries matching the filter.
+pub async fn fetch_entities(
+    pool: &SqlitePool,
+    filter: &EntityFilter,
+    limit: i64,
+) -> Result<Vec<EntityCount>, Error> {
+    let (from, to) = DateRange {
+        from: filter.from,
+        to: filter.to,
+    }
+    .bounds();
+    sqlx::query_as!(
+        EntityCount,
+        r#"SELECT
+            (SELECT e.name FROM entities e WHERE e.normalized_name = entities.normalized_name
+                ORDER BY e.timestamp DESC, e.id DESC LIMIT 1) as "name!: String",
+            kind as "kind!: EntityKind",
+            COUNT(DISTINCT summary_id) as "mention_count!: i64",
+            MAX(timestamp) as "last_mentioned_at!: DateTime<Utc>"
+        FROM entities
+        WHERE (?1 IS NULL OR guild_id = ?1) AND (?2 IS NULL OR kind = ?2)
+            AND timestamp >= ?3 AND timestamp < ?4
+        GROUP BY normalized_name, kind
+        ORDER BY 3 DESC, 4 DESC
+        LIMIT ?5"#,
+        filter.guild_id,
+        filter.kind,
+        from,
+        to,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A summary mentioning an entity.
+#[derive(Serialize)]
+pub struct EntityMention {
+    pub summary_id: i64,
+    pub daily_digest_id: Option<i64>,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    /// The name the summary mentions the entity by.
+    pub name: String,
+    pub kind: EntityKind,
+    pub timestamp: DateTime<Utc>,
+    pub summary: String,
+}
+
+/// Fetches the summaries matching the filter that mention an entity, however its name
+/// is capitalized, oldest first.
+pub async fn fetch_entity_mentions(
+    pool: &SqlitePool,
+    name: &str,
+    filter: &EntityFilter,
+) -> Result<Vec<EntityMention>, Error> {
+    let normalized_name = normalize_name(name);
+    let (from, to) = DateRange {
+        from: filter.from,
+        to: filter.to,
+    }
+    .bounds();
+    sqlx::query_as!(
+        EntityMention,
+        r#"SELECT entities.summary_id, summaries.daily_digest_id, entities.guild_id,
+            entities.channel_id, entities.name, entities.kind as "kind: EntityKind",
+            entities.timestamp as "timestamp: DateTime<Utc>", summaries.text as summary
+        FROM entities
+        JOIN summaries ON summaries.id = entities.summary_id
+        WHERE entities.normalized_name = ?1
+            AND (?2 IS NULL OR entities.guild_id = ?2) AND (?3 IS NULL OR entities.kind = ?3)
+            AND entities.timestamp >= ?4 AND entities.timestamp < ?5
+        ORDER BY entities.timestamp, entities.id"#,
+        normalized_name,
+        filter.guild_id,
+        filter.kind,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A summary that was not mined for jargon yet.
+pub struct GlossarySource {
+    pub id: i64,
+    pub guild_id: Option<i64>,
+    pub text: String,
+}
+
+/// Fetches up to `limit` summaries that were not mined for jargon yet, oldest first.
+pub async fn fetch_unmined_glossary_sources(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<GlossarySource>, Error> {
+    sqlx::query_as!(
+        GlossarySource,
+        r#"SELECT id as "id!", guild_id, text
+        FROM summaries
+        WHERE glossary_mined_at IS NULL
+        ORDER BY id
+        LIMIT ?1"#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A term of a guild's jargon and its definition.
+#[derive(Serialize)]
+pub struct GlossaryTerm {
+    pub id: i64,
+    pub guild_id: Option<i64>,
+    pub term: String,
+    pub definition: String,
+    /// How many batches of summaries the term came up in.
+    pub mention_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Restricts fetched glossary terms to a guild and/or those mentioning some text.
+#[derive(Deserialize, Default)]
+pub struct GlossaryFilter {
+    pub guild_id: Option<i64>,
+    pub search: Option<String>,
+}
+
+/// Fetches the glossary terms matching the filter, in alphabetical order.
+pub async fn fetch_glossary(
+    pool: &SqlitePool,
+    filter: &GlossaryFilter,
+) -> Result<Vec<GlossaryTerm>, Error> {
+    let search = filter
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    sqlx::query_as!(
+        GlossaryTerm,
+        r#"SELECT id as "id!", guild_id, term, definition, mention_count,
+            created_at as "created_at: DateTime<Utc>", updated_at as "updated_at: DateTime<Utc>"
+        FROM glossary
+        WHERE (?1 IS NULL OR guild_id = ?1)
+            AND (?2 IS NULL OR term LIKE '%' || ?2 || '%' OR definition LIKE '%' || ?2 || '%')
+        ORDER BY normalized_term, id"#,
+        filter.guild_id,
+        search
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Fetches every glossary term of a guild, or of messages outside of any guild when
+/// `guild_id` is `None`, the most mentioned first.
+pub async fn fetch_guild_glossary(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+) -> Result<Vec<GlossaryTerm>, Error> {
+    sqlx::query_as!(
+        GlossaryTerm,
+        r#"SELECT id as "id!", guild_id, term, definition, mention_count,
+            created_at as "created_at: DateTime<Utc>", updated_at as "updated_at: DateTime<Utc>"
+        FROM glossary
+        WHERE guild_id IS ?1
+        ORDER BY mention_count DESC, id"#,
+        guild_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// A term found while mining summaries, along with its latest definition.
+pub struct GlossaryUpdate {
+    pub term: String,
+    pub definition: String,
+}
+
+/// Stores the terms found in a guild's summaries and marks the summaries as mined, all
+/// at once. Terms already in the glossary, however they are capitalized, get the new
+/// definition.
+pub async fn apply_glossary_updates(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    updates: &[GlossaryUpdate],
+    summary_ids: &[i64],
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    for update in updates {
+        let normalized_term = normalize_name(&update.term);
+        let updated = sqlx::query!(
+            "UPDATE glossary
+            SET term = ?1, definition = ?2, mention_count = mention_count + 1,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE guild_id IS ?3 AND normalized_term = ?4",
+            update.term,
+            update.definition,
+            guild_id,
+            normalized_term
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            sqlx::query!(
+                "INSERT INTO glossary (guild_id, term, normalized_term, definition)
+                VALUES (?1, ?2, ?3, ?4)",
+                guild_id,
+                update.term,
+                normalized_term,
+                update.definition
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    let summary_ids = serde_json::to_string(summary_ids).unwrap_or_default();
+    sqlx::query!(
+        "UPDATE summaries SET glossary_mined_at = CURRENT_TIMESTAMP
+        WHERE id IN (SELECT value FROM json_each(?1))",
+        summary_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await
+}
+
+/// Fetches the terms of a guild added to the glossary before `before` that no weekly
+/// digest listed yet, in alphabetical order.
+pub async fn fetch_unlisted_glossary_terms(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+    before: DateTime<Utc>,
+) -> Result<Vec<GlossaryTerm>, Error> {
+    let before = before.naive_utc();
+    sqlx::query_as!(
+        GlossaryTerm,
+        r#"SELECT id as "id!", guild_id, term, definition, mention_count,
+            created_at as "created_at: DateTime<Utc>", updated_at as "updated_at: DateTime<Utc>"
+        FROM glossary
+        WHERE guild_id IS ?1 AND weekly_digest_id IS NULL AND created_at < ?2
+        ORDER BY normalized_term, id"#,
+        guild_id,
+        before
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Records the weekly digest that listed glossary terms as new.
+pub async fn link_glossary_terms_to_digest(
+    pool: &SqlitePool,
+    term_ids: &[i64],
+    weekly_digest_id: i64,
+) -> Result<(), Error> {
+    let term_ids = serde_json::to_string(term_ids).unwrap_or_default();
+    sqlx::query!(
+        "UPDATE glossary SET weekly_digest_id = ?1
+        WHERE id IN (SELECT value FROM json_each(?2))",
+        weekly_digest_id,
+        term_ids
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The latest daily digest of a guild, or of messages outside of any guild when
+/// `guild_id` is `None`, as its ID and text.
+pub async fn fetch_latest_daily_digest_text(
+    pool: &SqlitePool,
+    guild_id: Option<i64>,
+) -> Result<Option<(i64, String)>, Error> {
+    let digest = sqlx::query!(
+        r#"SELECT id as "id!", text FROM daily_digests
+        WHERE guild_id IS ?1
+        ORDER BY id DESC
+        LIMIT 1"#,
+        guild_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(digest.map(|digest| (digest.id, digest.text)))
+}
+
+/// What changed in a daily digest compared to the previous one of its guild.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DigestChanges {
+    /// Topics the previous digest did not mention.
+    #[serde(default)]
+    pub new_topics: Vec<String>,
+    /// Issues of the previous digest that got resolved.
+    #[serde(default)]
+    pub resolved: Vec<String>,
+    /// Discussions of the previous digest that went on.
+    #[serde(default)]
+    pub continuing: Vec<String>,
+}
+
+/// The changes of a stored daily digest.
+#[derive(Serialize)]
+pub struct StoredDigestChanges {
+    pub daily_digest_id: i64,
+    pub previous_digest_id: i64,
+    #[serde(flatten)]
+    pub changes: DigestChanges,
+    pub created_at: DateTime<Utc>,
+}
+
+pub async fn insert_digest_changes(
+    pool: &SqlitePool,
+    daily_digest_id: i64,
+    previous_digest_id: i64,
+    changes: &DigestChanges,
+) -> Result<(), Error> {
+    let new_topics = Json(&changes.new_topics);
+    let resolved = Json(&changes.resolved);
+    let continuing = Json(&changes.continuing);
+    sqlx::query!(
+        "INSERT INTO digest_changes (daily_digest_id, previous_digest_id, new_topics, resolved, continuing)
+        VALUES (?, ?, ?, ?, ?)",
+        daily_digest_id,
+        previous_digest_id,
+        new_topics,
+        resolved,
+        continuing
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_digest_changes(
+    pool: &SqlitePool,
+    daily_digest_id: i64,
+) -> Result<Option<StoredDigestChanges>, Error> {
+    let row = sqlx::query!(
+        r#"SELECT daily_digest_id as "daily_digest_id!", previous_digest_id,
+            new_topics as "new_topics: Json<Vec<String>>",
+            resolved as "resolved: Json<Vec<String>>",
+            continuing as "continuing: Json<Vec<String>>",
+            created_at as "created_at: DateTime<Utc>"
+        FROM digest_changes
+        WHERE daily_digest_id = ?"#,
+        daily_digest_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| StoredDigestChanges {
+        daily_digest_id: row.daily_digest_id,
+        previous_digest_id: row.previous_digest_id,
+        changes: DigestChanges {
+            new_topics: row.new_topics.0,
+            resolved: row.resolved.0,
+            continuing: row.continuing.0,
+        },
+        created_at: row.created_at,
+    }))
+}
+
+/// A digest of the summaries of the channels of a channel group, written along with the
+/// daily digest they were rolled up into.
+#[derive(Serialize)]
+pub struct GroupDigest {
+    pub id: i64,
+    pub daily_digest_id: i64,
+    pub group_name: String,
+    pub guild_id: Option<i64>,
+    pub text: String,
+    pub message_count: i64,
+    /// The channels of the group that were summarized.
+    pub channel_ids: Json<Vec<i64>>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct NewGroupDigest<'a> {
+    pub daily_digest_id: i64,
+    pub group_name: &'a str,
+    pub guild_id: Option<i64>,
+    pub text: &'a str,
+    pub message_count: i64,
+    pub channel_ids: &'a [i64],
+}
+
+/// Restricts fetched group digests to a channel group and/or a guild.
+#[derive(Deserialize, Default)]
+pub struct GroupDigestFilter {
+    pub group: Option<String>,
+    pub guild_id: Option<i64>,
+}
+
+pub async fn insert_group_digest(
+    pool: &SqlitePool,
+    digest: &NewGroupDigest<'_>,
+) -> Result<i64, Error> {
+    let channel_ids = Json(digest.channel_ids);
+    Ok(sqlx::query!(
+        "INSERT INTO group_digests (daily_digest_id, group_name, guild_id, text, message_count, channel_ids)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        digest.daily_digest_id,
+        digest.group_name,
+        digest.guild_id,
+        digest.text,
+        digest.message_count,
+        channel_ids
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid())
+}
+
+/// Fetches the group digests matching the filter, the most recent first.
+pub async fn fetch_group_digests(
+    pool: &SqlitePool,
+    filter: &GroupDigestFilter,
+    limit: i64,
+) -> Result<Vec<GroupDigest>, Error> {
+    sqlx::query_as!(
+        GroupDigest,
+        r#"SELECT id as "id!", daily_digest_id, group_name, guild_id, text, message_count,
+            channel_ids as "channel_ids: Json<Vec<i64>>",
+            created_at as "created_at: DateTime<Utc>"
+        FROM group_digests
+        WHERE (?1 IS NULL OR group_name = ?1) AND (?2 IS NULL OR guild_id = ?2)
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?3"#,
+        filter.group,
+        filter.guild_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn fetch_group_digest(pool: &SqlitePool, id: i64) -> Result<Option<GroupDigest>, Error> {
+    sqlx::query_as!(
+        GroupDigest,
+        r#"SELECT id as "id!", daily_digest_id, group_name, guild_id, text, message_count,
+            channel_ids as "channel_ids: Json<Vec<i64>>",
+            created_at as "created_at: DateTime<Utc>"
+        FROM group_digests
+        WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Fetches the digest of a channel group written along with a daily digest, if any of
+/// the group's channels were summarized in it.
+pub async fn fetch_daily_group_digest(
+    pool: &SqlitePool,
+    daily_digest_id: i64,
+    group_name: &str,
+) -> Result<Option<GroupDigest>, Error> {
+    sqlx::query_as!(
+        GroupDigest,
+        r#"SELECT id as "id!", daily_digest_id, group_name, guild_id, text, message_count,
+            channel_ids as "channel_ids: Json<Vec<i64>>",
+            created_at as "created_at: DateTime<Utc>"
+        FROM group_digests
+        WHERE daily_digest_id = ? AND group_name = ?"#,
+        daily_digest_id,
+        group_name
+    )
+    .fetch_optional(pool)
+    .await
 }
@@ -0,0 +1,309 @@
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use eyre::bail;
+use serenity::http::Http;
+use serenity::model::application::ApplicationFlags;
+use sqlx::SqlitePool;
+
+use crate::app;
+use crate::cli::{self, MigrationState};
+use crate::config::{AppConfig, DatabaseConfig, GptConfig, LlmProvider};
+use crate::gpt::{self, PromptGuard, UsageRecorder};
+use crate::prompts::Prompts;
+use crate::redaction::Redactor;
+use crate::services::github::GithubActivity;
+use crate::templates::DigestTemplates;
+
+/// Where bot tokens are reset and intents enabled.
+const DEVELOPER_PORTAL: &str = "https://discord.com/developers/applications";
+
+/// Checks that the bot can run with its config, printing what to do about each problem
+/// it finds, and fails when any check failed. No LLM API is called with `dry_run`.
+pub async fn run(config_file: &str, dry_run: bool) -> eyre::Result<()> {
+    let mut diagnosis = Diagnosis::default();
+    let mut config = match AppConfig::load_from_file(config_file) {
+        Ok(config) => config,
+        Err(e) => {
+            diagnosis.fail(
+                "config",
+                format!("could not load {config_file}: {e}"),
+                "Run the bot from the directory holding config.toml, and compare it with the example in the README",
+            );
+            return diagnosis.finish();
+        }
+    };
+    if dry_run {
+        config.gpt.dry_run = true;
+    }
+    match validate_config(&config) {
+        Ok(()) => diagnosis.ok("config", format!("{config_file} is valid")),
+        Err(e) => diagnosis.fail(
+            "config",
+            format!("{e:#}"),
+            "Fix the setting named above, the README documents every setting",
+        ),
+    }
+    check_discord(&mut diagnosis).await;
+    check_storage(&mut diagnosis, &config.database);
+    let db = check_database(&mut diagnosis, &config.database).await;
+    check_llm(&mut diagnosis, &config.gpt, db).await;
+    diagnosis.finish()
+}
+
+/// The outcome of every check so far.
+#[derive(Default)]
+struct Diagnosis {
+    failures: usize,
+    warnings: usize,
+}
+
+impl Diagnosis {
+    fn ok(&mut self, check: &str, detail: impl Display) {
+        println!("ok       {check}: {detail}");
+    }
+
+    fn warn(&mut self, check: &str, problem: impl Display, fix: &str) {
+        self.warnings += 1;
+        println!("warning  {check}: {problem}\n         {fix}");
+    }
+
+    fn fail(&mut self, check: &str, problem: impl Display, fix: &str) {
+        self.failures += 1;
+        println!("FAILED   {check}: {problem}\n         {fix}");
+    }
+
+    fn finish(self) -> eyre::Result<()> {
+        if self.failures > 0 {
+            bail!(
+                "{} of the checks failed, fix them and run `doctor` again",
+                self.failures
+            );
+        }
+        if self.warnings > 0 {
+            println!("The bot can run, but look into the warnings above");
+        } else {
+            println!("Everything looks good, start the bot with `serve`");
+        }
+        Ok(())
+    }
+}
+
+/// Parses every setting that is only checked once the service using it is created.
+fn validate_config(config: &AppConfig) -> eyre::Result<()> {
+    let timezone = config.timezone()?;
+    config.discord.channel_filter()?;
+    config.discord.author_filter()?;
+    config.rollup_schedules()?;
+    config.channel_configs()?;
+    config.channel_group_ids()?;
+    config.discord.digest_channels()?;
+    config.quiet_hours.quiet_hours(timezone)?;
+    Prompts::load(&config.prompts)?;
+    DigestTemplates::load(&config.templates, timezone)?;
+    PromptGuard::from_config(&config.gpt.prompt_guard)?;
+    Redactor::from_config(&config.privacy.redaction)?;
+    GithubActivity::from_config(&config.github)?;
+    config.gpt.budget.alert_channel()?;
+    config.anomalies.alert_channel()?;
+    config.keyword_watch.alert_channel()?;
+    config.standup.channel_id()?;
+    Ok(())
+}
+
+/// Checks that Discord accepts the token, and that the privileged intent the bot needs
+/// is enabled.
+async fn check_discord(diagnosis: &mut Diagnosis) {
+    let Ok(token) = env::var("DISCORD_BOT_SECRET") else {
+        diagnosis.fail(
+            "Discord token",
+            "DISCORD_BOT_SECRET is not set",
+            &format!("Set it, in the environment or in .env, to the token shown on the Bot page of your application at {DEVELOPER_PORTAL}"),
+        );
+        return;
+    };
+    let http = Http::new(&token);
+    match http.get_current_user().await {
+        Ok(user) => diagnosis.ok("Discord token", format!("logged in as {}", user.name)),
+        // Discord answers with an error status when it rejects the token.
+        Err(serenity::Error::Http(e)) if e.status_code().is_some() => {
+            diagnosis.fail(
+                "Discord token",
+                format!("Discord rejected the token: {e}"),
+                &format!("Reset the token on the Bot page of your application at {DEVELOPER_PORTAL}, and set DISCORD_BOT_SECRET to the new one"),
+            );
+            return;
+        }
+        Err(e) => {
+            diagnosis.fail(
+                "Discord token",
+                format!("could not reach Discord: {e}"),
+                "Check that the machine running the bot can connect to discord.com",
+            );
+            return;
+        }
+    }
+    match http.get_current_application_info().await {
+        Ok(info) => {
+            let message_content = ApplicationFlags::GATEWAY_MESSAGE_CONTENT
+                | ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED;
+            if info
+                .flags
+                .is_some_and(|flags| flags.intersects(message_content))
+            {
+                diagnosis.ok("Discord intents", "the Message Content intent is enabled");
+            } else {
+                diagnosis.fail(
+                    "Discord intents",
+                    "the Message Content intent is disabled, so messages arrive without their content",
+                    &format!("Enable Message Content Intent under Privileged Gateway Intents, on the Bot page of your application at {DEVELOPER_PORTAL}"),
+                );
+            }
+        }
+        Err(e) => diagnosis.warn(
+            "Discord intents",
+            format!("could not check the intents: {e}"),
+            &format!("Make sure Message Content Intent is enabled on the Bot page of your application at {DEVELOPER_PORTAL}"),
+        ),
+    }
+}
+
+/// Checks that SQLite can write the database file and its journal, which it keeps
+/// next to it.
+fn check_storage(diagnosis: &mut Diagnosis, config: &DatabaseConfig) {
+    let path = Path::new(&config.url);
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let probe = directory.join(".doctor-write-check");
+    if let Err(e) = fs::write(&probe, b"") {
+        diagnosis.fail(
+            "storage",
+            format!("cannot write to {}: {e}", directory.display()),
+            "Create the directory, or give the user running the bot write access to it, or change `url` under [database]",
+        );
+        return;
+    }
+    _ = fs::remove_file(&probe);
+    if fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly()) {
+        diagnosis.fail(
+            "storage",
+            format!("{} is read-only", path.display()),
+            "Give the user running the bot write access to it",
+        );
+        return;
+    }
+    diagnosis.ok("storage", format!("{} is writable", directory.display()));
+}
+
+/// Checks that the database can be opened and that its schema matches this version of
+/// the bot, and returns it when it can be opened.
+async fn check_database(diagnosis: &mut Diagnosis, config: &DatabaseConfig) -> Option<SqlitePool> {
+    let db = match app::connect_database(config).await {
+        Ok(db) => db,
+        Err(e) => {
+            diagnosis.fail(
+                "database",
+                format!("{e:#}"),
+                "Check `url` under [database], it is the path of the SQLite file, which is created when missing",
+            );
+            return None;
+        }
+    };
+    let statuses = match cli::migration_status(&db).await {
+        Ok(statuses) => statuses,
+        Err(e) => {
+            diagnosis.fail(
+                "database",
+                format!("could not read the applied migrations: {e:#}"),
+                "Check that the file is an SQLite database written by the bot",
+            );
+            return Some(db);
+        }
+    };
+    let count = |state: MigrationState| {
+        statuses
+            .iter()
+            .filter(|status| status.state == state)
+            .count()
+    };
+    let (changed, unknown, pending) = (
+        count(MigrationState::Changed),
+        count(MigrationState::Unknown),
+        count(MigrationState::Pending),
+    );
+    if unknown > 0 {
+        diagnosis.fail(
+            "database",
+            format!("{unknown} migrations were applied by a newer version of the bot"),
+            "Run that version, or restore a backup of the database made before it",
+        );
+    } else if changed > 0 {
+        diagnosis.fail(
+            "database",
+            format!("{changed} migrations changed since they were applied"),
+            "Restore the files of the migrations folder, `migrate status` lists which",
+        );
+    } else if pending > 0 {
+        diagnosis.warn(
+            "database",
+            format!("connected, but {pending} migrations are pending"),
+            "They are applied when the bot starts, or by `migrate run`",
+        );
+    } else {
+        diagnosis.ok("database", "connected, and every migration is applied");
+    }
+    Some(db)
+}
+
+/// Makes a request to the configured LLM provider, which costs a few tokens, to check
+/// its API key and model.
+async fn check_llm(diagnosis: &mut Diagnosis, config: &GptConfig, db: Option<SqlitePool>) {
+    if config.dry_run {
+        diagnosis.ok("LLM API", "not called in dry-run mode");
+        return;
+    }
+    let Some(db) = db else {
+        diagnosis.warn(
+            "LLM API",
+            "not checked, as the usage of the test request is recorded in the database",
+            "Fix the database, then run `doctor` again",
+        );
+        return;
+    };
+    let key = match config.provider {
+        LlmProvider::OpenAi => "OPEN_AI_SECRET",
+        LlmProvider::Anthropic => "ANTHROPIC_API_KEY",
+        LlmProvider::Ollama => "no key, but the `base_url` under [gpt.ollama]",
+    };
+    let usage = UsageRecorder::new(Arc::new(db), &config.prices);
+    let summarizer = match gpt::provider_summarizer(config, usage) {
+        Ok(summarizer) => summarizer,
+        Err(e) => {
+            diagnosis.fail(
+                "LLM API",
+                e,
+                &format!("The provider selected by `provider` under [gpt] needs {key}, set it in the environment or in .env"),
+            );
+            return;
+        }
+    };
+    let backend = summarizer.backend().unwrap_or_default();
+    match summarizer
+        .complete("Reply with OK and nothing else.", "ping")
+        .await
+    {
+        Ok(_) => diagnosis.ok("LLM API", format!("{backend} answered a test request")),
+        Err(e) => diagnosis.fail(
+            "LLM API",
+            format!("the test request to {backend} failed: {e}"),
+            &format!(
+                "Check {key}, and that the configured model exists and your account can use it"
+            ),
+        ),
+    }
+}
@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+/// Errors that keep a service from starting or from running any further. Services
+/// handle everything else by logging it and carrying on.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no {0} provided")]
+    MissingEnvVar(&'static str),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("could not run database migrations: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+    #[error("Discord client error: {0}")]
+    Discord(Box<serenity::Error>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl From<serenity::Error> for Error {
+    fn from(e: serenity::Error) -> Self {
+        Error::Discord(Box::new(e))
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,108 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::db::DigestData;
+
+/// Prefix of the tag URIs identifying the feed and its entries, which stay the same
+/// wherever the API is served from.
+const TAG_PREFIX: &str = "tag:daily-discord-summarizer,2024";
+
+/// Where the feed is served from, used to link to it and to its digests.
+pub struct FeedLinks<'a> {
+    /// Public base URL of the HTTP API, if configured.
+    pub base_url: Option<&'a str>,
+    /// Path and query the feed was requested with.
+    pub self_path: &'a str,
+}
+
+/// Renders daily digests, most recent first, as an Atom feed.
+pub fn render_atom(
+    digests: &[DigestData],
+    guild_id: Option<i64>,
+    links: &FeedLinks,
+    timezone: Tz,
+) -> String {
+    let feed_id = match guild_id {
+        Some(guild_id) => format!("{TAG_PREFIX}:daily-digests/guild/{guild_id}"),
+        None => format!("{TAG_PREFIX}:daily-digests"),
+    };
+    // Atom requires an update time even for a feed without entries.
+    let updated = digests
+        .iter()
+        .map(|digest| digest.timestamp)
+        .max()
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    let base_url = links.base_url.map(|url| url.trim_end_matches('/'));
+
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str(&format!("  <id>{}</id>\n", escape(&feed_id)));
+    feed.push_str("  <title>Daily digests</title>\n");
+    feed.push_str(&format!("  <updated>{}</updated>\n", rfc3339(updated)));
+    feed.push_str("  <author><name>Daily Discord Summarizer</name></author>\n");
+    if let Some(base_url) = base_url {
+        feed.push_str(&format!(
+            "  <link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>\n",
+            escape(&format!("{base_url}{}", links.self_path))
+        ));
+    }
+    for digest in digests {
+        let day = digest
+            .covers_to
+            .unwrap_or(digest.timestamp)
+            .with_timezone(&timezone)
+            .format("%b %-d, %Y");
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!(
+            "    <id>{TAG_PREFIX}:daily-digest/{}</id>\n",
+            digest.id
+        ));
+        feed.push_str(&format!("    <title>Daily digest for {day}</title>\n"));
+        feed.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            rfc3339(digest.timestamp)
+        ));
+        if let Some(covers_from) = digest.covers_from {
+            feed.push_str(&format!(
+                "    <published>{}</published>\n",
+                rfc3339(covers_from.min(digest.timestamp))
+            ));
+        }
+        if let Some(base_url) = base_url {
+            feed.push_str(&format!(
+                "    <link rel=\"alternate\" type=\"application/json\" href=\"{}\"/>\n",
+                escape(&format!("{base_url}/daily_digests/{}", digest.id))
+            ));
+        }
+        feed.push_str(&format!(
+            "    <content type=\"text\">{}</content>\n",
+            escape(&digest.text)
+        ));
+        feed.push_str("  </entry>\n");
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+fn rfc3339(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Escapes text for use in XML content and attribute values, dropping the control
+/// characters XML does not allow.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
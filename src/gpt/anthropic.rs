@@ -0,0 +1,156 @@
+use axum::async_trait;
+use eyre::eyre;
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use tracing::warn;
+
+use crate::config::AnthropicConfig;
+use crate::db::UsageKind;
+use crate::error::{Error, Result};
+
+use super::{ApiError, Summarizer, TokenUsage, UsageRecorder};
+
+pub(super) const MESSAGES_URL: &str = "https://api.anthropic.com/v1/messages";
+pub(super) const API_VERSION: &str = "2023-06-01";
+
+/// Largest `max_tokens` the Messages API accepts for current Claude models.
+const MAX_OUTPUT_TOKENS: u32 = 8192;
+
+#[derive(Deserialize, Debug)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+    usage: MessagesUsage,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessagesUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+/// Summarizes content using Anthropic's Messages API.
+pub struct AnthropicSummarizer {
+    client: reqwest::Client,
+    api_key: String,
+    model: String,
+    max_tokens: u32,
+    usage: UsageRecorder,
+}
+
+impl AnthropicSummarizer {
+    pub fn new(config: &AnthropicConfig, usage: UsageRecorder) -> Result<Self> {
+        if config.max_tokens > MAX_OUTPUT_TOKENS {
+            warn!(
+                "gpt.anthropic.max_tokens of {} exceeds the Messages API limit, using {MAX_OUTPUT_TOKENS}",
+                config.max_tokens
+            );
+        }
+        Ok(Self {
+            client: reqwest::Client::new(),
+            api_key: env::var("ANTHROPIC_API_KEY")
+                .map_err(|_| Error::MissingEnvVar("ANTHROPIC_API_KEY"))?,
+            model: config.model.clone(),
+            max_tokens: config.max_tokens.min(MAX_OUTPUT_TOKENS),
+            usage,
+        })
+    }
+
+    /// Uses a model other than the configured one, from the same provider.
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl Summarizer for AnthropicSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        let response = self
+            .client
+            .post(MESSAGES_URL)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&json!({
+                "model": self.model,
+                "max_tokens": self.max_tokens,
+                "system": instructions,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": text,
+                    }
+                ],
+            }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error = ApiError::from_response("Anthropic", response, |body| {
+                let ErrorResponse { error } = serde_json::from_str(body).ok()?;
+                Some(match error.kind.as_str() {
+                    "request_too_large" => format!(
+                        "request exceeds the model's context window: {}",
+                        error.message
+                    ),
+                    "rate_limit_error" | "overloaded_error" => {
+                        format!("API is rate limited or overloaded: {}", error.message)
+                    }
+                    _ => format!("{}: {}", error.kind, error.message),
+                })
+            })
+            .await;
+            return Err(error.into());
+        }
+
+        let response = response.json::<MessagesResponse>().await?;
+        let usage = TokenUsage {
+            prompt_tokens: response.usage.input_tokens,
+            completion_tokens: response.usage.output_tokens,
+        };
+        self.usage
+            .record("Anthropic", &self.model, UsageKind::Completion, usage)
+            .await;
+        if response.stop_reason.as_deref() == Some("max_tokens") {
+            warn!(
+                "Anthropic response was cut off at {} tokens, summary is truncated",
+                self.max_tokens
+            );
+        }
+        let summary: String = response
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect();
+        if summary.is_empty() {
+            return Err(eyre!("Anthropic API returned no text content"));
+        }
+        Ok(summary)
+    }
+
+    fn backend(&self) -> Option<String> {
+        Some(format!("anthropic/{}", self.model))
+    }
+}
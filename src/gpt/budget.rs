@@ -0,0 +1,141 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use axum::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use serenity::all::ChannelId;
+use serenity::http::Http;
+use sqlx::SqlitePool;
+use tracing::{error, warn};
+
+use crate::config::BudgetConfig;
+use crate::db;
+
+use super::Summarizer;
+
+/// Returned instead of calling the API once the daily budget is used up.
+#[derive(Debug)]
+pub struct BudgetExceeded {
+    /// When the budget window resets and requests are allowed again.
+    pub resets_at: DateTime<Utc>,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Daily LLM budget exceeded, requests resume at {}",
+            self.resets_at.format("%Y-%m-%d %H:%M UTC")
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Daily token and cost limits on LLM usage, counted from midnight UTC.
+#[derive(Clone)]
+pub struct Budget {
+    db: Arc<SqlitePool>,
+    daily_tokens: Option<u64>,
+    daily_cost_usd: Option<f64>,
+    alert: Option<(Arc<Http>, ChannelId)>,
+    /// Day an alert was last posted for, so it is only posted once per window.
+    alerted_on: Arc<Mutex<Option<NaiveDate>>>,
+}
+
+impl Budget {
+    /// Creates the budget described by the config, or `None` if it sets no limit.
+    pub fn from_config(
+        db: Arc<SqlitePool>,
+        config: &BudgetConfig,
+        http: Arc<Http>,
+    ) -> eyre::Result<Option<Self>> {
+        if config.daily_tokens.is_none() && config.daily_cost_usd.is_none() {
+            return Ok(None);
+        }
+        let alert = config.alert_channel()?.map(|channel_id| (http, channel_id));
+        Ok(Some(Self {
+            db,
+            daily_tokens: config.daily_tokens,
+            daily_cost_usd: config.daily_cost_usd,
+            alert,
+            alerted_on: Arc::new(Mutex::new(None)),
+        }))
+    }
+
+    /// Fails once today's usage reaches either limit, alerting the admin channel the
+    /// first time it does. Usage that cannot be read does not block requests.
+    pub(super) async fn check(&self) -> Result<(), BudgetExceeded> {
+        let today = Utc::now().date_naive();
+        let window_start = today.and_time(NaiveTime::MIN).and_utc();
+        let (tokens, cost) = match db::fetch_usage_since(&self.db, window_start).await {
+            Ok(usage) => usage,
+            Err(e) => {
+                error!("Could not check LLM usage against the daily budget: {e}");
+                return Ok(());
+            }
+        };
+        let tokens = u64::try_from(tokens).unwrap_or_default();
+        let exceeded = self.daily_tokens.is_some_and(|max| tokens >= max)
+            || self.daily_cost_usd.is_some_and(|max| cost >= max);
+        if !exceeded {
+            return Ok(());
+        }
+        let error = BudgetExceeded {
+            resets_at: window_start + Duration::days(1),
+        };
+        let first_time = {
+            let mut alerted_on = self
+                .alerted_on
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            alerted_on.replace(today) != Some(today)
+        };
+        if first_time {
+            warn!("{error}, {tokens} tokens and ${cost:.2} used today");
+            self.alert(&format!(
+                "**LLM budget exceeded:** {tokens} tokens and ${cost:.2} used today. Summaries are queued until {}.",
+                error.resets_at.format("%Y-%m-%d %H:%M UTC")
+            ))
+            .await;
+        }
+        Err(error)
+    }
+
+    async fn alert(&self, message: &str) {
+        let Some((http, channel_id)) = &self.alert else {
+            return;
+        };
+        if let Err(e) = channel_id.say(http, message).await {
+            error!("Could not post budget alert to channel {channel_id}: {e}");
+        }
+    }
+}
+
+/// Refuses to call the wrapped summarizer once the daily budget is used up.
+pub struct BudgetedSummarizer {
+    inner: Arc<dyn Summarizer>,
+    budget: Budget,
+}
+
+impl BudgetedSummarizer {
+    pub fn new(inner: Arc<dyn Summarizer>, budget: Budget) -> Self {
+        Self { inner, budget }
+    }
+}
+
+#[async_trait]
+impl Summarizer for BudgetedSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.budget.check().await?;
+        self.inner.complete(instructions, text).await
+    }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        self.inner.max_input_tokens()
+    }
+
+    fn backend(&self) -> Option<String> {
+        self.inner.backend()
+    }
+}
@@ -0,0 +1,199 @@
+use axum::async_trait;
+use eyre::eyre;
+use std::sync::Arc;
+use tracing::info;
+
+use super::{StructuredSummary, Summarizer, TokenCounter};
+
+/// How many times to summarize chunk summaries before giving up on fitting the input
+/// into the context window.
+const MAX_REDUCE_ROUNDS: usize = 4;
+
+/// Summarizes inputs too large for a single request map-reduce style: the input is
+/// split into windows that fit the request limit, each window is summarized, and the
+/// summaries are then summarized together.
+pub struct ChunkingSummarizer {
+    inner: Arc<dyn Summarizer>,
+    token_counter: Arc<dyn TokenCounter>,
+    window_tokens: usize,
+}
+
+impl ChunkingSummarizer {
+    pub fn new(
+        inner: Arc<dyn Summarizer>,
+        token_counter: Arc<dyn TokenCounter>,
+        window_tokens: usize,
+    ) -> Self {
+        Self {
+            inner,
+            token_counter,
+            window_tokens: window_tokens.max(1),
+        }
+    }
+
+    /// Splits text into chunks of at most `window_tokens` tokens, breaking between
+    /// lines where possible and between words for lines that are too long. A single
+    /// word longer than the window ends up in a chunk of its own.
+    fn split_into_windows(&self, text: &str) -> Vec<String> {
+        let mut chunks = vec![];
+        let mut current = String::new();
+        let mut current_tokens = 0;
+        let mut push = |piece: &str, current: &mut String, current_tokens: &mut usize| {
+            let piece_tokens = self.token_counter.count_tokens(piece);
+            if *current_tokens + piece_tokens > self.window_tokens && !current.is_empty() {
+                chunks.push(std::mem::take(current));
+                *current_tokens = 0;
+            }
+            current.push_str(piece);
+            *current_tokens += piece_tokens;
+        };
+        for line in text.split_inclusive('\n') {
+            if self.token_counter.count_tokens(line) <= self.window_tokens {
+                push(line, &mut current, &mut current_tokens);
+                continue;
+            }
+            for word in line.split_inclusive(' ') {
+                push(word, &mut current, &mut current_tokens);
+            }
+        }
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+        chunks
+    }
+
+    /// Summarizes the input chunk by chunk following the instructions, as many times as
+    /// needed for it to fit in a single window.
+    async fn reduce_to_window(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        let mut text = text.to_owned();
+        for round in 1..=MAX_REDUCE_ROUNDS {
+            let tokens = self.token_counter.count_tokens(&text);
+            if tokens <= self.window_tokens {
+                return Ok(text);
+            }
+            let chunks = self.split_into_windows(&text);
+            info!(
+                "Input of {tokens} tokens exceeds the {} token window, summarizing it in {} chunks (round {round})",
+                self.window_tokens,
+                chunks.len()
+            );
+            let mut summaries = Vec::with_capacity(chunks.len());
+            for chunk in &chunks {
+                summaries.push(self.inner.summarize(instructions, chunk).await?);
+            }
+            text = summaries.join("\n\n");
+        }
+        Err(eyre!(
+            "Could not reduce input to fit in {} tokens after {MAX_REDUCE_ROUNDS} rounds of summarization",
+            self.window_tokens
+        ))
+    }
+}
+
+#[async_trait]
+impl Summarizer for ChunkingSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.inner.complete(instructions, text).await
+    }
+
+    async fn summarize(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        let text = self.reduce_to_window(instructions, text).await?;
+        self.inner.summarize(instructions, &text).await
+    }
+
+    async fn summarize_structured(
+        &self,
+        instructions: &str,
+        text: &str,
+    ) -> eyre::Result<StructuredSummary> {
+        let text = self.reduce_to_window(instructions, text).await?;
+        self.inner.summarize_structured(instructions, &text).await
+    }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        self.inner.max_input_tokens()
+    }
+
+    fn backend(&self) -> Option<String> {
+        self.inner.backend()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpt::MockSummarizer;
+
+    /// Counts every word as a token.
+    struct WordCounter;
+
+    impl TokenCounter for WordCounter {
+        fn count_tokens(&self, text: &str) -> usize {
+            text.split_whitespace().count()
+        }
+    }
+
+    fn chunking(inner: Arc<dyn Summarizer>, window_tokens: usize) -> ChunkingSummarizer {
+        ChunkingSummarizer::new(inner, Arc::new(WordCounter), window_tokens)
+    }
+
+    #[test]
+    fn text_is_split_between_lines() {
+        let summarizer = chunking(Arc::new(MockSummarizer::new()), 4);
+        let chunks = summarizer.split_into_windows("a b\nc d\ne f\ng\n");
+        assert_eq!(chunks, ["a b\nc d\n", "e f\ng\n"]);
+    }
+
+    #[test]
+    fn lines_too_long_for_a_window_are_split_between_words() {
+        let summarizer = chunking(Arc::new(MockSummarizer::new()), 3);
+        let chunks = summarizer.split_into_windows("a b c d e\nf\n");
+        assert_eq!(chunks, ["a b c ", "d e\nf\n"]);
+    }
+
+    #[test]
+    fn words_longer_than_the_window_get_a_chunk_of_their_own() {
+        let summarizer = chunking(Arc::new(MockSummarizer::new()), 1);
+        let chunks = summarizer.split_into_windows("a b");
+        assert_eq!(chunks, ["a ", "b"]);
+    }
+
+    #[tokio::test]
+    async fn inputs_that_fit_are_summarized_at_once() {
+        let mock = Arc::new(MockSummarizer::new());
+        chunking(mock.clone(), 10)
+            .summarize("Summarize", "a b c")
+            .await
+            .unwrap();
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn large_inputs_are_summarized_chunk_by_chunk_then_together() {
+        let mock = Arc::new(MockSummarizer::new().with_reply("Summarize", "ok"));
+        let text = "a b c\n".repeat(6);
+        let summary = chunking(mock.clone(), 6)
+            .summarize("Summarize", &text)
+            .await
+            .unwrap();
+        assert_eq!(summary, "ok");
+        let texts: Vec<String> = mock.requests().into_iter().map(|r| r.text).collect();
+        assert_eq!(
+            texts,
+            [
+                "a b c\na b c\n",
+                "a b c\na b c\n",
+                "a b c\na b c\n",
+                "ok\n\nok\n\nok"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn inputs_that_never_fit_fail() {
+        // Each summary is as long as the window, so summaries never fit together.
+        let mock = Arc::new(MockSummarizer::new().with_reply("Summarize", "x y"));
+        let result = chunking(mock, 2).summarize("Summarize", "a b c d").await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use axum::async_trait;
+
+use super::{Embedder, Summarizer};
+
+/// Backend reported for the summaries written in dry-run mode.
+pub const DRY_RUN_BACKEND: &str = "dry-run";
+/// Lines of the input kept in each reply.
+const EXTRACT_LINES: usize = 5;
+/// Longest line kept in a reply, in characters.
+const MAX_LINE_CHARS: usize = 200;
+/// Dimensions of the vectors of the dry-run embedder.
+const EMBEDDING_DIMENSIONS: usize = 64;
+
+/// Replies with the first lines of the text it is given instead of calling an API, so
+/// that ingestion, batching and storage can be checked without spending anything. The
+/// same input always gets the same reply.
+pub struct DryRunSummarizer;
+
+#[async_trait]
+impl Summarizer for DryRunSummarizer {
+    async fn complete(&self, _instructions: &str, text: &str) -> eyre::Result<String> {
+        let mut reply = String::from("[Dry run] ");
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        for line in lines.iter().take(EXTRACT_LINES) {
+            reply.push_str("\n- ");
+            reply.extend(line.chars().take(MAX_LINE_CHARS));
+        }
+        if lines.len() > EXTRACT_LINES {
+            reply.push_str(&format!(
+                "\n- ...and {} more lines",
+                lines.len() - EXTRACT_LINES
+            ));
+        }
+        Ok(reply)
+    }
+
+    fn backend(&self) -> Option<String> {
+        Some(DRY_RUN_BACKEND.to_string())
+    }
+}
+
+/// Embeds text as a hashed bag of its words instead of calling an API. Its vectors are
+/// stored under a model of their own, so they are never compared with real ones.
+pub struct DryRunEmbedder;
+
+#[async_trait]
+impl Embedder for DryRunEmbedder {
+    async fn embed(&self, text: &str) -> eyre::Result<Vec<f32>> {
+        let mut vector = vec![0.0; EMBEDDING_DIMENSIONS];
+        for word in text.split_whitespace() {
+            let mut hasher = DefaultHasher::new();
+            word.to_lowercase().hash(&mut hasher);
+            vector[hasher.finish() as usize % EMBEDDING_DIMENSIONS] += 1.0;
+        }
+        Ok(vector)
+    }
+
+    fn model(&self) -> &str {
+        DRY_RUN_BACKEND
+    }
+}
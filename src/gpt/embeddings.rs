@@ -0,0 +1,214 @@
+use axum::async_trait;
+use eyre::{bail, eyre};
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use std::sync::Arc;
+
+use crate::config::{GptConfig, LlmProvider};
+use crate::db::UsageKind;
+
+use super::{ApiError, DryRunEmbedder, RequestLayers, RetryPolicy, TokenUsage};
+
+const DEFAULT_OPENAI_MODEL: &str = "text-embedding-3-small";
+const DEFAULT_OLLAMA_MODEL: &str = "nomic-embed-text";
+
+/// Turns text into a vector whose distance to other vectors reflects how similar
+/// their meaning is.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed(&self, text: &str) -> eyre::Result<Vec<f32>>;
+
+    /// Identifies the model, since vectors from different models cannot be compared.
+    fn model(&self) -> &str;
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbeddingsResponse {
+    data: Vec<OpenAiEmbedding>,
+    usage: Option<OpenAiEmbeddingsUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbeddingsUsage {
+    prompt_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+    embedding: Vec<f32>,
+}
+
+/// Embeds text with OpenAI's embeddings API, or any gateway that implements it.
+pub struct OpenAiEmbedder {
+    client: reqwest::Client,
+    /// Only required once something is embedded, so that other providers can be used
+    /// for summaries without an OpenAI key.
+    api_key: Option<String>,
+    embeddings_url: String,
+    azure_api_version: Option<String>,
+    model: String,
+    layers: RequestLayers,
+    retry: RetryPolicy,
+}
+
+impl OpenAiEmbedder {
+    async fn send(&self, api_key: &str, text: &str) -> eyre::Result<OpenAiEmbeddingsResponse> {
+        let request = match &self.azure_api_version {
+            Some(api_version) => self
+                .client
+                .post(&self.embeddings_url)
+                .query(&[("api-version", api_version)])
+                .header("api-key", api_key),
+            None => self
+                .client
+                .post(&self.embeddings_url)
+                .header("Authorization", format!("Bearer {api_key}")),
+        };
+        let response = request
+            .json(&json!({ "model": self.model, "input": text }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("OpenAI", response, |_| None)
+                .await
+                .into());
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAiEmbedder {
+    async fn embed(&self, text: &str) -> eyre::Result<Vec<f32>> {
+        let Some(api_key) = &self.api_key else {
+            bail!("No OPEN_AI_SECRET provided for embeddings");
+        };
+        let response = self
+            .layers
+            .run(&self.retry, || self.send(api_key, text))
+            .await?;
+        if let Some(usage) = &response.usage {
+            let usage = TokenUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: 0,
+            };
+            self.layers
+                .usage
+                .record("OpenAI", &self.model, UsageKind::Embedding, usage)
+                .await;
+        }
+        response
+            .data
+            .into_iter()
+            .next()
+            .map(|data| data.embedding)
+            .ok_or_else(|| eyre!("OpenAI returned no embedding"))
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+/// Embeds text with a model served by a local Ollama instance.
+pub struct OllamaEmbedder {
+    client: reqwest::Client,
+    embeddings_url: String,
+    model: String,
+    layers: RequestLayers,
+    retry: RetryPolicy,
+}
+
+impl OllamaEmbedder {
+    async fn send(&self, text: &str) -> eyre::Result<Vec<f32>> {
+        let response = self
+            .client
+            .post(&self.embeddings_url)
+            .json(&json!({ "model": self.model, "prompt": text }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Ollama", response, |_| None)
+                .await
+                .into());
+        }
+        Ok(response.json::<OllamaEmbeddingResponse>().await?.embedding)
+    }
+}
+
+#[async_trait]
+impl Embedder for OllamaEmbedder {
+    async fn embed(&self, text: &str) -> eyre::Result<Vec<f32>> {
+        let embedding = self.layers.run(&self.retry, || self.send(text)).await?;
+        // Ollama does not report the tokens used for embeddings.
+        self.layers
+            .usage
+            .record(
+                "Ollama",
+                &self.model,
+                UsageKind::Embedding,
+                TokenUsage::default(),
+            )
+            .await;
+        Ok(embedding)
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Creates the embedder selected in the config. Anthropic has no embeddings API, so
+/// it falls back to OpenAI's. Requests are retried, and refused while LLM calls are
+/// paused or once the daily budget is used up. Nothing is sent to an API in dry-run
+/// mode.
+pub fn embedder_from_config(config: &GptConfig, layers: &RequestLayers) -> Arc<dyn Embedder> {
+    if config.dry_run {
+        return Arc::new(DryRunEmbedder);
+    }
+    let model = config.embeddings.model.clone();
+    match config.embeddings.provider {
+        LlmProvider::Ollama => Arc::new(OllamaEmbedder {
+            client: reqwest::Client::new(),
+            embeddings_url: format!(
+                "{}/api/embeddings",
+                config.ollama.base_url.trim_end_matches('/')
+            ),
+            model: model.unwrap_or_else(|| DEFAULT_OLLAMA_MODEL.to_string()),
+            layers: layers.clone(),
+            retry: RetryPolicy::new(&config.retry),
+        }),
+        LlmProvider::OpenAi | LlmProvider::Anthropic => Arc::new(OpenAiEmbedder {
+            client: reqwest::Client::new(),
+            api_key: env::var("OPEN_AI_SECRET").ok(),
+            embeddings_url: format!(
+                "{}/embeddings",
+                config.openai.api_base.trim_end_matches('/')
+            ),
+            azure_api_version: config.openai.azure_api_version.clone(),
+            model: model.unwrap_or_else(|| DEFAULT_OPENAI_MODEL.to_string()),
+            layers: layers.clone(),
+            retry: RetryPolicy::new(&config.retry),
+        }),
+    }
+}
+
+/// Cosine similarity of two vectors, from -1 for opposite meanings to 1 for the same.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
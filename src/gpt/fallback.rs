@@ -0,0 +1,108 @@
+use axum::async_trait;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing::warn;
+
+use super::{BudgetExceeded, LlmPaused, StructuredSummary, Summarizer};
+
+/// Tries each backend in order until one succeeds, so that summaries keep coming when
+/// the primary provider is down. Backends are expected to retry failed requests
+/// themselves, so falling back only happens after repeated errors or timeouts.
+pub struct FallbackSummarizer {
+    primary: Arc<dyn Summarizer>,
+    fallbacks: Vec<Arc<dyn Summarizer>>,
+    /// The backend that answered the last successful request.
+    answered: Mutex<Option<String>>,
+}
+
+impl FallbackSummarizer {
+    pub fn new(primary: Arc<dyn Summarizer>, fallbacks: Vec<Arc<dyn Summarizer>>) -> Self {
+        Self {
+            primary,
+            fallbacks,
+            answered: Mutex::new(None),
+        }
+    }
+
+    async fn try_in_order<'a, T, F, Fut>(&'a self, request: F) -> eyre::Result<T>
+    where
+        F: Fn(&'a Arc<dyn Summarizer>) -> Fut,
+        Fut: Future<Output = eyre::Result<T>>,
+    {
+        let mut backend = &self.primary;
+        let mut result = request(backend).await;
+        for fallback in &self.fallbacks {
+            match &result {
+                Ok(_) => break,
+                // Every backend shares the same budget, and is paused along with the others.
+                Err(e) if e.downcast_ref::<BudgetExceeded>().is_some() => break,
+                Err(e) if e.downcast_ref::<LlmPaused>().is_some() => break,
+                Err(e) => warn!(
+                    "LLM backend {} failed, falling back to the next one: {e:#}",
+                    backend.backend().as_deref().unwrap_or("unknown")
+                ),
+            }
+            backend = fallback;
+            result = request(backend).await;
+        }
+        if result.is_ok() {
+            *self.answered.lock().unwrap_or_else(PoisonError::into_inner) = backend.backend();
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl Summarizer for FallbackSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.try_in_order(|backend| backend.complete(instructions, text))
+            .await
+    }
+
+    async fn summarize(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.try_in_order(|backend| backend.summarize(instructions, text))
+            .await
+    }
+
+    async fn summarize_structured(
+        &self,
+        instructions: &str,
+        text: &str,
+    ) -> eyre::Result<StructuredSummary> {
+        self.try_in_order(|backend| backend.summarize_structured(instructions, text))
+            .await
+    }
+
+    /// The limit of the primary backend, which inputs are sized for.
+    fn max_input_tokens(&self) -> Option<usize> {
+        self.primary.max_input_tokens()
+    }
+
+    /// The backend that answered the last successful request, or the primary one until
+    /// a request succeeds.
+    fn backend(&self) -> Option<String> {
+        self.answered
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .or_else(|| self.primary.backend())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpt::MockSummarizer;
+
+    #[tokio::test]
+    async fn the_backend_that_answered_is_reported() {
+        let summarizer = FallbackSummarizer::new(
+            Arc::new(MockSummarizer::new().with_backend("primary").failing()),
+            vec![Arc::new(MockSummarizer::new().with_backend("fallback"))],
+        );
+        assert_eq!(summarizer.backend().as_deref(), Some("primary"));
+
+        summarizer.summarize("Summarize", "text").await.unwrap();
+        assert_eq!(summarizer.backend().as_deref(), Some("fallback"));
+    }
+}
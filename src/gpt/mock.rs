@@ -0,0 +1,87 @@
+use std::sync::Mutex;
+
+use axum::async_trait;
+use eyre::bail;
+
+use super::Summarizer;
+
+/// A request the mock summarizer received.
+#[derive(Clone)]
+pub struct MockRequest {
+    pub instructions: String,
+    pub text: String,
+}
+
+/// Answers with canned replies instead of calling an API, and records every request,
+/// so that tests can run the pipeline end to end and check what the model was asked.
+/// Requests whose instructions match none of the canned replies get a reply quoting
+/// the first line of their text.
+#[derive(Default)]
+pub struct MockSummarizer {
+    /// Replies to the requests whose instructions contain the first string, checked in
+    /// order.
+    replies: Vec<(String, String)>,
+    /// Whether every request fails.
+    failing: bool,
+    /// The backend it claims to be, `mock` unless set.
+    backend: Option<String>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+impl MockSummarizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replies with `reply` to the requests whose instructions contain `instructions`.
+    pub fn with_reply(mut self, instructions: &str, reply: &str) -> Self {
+        self.replies
+            .push((instructions.to_string(), reply.to_string()));
+        self
+    }
+
+    /// Fails every request, as an unreachable API would.
+    pub fn failing(mut self) -> Self {
+        self.failing = true;
+        self
+    }
+
+    /// Claims to be `backend`, to tell mock backends apart.
+    pub fn with_backend(mut self, backend: &str) -> Self {
+        self.backend = Some(backend.to_string());
+        self
+    }
+
+    /// The requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Summarizer for MockSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.requests.lock().unwrap().push(MockRequest {
+            instructions: instructions.to_string(),
+            text: text.to_string(),
+        });
+        if self.failing {
+            bail!("Mock API is unavailable");
+        }
+        let canned = self
+            .replies
+            .iter()
+            .find(|(pattern, _)| instructions.contains(pattern.as_str()));
+        Ok(match canned {
+            Some((_, reply)) => reply.clone(),
+            None => format!(
+                "Mock summary of: {}",
+                text.lines().next().unwrap_or_default()
+            ),
+        })
+    }
+
+    fn backend(&self) -> Option<String> {
+        Some(self.backend.clone().unwrap_or_else(|| "mock".to_string()))
+    }
+}
@@ -0,0 +1,415 @@
+use axum::async_trait;
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::{GptConfig, LlmProvider};
+use crate::error::Error;
+
+mod anthropic;
+mod budget;
+mod chunked;
+mod dry_run;
+mod embeddings;
+mod fallback;
+mod guard;
+#[cfg(test)]
+mod mock;
+mod ollama;
+mod openai;
+mod pause;
+mod retry;
+mod routing;
+mod sentiment;
+mod structured;
+mod tokens;
+mod transcription;
+mod usage;
+mod vision;
+
+pub use anthropic::AnthropicSummarizer;
+pub use budget::{Budget, BudgetExceeded, BudgetedSummarizer};
+pub use chunked::ChunkingSummarizer;
+pub use dry_run::{DryRunEmbedder, DryRunSummarizer};
+pub use embeddings::{cosine_similarity, embedder_from_config, Embedder};
+pub use fallback::FallbackSummarizer;
+pub use guard::{GuardedSummarizer, PromptGuard};
+#[cfg(test)]
+pub use mock::MockSummarizer;
+pub use ollama::OllamaSummarizer;
+pub use openai::OpenAiSummarizer;
+pub use pause::{LlmPause, LlmPaused, PausableSummarizer, QuietHours};
+pub use retry::{RetryPolicy, RetryingSummarizer};
+pub use routing::{Route, RoutingSummarizer};
+pub use sentiment::{analyze_sentiment, Sentiment};
+pub use structured::{
+    ActionItem, Entity, EntityKind, StructuredSummary, STRUCTURED_SUMMARY_FORMAT,
+};
+pub use tokens::{token_counter_for_model, TokenCounter};
+pub use transcription::{transcriber_from_config, Transcriber};
+pub use usage::{TokenUsage, UsageRecorder};
+pub use vision::{image_describer_from_config, ImageDescriber};
+
+/// Instructions given to the model alongside the content to summarize, unless a
+/// prompt template is configured.
+pub const SYSTEM_PROMPT: &str = "You are a summarizer of large amount of content for a technical team. Summarize the following thoroughly:";
+
+/// A large language model backend able to summarize text.
+#[async_trait]
+pub trait Summarizer: Send + Sync {
+    /// Sends text to the model along with instructions on what to do with it, and
+    /// returns the model's reply.
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String>;
+
+    /// Summarizes text following the instructions, which wrappers may split up first.
+    async fn summarize(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.complete(instructions, text).await
+    }
+
+    /// Summarizes text into topics, decisions, action items and open questions,
+    /// following the instructions. A reply that is not valid JSON is kept as a plain
+    /// summary.
+    async fn summarize_structured(
+        &self,
+        instructions: &str,
+        text: &str,
+    ) -> eyre::Result<StructuredSummary> {
+        let instructions = format!("{instructions}\n\n{STRUCTURED_SUMMARY_FORMAT}");
+        let reply = self.complete(&instructions, text).await?;
+        let mut summary = StructuredSummary::parse(&reply).unwrap_or_else(|e| {
+            warn!("Could not parse structured summary, keeping it as plain text: {e}");
+            StructuredSummary::unstructured(reply.trim().to_string())
+        });
+        summary.backend = self.backend();
+        Ok(summary)
+    }
+
+    /// Largest number of input tokens the backend can handle in one request, when it
+    /// is limited by the model rather than by `max_gpt_request_tokens`.
+    fn max_input_tokens(&self) -> Option<usize> {
+        None
+    }
+
+    /// Provider and model answering requests, such as `openai/gpt-4`, or `None` when
+    /// it depends on the request.
+    fn backend(&self) -> Option<String> {
+        None
+    }
+}
+
+/// An unsuccessful response from an LLM API.
+#[derive(Debug)]
+pub struct ApiError {
+    pub provider: &'static str,
+    pub status: StatusCode,
+    /// How long the API asked us to wait before trying again.
+    pub retry_after: Option<Duration>,
+    pub message: String,
+}
+
+impl ApiError {
+    /// Builds an error from a non-success response, using `parse_message` to pull
+    /// a readable message out of the provider's error body.
+    pub async fn from_response(
+        provider: &'static str,
+        response: Response,
+        parse_message: impl FnOnce(&str) -> Option<String>,
+    ) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = response.text().await.unwrap_or_default();
+        let message = parse_message(&body).unwrap_or(body);
+        Self {
+            provider,
+            status,
+            retry_after,
+            message,
+        }
+    }
+
+    /// Rate limits, timeouts and server side failures are worth retrying, other
+    /// client errors such as a bad API key are not.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.status,
+            StatusCode::REQUEST_TIMEOUT | StatusCode::CONFLICT | StatusCode::TOO_MANY_REQUESTS
+        ) || self.status.is_server_error()
+            // Anthropic's "overloaded" status.
+            || self.status.as_u16() == 529
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} API returned {}: {}",
+            self.provider, self.status, self.message
+        )
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Summarizers for channel summaries and for digests, which may use different models.
+pub struct Summarizers {
+    pub summaries: Arc<dyn Summarizer>,
+    pub digests: Arc<dyn Summarizer>,
+    /// Channel summarizers of the other models prompt profiles use, by model.
+    pub models: Arc<HashMap<String, Arc<dyn Summarizer>>>,
+}
+
+/// Creates the summarizers for the provider selected in the config. Channel summaries
+/// are routed to the small, default or premium model by input size, and digests use
+/// the premium model, according to `[gpt.routing]`. Summarizers are also created for
+/// each of `profile_models`.
+pub fn summarizers_from_config(
+    config: &GptConfig,
+    token_counter: Arc<dyn TokenCounter>,
+    max_request_tokens: usize,
+    layers: &RequestLayers,
+    profile_models: &[String],
+) -> Result<Summarizers, Error> {
+    let models = profile_models
+        .iter()
+        .map(|model| {
+            let summarizer = summarizer_for_model(
+                config,
+                model,
+                token_counter_for_model(model).into(),
+                max_request_tokens,
+                layers,
+            )?;
+            Ok((model.clone(), summarizer))
+        })
+        .collect::<Result<HashMap<_, _>, Error>>()?;
+    let models = Arc::new(models);
+    let routing = &config.routing;
+    let default_model = provider_model(config);
+    let default = summarizer_for_model(
+        config,
+        default_model,
+        token_counter.clone(),
+        max_request_tokens,
+        layers,
+    )?;
+
+    let mut routes = vec![];
+    if let Some(model) = &routing.small_model {
+        routes.push(Route {
+            model: model.clone(),
+            max_input_tokens: routing.small_input_tokens,
+            summarizer: summarizer_for_model(
+                config,
+                model,
+                token_counter_for_model(model).into(),
+                max_request_tokens,
+                layers,
+            )?,
+        });
+    }
+    let Some(premium_model) = &routing.premium_model else {
+        let summaries = if routes.is_empty() {
+            default.clone()
+        } else {
+            Arc::new(RoutingSummarizer::new(
+                routes,
+                default_model.to_string(),
+                default.clone(),
+                token_counter,
+            ))
+        };
+        return Ok(Summarizers {
+            summaries,
+            digests: default,
+            models,
+        });
+    };
+    let premium = summarizer_for_model(
+        config,
+        premium_model,
+        token_counter_for_model(premium_model).into(),
+        routing
+            .premium_max_request_tokens
+            .unwrap_or(max_request_tokens),
+        layers,
+    )?;
+    routes.push(Route {
+        model: default_model.to_string(),
+        max_input_tokens: routing.large_input_tokens.unwrap_or(max_request_tokens),
+        summarizer: default,
+    });
+    Ok(Summarizers {
+        summaries: Arc::new(RoutingSummarizer::new(
+            routes,
+            premium_model.clone(),
+            premium.clone(),
+            token_counter,
+        )),
+        digests: premium,
+        models,
+    })
+}
+
+/// Creates a summarizer using the given model of the configured provider, falling
+/// back to the backends listed in `[[gpt.fallbacks]]` in order when it keeps failing.
+fn summarizer_for_model(
+    config: &GptConfig,
+    model: &str,
+    token_counter: Arc<dyn TokenCounter>,
+    max_request_tokens: usize,
+    layers: &RequestLayers,
+) -> Result<Arc<dyn Summarizer>, Error> {
+    let primary = backend_summarizer(
+        config,
+        config.provider,
+        model,
+        token_counter,
+        max_request_tokens,
+        layers,
+    )?;
+    if config.fallbacks.is_empty() {
+        return Ok(primary);
+    }
+    let fallbacks = config
+        .fallbacks
+        .iter()
+        .map(|fallback| {
+            let model = fallback
+                .model
+                .as_deref()
+                .unwrap_or_else(|| configured_model(config, fallback.provider));
+            backend_summarizer(
+                config,
+                fallback.provider,
+                model,
+                token_counter_for_model(model).into(),
+                max_request_tokens,
+                layers,
+            )
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Arc::new(FallbackSummarizer::new(primary, fallbacks)))
+}
+
+/// What every backend's requests go through besides retries and chunking.
+#[derive(Clone)]
+pub struct RequestLayers {
+    pub usage: UsageRecorder,
+    /// Refuses requests once the daily budget is used up, when set.
+    pub budget: Option<Budget>,
+    /// Guards requests against prompt injection, when set.
+    pub guard: Option<PromptGuard>,
+    /// Refuses requests while LLM calls are paused.
+    pub pause: LlmPause,
+}
+
+impl RequestLayers {
+    /// Makes a request that does not go through a summarizer, such as describing an
+    /// image, refusing it while LLM calls are paused or once the daily budget is used
+    /// up, and retrying it according to `retry`.
+    pub async fn run<T, F, Fut>(&self, retry: &RetryPolicy, request: F) -> eyre::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = eyre::Result<T>>,
+    {
+        self.pause.check()?;
+        if let Some(budget) = &self.budget {
+            budget.check().await?;
+        }
+        retry.run(request).await
+    }
+}
+
+/// Creates a summarizer using a model of the given provider, guarding requests against
+/// prompt injection, retrying failed requests according to the retry policy, refusing
+/// requests once the daily budget is used up or while LLM calls are paused, and
+/// splitting inputs larger than `max_request_tokens`, or the model's own limit, into
+/// chunks. In dry-run mode, the provider is never called.
+fn backend_summarizer(
+    config: &GptConfig,
+    provider: LlmProvider,
+    model: &str,
+    token_counter: Arc<dyn TokenCounter>,
+    max_request_tokens: usize,
+    layers: &RequestLayers,
+) -> Result<Arc<dyn Summarizer>, Error> {
+    let mut provider = provider_client(config, provider, model, layers.usage.clone())?;
+    if let Some(guard) = layers.guard.clone() {
+        provider = Arc::new(GuardedSummarizer::new(provider, guard));
+    }
+    let window_tokens = provider
+        .max_input_tokens()
+        .map_or(max_request_tokens, |max| max.min(max_request_tokens));
+    let mut summarizer: Arc<dyn Summarizer> =
+        Arc::new(RetryingSummarizer::new(provider, &config.retry));
+    if let Some(budget) = layers.budget.clone() {
+        summarizer = Arc::new(BudgetedSummarizer::new(summarizer, budget));
+    }
+    summarizer = Arc::new(PausableSummarizer::new(summarizer, layers.pause.clone()));
+    Ok(Arc::new(ChunkingSummarizer::new(
+        summarizer,
+        token_counter,
+        window_tokens,
+    )))
+}
+
+/// Creates a client of a provider's API using the given model, which makes a single
+/// request per call.
+fn provider_client(
+    config: &GptConfig,
+    provider: LlmProvider,
+    model: &str,
+    usage: UsageRecorder,
+) -> Result<Arc<dyn Summarizer>, Error> {
+    Ok(match provider {
+        _ if config.dry_run => Arc::new(DryRunSummarizer),
+        LlmProvider::OpenAi => {
+            Arc::new(OpenAiSummarizer::new(&config.openai, usage)?.with_model(model))
+        }
+        LlmProvider::Anthropic => {
+            Arc::new(AnthropicSummarizer::new(&config.anthropic, usage)?.with_model(model))
+        }
+        LlmProvider::Ollama => {
+            Arc::new(OllamaSummarizer::new(&config.ollama, usage).with_model(model))
+        }
+    })
+}
+
+/// Creates a client of the selected provider's API using its configured model, with
+/// none of the retries, budget, pause or chunking of the other summarizers, to check
+/// that the API can be reached.
+pub fn provider_summarizer(
+    config: &GptConfig,
+    usage: UsageRecorder,
+) -> Result<Arc<dyn Summarizer>, Error> {
+    provider_client(config, config.provider, provider_model(config), usage)
+}
+
+/// The model configured for the selected provider.
+fn provider_model(config: &GptConfig) -> &str {
+    configured_model(config, config.provider)
+}
+
+/// The model configured in a provider's section.
+fn configured_model(config: &GptConfig, provider: LlmProvider) -> &str {
+    match provider {
+        LlmProvider::OpenAi => &config.openai.model,
+        LlmProvider::Anthropic => &config.anthropic.model,
+        LlmProvider::Ollama => &config.ollama.model,
+    }
+}
+
+/// Creates a token counter matching the model of the provider selected in the config.
+pub fn token_counter_from_config(config: &GptConfig) -> Arc<dyn TokenCounter> {
+    token_counter_for_model(provider_model(config)).into()
+}
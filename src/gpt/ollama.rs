@@ -0,0 +1,112 @@
+use axum::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::OllamaConfig;
+use crate::db::UsageKind;
+
+use super::{ApiError, Summarizer, TokenUsage, UsageRecorder};
+
+#[derive(Deserialize, Debug)]
+struct ChatResponse {
+    message: ChatMessage,
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatMessage {
+    content: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Summarizes content with a model served by a local Ollama instance, so messages
+/// never leave the machine.
+pub struct OllamaSummarizer {
+    client: reqwest::Client,
+    chat_url: String,
+    model: String,
+    context_tokens: usize,
+    usage: UsageRecorder,
+}
+
+impl OllamaSummarizer {
+    pub fn new(config: &OllamaConfig, usage: UsageRecorder) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            chat_url: format!("{}/api/chat", config.base_url.trim_end_matches('/')),
+            model: config.model.clone(),
+            context_tokens: config.context_tokens,
+            usage,
+        }
+    }
+
+    /// Uses a model other than the configured one, from the same provider.
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl Summarizer for OllamaSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        let response = self
+            .client
+            .post(&self.chat_url)
+            .json(&json!({
+                "model": self.model,
+                "stream": false,
+                "options": {
+                    "num_ctx": self.context_tokens,
+                },
+                "messages": [
+                    {
+                        "role": "system",
+                        "content": instructions
+                    },
+                    {
+                        "role": "user",
+                        "content": text,
+                    }
+                ],
+            }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error = ApiError::from_response("Ollama", response, |body| {
+                serde_json::from_str::<ErrorResponse>(body)
+                    .ok()
+                    .map(|e| e.error)
+            })
+            .await;
+            return Err(error.into());
+        }
+        let response = response.json::<ChatResponse>().await?;
+        let usage = TokenUsage {
+            prompt_tokens: response.prompt_eval_count,
+            completion_tokens: response.eval_count,
+        };
+        self.usage
+            .record("Ollama", &self.model, UsageKind::Completion, usage)
+            .await;
+        Ok(response.message.content)
+    }
+
+    /// Local models have small context windows, so only half of it is used for the
+    /// input to leave room for the prompt and the summary itself.
+    fn max_input_tokens(&self) -> Option<usize> {
+        Some(self.context_tokens / 2)
+    }
+
+    fn backend(&self) -> Option<String> {
+        Some(format!("ollama/{}", self.model))
+    }
+}
@@ -0,0 +1,209 @@
+use axum::async_trait;
+use eyre::{bail, WrapErr};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use tracing::warn;
+
+use crate::config::OpenAiConfig;
+use crate::db::UsageKind;
+use crate::error::{Error, Result};
+
+use super::{ApiError, Summarizer, TokenUsage, UsageRecorder};
+
+#[derive(Deserialize, Debug)]
+pub struct ChatCompletionResponse {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CompletionUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Choice {
+    message: GptMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GptMessage {
+    content: Option<String>,
+    /// Set instead of `content` when the model declines to answer.
+    refusal: Option<String>,
+}
+
+/// Body of an error response. Some gateways also send it with a 200 status.
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// A string such as `context_length_exceeded`, or an HTTP status on gateways like
+    /// OpenRouter.
+    code: Option<serde_json::Value>,
+}
+
+impl ErrorDetail {
+    fn describe(&self) -> String {
+        let code = match &self.code {
+            Some(serde_json::Value::String(code)) => Some(code.as_str()),
+            _ => None,
+        };
+        match code.or(self.kind.as_deref()) {
+            Some("context_length_exceeded") => format!(
+                "request exceeds the model's context window: {}",
+                self.message
+            ),
+            Some("rate_limit_exceeded") | Some("insufficient_quota") => {
+                format!("API is rate limited or out of quota: {}", self.message)
+            }
+            Some(kind) => format!("{kind}: {}", self.message),
+            None => self.message.clone(),
+        }
+    }
+
+    /// The HTTP status a gateway reported inside a successful response.
+    fn status(&self) -> Option<StatusCode> {
+        let code = self.code.as_ref()?.as_u64()?;
+        StatusCode::from_u16(u16::try_from(code).ok()?).ok()
+    }
+}
+
+/// Summarizes content using OpenAI's chat completions API, or any gateway that
+/// implements it such as Azure OpenAI, OpenRouter or vLLM.
+pub struct OpenAiSummarizer {
+    client: reqwest::Client,
+    api_key: String,
+    completions_url: String,
+    azure_api_version: Option<String>,
+    model: String,
+    max_tokens: u32,
+    temperature: Option<f32>,
+    usage: UsageRecorder,
+}
+
+impl OpenAiSummarizer {
+    pub fn new(config: &OpenAiConfig, usage: UsageRecorder) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            api_key: env::var("OPEN_AI_SECRET")
+                .map_err(|_| Error::MissingEnvVar("OPEN_AI_SECRET"))?,
+            completions_url: format!("{}/chat/completions", config.api_base.trim_end_matches('/')),
+            azure_api_version: config.azure_api_version.clone(),
+            model: config.model.clone(),
+            max_tokens: config.max_tokens,
+            temperature: config.temperature,
+            usage,
+        })
+    }
+
+    /// Uses a model other than the configured one, from the same provider.
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl Summarizer for OpenAiSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": [
+                {
+                    "role": "system",
+                    "content": instructions
+                },
+                {
+                    "role": "user",
+                    "content": text,
+                }
+            ],
+            "max_tokens": self.max_tokens,
+        });
+        if let Some(temperature) = self.temperature {
+            body["temperature"] = json!(temperature);
+        }
+
+        // Azure authenticates with an `api-key` header and versions its API through
+        // a query parameter rather than the path.
+        let request = match &self.azure_api_version {
+            Some(api_version) => self
+                .client
+                .post(&self.completions_url)
+                .query(&[("api-version", api_version)])
+                .header("api-key", &self.api_key),
+            None => self
+                .client
+                .post(&self.completions_url)
+                .header("Authorization", format!("Bearer {}", self.api_key)),
+        };
+        let response = request.json(&body).send().await?;
+        if !response.status().is_success() {
+            let error = ApiError::from_response("OpenAI", response, |body| {
+                let ErrorResponse { error } = serde_json::from_str(body).ok()?;
+                Some(error.describe())
+            })
+            .await;
+            return Err(error.into());
+        }
+
+        let status = response.status();
+        let body = response.text().await?;
+        if let Ok(ErrorResponse { error }) = serde_json::from_str(&body) {
+            return Err(ApiError {
+                provider: "OpenAI",
+                status: error.status().unwrap_or(status),
+                retry_after: None,
+                message: error.describe(),
+            }
+            .into());
+        }
+        let response: ChatCompletionResponse = serde_json::from_str(&body)
+            .wrap_err_with(|| format!("Unexpected OpenAI API response: {body}"))?;
+        if let Some(usage) = &response.usage {
+            let usage = TokenUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+            };
+            self.usage
+                .record("OpenAI", &self.model, UsageKind::Completion, usage)
+                .await;
+        }
+        let Some(choice) = response.choices.into_iter().next() else {
+            bail!("OpenAI API returned no choices");
+        };
+        match choice.finish_reason.as_deref() {
+            Some("length") => warn!(
+                "OpenAI response was cut off at {} tokens, summary is truncated",
+                self.max_tokens
+            ),
+            Some("content_filter") => {
+                bail!("OpenAI API withheld the response with its content filter")
+            }
+            _ => {}
+        }
+        if let Some(refusal) = choice.message.refusal {
+            bail!("Model refused to summarize: {refusal}");
+        }
+        match choice.message.content {
+            Some(content) if !content.trim().is_empty() => Ok(content),
+            _ => bail!("OpenAI API returned an empty response"),
+        }
+    }
+
+    fn backend(&self) -> Option<String> {
+        Some(format!("openai/{}", self.model))
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::async_trait;
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use super::Summarizer;
+
+/// Returned instead of calling the API while LLM calls are paused.
+#[derive(Debug)]
+pub struct LlmPaused {
+    /// When the quiet hours end, or `None` when an admin paused LLM calls.
+    pub resumes_at: Option<DateTime<Utc>>,
+}
+
+impl fmt::Display for LlmPaused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.resumes_at {
+            Some(resumes_at) => write!(
+                f,
+                "LLM calls are paused for quiet hours, they resume at {}",
+                resumes_at.format("%Y-%m-%d %H:%M UTC")
+            ),
+            None => write!(f, "LLM calls are paused until an admin resumes them"),
+        }
+    }
+}
+
+impl std::error::Error for LlmPaused {}
+
+/// A daily period during which no LLM calls are made, in the reporting timezone. Ends
+/// the next day when it starts later than it ends, such as 22:00 to 07:00.
+#[derive(Clone, Copy)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub timezone: Tz,
+}
+
+impl QuietHours {
+    /// When the quiet hours `now` falls into end, or `None` outside of quiet hours.
+    fn ends_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = now.with_timezone(&self.timezone);
+        let time = local.time();
+        let today = local.date_naive();
+        let end_day = if self.start <= self.end {
+            (time >= self.start && time < self.end).then_some(today)?
+        } else if time >= self.start {
+            today + Duration::days(1)
+        } else if time < self.end {
+            today
+        } else {
+            return None;
+        };
+        let end = end_day
+            .and_time(self.end)
+            .and_local_timezone(self.timezone)
+            .earliest()?;
+        Some(end.with_timezone(&Utc))
+    }
+}
+
+/// Whether LLM calls are paused, either by an admin until they resume them, or for the
+/// configured quiet hours. Messages keep being logged while paused, and are summarized
+/// once calls resume.
+#[derive(Clone)]
+pub struct LlmPause {
+    paused: Arc<AtomicBool>,
+    quiet_hours: Option<QuietHours>,
+}
+
+impl LlmPause {
+    pub fn new(quiet_hours: Option<QuietHours>) -> Self {
+        Self {
+            paused: Arc::new(AtomicBool::new(false)),
+            quiet_hours,
+        }
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether an admin paused LLM calls, regardless of quiet hours.
+    pub fn paused_by_admin(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.check().is_err()
+    }
+
+    /// Fails while LLM calls are paused.
+    pub fn check(&self) -> Result<(), LlmPaused> {
+        if self.paused_by_admin() {
+            return Err(LlmPaused { resumes_at: None });
+        }
+        match self
+            .quiet_hours
+            .and_then(|quiet_hours| quiet_hours.ends_after(Utc::now()))
+        {
+            Some(resumes_at) => Err(LlmPaused {
+                resumes_at: Some(resumes_at),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Refuses to call the wrapped summarizer while LLM calls are paused.
+pub struct PausableSummarizer {
+    inner: Arc<dyn Summarizer>,
+    pause: LlmPause,
+}
+
+impl PausableSummarizer {
+    pub fn new(inner: Arc<dyn Summarizer>, pause: LlmPause) -> Self {
+        Self { inner, pause }
+    }
+}
+
+#[async_trait]
+impl Summarizer for PausableSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.pause.check()?;
+        self.inner.complete(instructions, text).await
+    }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        self.inner.max_input_tokens()
+    }
+
+    fn backend(&self) -> Option<String> {
+        self.inner.backend()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Paris;
+
+    use super::*;
+
+    fn quiet_hours(start: (u32, u32), end: (u32, u32), timezone: Tz) -> QuietHours {
+        QuietHours {
+            start: NaiveTime::from_hms_opt(start.0, start.1, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(end.0, end.1, 0).unwrap(),
+            timezone,
+        }
+    }
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let quiet = quiet_hours((12, 0), (14, 0), Tz::UTC);
+        assert_eq!(quiet.ends_after(utc(5, 13, 0)), Some(utc(5, 14, 0)));
+        assert_eq!(quiet.ends_after(utc(5, 12, 0)), Some(utc(5, 14, 0)));
+        assert_eq!(quiet.ends_after(utc(5, 14, 0)), None);
+        assert_eq!(quiet.ends_after(utc(5, 11, 59)), None);
+    }
+
+    #[test]
+    fn quiet_hours_over_midnight_end_the_next_day() {
+        let quiet = quiet_hours((22, 0), (7, 0), Tz::UTC);
+        assert_eq!(quiet.ends_after(utc(5, 23, 30)), Some(utc(6, 7, 0)));
+        assert_eq!(quiet.ends_after(utc(6, 3, 0)), Some(utc(6, 7, 0)));
+        assert_eq!(quiet.ends_after(utc(6, 7, 0)), None);
+        assert_eq!(quiet.ends_after(utc(6, 12, 0)), None);
+    }
+
+    #[test]
+    fn quiet_hours_follow_the_reporting_timezone() {
+        // Paris is an hour ahead of UTC in early March.
+        let quiet = quiet_hours((22, 0), (7, 0), Paris);
+        assert_eq!(quiet.ends_after(utc(5, 21, 30)), Some(utc(6, 6, 0)));
+        assert_eq!(quiet.ends_after(utc(5, 20, 30)), None);
+        // Clocks go forward on the night of March 31st, so they end an hour earlier in UTC.
+        assert_eq!(quiet.ends_after(utc(30, 23, 0)), Some(utc(31, 5, 0)));
+    }
+
+    #[test]
+    fn a_pause_by_an_admin_lasts_until_they_resume() {
+        let pause = LlmPause::new(None);
+        assert!(pause.check().is_ok());
+        pause.pause();
+        assert!(pause.check().is_err_and(|e| e.resumes_at.is_none()));
+        assert!(pause.paused_by_admin());
+        pause.resume();
+        assert!(!pause.is_paused());
+    }
+}
@@ -0,0 +1,232 @@
+use axum::async_trait;
+use eyre::eyre;
+use rand::Rng;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tracing::warn;
+
+use crate::config::RetryConfig;
+
+use super::{ApiError, Summarizer};
+
+/// When and how often failed LLM requests are retried, with exponential backoff and
+/// jitter, waiting for as long as the API asks through `Retry-After` when it does. Requests
+/// the API asks to wait longer than the maximum backoff for are not retried, so that
+/// callers are not held up for that long.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    request_timeout: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(config: &RetryConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            jitter: config.jitter,
+            request_timeout: Duration::from_secs(config.request_timeout_seconds),
+        }
+    }
+
+    /// Gives every attempt at least `request_timeout`, for requests that are known to
+    /// take long.
+    pub fn with_min_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = self.request_timeout.max(request_timeout);
+        self
+    }
+
+    /// Picks a random delay between half and all of the backoff, so that
+    /// concurrent retries do not hit the API at the same moment.
+    fn jittered(&self, backoff: Duration) -> Duration {
+        if !self.jitter || backoff.is_zero() {
+            return backoff;
+        }
+        rand::thread_rng().gen_range(backoff / 2..=backoff)
+    }
+
+    /// Makes the request until it succeeds, fails with an error not worth retrying, or
+    /// runs out of attempts.
+    pub async fn run<T, F, Fut>(&self, request: F) -> eyre::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = eyre::Result<T>>,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let err = match timeout(self.request_timeout, request()).await {
+                Ok(Ok(reply)) => return Ok(reply),
+                Ok(Err(e)) => e,
+                Err(_) => eyre!("LLM request timed out after {:?}", self.request_timeout),
+            };
+            let api_error = err.downcast_ref::<ApiError>();
+            let retryable = !matches!(api_error, Some(e) if !e.is_retryable());
+            if !retryable || attempt >= self.max_attempts {
+                return Err(err);
+            }
+            let delay = match api_error.and_then(|e| e.retry_after) {
+                Some(retry_after) if retry_after > self.max_backoff => return Err(err),
+                Some(retry_after) => retry_after,
+                None => self.jittered(backoff),
+            };
+            warn!(
+                "LLM request attempt {attempt}/{} failed, retrying in {delay:?}: {err}",
+                self.max_attempts
+            );
+            sleep(delay).await;
+            backoff = (backoff * 2).min(self.max_backoff);
+            attempt += 1;
+        }
+    }
+}
+
+/// Retries failed summarizations according to the retry policy.
+pub struct RetryingSummarizer {
+    inner: Arc<dyn Summarizer>,
+    policy: RetryPolicy,
+}
+
+impl RetryingSummarizer {
+    pub fn new(inner: Arc<dyn Summarizer>, config: &RetryConfig) -> Self {
+        Self {
+            inner,
+            policy: RetryPolicy::new(config),
+        }
+    }
+}
+
+#[async_trait]
+impl Summarizer for RetryingSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.policy
+            .run(|| self.inner.complete(instructions, text))
+            .await
+    }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        self.inner.max_input_tokens()
+    }
+
+    fn backend(&self) -> Option<String> {
+        self.inner.backend()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use eyre::bail;
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    fn policy(max_attempts: u32, jitter: bool) -> RetryPolicy {
+        RetryPolicy::new(&RetryConfig {
+            max_attempts,
+            initial_backoff_ms: 0,
+            max_backoff_ms: 0,
+            jitter,
+            request_timeout_seconds: 5,
+        })
+    }
+
+    fn api_error(status: StatusCode) -> eyre::Report {
+        rate_limited(status, None)
+    }
+
+    fn rate_limited(status: StatusCode, retry_after: Option<Duration>) -> eyre::Report {
+        ApiError {
+            provider: "Test",
+            status,
+            retry_after,
+            message: String::new(),
+        }
+        .into()
+    }
+
+    #[tokio::test]
+    async fn failed_requests_are_retried_until_they_succeed() {
+        let attempts = AtomicU32::new(0);
+        let reply = policy(5, false)
+            .run(|| async {
+                if attempts.fetch_add(1, Ordering::Relaxed) < 2 {
+                    return Err(api_error(StatusCode::TOO_MANY_REQUESTS));
+                }
+                Ok("done")
+            })
+            .await
+            .unwrap();
+        assert_eq!(reply, "done");
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_after_the_last_attempt() {
+        let attempts = AtomicU32::new(0);
+        let result: eyre::Result<()> = policy(3, false)
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                bail!("connection reset")
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let attempts = AtomicU32::new(0);
+        let result: eyre::Result<()> = policy(5, false)
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(api_error(StatusCode::UNAUTHORIZED))
+            })
+            .await;
+        assert!(result.unwrap_err().downcast_ref::<ApiError>().is_some());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn requests_asked_to_wait_longer_than_the_max_backoff_are_not_retried() {
+        let attempts = AtomicU32::new(0);
+        let retry_after = Some(Duration::from_secs(3600));
+        let result: eyre::Result<()> = policy(5, false)
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(rate_limited(StatusCode::TOO_MANY_REQUESTS, retry_after))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn jitter_waits_between_half_and_all_of_the_backoff() {
+        let backoff = Duration::from_millis(1000);
+        for _ in 0..100 {
+            let delay = policy(1, true).jittered(backoff);
+            assert!(delay >= backoff / 2 && delay <= backoff, "{delay:?}");
+        }
+        assert_eq!(policy(1, false).jittered(backoff), backoff);
+    }
+
+    #[test]
+    fn slow_requests_get_at_least_their_minimum_timeout() {
+        let timeout = policy(1, false)
+            .with_min_request_timeout(Duration::from_secs(600))
+            .request_timeout;
+        assert_eq!(timeout, Duration::from_secs(600));
+        let timeout = policy(1, false)
+            .with_min_request_timeout(Duration::from_secs(1))
+            .request_timeout;
+        assert_eq!(timeout, Duration::from_secs(5));
+    }
+}
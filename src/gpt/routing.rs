@@ -0,0 +1,79 @@
+use axum::async_trait;
+use std::sync::Arc;
+use tracing::debug;
+
+use super::{StructuredSummary, Summarizer, TokenCounter};
+
+/// A model handling inputs up to a given size.
+pub struct Route {
+    /// Name of the model, for logging.
+    pub model: String,
+    /// Largest input in tokens sent to this route.
+    pub max_input_tokens: usize,
+    pub summarizer: Arc<dyn Summarizer>,
+}
+
+/// Sends each input to the first route that takes inputs of its size, so that small
+/// inputs can go to a cheaper model and large ones to a more capable one. Each route
+/// chunks inputs according to its own request limit.
+pub struct RoutingSummarizer {
+    /// Ordered from smallest to largest input.
+    routes: Vec<Route>,
+    /// Model taking every input too large for the routes.
+    largest: (String, Arc<dyn Summarizer>),
+    token_counter: Arc<dyn TokenCounter>,
+}
+
+impl RoutingSummarizer {
+    pub fn new(
+        routes: Vec<Route>,
+        largest_model: String,
+        largest: Arc<dyn Summarizer>,
+        token_counter: Arc<dyn TokenCounter>,
+    ) -> Self {
+        Self {
+            routes,
+            largest: (largest_model, largest),
+            token_counter,
+        }
+    }
+
+    fn route(&self, text: &str) -> &Arc<dyn Summarizer> {
+        let tokens = self.token_counter.count_tokens(text);
+        let (model, summarizer) = self
+            .routes
+            .iter()
+            .find(|route| tokens <= route.max_input_tokens)
+            .map_or((&self.largest.0, &self.largest.1), |route| {
+                (&route.model, &route.summarizer)
+            });
+        debug!("Routing input of {tokens} tokens to {model}");
+        summarizer
+    }
+}
+
+#[async_trait]
+impl Summarizer for RoutingSummarizer {
+    async fn complete(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.route(text).complete(instructions, text).await
+    }
+
+    async fn summarize(&self, instructions: &str, text: &str) -> eyre::Result<String> {
+        self.route(text).summarize(instructions, text).await
+    }
+
+    async fn summarize_structured(
+        &self,
+        instructions: &str,
+        text: &str,
+    ) -> eyre::Result<StructuredSummary> {
+        self.route(text)
+            .summarize_structured(instructions, text)
+            .await
+    }
+
+    /// The limit of the model taking the largest inputs.
+    fn max_input_tokens(&self) -> Option<usize> {
+        self.largest.1.max_input_tokens()
+    }
+}
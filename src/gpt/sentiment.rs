@@ -0,0 +1,60 @@
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use super::Summarizer;
+
+/// Instructions asking the model to score the tone of a conversation.
+const SENTIMENT_INSTRUCTIONS: &str = r#"You rate the overall sentiment and tone of Discord conversations for moderators. Reply with a single JSON object and nothing else, in this format:
+{
+  "score": a number from -1 for hostile or very negative, through 0 for neutral, to 1 for very positive,
+  "tone": "the tone of the conversation in one to three words, such as friendly, heated or frustrated"
+}"#;
+
+/// Only the end of longer transcripts is scored, as it is where a conversation turning
+/// sour shows and it keeps the extra request small.
+const MAX_TRANSCRIPT_CHARS: usize = 24_000;
+
+/// The overall sentiment of a conversation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sentiment {
+    /// From -1, very negative, to 1, very positive.
+    pub score: f64,
+    pub tone: String,
+}
+
+impl Sentiment {
+    /// Parses the model's reply, tolerating Markdown code fences or text around the
+    /// JSON object.
+    fn parse(reply: &str) -> eyre::Result<Self> {
+        let start = reply.find('{');
+        let end = reply.rfind('}');
+        let json = match start.zip(end) {
+            Some((start, end)) if start < end => &reply[start..=end],
+            _ => return Err(eyre!("Reply does not contain a JSON object")),
+        };
+        let mut parsed: Self =
+            serde_json::from_str(json).wrap_err("Reply is not a valid sentiment")?;
+        if !parsed.score.is_finite() {
+            return Err(eyre!("Sentiment score is not a number"));
+        }
+        parsed.score = parsed.score.clamp(-1.0, 1.0);
+        parsed.tone = parsed.tone.trim().to_lowercase();
+        Ok(parsed)
+    }
+}
+
+/// Asks the model for the overall sentiment and tone of a transcript.
+pub async fn analyze_sentiment(
+    summarizer: &dyn Summarizer,
+    transcript: &str,
+) -> eyre::Result<Sentiment> {
+    let start = transcript
+        .char_indices()
+        .rev()
+        .nth(MAX_TRANSCRIPT_CHARS)
+        .map_or(0, |(i, _)| i);
+    let reply = summarizer
+        .complete(SENTIMENT_INSTRUCTIONS, &transcript[start..])
+        .await?;
+    Sentiment::parse(&reply)
+}
@@ -0,0 +1,147 @@
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+
+/// Appended to the summary instructions to ask the model for a JSON object matching
+/// [`StructuredSummary`].
+pub const STRUCTURED_SUMMARY_FORMAT: &str = r#"Reply with a single JSON object and nothing else, in this format:
+{
+  "summary": "a thorough summary of the discussion, as prose",
+  "topics": ["each topic discussed, in a few words"],
+  "decisions": ["each decision that was made"],
+  "action_items": [{"description": "a task someone has to do", "owner": "who is expected to do it, or null if nobody was named"}],
+  "open_questions": ["each question that was raised and not answered"],
+  "entities": [{"name": "each person, project, tool or ticket mentioned, as named in the discussion", "kind": "person, project, tool, ticket or other"}]
+}
+Use empty lists when there is nothing to report for a field."#;
+
+/// A summary broken down into the topics, decisions, action items, open questions and
+/// entities of the discussion it covers.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StructuredSummary {
+    pub summary: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub decisions: Vec<String>,
+    #[serde(default)]
+    pub action_items: Vec<ActionItem>,
+    #[serde(default)]
+    pub open_questions: Vec<String>,
+    #[serde(default)]
+    pub entities: Vec<Entity>,
+    /// Provider and model that produced the summary, such as `openai/gpt-4`.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ActionItem {
+    pub description: String,
+    /// Who is expected to do it, as named in the discussion.
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+/// A person, project, tool or ticket a discussion mentions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entity {
+    pub name: String,
+    #[serde(default)]
+    pub kind: EntityKind,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum EntityKind {
+    Person,
+    Project,
+    Tool,
+    Ticket,
+    /// Anything else, including kinds the model made up.
+    #[default]
+    #[serde(other)]
+    Other,
+}
+
+impl StructuredSummary {
+    /// Parses the model's reply to [`STRUCTURED_SUMMARY_FORMAT`], tolerating Markdown
+    /// code fences or text around the JSON object.
+    pub fn parse(reply: &str) -> eyre::Result<Self> {
+        let start = reply.find('{');
+        let end = reply.rfind('}');
+        let json = match start.zip(end) {
+            Some((start, end)) if start < end => &reply[start..=end],
+            _ => return Err(eyre!("Reply does not contain a JSON object")),
+        };
+        let mut parsed: Self =
+            serde_json::from_str(json).wrap_err("Reply is not a valid structured summary")?;
+        parsed.summary = parsed.summary.trim().to_string();
+        if parsed.summary.is_empty() {
+            return Err(eyre!("Structured summary has an empty summary"));
+        }
+        // Models sometimes pad lists with blank entries.
+        for list in [
+            &mut parsed.topics,
+            &mut parsed.decisions,
+            &mut parsed.open_questions,
+        ] {
+            list.retain(|item| !item.trim().is_empty());
+        }
+        parsed
+            .action_items
+            .retain(|item| !item.description.trim().is_empty());
+        for item in &mut parsed.action_items {
+            item.owner = item.owner.take().filter(|owner| !owner.trim().is_empty());
+        }
+        let mut entities: Vec<Entity> = vec![];
+        for mut entity in parsed.entities.drain(..) {
+            entity.name = entity.name.trim().to_string();
+            if !entity.name.is_empty()
+                && !entities
+                    .iter()
+                    .any(|seen| seen.name.to_lowercase() == entity.name.to_lowercase())
+            {
+                entities.push(entity);
+            }
+        }
+        parsed.entities = entities;
+        Ok(parsed)
+    }
+
+    /// Wraps a plain summary, for replies that could not be parsed.
+    pub fn unstructured(summary: String) -> Self {
+        Self {
+            summary,
+            ..Self::default()
+        }
+    }
+
+    /// Renders the summary as Markdown for Discord, with a section per non-empty list.
+    pub fn render(&self) -> String {
+        let mut rendered = self.summary.clone();
+        let mut section = |title: &str, items: Vec<String>| {
+            if items.is_empty() {
+                return;
+            }
+            rendered.push_str(&format!("\n\n**{title}**"));
+            for item in items {
+                rendered.push_str(&format!("\n- {item}"));
+            }
+        };
+        section("Topics", self.topics.clone());
+        section("Decisions", self.decisions.clone());
+        section(
+            "Action items",
+            self.action_items
+                .iter()
+                .map(|item| match &item.owner {
+                    Some(owner) => format!("{} ({owner})", item.description),
+                    None => item.description.clone(),
+                })
+                .collect(),
+        );
+        section("Open questions", self.open_questions.clone());
+        rendered
+    }
+}
@@ -0,0 +1,46 @@
+use tiktoken_rs::CoreBPE;
+use tracing::warn;
+
+/// Rough number of characters per token, for when no tokenizer is available.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Counts how many tokens a piece of text takes up in a model's context window.
+pub trait TokenCounter: Send + Sync {
+    fn count_tokens(&self, text: &str) -> usize;
+}
+
+/// Counts tokens with a byte pair encoding tokenizer.
+pub struct BpeTokenCounter {
+    bpe: CoreBPE,
+}
+
+impl TokenCounter for BpeTokenCounter {
+    fn count_tokens(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+}
+
+/// Estimates tokens from the character count of the text.
+pub struct HeuristicTokenCounter;
+
+impl TokenCounter for HeuristicTokenCounter {
+    fn count_tokens(&self, text: &str) -> usize {
+        text.chars().count() / CHARS_PER_TOKEN
+    }
+}
+
+/// Creates a counter using the tokenizer of an OpenAI model. Models tiktoken does not
+/// know, such as Claude or local models, are approximated with `cl100k_base`.
+pub fn token_counter_for_model(model: &str) -> Box<dyn TokenCounter> {
+    let bpe = match tiktoken_rs::get_bpe_from_model(model) {
+        Ok(bpe) => Ok(bpe),
+        Err(_) => tiktoken_rs::cl100k_base(),
+    };
+    match bpe {
+        Ok(bpe) => Box::new(BpeTokenCounter { bpe }),
+        Err(e) => {
+            warn!("Could not load a tokenizer for model {model}, estimating token counts: {e}");
+            Box::new(HeuristicTokenCounter)
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use axum::async_trait;
+use eyre::{bail, eyre};
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::GptConfig;
+use crate::db::UsageKind;
+use crate::error::{Error, Result};
+
+use super::vision::error_message;
+use super::{ApiError, RequestLayers, RetryPolicy, TokenUsage};
+
+/// Recordings can be long, and are transcribed in a single request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Turns recordings of meetings into text, so that they can be summarized like chat.
+#[async_trait]
+pub trait Transcriber: Send + Sync {
+    /// Downloads the recording at `url` and returns what was said in it.
+    async fn transcribe(&self, url: &str, filename: &str) -> eyre::Result<String>;
+
+    /// Returns what was said in a recording, whose format is told by its file name.
+    async fn transcribe_audio(&self, audio: Vec<u8>, filename: &str) -> eyre::Result<String>;
+
+    /// Recordings larger than this many bytes are not transcribed.
+    fn max_audio_bytes(&self) -> u64;
+}
+
+#[derive(Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+    usage: Option<TranscriptionUsage>,
+}
+
+/// Only reported by the newer transcription models, which are billed by token. Models
+/// billed by the second of audio report none.
+#[derive(Deserialize)]
+struct TranscriptionUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+/// Transcribes recordings with Whisper through the OpenAI audio API, or any server
+/// implementing it such as whisper.cpp's.
+pub struct WhisperTranscriber {
+    client: reqwest::Client,
+    transcriptions_url: String,
+    /// Local servers usually do not need one.
+    api_key: Option<String>,
+    model: String,
+    language: Option<String>,
+    max_audio_bytes: u64,
+    layers: RequestLayers,
+    retry: RetryPolicy,
+}
+
+impl WhisperTranscriber {
+    async fn send(&self, audio: &[u8], filename: &str) -> eyre::Result<TranscriptionResponse> {
+        let mut form = Form::new()
+            .part(
+                "file",
+                Part::bytes(audio.to_vec()).file_name(filename.to_string()),
+            )
+            .text("model", self.model.clone())
+            .text("response_format", "json");
+        if let Some(language) = &self.language {
+            form = form.text("language", language.clone());
+        }
+        let mut request = self.client.post(&self.transcriptions_url).multipart(form);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Whisper", response, error_message)
+                .await
+                .into());
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl Transcriber for WhisperTranscriber {
+    async fn transcribe(&self, url: &str, filename: &str) -> eyre::Result<String> {
+        self.layers.pause.check()?;
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        let audio = response.bytes().await?;
+        self.transcribe_audio(audio.to_vec(), filename).await
+    }
+
+    async fn transcribe_audio(&self, audio: Vec<u8>, filename: &str) -> eyre::Result<String> {
+        if audio.len() as u64 > self.max_audio_bytes {
+            bail!("recording is larger than {} bytes", self.max_audio_bytes);
+        }
+        let response = self
+            .layers
+            .run(&self.retry, || self.send(&audio, filename))
+            .await?;
+        let usage = response
+            .usage
+            .map_or_else(TokenUsage::default, |usage| TokenUsage {
+                prompt_tokens: usage.input_tokens,
+                completion_tokens: usage.output_tokens,
+            });
+        self.layers
+            .usage
+            .record("Whisper", &self.model, UsageKind::Transcription, usage)
+            .await;
+        let transcript = response.text.trim();
+        if transcript.is_empty() {
+            return Err(eyre!("Whisper returned an empty transcript"));
+        }
+        Ok(transcript.to_string())
+    }
+
+    fn max_audio_bytes(&self) -> u64 {
+        self.max_audio_bytes
+    }
+}
+
+/// Creates the transcriber configured under `[gpt.transcription]`, if it is enabled and
+/// not in dry-run mode. Requests are retried, and refused while LLM calls are paused or
+/// once the daily budget is used up.
+pub fn transcriber_from_config(
+    config: &GptConfig,
+    layers: &RequestLayers,
+) -> Result<Option<Arc<dyn Transcriber>>> {
+    let transcription = &config.transcription;
+    if !transcription.enabled || config.dry_run {
+        return Ok(None);
+    }
+    let api_key = env::var("OPEN_AI_SECRET").ok();
+    // Only servers of our own can be called without a key.
+    if transcription.api_base.is_none() && api_key.is_none() {
+        return Err(Error::MissingEnvVar("OPEN_AI_SECRET"));
+    }
+    let api_base = transcription
+        .api_base
+        .as_deref()
+        .unwrap_or(&config.openai.api_base);
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    Ok(Some(Arc::new(WhisperTranscriber {
+        client,
+        transcriptions_url: format!("{}/audio/transcriptions", api_base.trim_end_matches('/')),
+        api_key,
+        model: transcription.model.clone(),
+        language: transcription.language.clone(),
+        max_audio_bytes: transcription.max_audio_bytes,
+        layers: layers.clone(),
+        retry: RetryPolicy::new(&config.retry).with_min_request_timeout(REQUEST_TIMEOUT),
+    })))
+}
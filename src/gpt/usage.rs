@@ -0,0 +1,88 @@
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::error;
+
+use crate::config::ModelPrice;
+use crate::db::{self, UsageKind};
+
+/// Prices in US dollars per million input and output tokens of common models, used
+/// unless `[[gpt.prices]]` says otherwise.
+const BUILT_IN_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4", 30.0, 60.0),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-3.5-turbo", 0.5, 1.5),
+    ("text-embedding-3-small", 0.02, 0.0),
+    ("text-embedding-3-large", 0.13, 0.0),
+    ("claude-3-5-sonnet-20240620", 3.0, 15.0),
+    ("claude-3-opus-20240229", 15.0, 75.0),
+    ("claude-3-haiku-20240307", 0.25, 1.25),
+];
+
+/// Tokens used by a single LLM API call, as reported by the API.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Records the tokens used by every LLM API call, along with their estimated cost.
+#[derive(Clone)]
+pub struct UsageRecorder {
+    db: Arc<SqlitePool>,
+    /// Dollars per million input and output tokens, by model.
+    prices: Arc<HashMap<String, (f64, f64)>>,
+}
+
+impl UsageRecorder {
+    pub fn new(db: Arc<SqlitePool>, configured_prices: &[ModelPrice]) -> Self {
+        let mut prices: HashMap<String, (f64, f64)> = BUILT_IN_PRICES
+            .iter()
+            .map(|(model, input, output)| (model.to_string(), (*input, *output)))
+            .collect();
+        for price in configured_prices {
+            prices.insert(
+                price.model.clone(),
+                (
+                    price.input_per_million_tokens,
+                    price.output_per_million_tokens,
+                ),
+            );
+        }
+        Self {
+            db,
+            prices: Arc::new(prices),
+        }
+    }
+
+    /// Estimated cost of a call in US dollars. Models served by Ollama run locally and
+    /// are free, other models are only priced when their price is known.
+    pub fn cost(&self, provider: &str, model: &str, usage: TokenUsage) -> Option<f64> {
+        if provider == "Ollama" {
+            return Some(0.0);
+        }
+        let (input, output) = self.prices.get(model)?;
+        Some(
+            (usage.prompt_tokens as f64 * input + usage.completion_tokens as f64 * output)
+                / 1_000_000.0,
+        )
+    }
+
+    /// Stores the usage of a call. Failures are only logged, as losing track of a call
+    /// is not worth failing it over.
+    pub async fn record(&self, provider: &str, model: &str, kind: UsageKind, usage: TokenUsage) {
+        let new_usage = db::NewLlmUsage {
+            provider,
+            model,
+            kind,
+            prompt_tokens: usage.prompt_tokens as i64,
+            completion_tokens: usage.completion_tokens as i64,
+            cost_usd: self.cost(provider, model, usage),
+        };
+        if let Err(e) = db::insert_llm_usage(&self.db, new_usage).await {
+            error!("Could not record the usage of a {provider} {model} call: {e}");
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use axum::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use eyre::{bail, eyre};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{GptConfig, LlmProvider};
+use crate::db::UsageKind;
+use crate::error::{Error, Result};
+
+use super::anthropic::{API_VERSION, MESSAGES_URL};
+use super::{configured_model, ApiError, RequestLayers, RetryPolicy, TokenUsage};
+
+/// What images are described with, unless a prompt is configured.
+const DEFAULT_VISION_PROMPT: &str = "Describe this image in one or two sentences for someone reading the chat it was posted in. Include any short text it shows that matters to the conversation.";
+
+/// Largest description asked for, in tokens.
+const MAX_DESCRIPTION_TOKENS: u32 = 200;
+
+/// Images are described as messages are logged, which waits for them, so requests
+/// that hang are given up on well before those of summaries.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Describes images posted in channels, so that messages that are only an image still
+/// say something in summaries.
+#[async_trait]
+pub trait ImageDescriber: Send + Sync {
+    /// Downloads the image at `url` and returns a short description of it.
+    async fn describe(&self, url: &str, content_type: &str) -> eyre::Result<String>;
+
+    /// Images larger than this many bytes are not described.
+    fn max_image_bytes(&self) -> u64;
+}
+
+/// The APIs images can be sent to, each with its own request format.
+enum VisionApi {
+    OpenAi {
+        completions_url: String,
+        api_key: String,
+        azure_api_version: Option<String>,
+    },
+    Anthropic {
+        api_key: String,
+    },
+    Ollama {
+        chat_url: String,
+    },
+}
+
+/// Describes images with a vision-capable model of any of the supported providers.
+/// Images are sent inline, so they do not need to be reachable by the provider.
+pub struct VisionDescriber {
+    client: reqwest::Client,
+    api: VisionApi,
+    model: String,
+    prompt: String,
+    max_image_bytes: u64,
+    layers: RequestLayers,
+    retry: RetryPolicy,
+}
+
+#[derive(Deserialize)]
+struct OpenAiResponse {
+    choices: Vec<OpenAiChoice>,
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChoice {
+    message: OpenAiMessage,
+}
+
+#[derive(Deserialize)]
+struct OpenAiMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicBlock>,
+    usage: AnthropicUsage,
+}
+
+#[derive(Deserialize)]
+struct AnthropicBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct AnthropicUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+    message: OllamaMessage,
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
+}
+
+#[derive(Deserialize)]
+struct OllamaMessage {
+    content: String,
+}
+
+impl VisionDescriber {
+    /// Sends the image and the prompt to the API, returning its reply and the tokens
+    /// used.
+    async fn send(
+        &self,
+        image: &str,
+        content_type: &str,
+    ) -> eyre::Result<(&'static str, String, TokenUsage)> {
+        match &self.api {
+            VisionApi::OpenAi {
+                completions_url,
+                api_key,
+                azure_api_version,
+            } => {
+                let body = json!({
+                    "model": self.model,
+                    "max_tokens": MAX_DESCRIPTION_TOKENS,
+                    "messages": [{
+                        "role": "user",
+                        "content": [
+                            { "type": "text", "text": self.prompt },
+                            {
+                                "type": "image_url",
+                                "image_url": { "url": format!("data:{content_type};base64,{image}") }
+                            }
+                        ]
+                    }],
+                });
+                let request = match azure_api_version {
+                    Some(api_version) => self
+                        .client
+                        .post(completions_url)
+                        .query(&[("api-version", api_version)])
+                        .header("api-key", api_key),
+                    None => self
+                        .client
+                        .post(completions_url)
+                        .header("Authorization", format!("Bearer {api_key}")),
+                };
+                let response = request.json(&body).send().await?;
+                if !response.status().is_success() {
+                    return Err(ApiError::from_response("OpenAI", response, error_message)
+                        .await
+                        .into());
+                }
+                let response = response.json::<OpenAiResponse>().await?;
+                let usage = response
+                    .usage
+                    .map_or_else(TokenUsage::default, |usage| TokenUsage {
+                        prompt_tokens: usage.prompt_tokens,
+                        completion_tokens: usage.completion_tokens,
+                    });
+                let description = response
+                    .choices
+                    .into_iter()
+                    .next()
+                    .and_then(|choice| choice.message.content)
+                    .unwrap_or_default();
+                Ok(("OpenAI", description, usage))
+            }
+            VisionApi::Anthropic { api_key } => {
+                let body = json!({
+                    "model": self.model,
+                    "max_tokens": MAX_DESCRIPTION_TOKENS,
+                    "messages": [{
+                        "role": "user",
+                        "content": [
+                            {
+                                "type": "image",
+                                "source": { "type": "base64", "media_type": content_type, "data": image }
+                            },
+                            { "type": "text", "text": self.prompt }
+                        ]
+                    }],
+                });
+                let response = self
+                    .client
+                    .post(MESSAGES_URL)
+                    .header("x-api-key", api_key)
+                    .header("anthropic-version", API_VERSION)
+                    .json(&body)
+                    .send()
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(
+                        ApiError::from_response("Anthropic", response, error_message)
+                            .await
+                            .into(),
+                    );
+                }
+                let response = response.json::<AnthropicResponse>().await?;
+                let usage = TokenUsage {
+                    prompt_tokens: response.usage.input_tokens,
+                    completion_tokens: response.usage.output_tokens,
+                };
+                let description = response
+                    .content
+                    .into_iter()
+                    .filter(|block| block.kind == "text")
+                    .map(|block| block.text)
+                    .collect();
+                Ok(("Anthropic", description, usage))
+            }
+            VisionApi::Ollama { chat_url } => {
+                let body = json!({
+                    "model": self.model,
+                    "stream": false,
+                    "messages": [{
+                        "role": "user",
+                        "content": self.prompt,
+                        "images": [image],
+                    }],
+                });
+                let response = self.client.post(chat_url).json(&body).send().await?;
+                if !response.status().is_success() {
+                    return Err(ApiError::from_response("Ollama", response, error_message)
+                        .await
+                        .into());
+                }
+                let response = response.json::<OllamaResponse>().await?;
+                let usage = TokenUsage {
+                    prompt_tokens: response.prompt_eval_count,
+                    completion_tokens: response.eval_count,
+                };
+                Ok(("Ollama", response.message.content, usage))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ImageDescriber for VisionDescriber {
+    async fn describe(&self, url: &str, content_type: &str) -> eyre::Result<String> {
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        let image = response.bytes().await?;
+        if image.len() as u64 > self.max_image_bytes {
+            bail!("image is larger than {} bytes", self.max_image_bytes);
+        }
+        let image = STANDARD.encode(&image);
+        let (provider, description, usage) = self
+            .layers
+            .run(&self.retry, || self.send(&image, content_type))
+            .await?;
+        self.layers
+            .usage
+            .record(provider, &self.model, UsageKind::Completion, usage)
+            .await;
+        let description = description.trim();
+        if description.is_empty() {
+            return Err(eyre!("{provider} API returned no description"));
+        }
+        Ok(description.to_string())
+    }
+
+    fn max_image_bytes(&self) -> u64 {
+        self.max_image_bytes
+    }
+}
+
+/// Pulls the message out of the error bodies of every supported API, which all
+/// have an `error` that is either a string or an object with a `message`.
+pub(super) fn error_message(body: &str) -> Option<String> {
+    let body: Value = serde_json::from_str(body).ok()?;
+    let error = body.get("error")?;
+    error
+        .get("message")
+        .unwrap_or(error)
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Creates the image describer configured under `[gpt.vision]`, if it is enabled and
+/// not in dry-run mode. Its requests are retried, and refused while LLM calls are
+/// paused or once the daily budget is used up, as those of summaries are.
+pub fn image_describer_from_config(
+    config: &GptConfig,
+    layers: &RequestLayers,
+) -> Result<Option<Arc<dyn ImageDescriber>>> {
+    let vision = &config.vision;
+    if !vision.enabled || config.dry_run {
+        return Ok(None);
+    }
+    let provider = vision.provider.unwrap_or(config.provider);
+    let api = match provider {
+        LlmProvider::OpenAi => VisionApi::OpenAi {
+            completions_url: format!(
+                "{}/chat/completions",
+                config.openai.api_base.trim_end_matches('/')
+            ),
+            api_key: env::var("OPEN_AI_SECRET")
+                .map_err(|_| Error::MissingEnvVar("OPEN_AI_SECRET"))?,
+            azure_api_version: config.openai.azure_api_version.clone(),
+        },
+        LlmProvider::Anthropic => VisionApi::Anthropic {
+            api_key: env::var("ANTHROPIC_API_KEY")
+                .map_err(|_| Error::MissingEnvVar("ANTHROPIC_API_KEY"))?,
+        },
+        LlmProvider::Ollama => VisionApi::Ollama {
+            chat_url: format!("{}/api/chat", config.ollama.base_url.trim_end_matches('/')),
+        },
+    };
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    Ok(Some(Arc::new(VisionDescriber {
+        client,
+        api,
+        model: vision
+            .model
+            .clone()
+            .unwrap_or_else(|| configured_model(config, provider).to_string()),
+        prompt: vision
+            .prompt
+            .clone()
+            .unwrap_or_else(|| DEFAULT_VISION_PROMPT.to_string()),
+        max_image_bytes: vision.max_image_bytes,
+        layers: layers.clone(),
+        retry: RetryPolicy::new(&config.retry),
+    })))
+}
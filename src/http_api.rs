@@ -1,36 +1,1113 @@
+use crate::config::Verbosity;
 use crate::db;
+use crate::feed::{render_atom, FeedLinks};
+use crate::gpt::{Embedder, EntityKind, LlmPause};
+use crate::rate_limit::RateLimiter;
+use crate::services::embeddings::{self, SearchResult};
+use crate::services::events::{Event, EventBus};
+use crate::services::ingest::{
+    HttpIngest, IngestError, IngestPost, IngestReport, MAX_INGEST_BATCH,
+};
+use crate::services::knowledge_base::{KbError, KnowledgeBase};
+use crate::services::privacy::{DataEraser, ErasureReport};
+use crate::supervisor::{ServiceStatus, Supervisor};
+use crate::templates::{DigestTarget, DigestTemplates};
 
-use axum::{Extension, Json};
+use axum::body::Body;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, Path, Query, Request, State};
+use axum::http::{
+    header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER},
+    StatusCode,
+};
+use axum::middleware::Next;
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::{BoxError, Extension, Json};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use futures::future::ready;
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serenity::all::UserId;
 use sqlx::SqlitePool;
+use std::net::SocketAddr;
+use std::pin::pin;
 use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
+
+/// Header that API keys can be sent in, as an alternative to `Authorization: Bearer`.
+const API_KEY_HEADER: &str = "x-api-key";
+/// Query parameter that API keys can be sent in.
+const API_KEY_QUERY_PARAMETER: &str = "api_key";
+
+/// Rejects requests that do not carry one of the API keys. Every request is let through when no key is configured.
+pub async fn require_api_key(
+    State(keys): State<Arc<Vec<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if keys.is_empty() {
+        return next.run(request).await;
+    }
+    let matched = presented_api_keys(&request)
+        .find_map(|given| keys.iter().find(|key| constant_time_eq(key, given)));
+    let Some(key) = matched.cloned() else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let mut request = request;
+    request.extensions_mut().insert(AuthenticatedKey(key));
+    next.run(request).await
+}
+
+/// The configured API key a request was authenticated with, for the middleware that
+/// runs after authentication.
+#[derive(Clone)]
+pub struct AuthenticatedKey(String);
+
+/// The API keys a request carries, as a bearer token, in the `X-API-Key` header or, for
+/// clients such as feed readers that cannot set headers, in the `api_key` query parameter.
+fn presented_api_keys(request: &Request) -> impl Iterator<Item = &str> {
+    let headers = request.headers();
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let header = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let query = request.uri().query().and_then(|query| {
+        query.split('&').find_map(|pair| {
+            pair.strip_prefix(API_KEY_QUERY_PARAMETER)?
+                .strip_prefix('=')
+        })
+    });
+    bearer.into_iter().chain(header).chain(query).map(str::trim)
+}
+
+/// Rejects requests from clients that went over their rate limit with a 429 telling
+/// them when to retry. Runs after authentication, so that authenticated clients are
+/// told apart by the API key they were let in with, and the others by IP address.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = match request.extensions().get::<AuthenticatedKey>() {
+        Some(AuthenticatedKey(key)) => format!("key:{key}"),
+        None => format!("ip:{}", address.ip()),
+    };
+    if let Err(retry_after) = limiter.check(&client) {
+        let retry_after = retry_after.as_secs().max(1).to_string();
+        return (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, retry_after)]).into_response();
+    }
+    next.run(request).await
+}
+
+/// Compares two keys in a time that does not depend on how much of them match.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Liveness check, reachable without an API key.
+pub async fn health_handler() -> &'static str {
+    "ok"
+}
+
+/// Reports the health of every background service, including how often it crashed.
+pub async fn status_handler(
+    Extension(supervisor): Extension<Supervisor>,
+) -> Json<Vec<ServiceStatus>> {
+    Json(supervisor.statuses())
+}
+
+/// Serves the dashboard page. The page itself holds no data, it asks for an API key
+/// when needed and loads everything from the JSON API.
+pub async fn dashboard_handler() -> Html<&'static str> {
+    Html(include_str!("../assets/dashboard.html"))
+}
 
 pub async fn summaries_handler(
+    Query(filter): Query<db::ContentFilter>,
+    Query(range): Query<db::DateRange>,
     Extension(db): Extension<Arc<SqlitePool>>,
 ) -> Json<Vec<db::Summary>> {
-    let summaries = db::fetch_summaries(db.clone()).await;
+    let summaries = db::fetch_summaries(db.clone(), &filter, &range).await;
     Json(summaries)
 }
 
+pub async fn summary_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<db::Summary>, StatusCode> {
+    match db::fetch_summary(&db, id).await {
+        Ok(Some(summary)) => Ok(Json(summary)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Returns a daily digest along with all of its summaries.
+pub async fn daily_digest_handler(
+    Path(id): Path<i64>,
+    Query(params): Query<VerbosityParams>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<db::DailyDigest>, StatusCode> {
+    match db::fetch_daily_digest(&db, id).await {
+        Ok(Some(digest)) => Ok(Json(params.apply(digest))),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VerbosityParams {
+    verbosity: Option<Verbosity>,
+}
+
+impl VerbosityParams {
+    /// Trims a daily digest down to the requested length: its TL;DR, falling back to
+    /// its full text, without summaries for `tldr`, its text without summaries for
+    /// `standard`, and everything for `detailed`.
+    fn apply(&self, mut digest: db::DailyDigest) -> db::DailyDigest {
+        match self.verbosity {
+            Some(Verbosity::Tldr) => {
+                if let Some(tldr) = &digest.tldr {
+                    digest.text = tldr.clone();
+                }
+                digest.summaries.clear();
+            }
+            Some(Verbosity::Standard) => digest.summaries.clear(),
+            Some(Verbosity::Detailed) | None => {}
+        }
+        digest
+    }
+}
+
+/// Group digests returned when no count is given.
+const DEFAULT_GROUP_DIGESTS: i64 = 20;
+/// Most group digests returned at once.
+const MAX_GROUP_DIGESTS: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct GroupDigestsParams {
+    count: Option<i64>,
+}
+
+/// Lists the digests of channel groups, the most recent first, optionally only those
+/// of one `group` and/or guild.
+pub async fn group_digests_handler(
+    Query(filter): Query<db::GroupDigestFilter>,
+    Query(params): Query<GroupDigestsParams>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::GroupDigest>>, StatusCode> {
+    let count = params
+        .count
+        .unwrap_or(DEFAULT_GROUP_DIGESTS)
+        .clamp(1, MAX_GROUP_DIGESTS);
+    match db::fetch_group_digests(&db, &filter, count).await {
+        Ok(digests) => Ok(Json(digests)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn group_digest_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<db::GroupDigest>, StatusCode> {
+    match db::fetch_group_digest(&db, id).await {
+        Ok(Some(digest)) => Ok(Json(digest)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Returns what changed in a daily digest compared to the previous one of its guild.
+pub async fn digest_changes_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<db::StoredDigestChanges>, StatusCode> {
+    match db::fetch_digest_changes(&db, id).await {
+        Ok(Some(changes)) => Ok(Json(changes)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Renders a daily, weekly or monthly digest with the API template, as Markdown.
+pub async fn rendered_digest_handler(
+    Path((tier, id)): Path<(String, i64)>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+    Extension(templates): Extension<Arc<DigestTemplates>>,
+) -> Result<Response, StatusCode> {
+    let tier = match tier.as_str() {
+        "daily" => db::RollupTier::Daily,
+        "weekly" => db::RollupTier::Weekly,
+        "monthly" => db::RollupTier::Monthly,
+        _ => return Err(StatusCode::NOT_FOUND),
+    };
+    let digest = match db::fetch_digest(&db, tier, id).await {
+        Ok(Some(digest)) => digest,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    let view = templates.view(
+        tier,
+        Some(digest.id),
+        digest.guild_id,
+        &digest.text,
+        digest.message_count,
+        digest.covers_from.zip(digest.covers_to),
+    );
+    Ok((
+        [(CONTENT_TYPE, "text/markdown; charset=utf-8")],
+        templates.render(DigestTarget::Api, &view),
+    )
+        .into_response())
+}
+
+/// Opts a member out of logging, deletes their stored messages and rewrites the
+/// summaries written from them.
+pub async fn delete_user_data_handler(
+    Path(id): Path<u64>,
+    Extension(eraser): Extension<DataEraser>,
+) -> Result<Json<ErasureReport>, StatusCode> {
+    if id == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    match eraser.erase(UserId::new(id)).await {
+        Ok(report) => Ok(Json(report)),
+        Err(e) => {
+            error!("Could not delete the data of user {id}: {e}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Default and maximum number of digests in the feed.
+const DEFAULT_FEED_SIZE: u32 = 20;
+const MAX_FEED_SIZE: u32 = 100;
+
+/// What the digest feed needs besides the database.
+pub struct FeedSettings {
+    pub public_url: Option<String>,
+    pub timezone: Tz,
+}
+
+#[derive(Deserialize)]
+pub struct FeedParams {
+    guild_id: Option<i64>,
+    count: Option<u32>,
+}
+
+/// Returns the most recent daily digests as an Atom feed.
+pub async fn digests_feed_handler(
+    Query(params): Query<FeedParams>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+    Extension(settings): Extension<Arc<FeedSettings>>,
+) -> Result<Response, StatusCode> {
+    let count = params
+        .count
+        .unwrap_or(DEFAULT_FEED_SIZE)
+        .clamp(1, MAX_FEED_SIZE);
+    let digests = db::fetch_recent_daily_digests(&db, params.guild_id, count)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // The self link leaves out the API key the feed may have been requested with.
+    let self_path = match params.guild_id {
+        Some(guild_id) => format!("/digests.atom?guild_id={guild_id}"),
+        None => "/digests.atom".to_string(),
+    };
+    let links = FeedLinks {
+        base_url: settings.public_url.as_deref(),
+        self_path: &self_path,
+    };
+    let feed = render_atom(&digests, params.guild_id, &links, settings.timezone);
+    Ok((
+        [(CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        feed,
+    )
+        .into_response())
+}
+
+/// Streams the summaries, digests and status events created from now on as server-sent
+/// events, named after the event type, optionally only those of a guild or channel.
+/// The stream ends when the server shuts down.
+pub async fn events_handler(
+    Query(filter): Query<db::ContentFilter>,
+    Extension(events): Extension<EventBus>,
+    Extension(shutdown): Extension<CancellationToken>,
+) -> Sse<impl Stream<Item = Result<SseEvent, axum::Error>>> {
+    let stream = stream::unfold(events.subscribe(), |mut rx| async move {
+        next_event(&mut rx).await.map(|event| (event, rx))
+    })
+    .take_until(shutdown.cancelled_owned())
+    .filter(move |event| ready(event.matches(&filter)))
+    .map(|event| {
+        SseEvent::default()
+            .event(event.kind.as_str())
+            .json_data(&event.data)
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Sends the summaries, digests and status events created from now on as JSON text
+/// frames over a WebSocket, optionally only those of a guild or channel.
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    Query(filter): Query<db::ContentFilter>,
+    Extension(events): Extension<EventBus>,
+    Extension(shutdown): Extension<CancellationToken>,
+) -> Response {
+    let rx = events.subscribe();
+    ws.on_upgrade(move |socket| forward_events(socket, rx, filter, shutdown))
+}
+
+async fn forward_events(
+    mut socket: WebSocket,
+    mut rx: broadcast::Receiver<Event>,
+    filter: db::ContentFilter,
+    shutdown: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                let _ = socket.send(Message::Close(None)).await;
+                break;
+            }
+            event = next_event(&mut rx) => {
+                let Some(event) = event else {
+                    break;
+                };
+                if !event.matches(&filter) {
+                    continue;
+                }
+                let frame = match serde_json::to_string(&event) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        error!("Could not serialize {} event: {e}", event.kind.as_str());
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(frame)).await.is_err() {
+                    break;
+                }
+            }
+            // Incoming frames are only read to notice the client going away, pings
+            // are answered by axum.
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Waits for the next event, skipping over the ones a slow subscriber missed.
+async fn next_event(rx: &mut broadcast::Receiver<Event>) -> Option<Event> {
+    loop {
+        match rx.recv().await {
+            Ok(event) => return Some(event),
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Event subscriber fell behind and missed {missed} events")
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Returns the daily digests along with their summaries, all of them or a page at a
+/// time when `count` or `page` is given. The JSON array is streamed as the digests are
+/// read from the database, so that large ranges are never held in memory at once.
 pub async fn daily_digests_handler(
+    Query(filter): Query<db::ContentFilter>,
+    Query(range): Query<db::DateRange>,
+    Query(pagination): Query<PaginationParams>,
+    Query(params): Query<VerbosityParams>,
     Extension(db): Extension<Arc<SqlitePool>>,
-) -> Json<Vec<db::DailyDigest>> {
-    let digests = db::fetch_daily_digests(db.clone()).await;
+) -> Result<Response, StatusCode> {
+    let page = (pagination.count.is_some() || pagination.page.is_some()).then(|| pagination.page());
+    let query = db::DigestQuery::new(&filter, &range, page.as_ref());
+    // The stream borrows the pool and the query, so it is read by a task of its own.
+    let (tx, mut rx) = mpsc::channel(DIGEST_CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        let mut digests = pin!(db::stream_daily_digests(&db, &query));
+        while let Some(digest) = digests.next().await {
+            let digest = digest.map(|digest| params.apply(digest));
+            // The client went away.
+            if tx.send(digest).await.is_err() {
+                return;
+            }
+        }
+    });
+    // Failing before anything was sent is still reported with an error status, later
+    // failures cut the response short.
+    let first = match rx.recv().await {
+        Some(Err(e)) => {
+            error!("Could not fetch daily digests: {e}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        first => first,
+    };
+    let rest = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|digest| (digest, rx))
+    });
+    Ok(json_array_response(stream::iter(first).chain(rest)))
+}
+
+/// How many digests are read ahead of what was sent to the client.
+const DIGEST_CHANNEL_CAPACITY: usize = 16;
+
+/// Streams a JSON array, serializing each item as the stream yields it.
+fn json_array_response<T, E>(items: impl Stream<Item = Result<T, E>> + Send + 'static) -> Response
+where
+    T: Serialize,
+    E: Into<BoxError>,
+{
+    let open = stream::once(async { Ok::<_, BoxError>(b"[".to_vec()) });
+    let items = items.enumerate().map(|(i, item)| {
+        let mut chunk = if i == 0 { vec![] } else { b",".to_vec() };
+        serde_json::to_writer(&mut chunk, &item.map_err(Into::into)?)?;
+        Ok(chunk)
+    });
+    let close = stream::once(async { Ok(b"]".to_vec()) });
+    (
+        [(CONTENT_TYPE, "application/json")],
+        Body::from_stream(open.chain(items).chain(close)),
+    )
+        .into_response()
+}
+
+pub async fn weekly_digests_handler(
+    Query(filter): Query<db::ContentFilter>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Json<Vec<db::RollupDigest>> {
+    let digests = db::fetch_weekly_digests(db.clone(), &filter).await;
     Json(digests)
 }
 
-use axum::extract::Query;
-use serde::Deserialize;
+pub async fn monthly_digests_handler(
+    Query(filter): Query<db::ContentFilter>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Json<Vec<db::RollupDigest>> {
+    let digests = db::fetch_monthly_digests(db.clone(), &filter).await;
+    Json(digests)
+}
 
 #[derive(Deserialize)]
-struct SummariesQueryParams {
-    count: usize, // Number of summaries to fetch
-    page: usize,  // Page number for pagination
+pub struct UsageParams {
+    #[serde(default)]
+    period: db::UsagePeriod,
+}
+
+/// Returns the tokens used and estimated cost of LLM calls per day, or per month with
+/// `period=monthly`, and model.
+pub async fn usage_handler(
+    Query(params): Query<UsageParams>,
+    Query(range): Query<db::DateRange>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::UsageTotals>>, StatusCode> {
+    db::fetch_usage_totals(&db, params.period, &range)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Could not fetch LLM usage: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
 }
 
+#[derive(Deserialize)]
+pub struct ActivityParams {
+    #[serde(default)]
+    granularity: db::ActivityGranularity,
+}
+
+/// Returns the message volume, unique authors and tokens of each channel per day, or
+/// per hour with `granularity=hour`.
+pub async fn channel_activity_handler(
+    Query(params): Query<ActivityParams>,
+    Query(filter): Query<db::ContentFilter>,
+    Query(range): Query<db::DateRange>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::ChannelActivity>>, StatusCode> {
+    db::fetch_channel_activity(&db, params.granularity, &filter, &range)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Could not fetch channel activity: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Returns the average sentiment of each channel's summaries per day, or per hour with
+/// `granularity=hour`.
+pub async fn channel_moods_handler(
+    Query(params): Query<ActivityParams>,
+    Query(filter): Query<db::ContentFilter>,
+    Query(range): Query<db::DateRange>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::ChannelMood>>, StatusCode> {
+    db::fetch_channel_moods(&db, params.granularity, &filter, &range)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Could not fetch channel moods: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Default and maximum number of days `/topics/trending` looks back.
+const DEFAULT_TRENDING_DAYS: i64 = 7;
+const MAX_TRENDING_DAYS: i64 = 90;
+/// Default and maximum number of topics returned by `/topics/trending`.
+const DEFAULT_TRENDING_LIMIT: i64 = 10;
+const MAX_TRENDING_LIMIT: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct TrendingParams {
+    guild_id: Option<i64>,
+    days: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// Returns the topics of the daily digests of the last `days` days, most mentioned
+/// first, along with how often they came up in the days before.
+pub async fn trending_topics_handler(
+    Query(params): Query<TrendingParams>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::TrendingTopic>>, StatusCode> {
+    let days = params
+        .days
+        .unwrap_or(DEFAULT_TRENDING_DAYS)
+        .clamp(1, MAX_TRENDING_DAYS);
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_TRENDING_LIMIT)
+        .clamp(1, MAX_TRENDING_LIMIT);
+    let since = Utc::now() - chrono::Duration::days(days);
+    db::fetch_trending_topics(&db, params.guild_id, since, limit)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Could not fetch trending topics: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Default and maximum number of entities returned by `/entities`.
+const DEFAULT_ENTITY_LIMIT: i64 = 50;
+const MAX_ENTITY_LIMIT: i64 = 500;
+
+#[derive(Deserialize)]
+pub struct EntitiesParams {
+    guild_id: Option<i64>,
+    kind: Option<EntityKind>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+}
+
+/// Returns the people, projects, tools and tickets mentioned by the most summaries.
+pub async fn entities_handler(
+    Query(params): Query<EntitiesParams>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::EntityCount>>, StatusCode> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_ENTITY_LIMIT)
+        .clamp(1, MAX_ENTITY_LIMIT);
+    let filter = db::EntityFilter {
+        guild_id: params.guild_id,
+        kind: params.kind,
+        from: params.from,
+        to: params.to,
+    };
+    db::fetch_entities(&db, &filter, limit)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Could not fetch entities: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Returns every summary mentioning an entity, oldest first.
+pub async fn entity_mentions_handler(
+    Path(name): Path<String>,
+    Query(filter): Query<db::EntityFilter>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::EntityMention>>, StatusCode> {
+    db::fetch_entity_mentions(&db, &name, &filter)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Could not fetch the mentions of entity {name:?}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Default and maximum number of results returned by `/search`.
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 50;
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    q: String,
+    guild_id: Option<i64>,
+    limit: Option<usize>,
+}
+
+/// Returns the summaries and digests closest in meaning to the `q` query, ranked by
+/// cosine similarity.
+pub async fn search_handler(
+    Query(params): Query<SearchParams>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+    Extension(embedder): Extension<Arc<dyn Embedder>>,
+) -> Result<Json<Vec<SearchResult>>, StatusCode> {
+    let query = params.q.trim();
+    if query.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+    match embeddings::search(&db, embedder.as_ref(), query, params.guild_id, limit).await {
+        Ok(results) => Ok(Json(results)),
+        Err(e) => {
+            error!("Could not search summaries for {query:?}: {e}");
+            Err(StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
+pub async fn shared_links_handler(
+    Query(filter): Query<db::ContentFilter>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::SharedLink>>, StatusCode> {
+    match db::fetch_shared_links(&db, &filter).await {
+        Ok(links) => Ok(Json(links)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn action_items_handler(
+    Query(filter): Query<db::ActionItemFilter>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::TrackedActionItem>>, StatusCode> {
+    match db::fetch_action_items(&db, &filter).await {
+        Ok(items) => Ok(Json(items)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Lists the frequently asked questions mined from summaries, the most asked first.
+pub async fn faq_handler(
+    Query(filter): Query<db::FaqFilter>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::FaqEntry>>, StatusCode> {
+    match db::fetch_faq(&db, &filter).await {
+        Ok(entries) => Ok(Json(entries)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Lists the glossary of community jargon mined from summaries, in alphabetical order.
+pub async fn glossary_handler(
+    Query(filter): Query<db::GlossaryFilter>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::GlossaryTerm>>, StatusCode> {
+    match db::fetch_glossary(&db, &filter).await {
+        Ok(terms) => Ok(Json(terms)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Marks an action item as resolved.
+pub async fn resolve_action_item_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> StatusCode {
+    set_action_item_status(&db, id, db::ActionItemStatus::Resolved).await
+}
+
+/// Marks a resolved action item as open again.
+pub async fn reopen_action_item_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> StatusCode {
+    set_action_item_status(&db, id, db::ActionItemStatus::Open).await
+}
+
+async fn set_action_item_status(
+    db: &SqlitePool,
+    id: i64,
+    status: db::ActionItemStatus,
+) -> StatusCode {
+    match db::set_action_item_status(db, id, None, status).await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+pub async fn pending_summaries_handler(
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Json<Vec<db::PendingSummary>> {
+    let pending = db::fetch_pending_summaries(db.clone()).await;
+    Json(pending)
+}
+
+#[derive(Deserialize)]
+pub struct WebhookDeliveriesParams {
+    status: Option<db::DeliveryStatus>,
+    limit: Option<u32>,
+}
+
+/// Lists the most recent webhook deliveries, 100 by default.
+pub async fn webhook_deliveries_handler(
+    Query(params): Query<WebhookDeliveriesParams>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::WebhookDelivery>>, StatusCode> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    match db::fetch_webhook_deliveries(&db, params.status, i64::from(limit)).await {
+        Ok(deliveries) => Ok(Json(deliveries)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Sends a failed webhook delivery again, with a fresh set of attempts.
+pub async fn redeliver_webhook_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> StatusCode {
+    match db::redeliver_webhook(&db, id).await {
+        Ok(true) => StatusCode::ACCEPTED,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Whether LLM calls are paused, and until when.
+#[derive(Serialize)]
+pub struct PauseStatus {
+    paused: bool,
+    /// Whether an admin paused LLM calls, rather than quiet hours.
+    paused_by_admin: bool,
+    /// When the quiet hours end, while in them and not paused by an admin.
+    resumes_at: Option<DateTime<Utc>>,
+}
+
+impl PauseStatus {
+    fn of(pause: &LlmPause) -> Self {
+        let resumes_at = pause.check().err().and_then(|paused| paused.resumes_at);
+        Self {
+            paused: pause.is_paused(),
+            paused_by_admin: pause.paused_by_admin(),
+            resumes_at,
+        }
+    }
+}
+
+pub async fn pause_status_handler(Extension(pause): Extension<LlmPause>) -> Json<PauseStatus> {
+    Json(PauseStatus::of(&pause))
+}
+
+/// Stops making LLM calls until resumed. Messages are still logged, and summarized
+/// once calls resume.
+pub async fn pause_handler(Extension(pause): Extension<LlmPause>) -> Json<PauseStatus> {
+    pause.pause();
+    warn!("LLM calls paused by an admin");
+    Json(PauseStatus::of(&pause))
+}
+
+/// Resumes LLM calls paused by an admin. Quiet hours still apply.
+pub async fn resume_handler(Extension(pause): Extension<LlmPause>) -> Json<PauseStatus> {
+    pause.resume();
+    warn!("LLM calls resumed by an admin");
+    Json(PauseStatus::of(&pause))
+}
+
+/// Retries a pending summarization on the next run of the pending summary service.
+pub async fn redrive_pending_summary_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> StatusCode {
+    match db::redrive_pending_summary(&db, id).await {
+        Ok(true) => StatusCode::ACCEPTED,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Gives up on a pending summarization. The messages of its batch stay stored, and are
+/// summarized along with the next batch of their channel.
+pub async fn discard_pending_summary_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> StatusCode {
+    match db::delete_pending_summary(&db, id).await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Feeds a batch of messages of another system to the message log, to be summarized
+/// like those of Discord.
+pub async fn ingest_handler(
+    Extension(ingest): Extension<HttpIngest>,
+    Json(posts): Json<Vec<IngestPost>>,
+) -> Result<(StatusCode, Json<IngestReport>), StatusCode> {
+    if posts.len() > MAX_INGEST_BATCH {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    match ingest.ingest(posts).await {
+        Ok(report) => Ok((StatusCode::ACCEPTED, Json(report))),
+        Err(IngestError::Invalid) => Err(StatusCode::UNPROCESSABLE_ENTITY),
+        Err(IngestError::Closed) => Err(StatusCode::SERVICE_UNAVAILABLE),
+        Err(IngestError::Database) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct KbGenerateParams {
+    topic: String,
+    guild_id: Option<i64>,
+}
+
+/// Writes a knowledge base article from every summary mentioning `topic`.
+pub async fn generate_kb_article_handler(
+    Query(params): Query<KbGenerateParams>,
+    Extension(kb): Extension<KnowledgeBase>,
+) -> Result<(StatusCode, Json<db::KbArticle>), StatusCode> {
+    match kb.generate(&params.topic, params.guild_id).await {
+        Ok(article) => Ok((StatusCode::CREATED, Json(article))),
+        Err(KbError::Invalid) => Err(StatusCode::BAD_REQUEST),
+        Err(KbError::NoSources) => Err(StatusCode::NOT_FOUND),
+        Err(KbError::Llm) => Err(StatusCode::BAD_GATEWAY),
+        Err(KbError::Database) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct KbArticlesParams {
+    guild_id: Option<i64>,
+}
+
+pub async fn kb_articles_handler(
+    Query(params): Query<KbArticlesParams>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<Vec<db::KbArticle>>, StatusCode> {
+    match db::fetch_kb_articles(&db, params.guild_id).await {
+        Ok(articles) => Ok(Json(articles)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn kb_article_handler(
+    Path(id): Path<i64>,
+    Extension(db): Extension<Arc<SqlitePool>>,
+) -> Result<Json<db::KbArticle>, StatusCode> {
+    match db::fetch_kb_article(&db, id).await {
+        Ok(Some(article)) => Ok(Json(article)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Default and maximum number of results per page of paginated routes.
+const DEFAULT_PAGE_SIZE: u32 = 10;
+const MAX_PAGE_SIZE: u32 = 100;
+
+#[derive(Deserialize)]
+pub struct PaginationParams {
+    count: Option<u32>, // Number of results to fetch
+    page: Option<u32>,  // Page number for pagination, starting at 1
+}
+
+impl PaginationParams {
+    /// The requested page, with `count` clamped between 1 and 100.
+    fn page(&self) -> db::Page {
+        db::Page {
+            count: self
+                .count
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+            page: self.page.unwrap_or(1).max(1),
+        }
+    }
+}
+
+/// A page of summaries along with what is needed to fetch the next one.
+#[derive(Serialize)]
+pub struct SummariesPage {
+    summaries: Vec<db::Summary>,
+    page: u32,
+    count: u32,
+    total: i64,
+    next_page: Option<u32>,
+}
+
+/// Returns the most recent summaries a page at a time. `count` is clamped between 1 and
+/// 100 and pages start at 1.
 pub async fn fetch_latest_summaries_handler(
-    Query(params): Query<SummariesQueryParams>,
+    Query(pagination): Query<PaginationParams>,
+    Query(filter): Query<db::ContentFilter>,
     Extension(db): Extension<Arc<SqlitePool>>,
-) -> Json<Vec<db::Summary>> {
-    let summaries = db::fetch_latest_summaries(db.clone(), params.count, params.page).await;
-    Json(summaries)
+) -> Result<Json<SummariesPage>, StatusCode> {
+    let db::Page { count, page } = pagination.page();
+    let (summaries, total) = tokio::try_join!(
+        db::fetch_latest_summaries(&db, &filter, count, page),
+        db::count_summaries(&db, &filter)
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let next_page = (i64::from(page) * i64::from(count) < total).then_some(page + 1);
+    Ok(Json(SummariesPage {
+        summaries,
+        page,
+        count,
+        total,
+        next_page,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(keys: &[&str], limiter: RateLimiter) -> Router {
+        let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state(
+                Arc::new(limiter),
+                rate_limit,
+            ))
+            .route_layer(axum::middleware::from_fn_with_state(
+                Arc::new(keys),
+                require_api_key,
+            ))
+    }
+
+    async fn status(app: &Router, ip: [u8; 4], headers: &[(&str, &str)]) -> StatusCode {
+        status_of(app, "/", ip, headers).await
+    }
+
+    async fn status_of(
+        app: &Router,
+        uri: &str,
+        ip: [u8; 4],
+        headers: &[(&str, &str)],
+    ) -> StatusCode {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let mut request = request.body(Body::empty()).unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 4000))));
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn made_up_keys_sent_along_a_valid_one_do_not_reset_the_limit() {
+        let app = app(&["secret"], RateLimiter::new(1, 2));
+        let mut statuses = vec![];
+        for attempt in 0..3 {
+            let bearer = format!("Bearer made-up-{attempt}");
+            let headers = [
+                (AUTHORIZATION.as_str(), bearer.as_str()),
+                (API_KEY_HEADER, "secret"),
+            ];
+            statuses.push(status(&app, [10, 0, 0, 1], &headers).await);
+        }
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::TOO_MANY_REQUESTS
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_configured_key_are_rejected() {
+        let app = app(&["secret"], RateLimiter::new(60, 10));
+        let bearer = [(AUTHORIZATION.as_str(), "Bearer made-up")];
+        assert_eq!(
+            status(&app, [10, 0, 0, 1], &bearer).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&app, [10, 0, 0, 1], &[]).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status_of(&app, "/?api_key=secret", [10, 0, 0, 1], &[]).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn each_key_is_limited_on_its_own_whatever_the_ip() {
+        let app = app(&["first", "second"], RateLimiter::new(1, 1));
+        let first = [(API_KEY_HEADER, "first")];
+        let second = [(AUTHORIZATION.as_str(), "Bearer second")];
+        assert_eq!(status(&app, [10, 0, 0, 1], &first).await, StatusCode::OK);
+        assert_eq!(
+            status(&app, [10, 0, 0, 2], &first).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(status(&app, [10, 0, 0, 1], &second).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn clients_are_told_apart_by_ip_without_keys() {
+        let app = app(&[], RateLimiter::new(1, 1));
+        assert_eq!(status(&app, [10, 0, 0, 1], &[]).await, StatusCode::OK);
+        assert_eq!(
+            status(&app, [10, 0, 0, 1], &[]).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        // Keys are ignored when none is configured.
+        let bearer = [(AUTHORIZATION.as_str(), "Bearer anything")];
+        assert_eq!(
+            status(&app, [10, 0, 0, 1], &bearer).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(status(&app, [10, 0, 0, 2], &[]).await, StatusCode::OK);
+    }
+
+    async fn body_of(response: Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn json_arrays_are_streamed_item_by_item() {
+        let items = stream::iter([1, 2, 3].map(Ok::<_, std::io::Error>));
+        assert_eq!(body_of(json_array_response(items)).await, "[1,2,3]");
+
+        let empty = stream::empty::<Result<u32, std::io::Error>>();
+        assert_eq!(body_of(json_array_response(empty)).await, "[]");
+    }
 }
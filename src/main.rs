@@ -1,25 +1,83 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::env;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use axum::routing::get;
+use app::App;
+use axum::middleware;
+use axum::routing::{delete, get, post};
 use axum::{Extension, Router};
+use clap::Parser;
+use cli::{Cli, Command};
+use config::ChannelPriority;
+use db::RollupTier;
 use dotenv::dotenv;
+use error::Error;
+use eyre::WrapErr;
 use futures::future::join_all;
+use rate_limit::RateLimiter;
+use redaction::Redactor;
 use serenity::model::prelude::*;
 use serenity::prelude::*;
-use services::digests::DailyRecapService;
-use services::discord_handler::Handler;
-use services::message_listener::MessageLogService;
+use services::alerts::Alerts;
+use services::anomalies::AnomalyService;
+use services::backfill::Backfiller;
+use services::channel_groups::ChannelGroups;
+use services::commands::Commands;
+use services::digests::RecapService;
+use services::discord_handler::{Handler, MessageIntake};
+use services::embeddings::EmbeddingService;
+use services::events::EventBus;
+use services::faq::FaqService;
+use services::github::GithubActivity;
+use services::glossary::GlossaryService;
+use services::highlights::HighlightEmoji;
+use services::ingest::HttpIngest;
+use services::keyword_watch::KeywordWatch;
+use services::knowledge_base::KnowledgeBase;
+use services::links::LinkPreviewService;
+use services::message_listener::{ChannelSchedule, MessageLogService};
+use services::pending::PendingSummaryService;
+use services::privacy::{DataEraser, Pseudonyms};
+use services::prompt_reload::PromptReloadService;
+use services::scheduled_events::ScheduledEvents;
+use services::sinks::Sinks;
+use services::sources::{DiscordSource, MessageSource};
+use services::standup::StandupService;
+use services::subscriptions::SubscriptionService;
 use services::summarizer::SummarizerService;
+#[cfg(feature = "voice")]
+use services::voice::{self, VoiceRecorder};
+use services::webhooks::{WebhookService, Webhooks};
+#[cfg(feature = "voice")]
+use songbird::SerenityInit;
+use supervisor::Supervisor;
 use tokio::task::{self, JoinError};
-use tracing::{error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
+mod app;
+mod cli;
 mod config;
 mod db;
+mod doctor;
+mod error;
+mod feed;
 mod gpt;
 mod http_api;
+mod names;
+mod prompts;
+mod rate_limit;
+mod redaction;
+mod schedule;
 mod services;
+mod supervisor;
+mod templates;
+#[cfg(test)]
+mod tests;
+
+const CONFIG_FILE: &str = "config.toml";
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
@@ -27,95 +85,700 @@ async fn main() -> eyre::Result<()> {
 
     tracing_subscriber::fmt::init();
 
-    let token = env::var("DISCORD_BOT_SECRET").expect("No DISCORD_BOT_SECRET provided");
-    let config = config::AppConfig::load_from_file("config.toml")?;
+    let cli = Cli::parse();
+    // The doctor reports a config that cannot be loaded instead of failing on it.
+    if let Some(Command::Doctor) = cli.command {
+        return doctor::run(CONFIG_FILE, cli.dry_run).await;
+    }
+    let mut config = config::AppConfig::load_from_file(CONFIG_FILE)?;
     _ = config;
-    let messages_base = config.service.message_log_directory;
-
-    // Initiate a connection to the database file, creating the file if required.
-    let database = sqlx::sqlite::SqlitePoolOptions::new()
-        .max_connections(4)
-        .connect_with(
-            sqlx::sqlite::SqliteConnectOptions::new()
-                .filename(config.database.url)
-                .create_if_missing(true),
-        )
-        .await
-        .expect("Couldn't connect to database");
+    if cli.dry_run {
+        config.gpt.dry_run = true;
+    }
+    if config.gpt.dry_run {
+        warn!("Running in dry-run mode, summaries are placeholders and no LLM API is called");
+    }
 
-    // Run migrations, which updates the database's schema to the latest version.
-    sqlx::migrate!("./migrations")
-        .run(&database)
-        .await
-        .expect("Couldn't run database migrations");
+    // Only running the bot requires a token, other commands name guilds and channels
+    // by their IDs without one.
+    let token = env::var("DISCORD_BOT_SECRET").ok();
+    match cli.command.unwrap_or_default() {
+        Command::Serve => {
+            let token = token.ok_or(Error::MissingEnvVar("DISCORD_BOT_SECRET"))?;
+            serve(App::setup(config, &token).await?, &token).await
+        }
+        Command::Import { paths } => {
+            cli::import(
+                App::setup(config, &token.unwrap_or_default()).await?,
+                &paths,
+            )
+            .await
+        }
+        Command::Summarize {
+            file,
+            channel,
+            json,
+        } => {
+            let app = App::setup(config, &token.unwrap_or_default()).await?;
+            cli::summarize(&app, &file, channel, json).await
+        }
+        Command::Digest { date } => {
+            let app = App::setup(config, &token.unwrap_or_default()).await?;
+            cli::digest(&app, date).await
+        }
+        Command::Export {
+            format,
+            from,
+            to,
+            guild,
+            output,
+        } => cli::export(&config, format, (from, to), guild, output.as_deref()).await,
+        Command::Migrate { command } => cli::migrate(&config.database, command).await,
+        Command::Doctor => doctor::run(CONFIG_FILE, cli.dry_run).await,
+    }
+}
 
-    let shared_db = Arc::new(database);
+/// Runs the bot until it is shut down.
+async fn serve(app: App, token: &str) -> eyre::Result<()> {
+    let App {
+        config,
+        timezone,
+        db: shared_db,
+        opt_outs,
+        token_counter,
+        layers,
+        http,
+        names,
+        prompts,
+        templates,
+        pause,
+        summarizers,
+        embedder,
+        summary_tokens_threshold,
+    } = app;
+    let channel_filter = config.discord.channel_filter()?;
+    let author_filter = config.discord.author_filter()?;
+    let rollup_schedules = config.rollup_schedules()?;
 
     let mut tasks = vec![];
+    // Cancelled once the Discord client is shut down, to stop every other service.
+    let shutdown = CancellationToken::new();
 
     let (summarize_tx, summarize_rx) = tokio::sync::mpsc::channel(100);
-    let (discord_tx, discord_rx) = tokio::sync::mpsc::channel(100);
+    let (ingest_tx, ingest_rx) = tokio::sync::mpsc::channel(100);
 
-    let mut summary_srv =
-        SummarizerService::new(messages_base.clone(), summarize_rx, shared_db.clone());
-    tasks.push(task::spawn(async move {
-        info!("Running summary service");
-        summary_srv.run().await;
-    }));
+    let events = EventBus::new();
+    let supervisor = Supervisor::new(shutdown.clone(), events.clone());
+    let webhooks = Webhooks::new(shared_db.clone(), config.webhooks.clone());
+    let webhook_srv = WebhookService::new(webhooks.clone());
+    tasks.push(
+        supervisor.spawn("webhook", webhook_srv, |mut srv, shutdown| async move {
+            shutdown.run_until_cancelled(srv.run()).await;
+            Ok(())
+        }),
+    );
+
+    let mut summary_srv = SummarizerService::new(
+        summarize_rx,
+        shared_db.clone(),
+        summarizers.summaries.clone(),
+        embedder.clone(),
+        token_counter.clone(),
+        config.service.pending_retry_interval_seconds,
+        webhooks.clone(),
+    )
+    .with_events(events.clone())
+    .with_prompts(prompts.clone(), names.clone(), summarizers.models.clone());
+    if config.sentiment.enabled {
+        summary_srv = summary_srv.with_sentiment(summarizers.summaries.clone());
+    }
+    if config.verification.enabled {
+        summary_srv = summary_srv.with_verification(config.verification.clone());
+    }
+    let drain_timeout = Duration::from_secs(config.service.shutdown_timeout_seconds);
+    tasks.push(supervisor.spawn(
+        "summary",
+        summary_srv,
+        move |mut srv, shutdown| async move {
+            // The service stops by itself once every batch sent before shutting down is
+            // summarized, unless that takes too long.
+            let drain_deadline = async {
+                shutdown.cancelled().await;
+                tokio::time::sleep(drain_timeout).await;
+            };
+            tokio::select! {
+                _ = srv.run() => {}
+                _ = drain_deadline => {
+                    warn!("Stopped summarizing before shutting down, the remaining messages will be summarized on the next start");
+                }
+            }
+            Ok(())
+        },
+    ));
+
+    let pending_srv = PendingSummaryService::new(
+        shared_db.clone(),
+        summarize_tx.clone(),
+        config.service.pending_retry_interval_seconds,
+    );
+    tasks.push(supervisor.spawn(
+        "pending summary retry",
+        pending_srv,
+        |mut srv, shutdown| async move {
+            shutdown.run_until_cancelled(srv.run()).await;
+            Ok(())
+        },
+    ));
+
+    let embedding_srv = EmbeddingService::new(
+        shared_db.clone(),
+        embedder.clone(),
+        config.gpt.embeddings.interval_seconds,
+    );
+    tasks.push(
+        supervisor.spawn("embedding", embedding_srv, |mut srv, shutdown| async move {
+            shutdown.run_until_cancelled(srv.run()).await;
+            Ok(())
+        }),
+    );
+
+    if config.anomalies.enabled {
+        let alerts = Alerts::new(
+            http.clone(),
+            config.anomalies.alert_channel()?,
+            webhooks.clone(),
+        );
+        let mut anomaly_srv =
+            AnomalyService::new(shared_db.clone(), alerts, config.anomalies.clone());
+        if config.anomalies.summarize {
+            anomaly_srv = anomaly_srv.with_summarizer(summarizers.summaries.clone());
+        }
+        tasks.push(supervisor.spawn(
+            "anomaly detection",
+            anomaly_srv,
+            |mut srv, shutdown| async move {
+                shutdown.run_until_cancelled(srv.run()).await;
+                Ok(())
+            },
+        ));
+    }
+
+    if config.faq.enabled {
+        let faq_srv = FaqService::new(
+            shared_db.clone(),
+            summarizers.summaries.clone(),
+            config.faq.interval_seconds,
+        );
+        tasks.push(
+            supervisor.spawn("FAQ", faq_srv, |mut srv, shutdown| async move {
+                shutdown.run_until_cancelled(srv.run()).await;
+                Ok(())
+            }),
+        );
+    }
+
+    if config.glossary.enabled {
+        let glossary_srv = GlossaryService::new(
+            shared_db.clone(),
+            summarizers.summaries.clone(),
+            config.glossary.interval_seconds,
+        );
+        tasks.push(
+            supervisor.spawn("glossary", glossary_srv, |mut srv, shutdown| async move {
+                shutdown.run_until_cancelled(srv.run()).await;
+                Ok(())
+            }),
+        );
+    }
+
+    if config.links.fetch_metadata {
+        let link_preview_srv =
+            LinkPreviewService::new(shared_db.clone(), config.links.fetch_interval_seconds);
+        tasks.push(supervisor.spawn(
+            "link preview",
+            link_preview_srv,
+            |mut srv, shutdown| async move {
+                shutdown.run_until_cancelled(srv.run()).await;
+                Ok(())
+            },
+        ));
+    }
 
     let mut message_log_srv = MessageLogService::new(
-        messages_base,
+        shared_db.clone(),
         summarize_tx,
-        discord_rx,
-        config.service.max_gpt_request_tokens,
+        ingest_rx,
+        token_counter,
+        summary_tokens_threshold,
+        config.service.summarize_after_seconds,
+    )
+    .with_redactor(Redactor::from_config(&config.privacy.redaction)?)
+    .with_deleted_messages(config.discord.deleted_messages)
+    .with_message_log_directory(config.service.message_log_directory.clone())
+    .with_pause(pause.clone())
+    .with_channel_schedules(
+        config
+            .channel_configs()?
+            .into_iter()
+            .map(|(channel_id, channel)| {
+                let schedule = ChannelSchedule {
+                    summary_tokens_threshold: channel
+                        .summary_tokens_threshold(summary_tokens_threshold)
+                        .map(|tokens| {
+                            summarizers
+                                .summaries
+                                .max_input_tokens()
+                                .map_or(tokens, |max| max.min(tokens))
+                        }),
+                    summarize_after: channel.summarize_after_seconds.map(Duration::from_secs),
+                };
+                (channel_id, schedule)
+            })
+            .collect(),
     );
-    tasks.push(task::spawn(async move {
-        info!("Running message log service");
-        message_log_srv.run().await;
-    }));
+    if let Some(image_describer) = gpt::image_describer_from_config(&config.gpt, &layers)? {
+        message_log_srv = message_log_srv.with_image_describer(image_describer);
+    }
+    if !config.keyword_watch.rules.is_empty() {
+        let alerts = Alerts::new(
+            http.clone(),
+            config.keyword_watch.alert_channel()?,
+            webhooks.clone(),
+        );
+        let keyword_watch =
+            KeywordWatch::from_config(shared_db.clone(), alerts, &config.keyword_watch)?;
+        message_log_srv = message_log_srv.with_keyword_watch(keyword_watch);
+    }
+    let pseudonyms = config
+        .privacy
+        .anonymize_authors
+        .then(|| Pseudonyms::new(config.privacy.pseudonym_key.as_deref()));
+    if let Some(pseudonyms) = &pseudonyms {
+        message_log_srv = message_log_srv.with_pseudonyms(pseudonyms.clone());
+    }
+    tasks.push(supervisor.spawn(
+        "message log",
+        message_log_srv,
+        |mut srv, shutdown| async move {
+            srv.run(shutdown).await;
+            Ok(())
+        },
+    ));
+
+    let prompt_reload_srv =
+        PromptReloadService::new(prompts.clone(), CONFIG_FILE, config.prompts.clone());
+    tasks.push(supervisor.spawn(
+        "prompt reload",
+        prompt_reload_srv,
+        |mut srv, shutdown| async move {
+            shutdown.run_until_cancelled(srv.run()).await;
+            Ok(())
+        },
+    ));
+
+    let eraser = DataEraser::new(
+        shared_db.clone(),
+        http.clone(),
+        opt_outs.clone(),
+        summarizers.summaries.clone(),
+    )
+    .with_prompts(prompts.clone(), names.clone(), summarizers.models.clone());
+    let mut intake = MessageIntake::new(channel_filter, author_filter.with_opt_outs(opt_outs));
+    if let Some(pseudonyms) = pseudonyms {
+        intake = intake.with_pseudonyms(pseudonyms);
+    }
+    let intake = Arc::new(intake);
+    let http_ingest = if config.ingest.enabled {
+        Some(HttpIngest::new(
+            shared_db.clone(),
+            ingest_tx.clone(),
+            &config.ingest,
+        )?)
+    } else {
+        None
+    };
+    let backfiller = Backfiller::new(shared_db.clone(), intake.clone(), ingest_tx.clone());
+    let mut commands = Commands::new(
+        shared_db.clone(),
+        timezone,
+        ingest_tx.clone(),
+        summarizers.summaries.clone(),
+        embedder.clone(),
+        eraser.clone(),
+        backfiller.clone(),
+    )
+    .with_prompts(prompts.clone(), names.clone(), summarizers.models.clone());
+    let transcriber = gpt::transcriber_from_config(&config.gpt, &layers)?;
+    let record_voice = transcriber.is_some() && config.gpt.transcription.record_voice;
+    if let Some(transcriber) = transcriber {
+        commands = commands.with_transcriber(transcriber);
+    }
+    #[cfg(feature = "voice")]
+    if record_voice {
+        commands = commands
+            .with_voice_recorder(VoiceRecorder::new(config.gpt.transcription.max_audio_bytes));
+    }
+    #[cfg(not(feature = "voice"))]
+    if record_voice {
+        warn!("Meetings in voice channels are not recorded, as the bot was built without the voice feature");
+    }
+    if config.faq.enabled {
+        commands = commands.with_faq();
+    }
+    // Guild events fill the cache that channel and role mentions are resolved from.
+    let mut intents = GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS
+        | GatewayIntents::MESSAGE_CONTENT;
+    let mut handler = Handler::new(ingest_tx, intake, commands)
+        .with_highlight_emoji(HighlightEmoji::new(&config.highlights.emoji));
+    if config.discord.gap_recovery_days > 0 {
+        handler = handler.with_gap_recovery(backfiller.clone(), config.discord.gap_recovery_days);
+    }
+    if let Some(days) = config.discord.backfill_days_on_join {
+        handler = handler.with_backfill_on_join(backfiller, days);
+    }
+    let scheduled_events = ScheduledEvents::new(shared_db.clone(), http.clone());
+    if config.scheduled_events.digest_section {
+        intents |= GatewayIntents::GUILD_SCHEDULED_EVENTS;
+        handler = handler.with_scheduled_events(scheduled_events.clone());
+    }
+    if record_voice {
+        intents |= GatewayIntents::GUILD_VOICE_STATES;
+    }
+    let discord_client = Client::builder(token, intents).event_handler(handler);
+    #[cfg(feature = "voice")]
+    let discord_client = discord_client.register_songbird_from_config(voice::voice_config());
+    let discord_client = discord_client
+        .await
+        .map_err(Error::from)
+        .wrap_err("Error creating Discord client")?;
 
-    let mut daily_recap_srv = DailyRecapService::new(
+    let subscription_srv = SubscriptionService::new(
         shared_db.clone(),
-        config.service.produce_digest_interval_seconds,
+        discord_client.http.clone(),
+        templates.clone(),
+        timezone,
     );
-    tasks.push(task::spawn(async move {
-        info!("Running daily digest service");
-        daily_recap_srv.run().await;
-    }));
+    tasks.push(supervisor.spawn(
+        "digest subscription",
+        subscription_srv,
+        |mut srv, shutdown| async move {
+            shutdown.run_until_cancelled(srv.run()).await;
+            Ok(())
+        },
+    ));
 
-    let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
-    let mut discord_client = Client::builder(token, intents)
-        .event_handler(Handler::new(discord_tx, HashSet::default()))
-        .await
-        .expect("Error creating client");
+    if let Some(channel_id) = config.standup.channel_id()? {
+        let standup_srv = StandupService::new(
+            shared_db.clone(),
+            discord_client.http.clone(),
+            summarizers.summaries.clone(),
+            channel_id,
+            &config.standup,
+            timezone,
+        )?;
+        tasks.push(
+            supervisor.spawn("standup", standup_srv, |mut srv, shutdown| async move {
+                shutdown.run_until_cancelled(srv.run()).await;
+                Ok(())
+            }),
+        );
+    }
 
-    tasks.push(task::spawn(async move {
-        // The Serenity crate Will automatically attempt to reconnect, and will perform
-        // exponential backoff until it reconnects.
-        if let Err(why) = discord_client.start().await {
-            error!("Client error: {why:?}");
+    let sinks = Sinks::from_config(
+        shared_db.clone(),
+        templates.clone(),
+        discord_client.http.clone(),
+        &config,
+    )?;
+    let github = GithubActivity::from_config(&config.github)?;
+    let channel_priorities: HashMap<i64, ChannelPriority> = config
+        .channel_configs()?
+        .into_iter()
+        .filter(|(_, channel)| channel.priority != ChannelPriority::Normal)
+        .map(|(channel_id, channel)| (channel_id.get() as i64, channel.priority))
+        .collect();
+    let channel_groups = if config.group_digests.enabled {
+        Some(ChannelGroups::new(
+            shared_db.clone(),
+            summarizers.digests.clone(),
+            config.channel_group_ids()?,
+        ))
+    } else {
+        None
+    };
+    for (tier, schedule) in rollup_schedules {
+        let mut recap_srv = RecapService::new(
+            shared_db.clone(),
+            tier,
+            schedule,
+            timezone,
+            summarizers.digests.clone(),
+        )
+        .with_embedder(embedder.clone())
+        .with_events(events.clone())
+        .with_prompts(prompts.clone(), names.clone())
+        .with_templates(templates.clone())
+        .with_sinks(sinks.clone())
+        .with_verbosity(config.digests.verbosity);
+        if config.digests.tldr {
+            recap_srv = recap_srv.with_tldr();
         }
-    }));
+        if matches!(tier, RollupTier::Daily) {
+            recap_srv = recap_srv.with_webhooks(webhooks.clone());
+            recap_srv = recap_srv.with_open_questions(chrono::Duration::seconds(
+                config.service.question_answer_window_seconds as i64,
+            ));
+            if config.service.calendar_day_digests {
+                recap_srv = recap_srv.with_calendar_days();
+            }
+            if config.citations.enabled {
+                recap_srv = recap_srv.with_citations();
+            }
+            if !config.highlights.emoji.is_empty() {
+                recap_srv = recap_srv.with_highlights();
+            }
+            if config.links.digest_section {
+                recap_srv = recap_srv.with_shared_links();
+            }
+            if config.scheduled_events.digest_section {
+                recap_srv = recap_srv.with_scheduled_events(
+                    scheduled_events.clone(),
+                    chrono::Duration::days(config.scheduled_events.upcoming_days.into()),
+                );
+            }
+            if let Some(github) = &github {
+                recap_srv = recap_srv.with_github(github.clone());
+            }
+            if config.stats.digest_section {
+                recap_srv = recap_srv.with_participant_stats();
+            }
+            if config.changes.digest_section {
+                recap_srv = recap_srv.with_changes();
+            }
+            if !channel_priorities.is_empty() {
+                recap_srv = recap_srv.with_channel_priorities(channel_priorities.clone());
+            }
+            if let Some(channel_groups) = &channel_groups {
+                recap_srv = recap_srv.with_channel_groups(channel_groups.clone());
+            }
+        }
+        if matches!(tier, RollupTier::Weekly) && config.rollups.recurring_topics {
+            recap_srv = recap_srv.with_recurring_topics();
+        }
+        if matches!(tier, RollupTier::Weekly)
+            && config.glossary.enabled
+            && config.glossary.digest_section
+        {
+            recap_srv = recap_srv.with_new_terms();
+        }
+        tasks.push(supervisor.spawn(
+            &format!("{} recap", tier.name()),
+            recap_srv,
+            |mut srv, shutdown| async move {
+                shutdown.run_until_cancelled(srv.run()).await;
+                Ok(())
+            },
+        ));
+    }
+
+    // Stop receiving messages from Discord first, so that the message log service can
+    // store the last ones before everything else stops.
+    let shard_manager = discord_client.shard_manager.clone();
+    let signal_shutdown = shutdown.clone();
+    task::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down, press Ctrl-C again to exit immediately");
+        shard_manager.shutdown_all().await;
+        signal_shutdown.cancel();
+        shutdown_signal().await;
+        std::process::exit(1);
+    });
+
+    let sources: Vec<Box<dyn MessageSource>> = vec![Box::new(DiscordSource::new(discord_client))];
+    for source in sources {
+        tasks.push(
+            supervisor.spawn(&source.name(), source, |mut source, shutdown| async move {
+                source.run(shutdown).await
+            }),
+        );
+    }
 
-    let app = Router::new()
+    let api_keys = Arc::new(config.api.api_keys());
+    if api_keys.is_empty() {
+        warn!("No API keys are configured, the HTTP API is open to anyone who can reach it");
+    }
+    let mut app = Router::new()
         .route("/summaries", get(http_api::summaries_handler))
+        .route(
+            "/summaries/latest",
+            get(http_api::fetch_latest_summaries_handler),
+        )
+        .route("/summaries/:id", get(http_api::summary_handler))
         .route("/daily_digests", get(http_api::daily_digests_handler))
-        .layer(Extension(shared_db));
-
-    tasks.push(task::spawn(async move {
-        info!("Serving http API on port {}", config.service.port);
-        let listener = tokio::net::TcpListener::bind(format!(
-            "{}:{}",
-            config.service.host, config.service.port
+        .route("/daily_digests/:id", get(http_api::daily_digest_handler))
+        .route(
+            "/daily_digests/:id/changes",
+            get(http_api::digest_changes_handler),
+        )
+        .route("/digests.atom", get(http_api::digests_feed_handler))
+        .route("/events", get(http_api::events_handler))
+        .route("/ws", get(http_api::ws_handler))
+        .route("/weekly_digests", get(http_api::weekly_digests_handler))
+        .route("/monthly_digests", get(http_api::monthly_digests_handler))
+        .route(
+            "/digests/:tier/:id/rendered",
+            get(http_api::rendered_digest_handler),
+        )
+        .route(
+            "/admin/pending_summaries",
+            get(http_api::pending_summaries_handler),
+        )
+        .route(
+            "/admin/pending_summaries/:id",
+            delete(http_api::discard_pending_summary_handler),
+        )
+        .route(
+            "/admin/pending_summaries/:id/retry",
+            post(http_api::redrive_pending_summary_handler),
+        )
+        .route(
+            "/admin/webhook_deliveries",
+            get(http_api::webhook_deliveries_handler),
+        )
+        .route(
+            "/admin/pause",
+            get(http_api::pause_status_handler).post(http_api::pause_handler),
+        )
+        .route("/admin/resume", post(http_api::resume_handler))
+        .route(
+            "/admin/webhook_deliveries/:id/retry",
+            post(http_api::redeliver_webhook_handler),
+        )
+        .route("/status", get(http_api::status_handler))
+        .route("/usage", get(http_api::usage_handler))
+        .route("/stats/activity", get(http_api::channel_activity_handler))
+        .route("/topics/trending", get(http_api::trending_topics_handler))
+        .route("/entities", get(http_api::entities_handler))
+        .route(
+            "/entities/:name/mentions",
+            get(http_api::entity_mentions_handler),
+        )
+        .route("/stats/sentiment", get(http_api::channel_moods_handler))
+        .route("/search", get(http_api::search_handler))
+        .route("/links", get(http_api::shared_links_handler))
+        .route("/action_items", get(http_api::action_items_handler))
+        .route("/faq", get(http_api::faq_handler))
+        .route("/glossary", get(http_api::glossary_handler))
+        .route("/group_digests", get(http_api::group_digests_handler))
+        .route("/group_digests/:id", get(http_api::group_digest_handler))
+        .route(
+            "/users/:id/data",
+            delete(http_api::delete_user_data_handler),
+        )
+        .route(
+            "/action_items/:id/resolve",
+            post(http_api::resolve_action_item_handler),
+        )
+        .route(
+            "/action_items/:id/reopen",
+            post(http_api::reopen_action_item_handler),
+        );
+    if let Some(http_ingest) = http_ingest {
+        app = app
+            .route("/ingest", post(http_api::ingest_handler))
+            .layer(Extension(http_ingest));
+    }
+    if config.knowledge_base.enabled {
+        let knowledge_base = KnowledgeBase::new(shared_db.clone(), summarizers.summaries.clone());
+        app = app
+            .route("/kb/generate", post(http_api::generate_kb_article_handler))
+            .route("/kb/articles", get(http_api::kb_articles_handler))
+            .route("/kb/articles/:id", get(http_api::kb_article_handler))
+            .layer(Extension(knowledge_base));
+    }
+    // Rate limits apply once a request is authenticated, so that clients can be told
+    // apart by their API key.
+    if config.service.rate_limit_per_minute > 0 {
+        let limiter = Arc::new(RateLimiter::new(
+            config.service.rate_limit_per_minute,
+            config.service.rate_limit_burst,
+        ));
+        app = app.route_layer(middleware::from_fn_with_state(
+            limiter,
+            http_api::rate_limit,
+        ));
+    }
+    let app = app
+        .route_layer(middleware::from_fn_with_state(
+            api_keys,
+            http_api::require_api_key,
         ))
-        .await
-        .unwrap();
-        axum::serve(listener, app).await.unwrap();
+        .route("/health", get(http_api::health_handler))
+        .route("/", get(http_api::dashboard_handler))
+        .layer(Extension(shared_db))
+        .layer(Extension(embedder))
+        .layer(Extension(events))
+        .layer(Extension(templates))
+        .layer(Extension(eraser))
+        .layer(Extension(shutdown.clone()))
+        .layer(Extension(supervisor.clone()))
+        .layer(Extension(pause))
+        .layer(Extension(Arc::new(http_api::FeedSettings {
+            public_url: config.service.public_url.clone(),
+            timezone,
+        })));
+
+    let address = format!("{}:{}", config.service.host, config.service.port);
+    tasks.push(supervisor.spawn("http API", app, move |app, shutdown| {
+        let address = address.clone();
+        async move {
+            info!("Serving http API on {address}");
+            let listener = tokio::net::TcpListener::bind(address).await?;
+            axum::serve(
+                listener,
+                app.clone()
+                    .into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await?;
+            Ok(())
+        }
     }));
 
     join_all(tasks)
         .await
         .into_iter()
         .collect::<Result<Vec<_>, JoinError>>()?;
+    info!("Shut down");
     Ok(())
 }
+
+/// Resolves on Ctrl-C, or when the process is asked to terminate.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Could not listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Could not listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
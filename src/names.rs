@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serenity::all::{ChannelId, GuildId};
+use serenity::http::Http;
+use sqlx::SqlitePool;
+use tracing::warn;
+
+use crate::db;
+use crate::services::ingest::is_synthetic;
+
+/// Looks up the names of guilds and channels, remembering them once found. Names that
+/// cannot be looked up are replaced by the ID.
+#[derive(Clone)]
+pub struct DiscordNames {
+    http: Arc<Http>,
+    guilds: Arc<Mutex<HashMap<GuildId, String>>>,
+    channels: Arc<Mutex<HashMap<ChannelId, String>>>,
+    /// Where the names of the channels of `POST /ingest` are looked up, when set.
+    db: Option<Arc<SqlitePool>>,
+}
+
+impl DiscordNames {
+    pub fn new(http: Arc<Http>) -> Self {
+        Self {
+            http,
+            guilds: Arc::new(Mutex::new(HashMap::new())),
+            channels: Arc::new(Mutex::new(HashMap::new())),
+            db: None,
+        }
+    }
+
+    /// Looks up the names of ingested channels in the database rather than Discord.
+    pub fn with_ingest_channels(mut self, db: Arc<SqlitePool>) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Name of a guild, or "direct messages" for messages sent outside of one.
+    pub async fn guild(&self, guild_id: Option<GuildId>) -> String {
+        let Some(guild_id) = guild_id else {
+            return "direct messages".to_string();
+        };
+        if let Some(name) = lock(&self.guilds).get(&guild_id) {
+            return name.clone();
+        }
+        match guild_id.to_partial_guild(&self.http).await {
+            Ok(guild) => {
+                lock(&self.guilds).insert(guild_id, guild.name.clone());
+                guild.name
+            }
+            Err(e) => {
+                warn!("Could not look up the name of guild {guild_id}: {e}");
+                guild_id.to_string()
+            }
+        }
+    }
+
+    pub async fn channel(&self, channel_id: ChannelId) -> String {
+        if let Some(name) = lock(&self.channels).get(&channel_id) {
+            return name.clone();
+        }
+        if let Some(db) = self.db.as_ref().filter(|_| is_synthetic(channel_id.get())) {
+            return match db::fetch_ingest_channel_name(db, channel_id.get() as i64).await {
+                Ok(Some(name)) => {
+                    lock(&self.channels).insert(channel_id, name.clone());
+                    name
+                }
+                Ok(None) => channel_id.to_string(),
+                Err(e) => {
+                    warn!("Could not look up the name of ingested channel {channel_id}: {e}");
+                    channel_id.to_string()
+                }
+            };
+        }
+        match channel_id.name(&self.http).await {
+            Ok(name) => {
+                lock(&self.channels).insert(channel_id, name.clone());
+                name
+            }
+            Err(e) => {
+                warn!("Could not look up the name of channel {channel_id}: {e}");
+                channel_id.to_string()
+            }
+        }
+    }
+}
+
+fn lock<K, V>(names: &Mutex<HashMap<K, V>>) -> std::sync::MutexGuard<'_, HashMap<K, V>> {
+    // Names are only ever inserted whole, so the map stays usable after a panic.
+    names.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use chrono::{DateTime, Utc};
+use eyre::WrapErr;
+use serenity::all::{ChannelId, GuildId};
+
+use crate::config::{PromptsConfig, SummaryFormat};
+use crate::gpt::SYSTEM_PROMPT;
+use crate::names::DiscordNames;
+
+/// Values substituted for the variables of a prompt template.
+pub struct PromptVars {
+    /// Name of the guild, `{guild}`.
+    pub guild: String,
+    /// Name of the channel, `{channel}`. Empty for digests, which cover every channel.
+    pub channel: String,
+    /// Start and end of the period covered, `{from}` and `{to}`.
+    pub from: String,
+    pub to: String,
+}
+
+impl PromptVars {
+    /// Looks up the names of the guild and channel, or uses their IDs without `names`.
+    pub async fn lookup(
+        names: Option<&DiscordNames>,
+        guild_id: Option<GuildId>,
+        channel_id: Option<ChannelId>,
+        from: String,
+        to: String,
+    ) -> Self {
+        let (guild, channel) = match names {
+            Some(names) => (
+                names.guild(guild_id).await,
+                match channel_id {
+                    Some(channel_id) => names.channel(channel_id).await,
+                    None => String::new(),
+                },
+            ),
+            None => (
+                guild_id.map(|id| id.to_string()).unwrap_or_default(),
+                channel_id.map(|id| id.to_string()).unwrap_or_default(),
+            ),
+        };
+        Self {
+            guild,
+            channel,
+            from,
+            to,
+        }
+    }
+}
+
+/// Instructions for summarizing a channel, along with how to summarize it.
+#[derive(Clone)]
+pub struct SummaryPrompt {
+    pub instructions: String,
+    /// Model to use instead of the configured one.
+    pub model: Option<String>,
+    pub format: SummaryFormat,
+}
+
+/// The language summaries and digests are written in.
+#[derive(Clone)]
+enum Language {
+    Named(String),
+    /// The dominant language of the text being summarized.
+    Detect,
+}
+
+impl Language {
+    fn parse(language: &str) -> Self {
+        if language.eq_ignore_ascii_case("auto") {
+            Self::Detect
+        } else {
+            Self::Named(language.to_string())
+        }
+    }
+
+    /// Name of the language to write in, or `None` when `text` is in no language that
+    /// can be detected.
+    fn resolve(&self, text: &str) -> Option<String> {
+        match self {
+            Self::Named(name) => Some(name.clone()),
+            Self::Detect => whatlang::detect(text).map(|info| info.lang().eng_name().to_string()),
+        }
+    }
+}
+
+/// Appends the instruction to write in `language` to `instructions`, when set.
+fn with_language(instructions: String, language: Option<&Language>, text: &str) -> String {
+    match language.and_then(|language| language.resolve(text)) {
+        Some(name) => format!("{instructions}\n\nWrite your reply in {name}."),
+        None => instructions,
+    }
+}
+
+struct Profile {
+    summary: String,
+    model: Option<String>,
+    format: SummaryFormat,
+    language: Option<Language>,
+}
+
+struct Templates {
+    summary: String,
+    digest: String,
+    language: Option<Language>,
+    profiles: HashMap<String, Profile>,
+    /// The profile of each channel that has one.
+    channels: HashMap<ChannelId, String>,
+}
+
+impl Templates {
+    fn load(config: &PromptsConfig) -> eyre::Result<Self> {
+        let summary = load_template(
+            config.summary.as_deref(),
+            config.summary_file.as_deref(),
+            SYSTEM_PROMPT,
+        )?;
+        let language = config.language.as_deref().map(Language::parse);
+        let profiles = config
+            .profiles
+            .iter()
+            .map(|(name, profile)| {
+                let template = load_template(
+                    profile.summary.as_deref(),
+                    profile.summary_file.as_deref(),
+                    &summary,
+                )?;
+                let profile = Profile {
+                    summary: template,
+                    model: profile.model.clone(),
+                    format: profile.format,
+                    language: match &profile.language {
+                        Some(language) => Some(Language::parse(language)),
+                        None => language.clone(),
+                    },
+                };
+                Ok((name.clone(), profile))
+            })
+            .collect::<eyre::Result<_>>()?;
+        Ok(Self {
+            digest: load_template(
+                config.digest.as_deref(),
+                config.digest_file.as_deref(),
+                SYSTEM_PROMPT,
+            )?,
+            summary,
+            language,
+            profiles,
+            channels: config.channel_profiles()?,
+        })
+    }
+
+    fn default_summary(&self, vars: &PromptVars, text: &str) -> SummaryPrompt {
+        SummaryPrompt {
+            instructions: with_language(render(&self.summary, vars), self.language.as_ref(), text),
+            model: None,
+            format: SummaryFormat::default(),
+        }
+    }
+}
+
+/// Reads a template from its file if it has one, falling back to the inline template
+/// and then to `default`.
+fn load_template(inline: Option<&str>, file: Option<&str>, default: &str) -> eyre::Result<String> {
+    let template = match file {
+        Some(path) => fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read prompt template {path}"))?,
+        None => inline.unwrap_or(default).to_string(),
+    };
+    Ok(template.trim().to_string())
+}
+
+/// The prompt templates in use, shared by every service and replaced whenever they are
+/// reloaded.
+#[derive(Clone)]
+pub struct Prompts {
+    templates: Arc<RwLock<Templates>>,
+}
+
+impl Prompts {
+    pub fn load(config: &PromptsConfig) -> eyre::Result<Self> {
+        Ok(Self {
+            templates: Arc::new(RwLock::new(Templates::load(config)?)),
+        })
+    }
+
+    /// Replaces the templates with those of the config, keeping the current ones if any
+    /// cannot be read.
+    pub fn reload(&self, config: &PromptsConfig) -> eyre::Result<()> {
+        let templates = Templates::load(config)?;
+        *self
+            .templates
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = templates;
+        Ok(())
+    }
+
+    /// Instructions for summarizing a channel's messages, from the channel's profile if
+    /// it has one. `text` is what is detected when the language is "auto".
+    pub fn summary(&self, channel_id: ChannelId, vars: &PromptVars, text: &str) -> SummaryPrompt {
+        let templates = self.read();
+        let profile = templates
+            .channels
+            .get(&channel_id)
+            .and_then(|name| templates.profiles.get(name));
+        match profile {
+            Some(profile) => SummaryPrompt {
+                instructions: with_language(
+                    render(&profile.summary, vars),
+                    profile.language.as_ref(),
+                    text,
+                ),
+                model: profile.model.clone(),
+                format: profile.format,
+            },
+            None => templates.default_summary(vars, text),
+        }
+    }
+
+    /// Instructions for summarizing messages of no channel in particular, such as a
+    /// transcript summarized from the command line.
+    pub fn default_summary(&self, vars: &PromptVars, text: &str) -> SummaryPrompt {
+        self.read().default_summary(vars, text)
+    }
+
+    /// Instructions for rolling summaries or digests up into a digest, in the global
+    /// language.
+    pub fn digest(&self, vars: &PromptVars, text: &str) -> String {
+        let templates = self.read();
+        with_language(
+            render(&templates.digest, vars),
+            templates.language.as_ref(),
+            text,
+        )
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Templates> {
+        self.templates
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for Prompts {
+    /// The built-in prompts.
+    fn default() -> Self {
+        Self {
+            templates: Arc::new(RwLock::new(Templates {
+                summary: SYSTEM_PROMPT.to_string(),
+                digest: SYSTEM_PROMPT.to_string(),
+                language: None,
+                profiles: HashMap::new(),
+                channels: HashMap::new(),
+            })),
+        }
+    }
+}
+
+fn render(template: &str, vars: &PromptVars) -> String {
+    template
+        .replace("{guild}", &vars.guild)
+        .replace("{channel}", &vars.channel)
+        .replace("{from}", &vars.from)
+        .replace("{to}", &vars.to)
+}
+
+/// Formats the start or end of the period a prompt covers, in UTC.
+pub fn format_prompt_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
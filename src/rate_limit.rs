@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of tracked clients past which full buckets are forgotten, then the least
+/// recently used ones.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+/// Share of `MAX_TRACKED_CLIENTS` kept when evicting buckets that are in use, so that
+/// evictions do not happen on every request.
+const EVICT_DOWN_TO: usize = MAX_TRACKED_CLIENTS * 9 / 10;
+
+/// Token bucket rate limiter keyed by client. Every client starts with `burst` tokens,
+/// each request takes one, and tokens are given back at a steady rate.
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    tokens_per_second: f64,
+    burst: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        Self {
+            buckets: Mutex::new(HashMap::new()),
+            tokens_per_second: f64::from(requests_per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+        }
+    }
+
+    /// Takes a token from the client's bucket, or returns how long to wait until one is
+    /// available.
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            buckets.retain(|_, bucket| self.refill(bucket, now) < self.burst);
+            if buckets.len() >= MAX_TRACKED_CLIENTS {
+                let mut by_age: Vec<(Instant, String)> = buckets
+                    .iter()
+                    .map(|(client, bucket)| (bucket.updated_at, client.clone()))
+                    .collect();
+                by_age.sort_unstable();
+                for (_, client) in by_age.drain(..buckets.len() - EVICT_DOWN_TO) {
+                    buckets.remove(&client);
+                }
+            }
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        let tokens = self.refill(bucket, now);
+        bucket.updated_at = now;
+        if tokens >= 1.0 {
+            bucket.tokens = tokens - 1.0;
+            return Ok(());
+        }
+        bucket.tokens = tokens;
+        Err(Duration::from_secs_f64(
+            (1.0 - tokens) / self.tokens_per_second,
+        ))
+    }
+
+    /// The tokens a bucket holds once refilled up to `now`.
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.tokens_per_second).min(self.burst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_are_allowed_then_limited() {
+        let limiter = RateLimiter::new(60, 3);
+        for _ in 0..3 {
+            assert!(limiter.check("client").is_ok());
+        }
+        let retry_after = limiter.check("client").unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+        // Other clients have buckets of their own.
+        assert!(limiter.check("other").is_ok());
+    }
+
+    #[test]
+    fn tokens_are_given_back_over_time() {
+        let limiter = RateLimiter::new(60, 1);
+        assert!(limiter.check("client").is_ok());
+        assert!(limiter.check("client").is_err());
+        let mut buckets = limiter.buckets.lock().unwrap();
+        buckets.get_mut("client").unwrap().updated_at -= Duration::from_secs(2);
+        drop(buckets);
+        assert!(limiter.check("client").is_ok());
+    }
+
+    #[test]
+    fn partly_drained_buckets_are_evicted_once_too_many_clients_are_tracked() {
+        let limiter = RateLimiter::new(1, 10);
+        for client in 0..MAX_TRACKED_CLIENTS + 5 {
+            assert!(limiter.check(&client.to_string()).is_ok());
+        }
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.len() <= MAX_TRACKED_CLIENTS);
+        // The most recent clients are kept.
+        assert!(buckets.contains_key(&(MAX_TRACKED_CLIENTS + 4).to_string()));
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use eyre::{eyre, WrapErr};
+use std::time::Duration;
+
+/// How far back to look for the previous run of a cron schedule, tried in order so
+/// that frequent schedules do not have to walk through a year of occurrences.
+const PREVIOUS_RUN_LOOKBACKS_HOURS: [i64; 4] = [1, 24, 24 * 32, 24 * 366];
+
+/// When a recap service produces its digests.
+pub enum Schedule {
+    /// Every fixed amount of time, counted from startup. Digests cover whatever has
+    /// accumulated since the previous digest.
+    Interval(Duration),
+    /// At the times matching a cron expression. Digests cover the window between the
+    /// previous scheduled time and the current one.
+    Cron(Box<CronSchedule>),
+}
+
+impl Schedule {
+    /// Uses the cron expression when one is configured, falling back to the interval.
+    pub fn from_config(
+        cron_expression: Option<&str>,
+        interval_seconds: Option<u64>,
+        timezone: Tz,
+    ) -> eyre::Result<Option<Self>> {
+        if let Some(expression) = cron_expression {
+            let schedule = CronSchedule::new(expression, timezone)?;
+            return Ok(Some(Schedule::Cron(Box::new(schedule))));
+        }
+        Ok(interval_seconds.map(|seconds| Schedule::Interval(Duration::from_secs(seconds))))
+    }
+}
+
+/// A cron expression, such as `0 8 * * *` for 8am every day, evaluated in a timezone.
+pub struct CronSchedule {
+    cron: Cron,
+    timezone: Tz,
+}
+
+impl CronSchedule {
+    pub fn new(expression: &str, timezone: Tz) -> eyre::Result<Self> {
+        let cron = Cron::new(expression)
+            .with_seconds_optional()
+            .parse()
+            .wrap_err_with(|| format!("Invalid cron expression {expression:?}"))?;
+        Ok(Self { cron, timezone })
+    }
+
+    /// The first scheduled time strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> eyre::Result<DateTime<Utc>> {
+        let next = self
+            .cron
+            .find_next_occurrence(&after.with_timezone(&self.timezone), false)?;
+        Ok(next.with_timezone(&Utc))
+    }
+
+    /// The last scheduled time strictly before `before`, if there was one within a year.
+    pub fn previous_before(&self, before: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        for hours in PREVIOUS_RUN_LOOKBACKS_HOURS {
+            let mut previous = None;
+            let mut time = before - ChronoDuration::hours(hours);
+            while let Ok(next) = self.next_after(time) {
+                if next >= before {
+                    break;
+                }
+                previous = Some(next);
+                time = next;
+            }
+            if previous.is_some() {
+                return previous;
+            }
+        }
+        None
+    }
+}
+
+/// Parses an IANA timezone name such as `Europe/Berlin`.
+pub fn parse_timezone(name: &str) -> eyre::Result<Tz> {
+    name.parse::<Tz>()
+        .map_err(|e| eyre!("Invalid timezone {name:?}: {e}"))
+}
+
+/// The moment a calendar day starts in a timezone. Days whose midnight is skipped by a
+/// daylight saving change start at the first hour that exists.
+pub fn start_of_day(day: NaiveDate, timezone: Tz) -> DateTime<Utc> {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+    (0..24)
+        .find_map(|hour| {
+            timezone
+                .from_local_datetime(&(midnight + ChronoDuration::hours(hour)))
+                .earliest()
+        })
+        .map_or_else(|| midnight.and_utc(), |start| start.with_timezone(&Utc))
+}
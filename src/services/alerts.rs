@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use serenity::all::ChannelId;
+use serenity::http::Http;
+use tracing::error;
+
+use crate::config::WebhookEvent;
+
+use super::digests::{split_message, DISCORD_MESSAGE_LIMIT};
+use super::webhooks::Webhooks;
+
+/// Sends alerts about what happens in the listened to channels to an admin channel,
+/// when one is configured, and to the webhooks that want them.
+#[derive(Clone)]
+pub struct Alerts {
+    http: Arc<Http>,
+    channel_id: Option<ChannelId>,
+    webhooks: Webhooks,
+}
+
+impl Alerts {
+    pub fn new(http: Arc<Http>, channel_id: Option<ChannelId>, webhooks: Webhooks) -> Self {
+        Self {
+            http,
+            channel_id,
+            webhooks,
+        }
+    }
+
+    /// Posts `text` to the admin channel and sends `data` to webhooks as `event`.
+    pub async fn send<T: Serialize>(&self, event: WebhookEvent, text: &str, data: &T) {
+        if let Some(channel_id) = self.channel_id {
+            for chunk in split_message(text, DISCORD_MESSAGE_LIMIT) {
+                if let Err(e) = channel_id.say(&self.http, chunk).await {
+                    error!(
+                        "Could not post {} alert to channel {channel_id}: {e}",
+                        event.as_str()
+                    );
+                    break;
+                }
+            }
+        }
+        self.webhooks.publish(event, data).await;
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::config::{AnomaliesConfig, SummaryFormat, WebhookEvent};
+use crate::db;
+use crate::gpt::Summarizer;
+use crate::prompts::SummaryPrompt;
+
+use super::alerts::Alerts;
+use super::summarizer::summarize_channel_messages;
+
+/// Instructions for the summary of what a channel is blowing up about.
+const ANOMALY_SUMMARY_PROMPT: &str = "A Discord channel suddenly got much busier than usual. In two or three sentences, explain what the following messages are about and what set them off, so that moderators can decide whether to step in.";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// A channel got many more messages than it usually does.
+    Spike,
+    /// A single member posted a burst of messages.
+    Flood,
+}
+
+/// What webhooks are sent about an anomaly.
+#[derive(Serialize)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    /// Who flooded the channel, for floods.
+    pub author: Option<String>,
+    /// Messages posted in the window, by the author for floods.
+    pub message_count: i64,
+    /// Messages the channel usually gets in a window of the same length.
+    pub baseline: f64,
+    pub window_minutes: u64,
+    /// What the messages of the window are about, when it could be summarized.
+    pub summary: Option<String>,
+}
+
+/// Periodically compares the latest activity of each channel to its baseline, and
+/// alerts about spikes in volume and members flooding a channel.
+pub struct AnomalyService {
+    db: Arc<SqlitePool>,
+    alerts: Alerts,
+    config: AnomaliesConfig,
+    /// Summarizes the messages of a channel when alerting about it, when set.
+    summarizer: Option<Arc<dyn Summarizer>>,
+    /// When each channel was last alerted about, per kind of anomaly.
+    alerted: HashMap<(i64, AnomalyKind), DateTime<Utc>>,
+}
+
+impl AnomalyService {
+    pub fn new(db: Arc<SqlitePool>, alerts: Alerts, config: AnomaliesConfig) -> Self {
+        Self {
+            db,
+            alerts,
+            config,
+            summarizer: None,
+            alerted: HashMap::new(),
+        }
+    }
+
+    /// Includes a short summary of the channel's latest messages in each alert.
+    pub fn with_summarizer(mut self, summarizer: Arc<dyn Summarizer>) -> Self {
+        self.summarizer = Some(summarizer);
+        self
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval_timer = interval(std::time::Duration::from_secs(
+            self.config.check_interval_seconds,
+        ));
+        loop {
+            interval_timer.tick().await;
+            if let Err(e) = self.check().await {
+                error!("Could not check channel activity for anomalies: {e}");
+            }
+        }
+    }
+
+    async fn check(&mut self) -> Result<(), sqlx::Error> {
+        let now = Utc::now();
+        let window = Duration::minutes(self.config.window_minutes as i64);
+        let since = now - window;
+        let baseline_period = Duration::days(i64::from(self.config.baseline_days));
+        // Hours the window overlaps are left out of the baseline.
+        let baselines: HashMap<i64, i64> =
+            db::fetch_channel_activity_totals(&self.db, since - baseline_period, since)
+                .await?
+                .into_iter()
+                .map(|total| (total.channel_id, total.message_count))
+                .collect();
+        let windows_per_baseline =
+            baseline_period.num_seconds() as f64 / window.num_seconds() as f64;
+        let baseline_of =
+            |channel_id| *baselines.get(&channel_id).unwrap_or(&0) as f64 / windows_per_baseline;
+
+        let mut anomalies = vec![];
+        for count in db::fetch_channel_message_counts(&self.db, since).await? {
+            let baseline = baseline_of(count.channel_id);
+            if count.message_count >= self.config.min_messages
+                && count.message_count as f64 >= self.config.spike_factor * baseline
+            {
+                anomalies.push(Anomaly {
+                    kind: AnomalyKind::Spike,
+                    guild_id: count.guild_id,
+                    channel_id: count.channel_id,
+                    author: None,
+                    message_count: count.message_count,
+                    baseline,
+                    window_minutes: self.config.window_minutes,
+                    summary: None,
+                });
+            }
+        }
+        for count in db::fetch_prolific_authors(&self.db, since, self.config.flood_messages).await?
+        {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::Flood,
+                guild_id: count.guild_id,
+                channel_id: count.channel_id,
+                author: Some(count.author),
+                message_count: count.message_count,
+                baseline: baseline_of(count.channel_id),
+                window_minutes: self.config.window_minutes,
+                summary: None,
+            });
+        }
+
+        let cooldown = Duration::minutes(self.config.cooldown_minutes as i64);
+        for mut anomaly in anomalies {
+            let key = (anomaly.channel_id, anomaly.kind);
+            if self
+                .alerted
+                .get(&key)
+                .is_some_and(|alerted| now - *alerted < cooldown)
+            {
+                continue;
+            }
+            self.alerted.insert(key, now);
+            anomaly.summary = self.summarize(anomaly.channel_id, since).await;
+            self.alert(&anomaly).await;
+        }
+        self.alerted.retain(|_, alerted| now - *alerted < cooldown);
+        Ok(())
+    }
+
+    /// Summarizes the messages a channel got since `since`.
+    async fn summarize(&self, channel_id: i64, since: DateTime<Utc>) -> Option<String> {
+        let summarizer = self.summarizer.as_ref()?;
+        let messages = match db::fetch_channel_messages_since(&self.db, channel_id, since).await {
+            Ok(messages) => messages,
+            Err(e) => {
+                error!("Could not fetch the latest messages of channel {channel_id}: {e}");
+                return None;
+            }
+        };
+        let prompt = SummaryPrompt {
+            instructions: ANOMALY_SUMMARY_PROMPT.to_string(),
+            model: None,
+            format: SummaryFormat::Prose,
+        };
+        match summarize_channel_messages(summarizer, &HashMap::new(), &prompt, &messages).await {
+            Ok(summary) => Some(summary.summary),
+            Err(e) => {
+                warn!("Could not summarize the latest messages of channel {channel_id}: {e:#}");
+                None
+            }
+        }
+    }
+
+    async fn alert(&self, anomaly: &Anomaly) {
+        let mut text = match (&anomaly.kind, &anomaly.author) {
+            (AnomalyKind::Flood, Some(author)) => format!(
+                "**Message flood in <#{}>:** {author} posted {} messages in the last {} minutes.",
+                anomaly.channel_id, anomaly.message_count, anomaly.window_minutes
+            ),
+            _ => format!(
+                "**Activity spike in <#{}>:** {} messages in the last {} minutes, against about {:.1} usually.",
+                anomaly.channel_id, anomaly.message_count, anomaly.window_minutes, anomaly.baseline
+            ),
+        };
+        if let Some(summary) = &anomaly.summary {
+            text = format!("{text}\n\n{summary}");
+        }
+        info!("{text}");
+        self.alerts
+            .send(WebhookEvent::Anomaly, &text, anomaly)
+            .await;
+    }
+}
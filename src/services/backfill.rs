@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serenity::all::{ChannelId, ChannelType, Context, GetMessages, GuildId, Message, MessageId};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+
+use crate::db;
+
+use super::discord_handler::MessageIntake;
+use super::ingest::is_synthetic;
+use super::sources::IngestEvent;
+
+/// Milliseconds from the Unix epoch to the first second of 2015, which Discord IDs
+/// count from.
+const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+/// Most messages the Discord API returns per page of channel history.
+const PAGE_SIZE: u8 = 100;
+
+/// Fetches the history of channels and logs it like messages received live, so that
+/// summaries can be produced retroactively.
+#[derive(Clone)]
+pub struct Backfiller {
+    db: Arc<SqlitePool>,
+    intake: Arc<MessageIntake>,
+    ingest_tx: Sender<IngestEvent>,
+}
+
+impl Backfiller {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        intake: Arc<MessageIntake>,
+        ingest_tx: Sender<IngestEvent>,
+    ) -> Self {
+        Self {
+            db,
+            intake,
+            ingest_tx,
+        }
+    }
+
+    /// Backfills every text channel of a guild the bot listens to. Returns how many
+    /// messages were logged.
+    pub async fn backfill_guild(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        days: u32,
+    ) -> eyre::Result<usize> {
+        let mut count = 0;
+        for (channel_id, channel) in guild_id.channels(&ctx.http).await? {
+            if !matches!(channel.kind, ChannelType::Text | ChannelType::News)
+                || !self.intake.allows_channel(Some(guild_id), &channel_id)
+            {
+                continue;
+            }
+            match self.backfill_channel(ctx, guild_id, channel_id, days).await {
+                Ok(logged) => count += logged,
+                Err(e) => warn!("Could not backfill channel {channel_id}: {e}"),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Logs the messages of the last `days` days of a channel that were posted before
+    /// the first one it has logged, and has them summarized. Returns how many messages
+    /// were logged.
+    pub async fn backfill_channel(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        days: u32,
+    ) -> eyre::Result<usize> {
+        let since = (Utc::now() - Duration::days(days.into())).timestamp();
+        let mut before = db::fetch_first_message_id(&self.db, channel_id.get() as i64)
+            .await?
+            .map(|id| MessageId::new(id as u64));
+        // Pages come newest first.
+        let mut history = vec![];
+        loop {
+            let mut request = GetMessages::new().limit(PAGE_SIZE);
+            if let Some(before) = before {
+                request = request.before(before);
+            }
+            let page = channel_id.messages(&ctx.http, request).await?;
+            let Some(oldest) = page.last() else {
+                break;
+            };
+            before = Some(oldest.id);
+            let done = page.len() < PAGE_SIZE as usize || oldest.timestamp.unix_timestamp() < since;
+            history.extend(
+                page.into_iter()
+                    .filter(|msg| msg.timestamp.unix_timestamp() >= since),
+            );
+            if done {
+                break;
+            }
+        }
+
+        history.reverse();
+        self.log_history(ctx, guild_id, channel_id, history).await
+    }
+
+    /// Logs what every channel with logged messages received since its last one, such
+    /// as while the bot was offline, going back at most `max_days` days. Returns how
+    /// many messages were logged.
+    pub async fn recover_gaps(&self, ctx: &Context, max_days: u32) -> eyre::Result<usize> {
+        let now = Utc::now();
+        // Messages from now on are received live.
+        let until = snowflake_at(now);
+        let oldest = snowflake_at(now - Duration::days(max_days.into()));
+        let mut count = 0;
+        for channel in db::fetch_last_message_ids(&self.db).await? {
+            let Some(guild_id) = channel.guild_id.map(|id| GuildId::new(id as u64)) else {
+                continue;
+            };
+            let channel_id = ChannelId::new(channel.channel_id as u64);
+            // Ingested channels have no history on Discord.
+            if is_synthetic(channel_id.get())
+                || !self.intake.allows_channel(Some(guild_id), &channel_id)
+            {
+                continue;
+            }
+            let after = MessageId::new(channel.message_id as u64).max(oldest);
+            match self
+                .recover_channel(ctx, guild_id, channel_id, after, until)
+                .await
+            {
+                Ok(logged) => count += logged,
+                Err(e) => warn!("Could not recover missed messages of channel {channel_id}: {e}"),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Logs the messages of a channel posted after `after` and before `until`.
+    async fn recover_channel(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        mut after: MessageId,
+        until: MessageId,
+    ) -> eyre::Result<usize> {
+        let mut history = vec![];
+        loop {
+            let request = GetMessages::new().after(after).limit(PAGE_SIZE);
+            let mut page = channel_id.messages(&ctx.http, request).await?;
+            page.sort_by_key(|msg| msg.id);
+            let Some(newest) = page.last() else {
+                break;
+            };
+            after = newest.id;
+            let done = page.len() < PAGE_SIZE as usize || newest.id >= until;
+            history.extend(page.into_iter().filter(|msg| msg.id < until));
+            if done {
+                break;
+            }
+        }
+        if !history.is_empty() {
+            info!(
+                "Recovering {} messages of channel {channel_id} missed while offline",
+                history.len()
+            );
+        }
+        self.log_history(ctx, guild_id, channel_id, history).await
+    }
+
+    /// Sends messages fetched from a channel's history, oldest first, to be logged
+    /// and summarized. Returns how many were logged.
+    async fn log_history(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        history: Vec<Message>,
+    ) -> eyre::Result<usize> {
+        let mut messages = vec![];
+        for mut msg in history {
+            // Messages fetched from the API do not say which guild they belong to.
+            msg.guild_id = Some(guild_id);
+            if let Some(accepted) = self.intake.accept(ctx, msg).await {
+                messages.push(accepted);
+            }
+        }
+        let count = messages.len();
+        if count > 0 {
+            self.ingest_tx
+                .send(IngestEvent::Backfilled {
+                    channel_id,
+                    messages,
+                })
+                .await?;
+        }
+        Ok(count)
+    }
+}
+
+/// The smallest Discord ID of anything created at `time`, IDs being ordered by creation
+/// time.
+fn snowflake_at(time: DateTime<Utc>) -> MessageId {
+    let since_epoch = (time.timestamp_millis() - DISCORD_EPOCH_MS).max(1) as u64;
+    MessageId::new(since_epoch << 22)
+}
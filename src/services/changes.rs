@@ -0,0 +1,63 @@
+use eyre::{eyre, WrapErr};
+
+use crate::db::DigestChanges;
+use crate::gpt::Summarizer;
+
+/// Instructions asking the model what changed between two daily digests.
+const CHANGES_INSTRUCTIONS: &str = r#"You compare two consecutive daily digests of a Discord server, yesterday's and today's, to tell members what changed. List the topics today's digest discusses that yesterday's did not, the issues or questions of yesterday's digest that today's shows were resolved, and the discussions of yesterday's digest that continued today. Keep each item to one short sentence. Reply with a single JSON object and nothing else, in this format:
+{
+  "new_topics": ["..."],
+  "resolved": ["..."],
+  "continuing": ["..."]
+}
+Use empty lists when there is nothing to report for a field."#;
+
+/// Asks the model what changed in `current` compared to the `previous` digest.
+pub async fn compare_digests(
+    summarizer: &dyn Summarizer,
+    previous: &str,
+    current: &str,
+) -> eyre::Result<DigestChanges> {
+    let text = format!("Yesterday's digest:\n{previous}\n\nToday's digest:\n{current}");
+    let reply = summarizer.complete(CHANGES_INSTRUCTIONS, &text).await?;
+    parse_changes(&reply)
+}
+
+/// Parses the model's reply, tolerating Markdown code fences or text around the JSON
+/// object.
+fn parse_changes(reply: &str) -> eyre::Result<DigestChanges> {
+    let start = reply.find('{');
+    let end = reply.rfind('}');
+    let json = match start.zip(end) {
+        Some((start, end)) if start < end => &reply[start..=end],
+        _ => return Err(eyre!("Reply does not contain a JSON object")),
+    };
+    let mut changes: DigestChanges =
+        serde_json::from_str(json).wrap_err("Reply is not a valid list of changes")?;
+    for list in [
+        &mut changes.new_topics,
+        &mut changes.resolved,
+        &mut changes.continuing,
+    ] {
+        list.retain(|item| !item.trim().is_empty());
+    }
+    Ok(changes)
+}
+
+/// Renders the "What changed since yesterday" section of daily digests, or `None` when
+/// nothing did.
+pub fn changes_section(changes: &DigestChanges) -> Option<String> {
+    let mut section = String::from("**What changed since yesterday**");
+    let mut listed = false;
+    for (label, items) in [
+        ("New", &changes.new_topics),
+        ("Resolved", &changes.resolved),
+        ("Continuing", &changes.continuing),
+    ] {
+        for item in items {
+            listed = true;
+            section.push_str(&format!("\n- {label}: {item}"));
+        }
+    }
+    listed.then_some(section)
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serenity::all::ChannelId;
+use sqlx::SqlitePool;
+use tracing::{error, info};
+
+use crate::db::{self, NewGroupDigest, RollupSource};
+use crate::gpt::Summarizer;
+
+/// Writes a digest of the summaries of each channel group along with each daily digest,
+/// so that the people following a few channels get a digest of their own.
+#[derive(Clone)]
+pub struct ChannelGroups {
+    db: Arc<SqlitePool>,
+    summarizer: Arc<dyn Summarizer>,
+    /// The channels of each group, in the order their sections are written in.
+    groups: Arc<BTreeMap<String, Vec<i64>>>,
+}
+
+impl ChannelGroups {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        summarizer: Arc<dyn Summarizer>,
+        groups: BTreeMap<String, Vec<ChannelId>>,
+    ) -> Self {
+        let groups = groups
+            .into_iter()
+            .map(|(group, channel_ids)| {
+                let channel_ids = channel_ids.iter().map(|id| id.get() as i64).collect();
+                (group, channel_ids)
+            })
+            .collect();
+        Self {
+            db,
+            summarizer,
+            groups: Arc::new(groups),
+        }
+    }
+
+    /// Writes and stores the digest of each group whose channels were summarized among
+    /// the summaries a daily digest rolls up.
+    pub async fn write_digests(
+        &self,
+        daily_digest_id: i64,
+        guild_id: Option<i64>,
+        sources: &[RollupSource],
+    ) {
+        for (group, channel_ids) in self.groups.iter() {
+            let mut summarized = vec![];
+            let mut text = String::new();
+            let mut message_count = 0;
+            for channel_id in channel_ids {
+                let summaries: Vec<&RollupSource> = sources
+                    .iter()
+                    .filter(|source| source.channel_id == Some(*channel_id))
+                    .collect();
+                if summaries.is_empty() {
+                    continue;
+                }
+                summarized.push(*channel_id);
+                text.push_str(&format!("**<#{channel_id}>**\n"));
+                for summary in summaries {
+                    text.push_str(&format!("{}\n\n", summary.text.trim()));
+                    message_count += summary.message_count;
+                }
+            }
+            if summarized.is_empty() {
+                continue;
+            }
+            let instructions = group_instructions(group);
+            let digest = match self.summarizer.complete(&instructions, &text).await {
+                Ok(digest) => digest,
+                Err(e) => {
+                    error!("Could not write the digest of channel group {group:?} for daily digest {daily_digest_id}: {e}");
+                    continue;
+                }
+            };
+            let new_digest = NewGroupDigest {
+                daily_digest_id,
+                group_name: group,
+                guild_id,
+                text: digest.trim(),
+                message_count,
+                channel_ids: &summarized,
+            };
+            match db::insert_group_digest(&self.db, &new_digest).await {
+                Ok(id) => info!(
+                    "Saved digest {id} of channel group {group:?} for daily digest {daily_digest_id}"
+                ),
+                Err(e) => error!(
+                    "Could not store the digest of channel group {group:?} for daily digest {daily_digest_id}: {e}"
+                ),
+            }
+        }
+    }
+}
+
+/// Instructions for the digest of a channel group, which keeps the heading of each
+/// channel so that the digest is sectioned by channel.
+fn group_instructions(group: &str) -> String {
+    format!("You write the digest of the \"{group}\" group of channels of a Discord server, from the summaries of the discussions of each of its channels. Start with one or two sentences on what happened across the group. Then write a section per channel, in the order given, each starting with the channel's heading line exactly as given, such as **<#123>**, followed by the most important discussions, decisions and open issues of that channel. Only use what the summaries say.")
+}
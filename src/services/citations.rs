@@ -0,0 +1,54 @@
+use std::collections::BTreeSet;
+
+use regex::Regex;
+
+use crate::db::Citation;
+
+use super::highlights::jump_link;
+
+/// Appended to the digest instructions when the summaries are numbered.
+pub const CITATIONS_FORMAT: &str = "Each summary starts with its number in brackets, such as [1]. After each point of the digest, cite the summaries it comes from by their numbers in brackets, such as [1][3].";
+
+/// Joins the summaries a daily digest is written from, each preceded by the number the
+/// digest cites it by.
+pub fn numbered_sources(sources: &[String]) -> String {
+    sources
+        .iter()
+        .enumerate()
+        .map(|(i, text)| format!("[{}] {text}", i + 1))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Renders the "Sources" section linking the summaries a digest cites to a message of
+/// each, given the citation of every source in order. Every summary is listed when the
+/// digest cites none of them. Returns `None` when there is nothing to link to.
+pub fn sources_section(text: &str, citations: &[Option<Citation>]) -> Option<String> {
+    let marker = Regex::new(r"\[(\d+)\]").expect("the citation pattern is valid");
+    let mut cited: BTreeSet<usize> = marker
+        .captures_iter(text)
+        .filter_map(|captures| captures[1].parse().ok())
+        .filter(|number| (1..=citations.len()).contains(number))
+        .collect();
+    if cited.is_empty() {
+        cited = (1..=citations.len()).collect();
+    }
+    let mut lines = vec![];
+    for number in cited {
+        let Some(citation) = &citations[number - 1] else {
+            continue;
+        };
+        // Messages of threads are stored under their parent channel, but only open
+        // from the thread.
+        let channel_id = citation.thread_id.unwrap_or(citation.channel_id);
+        lines.push(format!(
+            "[{number}] <#{}> ([jump](<{}>))",
+            citation.channel_id,
+            jump_link(citation.guild_id, channel_id, citation.message_id)
+        ));
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    Some(format!("**Sources**\n{}", lines.join("\n")))
+}
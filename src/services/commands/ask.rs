@@ -0,0 +1,94 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+};
+
+use crate::services::embeddings::{search, SearchResult};
+
+use super::{bool_option, respond_deferred, string_option, Commands};
+
+pub const NAME: &str = "ask";
+
+/// How many summaries and digests to give the model to answer from.
+const MAX_SOURCES: usize = 5;
+
+const ASK_PROMPT: &str = "You answer questions about a Discord community using only the numbered excerpts from its summaries and digests below. Cite every excerpt you rely on by its number, like [1]. If the excerpts do not contain the answer, say so instead of guessing.";
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Ask a question about past discussions in this server")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "question", "What to ask")
+                .required(true),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "public",
+            "Post the answer in the channel instead of only showing it to you",
+        ))
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let options = command.data.options();
+    let question = string_option(&options, "question").unwrap_or_default();
+    let public = bool_option(&options, "public").unwrap_or(false);
+
+    // Searching and answering can take a while, longer than Discord waits for a reply.
+    if public {
+        command.defer(&ctx.http).await?;
+    } else {
+        command.defer_ephemeral(&ctx.http).await?;
+    }
+
+    let guild_id = command.guild_id.map(|id| id.get() as i64);
+    let sources = search(
+        &commands.db,
+        commands.embedder.as_ref(),
+        question,
+        guild_id,
+        MAX_SOURCES,
+    )
+    .await?;
+    if sources.is_empty() {
+        let reply = "There are no summaries to answer from yet.";
+        respond_deferred(ctx, command, reply, !public).await?;
+        return Ok(());
+    }
+
+    let labels: Vec<String> = sources
+        .iter()
+        .map(|source| source_label(commands, source))
+        .collect();
+    let mut excerpts = format!("Question: {question}\n\nExcerpts:\n");
+    for (i, (source, label)) in sources.iter().zip(&labels).enumerate() {
+        excerpts.push_str(&format!("\n[{}] {label}\n{}\n", i + 1, source.text));
+    }
+    let answer = commands.summarizer.complete(ASK_PROMPT, &excerpts).await?;
+
+    let mut reply = format!("**{question}**\n\n{answer}\n\n**Sources**\n");
+    for (i, label) in labels.iter().enumerate() {
+        reply.push_str(&format!("[{}] {label}\n", i + 1));
+    }
+    respond_deferred(ctx, command, &reply, !public).await?;
+    Ok(())
+}
+
+/// Describes a source as its kind and ID, along with the channel and period it covers.
+fn source_label(commands: &Commands, source: &SearchResult) -> String {
+    let mut label = format!("{} #{}", source.kind.label(), source.id);
+    if let Some(channel_id) = source.channel_id {
+        label.push_str(&format!(" in <#{channel_id}>"));
+    }
+    if let Some((from, to)) = source.covers_from.zip(source.covers_to) {
+        let format = "%b %-d, %H:%M";
+        label.push_str(&format!(
+            " ({} to {})",
+            from.with_timezone(&commands.timezone).format(format),
+            to.with_timezone(&commands.timezone).format(format)
+        ));
+    }
+    label
+}
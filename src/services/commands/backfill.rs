@@ -0,0 +1,85 @@
+use serenity::all::{
+    ChannelType, CommandInteraction, CommandOptionType, Context, CreateCommand,
+    CreateCommandOption, Permissions, ResolvedValue,
+};
+
+use super::{respond_deferred, Commands};
+
+pub const NAME: &str = "backfill";
+
+const DEFAULT_DAYS: i64 = 7;
+const MAX_DAYS: i64 = 30;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Log and summarize the history of channels from before the bot joined")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "days",
+                "How many days back to look, 7 by default",
+            )
+            .min_int_value(1)
+            .max_int_value(MAX_DAYS as u64),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "channel",
+                "Channel to backfill, every channel the bot listens to by default",
+            )
+            .channel_types(vec![ChannelType::Text, ChannelType::News]),
+        )
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let Some(guild_id) = command.guild_id else {
+        return Ok(());
+    };
+    let options = command.data.options();
+    let days = options
+        .iter()
+        .find_map(|option| match option.value {
+            ResolvedValue::Integer(days) if option.name == "days" => Some(days),
+            _ => None,
+        })
+        .unwrap_or(DEFAULT_DAYS)
+        .clamp(1, MAX_DAYS) as u32;
+    let channel_id = options.iter().find_map(|option| match option.value {
+        ResolvedValue::Channel(channel) if option.name == "channel" => Some(channel.id),
+        _ => None,
+    });
+
+    // Paging through history takes a while, longer than Discord waits for a reply.
+    command.defer_ephemeral(&ctx.http).await?;
+
+    let result = match channel_id {
+        Some(channel_id) => {
+            commands
+                .backfiller
+                .backfill_channel(ctx, guild_id, channel_id, days)
+                .await
+        }
+        None => {
+            commands
+                .backfiller
+                .backfill_guild(ctx, guild_id, days)
+                .await
+        }
+    };
+    let reply = match result {
+        Ok(0) => format!("There was nothing to backfill from the last {days} days."),
+        Ok(count) => format!(
+            "Backfilled {count} messages from the last {days} days, their summaries are on the way."
+        ),
+        Err(e) => format!("Could not backfill: {e}"),
+    };
+    respond_deferred(ctx, command, &reply, true).await?;
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use chrono::{Duration, Utc};
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+    ResolvedValue,
+};
+
+use crate::db;
+use crate::prompts::{format_prompt_time, PromptVars};
+use crate::services::summarizer::{message_contents, summarize_channel_messages};
+
+use super::{respond_deferred, Commands};
+
+pub const NAME: &str = "catchup";
+
+const DEFAULT_HOURS: i64 = 24;
+/// Two weeks, long enough to cover a vacation.
+const MAX_HOURS: i64 = 24 * 14;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Summarize what you missed in this channel, only visible to you")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "hours",
+                "How many hours back to look, 24 by default",
+            )
+            .min_int_value(1)
+            .max_int_value(MAX_HOURS as u64),
+        )
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let hours = command
+        .data
+        .options()
+        .iter()
+        .find_map(|option| match option.value {
+            ResolvedValue::Integer(hours) if option.name == "hours" => Some(hours),
+            _ => None,
+        })
+        .unwrap_or(DEFAULT_HOURS)
+        .clamp(1, MAX_HOURS);
+
+    // Summarizing can take a while, longer than Discord waits for a reply.
+    command.defer_ephemeral(&ctx.http).await?;
+
+    let since = Utc::now() - Duration::hours(hours);
+    let messages =
+        db::fetch_channel_messages_since(&commands.db, command.channel_id.get() as i64, since)
+            .await?;
+    let reply = if messages.is_empty() {
+        format!("Nothing was said here in the last {hours} hours.")
+    } else {
+        let vars = PromptVars::lookup(
+            commands.names.as_ref(),
+            command.guild_id,
+            Some(command.channel_id),
+            format_prompt_time(Some(since)),
+            format_prompt_time(Some(Utc::now())),
+        )
+        .await;
+        let prompt =
+            commands
+                .prompts
+                .summary(command.channel_id, &vars, &message_contents(&messages));
+        match summarize_channel_messages(&commands.summarizer, &commands.models, &prompt, &messages)
+            .await
+        {
+            Ok(summary) => format!(
+                "**Catch up on the last {hours} hours** ({} messages)\n\n{}",
+                messages.len(),
+                summary.render()
+            ),
+            Err(e) => format!("Could not summarize the last {hours} hours: {e}"),
+        }
+    };
+    respond_deferred(ctx, command, &reply, true).await?;
+    Ok(())
+}
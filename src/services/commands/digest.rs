@@ -0,0 +1,105 @@
+use chrono::{Duration, NaiveDate};
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+};
+
+use crate::config::Verbosity;
+use crate::db;
+use crate::schedule::start_of_day;
+use crate::services::digests::channel_summaries;
+
+use super::{bool_option, respond, string_option, Commands};
+
+pub const NAME: &str = "digest";
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Show the latest daily digest of this server")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "date",
+            "Show the digest of a specific day instead, as YYYY-MM-DD",
+        ))
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "verbosity",
+                "How much of the digest to show",
+            )
+            .add_string_choice("TL;DR", "tldr")
+            .add_string_choice("Standard", "standard")
+            .add_string_choice("Detailed, with each channel's summaries", "detailed"),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "public",
+            "Post the digest in the channel instead of only showing it to you",
+        ))
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let options = command.data.options();
+    let public = bool_option(&options, "public").unwrap_or(false);
+    let verbosity = string_option(&options, "verbosity")
+        .and_then(Verbosity::parse)
+        .unwrap_or_default();
+
+    let day = match string_option(&options, "date") {
+        Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(day) => Some(day),
+            Err(_) => {
+                let reply = format!("`{date}` is not a date, use the YYYY-MM-DD format.");
+                respond(ctx, command, &reply, true).await?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    let during = day.map(|day| {
+        (
+            start_of_day(day, commands.timezone),
+            start_of_day(day + Duration::days(1), commands.timezone),
+        )
+    });
+
+    let guild_id = command.guild_id.map(|id| id.get() as i64);
+    let digest = db::fetch_latest_daily_digest(&commands.db, guild_id, during).await?;
+    let digest = match digest {
+        Some(digest) => db::fetch_daily_digest(&commands.db, digest.id).await?,
+        None => None,
+    };
+    let reply = match (digest, day) {
+        (Some(digest), _) => render_digest(&digest, verbosity),
+        (None, Some(day)) => format!("There is no digest for {day}."),
+        (None, None) => "There is no digest yet.".to_string(),
+    };
+    respond(ctx, command, &reply, !public).await?;
+    Ok(())
+}
+
+fn render_digest(digest: &db::DailyDigest, verbosity: Verbosity) -> String {
+    match verbosity {
+        Verbosity::Tldr => format!(
+            "**Daily digest TL;DR**\n\n{}",
+            digest.tldr.as_deref().unwrap_or(&digest.text)
+        ),
+        Verbosity::Standard => format!("**Daily digest**\n\n{}", digest.text),
+        Verbosity::Detailed => {
+            let mut reply = format!("**Daily digest**\n\n{}", digest.text);
+            let mut channel_ids: Vec<i64> = vec![];
+            for channel_id in digest.summaries.iter().filter_map(|s| s.channel_id) {
+                if !channel_ids.contains(&channel_id) {
+                    channel_ids.push(channel_id);
+                }
+            }
+            if let Some((summaries, _)) = channel_summaries(digest, &channel_ids) {
+                reply.push_str(&format!("\n\n{}", summaries.trim_end()));
+            }
+            reply
+        }
+    }
+}
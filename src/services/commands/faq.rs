@@ -0,0 +1,60 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+};
+
+use crate::db::{self, FaqFilter};
+
+use super::{respond, string_option, Commands};
+
+pub const NAME: &str = "faq";
+
+/// Most questions listed in a single reply.
+const MAX_LISTED: usize = 10;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Browse the questions frequently asked in this server, with their answers")
+        .dm_permission(false)
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "search",
+            "Only list the questions mentioning this",
+        ))
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let options = command.data.options();
+    let search = string_option(&options, "search");
+    let filter = FaqFilter {
+        guild_id: command.guild_id.map(|id| id.get() as i64),
+        search: search.map(str::to_string),
+    };
+    let entries = db::fetch_faq(&commands.db, &filter).await?;
+    let reply = render_faq(&entries, search);
+    respond(ctx, command, &reply, true).await?;
+    Ok(())
+}
+
+fn render_faq(entries: &[db::FaqEntry], search: Option<&str>) -> String {
+    if entries.is_empty() {
+        return match search {
+            Some(search) => format!("No frequently asked question mentions \"{search}\"."),
+            None => "No frequently asked questions were found yet.".to_string(),
+        };
+    }
+    let mut reply = String::from("**Frequently asked questions**");
+    for entry in entries.iter().take(MAX_LISTED) {
+        reply.push_str(&format!("\n\n**Q: {}**\n{}", entry.question, entry.answer));
+    }
+    if entries.len() > MAX_LISTED {
+        reply.push_str(&format!(
+            "\n\n...and {} more, search for a question to narrow them down.",
+            entries.len() - MAX_LISTED
+        ));
+    }
+    reply
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use serenity::all::{
+    ChannelId, CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+    GuildId, Permissions, ResolvedValue,
+};
+
+use crate::db;
+
+use super::{respond, respond_deferred, string_option, Commands};
+
+pub const NAME: &str = "meeting-notes";
+
+const MEETING_NOTES_PROMPT: &str = "You write the notes of a meeting from its transcript, for members of a technical team who missed it. Summarize what was discussed thoroughly. The transcript comes from speech recognition, so names and technical terms may be misspelled.";
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Transcribe a meeting recording and add its notes to today's digest")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Attachment,
+                "recording",
+                "Audio or video recording of the meeting",
+            )
+            .required(true),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "title",
+            "What the meeting was about",
+        ))
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let Some(transcriber) = &commands.transcriber else {
+        let reply = "Transcription is not enabled.";
+        respond(ctx, command, reply, true).await?;
+        return Ok(());
+    };
+    let options = command.data.options();
+    let Some(recording) = options.iter().find_map(|option| match option.value {
+        ResolvedValue::Attachment(attachment) if option.name == "recording" => Some(attachment),
+        _ => None,
+    }) else {
+        return Ok(());
+    };
+    let title = string_option(&options, "title").unwrap_or("Meeting");
+    let is_recording = recording
+        .content_type
+        .as_deref()
+        .is_some_and(|kind| kind.starts_with("audio/") || kind.starts_with("video/"));
+    if !is_recording {
+        let reply = "The recording must be an audio or video file.";
+        respond(ctx, command, reply, true).await?;
+        return Ok(());
+    }
+    if u64::from(recording.size) > transcriber.max_audio_bytes() {
+        let reply = format!(
+            "The recording is too large, at most {} MB can be transcribed.",
+            transcriber.max_audio_bytes() / 1_000_000
+        );
+        respond(ctx, command, &reply, true).await?;
+        return Ok(());
+    }
+
+    // Transcribing and summarizing take much longer than Discord waits for a reply.
+    command.defer(&ctx.http).await?;
+
+    let transcript = match transcriber
+        .transcribe(&recording.url, &recording.filename)
+        .await
+    {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            let reply = format!("Could not transcribe the recording: {e}");
+            respond_deferred(ctx, command, &reply, false).await?;
+            return Ok(());
+        }
+    };
+    let notes = write_notes(
+        commands,
+        command.guild_id,
+        command.channel_id,
+        title,
+        &transcript,
+        Utc::now(),
+    )
+    .await?;
+    respond_deferred(ctx, command, &notes, false).await?;
+    Ok(())
+}
+
+/// Summarizes the transcript of a meeting that started at `started_at` into notes, which
+/// are stored as a summary of the channel for the next daily digest to roll up along
+/// with the others, and returns them.
+pub(super) async fn write_notes(
+    commands: &Commands,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    title: &str,
+    transcript: &str,
+    started_at: DateTime<Utc>,
+) -> eyre::Result<String> {
+    let mut notes = commands
+        .summarizer
+        .summarize_structured(MEETING_NOTES_PROMPT, transcript)
+        .await?;
+    notes.summary = format!("**Meeting notes: {title}**\n{}", notes.summary);
+    db::insert_summary(
+        &commands.db,
+        db::NewSummary {
+            guild_id: guild_id.map(|id| id.get() as i64),
+            channel_id: channel_id.get() as i64,
+            summary: &notes,
+            message_count: 0,
+            covers_from: Some(started_at),
+            covers_to: Some(Utc::now()),
+        },
+        // No logged message is part of the notes.
+        0,
+    )
+    .await?;
+    Ok(notes.summary)
+}
@@ -0,0 +1,121 @@
+use serenity::all::{
+    ChannelType, CommandInteraction, CommandOptionType, Context, CreateCommand,
+    CreateCommandOption, Permissions, ResolvedValue,
+};
+
+use super::meeting_notes::write_notes;
+use super::{respond, respond_deferred, string_option, Commands};
+
+pub const START: &str = "meeting-start";
+pub const STOP: &str = "meeting-stop";
+
+pub fn register_start() -> CreateCommand {
+    CreateCommand::new(START)
+        .description("Join a voice channel to record a meeting, and add its notes to today's digest once stopped")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "channel",
+                "Voice channel the meeting is held in",
+            )
+            .channel_types(vec![ChannelType::Voice, ChannelType::Stage])
+            .required(true),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "title",
+            "What the meeting is about",
+        ))
+}
+
+pub fn register_stop() -> CreateCommand {
+    CreateCommand::new(STOP)
+        .description("Stop recording the meeting, and add its notes to today's digest")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+}
+
+pub async fn start(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let (Some(recorder), Some(guild_id)) = (&commands.recorder, command.guild_id) else {
+        respond(ctx, command, "Recording meetings is not enabled.", true).await?;
+        return Ok(());
+    };
+    let options = command.data.options();
+    let Some(channel_id) = options.iter().find_map(|option| match option.value {
+        ResolvedValue::Channel(channel) if option.name == "channel" => Some(channel.id),
+        _ => None,
+    }) else {
+        return Ok(());
+    };
+    let title = string_option(&options, "title").unwrap_or("Meeting");
+    let reply = match recorder.start(ctx, guild_id, channel_id, title).await {
+        Ok(()) => format!(
+            "Recording the meeting in <#{channel_id}>, stop it with `/{STOP}` to get its notes."
+        ),
+        Err(e) => format!("Could not start recording: {e:#}"),
+    };
+    respond(ctx, command, &reply, false).await?;
+    Ok(())
+}
+
+pub async fn stop(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let (Some(recorder), Some(transcriber), Some(guild_id)) =
+        (&commands.recorder, &commands.transcriber, command.guild_id)
+    else {
+        respond(ctx, command, "Recording meetings is not enabled.", true).await?;
+        return Ok(());
+    };
+    // Transcribing and summarizing take much longer than Discord waits for a reply.
+    command.defer(&ctx.http).await?;
+
+    let recording = match recorder.stop(ctx, guild_id).await {
+        Ok(Some(recording)) => recording,
+        Ok(None) => {
+            let reply = "No meeting is being recorded.";
+            respond_deferred(ctx, command, reply, false).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            let reply = format!("Could not stop recording: {e:#}");
+            respond_deferred(ctx, command, &reply, false).await?;
+            return Ok(());
+        }
+    };
+    let transcript = match transcriber
+        .transcribe_audio(recording.wav, "meeting.wav")
+        .await
+    {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            let reply = format!("Could not transcribe the recording: {e}");
+            respond_deferred(ctx, command, &reply, false).await?;
+            return Ok(());
+        }
+    };
+    let mut notes = write_notes(
+        commands,
+        Some(guild_id),
+        recording.channel_id,
+        &recording.title,
+        &transcript,
+        recording.started_at,
+    )
+    .await?;
+    if recording.truncated {
+        notes.push_str(
+            "\n\n*The recording reached its size limit, so the end of the meeting is missing.*",
+        );
+    }
+    respond_deferred(ctx, command, &notes, false).await?;
+    Ok(())
+}
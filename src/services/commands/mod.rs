@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono_tz::Tz;
+use serenity::all::{
+    Command, CommandInteraction, Context, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage, EditInteractionResponse,
+    Http, ResolvedOption, ResolvedValue,
+};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc::Sender;
+use tracing::{error, warn};
+
+use crate::gpt::{Embedder, Summarizer, Transcriber};
+use crate::names::DiscordNames;
+use crate::prompts::Prompts;
+
+use super::backfill::Backfiller;
+use super::digests::{split_message, DISCORD_MESSAGE_LIMIT};
+use super::privacy::DataEraser;
+use super::sources::IngestEvent;
+#[cfg(feature = "voice")]
+use super::voice::VoiceRecorder;
+
+mod ask;
+mod backfill;
+mod catchup;
+mod digest;
+mod faq;
+mod meeting_notes;
+#[cfg(feature = "voice")]
+mod meeting_recording;
+mod optout;
+mod subscribe;
+mod summarize_now;
+mod todos;
+mod unsubscribe;
+
+/// The slash commands the bot registers with Discord, and what they need to respond.
+pub struct Commands {
+    db: Arc<SqlitePool>,
+    timezone: Tz,
+    ingest_tx: Sender<IngestEvent>,
+    summarizer: Arc<dyn Summarizer>,
+    embedder: Arc<dyn Embedder>,
+    eraser: DataEraser,
+    backfiller: Backfiller,
+    prompts: Prompts,
+    /// Looks up the guild and channel names used in prompts, when set.
+    names: Option<DiscordNames>,
+    /// Summarizers of the models prompt profiles use.
+    models: Arc<HashMap<String, Arc<dyn Summarizer>>>,
+    /// Transcribes meeting recordings, when set.
+    transcriber: Option<Arc<dyn Transcriber>>,
+    /// Records meetings in voice channels, when set.
+    #[cfg(feature = "voice")]
+    recorder: Option<VoiceRecorder>,
+    /// Whether `/faq` is registered.
+    faq: bool,
+}
+
+impl Commands {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        timezone: Tz,
+        ingest_tx: Sender<IngestEvent>,
+        summarizer: Arc<dyn Summarizer>,
+        embedder: Arc<dyn Embedder>,
+        eraser: DataEraser,
+        backfiller: Backfiller,
+    ) -> Self {
+        Self {
+            db,
+            timezone,
+            ingest_tx,
+            summarizer,
+            embedder,
+            eraser,
+            backfiller,
+            prompts: Prompts::default(),
+            names: None,
+            models: Arc::default(),
+            transcriber: None,
+            #[cfg(feature = "voice")]
+            recorder: None,
+            faq: false,
+        }
+    }
+
+    /// Registers `/meeting-notes`, which transcribes meeting recordings to add their
+    /// notes to the day's digest.
+    pub fn with_transcriber(mut self, transcriber: Arc<dyn Transcriber>) -> Self {
+        self.transcriber = Some(transcriber);
+        self
+    }
+
+    /// Registers `/meeting-start` and `/meeting-stop`, which record meetings in voice
+    /// channels to add their notes to the day's digest. Needs a transcriber.
+    #[cfg(feature = "voice")]
+    pub fn with_voice_recorder(mut self, recorder: VoiceRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Registers `/faq`, which lists the questions mined from the server's summaries.
+    pub fn with_faq(mut self) -> Self {
+        self.faq = true;
+        self
+    }
+
+    /// Summarizes with the configured prompt templates and profiles, filled in with the
+    /// names of each channel and its guild. `models` holds the summarizers of the
+    /// models profiles use.
+    pub fn with_prompts(
+        mut self,
+        prompts: Prompts,
+        names: DiscordNames,
+        models: Arc<HashMap<String, Arc<dyn Summarizer>>>,
+    ) -> Self {
+        self.prompts = prompts;
+        self.names = Some(names);
+        self.models = models;
+        self
+    }
+
+    /// Registers every command globally, replacing any registered by a previous version.
+    pub async fn register(&self, http: &Http) -> serenity::Result<()> {
+        let mut commands = vec![
+            digest::register(),
+            summarize_now::register(),
+            catchup::register(),
+            ask::register(),
+            todos::register(),
+            optout::register(),
+            subscribe::register(),
+            unsubscribe::register(),
+            backfill::register(),
+        ];
+        if self.transcriber.is_some() {
+            commands.push(meeting_notes::register());
+            #[cfg(feature = "voice")]
+            if self.recorder.is_some() {
+                commands.push(meeting_recording::register_start());
+                commands.push(meeting_recording::register_stop());
+            }
+        }
+        if self.faq {
+            commands.push(faq::register());
+        }
+        Command::set_global_commands(http, commands).await?;
+        Ok(())
+    }
+
+    pub async fn handle(&self, ctx: &Context, command: &CommandInteraction) {
+        let name = command.data.name.as_str();
+        let result = match name {
+            digest::NAME => digest::run(self, ctx, command).await,
+            summarize_now::NAME => summarize_now::run(self, ctx, command).await,
+            catchup::NAME => catchup::run(self, ctx, command).await,
+            ask::NAME => ask::run(self, ctx, command).await,
+            todos::NAME => todos::run(self, ctx, command).await,
+            optout::NAME => optout::run(self, ctx, command).await,
+            subscribe::NAME => subscribe::run(self, ctx, command).await,
+            unsubscribe::NAME => unsubscribe::run(self, ctx, command).await,
+            backfill::NAME => backfill::run(self, ctx, command).await,
+            meeting_notes::NAME => meeting_notes::run(self, ctx, command).await,
+            #[cfg(feature = "voice")]
+            meeting_recording::START => meeting_recording::start(self, ctx, command).await,
+            #[cfg(feature = "voice")]
+            meeting_recording::STOP => meeting_recording::stop(self, ctx, command).await,
+            faq::NAME => faq::run(self, ctx, command).await,
+            _ => {
+                warn!("Received unknown command /{name}");
+                return;
+            }
+        };
+        if let Err(e) = result {
+            error!("Could not respond to /{name}: {e}");
+        }
+    }
+}
+
+/// Replies to a command, splitting replies longer than a Discord message into follow-ups.
+async fn respond(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: &str,
+    ephemeral: bool,
+) -> serenity::Result<()> {
+    let mut chunks = split_message(content, DISCORD_MESSAGE_LIMIT).into_iter();
+    let first = chunks.next().unwrap_or_default();
+    let message = CreateInteractionResponseMessage::new()
+        .content(first)
+        .ephemeral(ephemeral);
+    command
+        .create_response(&ctx.http, CreateInteractionResponse::Message(message))
+        .await?;
+    for chunk in chunks {
+        let followup = CreateInteractionResponseFollowup::new()
+            .content(chunk)
+            .ephemeral(ephemeral);
+        command.create_followup(&ctx.http, followup).await?;
+    }
+    Ok(())
+}
+
+/// Replies to a command whose response was deferred, splitting replies longer than a
+/// Discord message into follow-ups.
+async fn respond_deferred(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: &str,
+    ephemeral: bool,
+) -> serenity::Result<()> {
+    let mut chunks = split_message(content, DISCORD_MESSAGE_LIMIT).into_iter();
+    let first = chunks.next().unwrap_or_default();
+    command
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(first))
+        .await?;
+    for chunk in chunks {
+        let followup = CreateInteractionResponseFollowup::new()
+            .content(chunk)
+            .ephemeral(ephemeral);
+        command.create_followup(&ctx.http, followup).await?;
+    }
+    Ok(())
+}
+
+fn string_option<'a>(options: &[ResolvedOption<'a>], name: &str) -> Option<&'a str> {
+    options.iter().find_map(|option| match option.value {
+        ResolvedValue::String(value) if option.name == name => Some(value),
+        _ => None,
+    })
+}
+
+fn bool_option(options: &[ResolvedOption], name: &str) -> Option<bool> {
+    options.iter().find_map(|option| match option.value {
+        ResolvedValue::Boolean(value) if option.name == name => Some(value),
+        _ => None,
+    })
+}
+
+fn integer_option(options: &[ResolvedOption], name: &str) -> Option<i64> {
+    options.iter().find_map(|option| match option.value {
+        ResolvedValue::Integer(value) if option.name == name => Some(value),
+        _ => None,
+    })
+}
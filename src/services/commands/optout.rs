@@ -0,0 +1,30 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand};
+
+use super::{respond_deferred, Commands};
+
+pub const NAME: &str = "optout";
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME).description(
+        "Stop logging your messages and delete those already stored, only visible to you",
+    )
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    // Rewriting the summaries the messages went into can take a while.
+    command.defer_ephemeral(&ctx.http).await?;
+
+    let reply = match commands.eraser.erase(command.user.id).await {
+        Ok(report) => format!(
+            "Your messages will no longer be logged. Deleted {} of your stored messages, rewrote {} summaries without them and removed {} that could not be.",
+            report.messages_deleted, report.summaries_rewritten, report.summaries_removed
+        ),
+        Err(e) => format!("Could not delete your data: {e}"),
+    };
+    respond_deferred(ctx, command, &reply, true).await?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use chrono_tz::Tz;
+use serenity::all::{
+    ChannelType, CommandInteraction, CommandOptionType, Context, CreateCommand,
+    CreateCommandOption, ResolvedOption, ResolvedValue,
+};
+
+use crate::db::{self, DigestSubscription};
+
+use super::{bool_option, integer_option, respond, Commands};
+
+pub const NAME: &str = "subscribe";
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Get this server's daily digests in your DMs")
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "channel",
+                "Only get the summaries of this channel, and of any other added the same way",
+            )
+            .channel_types(vec![ChannelType::Text, ChannelType::News]),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "all_channels",
+            "Get the whole digest again, rather than the summaries of some channels",
+        ))
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "hour",
+                "Hour of the day to get digests at, rather than as soon as they are produced",
+            )
+            .min_int_value(0)
+            .max_int_value(23),
+        )
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let Some(guild_id) = command.guild_id else {
+        respond(ctx, command, "Subscribe from a server.", true).await?;
+        return Ok(());
+    };
+    let user_id = command.user.id.get() as i64;
+    let guild_id = guild_id.get() as i64;
+    let options = command.data.options();
+
+    let mut subscription = db::fetch_digest_subscription(&commands.db, user_id, guild_id)
+        .await?
+        .unwrap_or(DigestSubscription {
+            user_id,
+            guild_id,
+            delivery_hour: None,
+            channel_ids: vec![],
+        });
+    if bool_option(&options, "all_channels").unwrap_or(false) {
+        subscription.channel_ids.clear();
+    }
+    if let Some(channel_id) = channel_option(&options) {
+        if !subscription.channel_ids.contains(&channel_id) {
+            subscription.channel_ids.push(channel_id);
+        }
+    }
+    if let Some(hour) = integer_option(&options, "hour") {
+        subscription.delivery_hour = Some(hour);
+    }
+    db::save_digest_subscription(&commands.db, &subscription).await?;
+
+    let reply = format!(
+        "You will get this server's daily digests in your DMs {}.",
+        describe(&subscription, commands.timezone)
+    );
+    respond(ctx, command, &reply, true).await?;
+    Ok(())
+}
+
+pub fn channel_option(options: &[ResolvedOption]) -> Option<i64> {
+    options.iter().find_map(|option| match option.value {
+        ResolvedValue::Channel(channel) if option.name == "channel" => {
+            Some(channel.id.get() as i64)
+        }
+        _ => None,
+    })
+}
+
+/// When and what a subscriber gets, such as "at 08:00 UTC, with the summaries of #general".
+pub fn describe(subscription: &DigestSubscription, timezone: Tz) -> String {
+    let when = match subscription.delivery_hour {
+        Some(hour) => format!("at {hour:02}:00 {}", timezone.name()),
+        None => "as soon as they are produced".to_string(),
+    };
+    if subscription.channel_ids.is_empty() {
+        return when;
+    }
+    let channels: Vec<String> = subscription
+        .channel_ids
+        .iter()
+        .map(|id| format!("<#{id}>"))
+        .collect();
+    format!("{when}, with the summaries of {}", channels.join(", "))
+}
@@ -0,0 +1,62 @@
+use serenity::all::{
+    ChannelType, CommandInteraction, CommandOptionType, Context, CreateCommand,
+    CreateCommandOption, Permissions, ResolvedValue,
+};
+use tokio::sync::oneshot;
+
+use crate::services::sources::IngestEvent;
+
+use super::{respond_deferred, Commands};
+
+pub const NAME: &str = "summarize-now";
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Summarize the messages collected so far without waiting for a full batch")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "channel",
+                "Channel to summarize, this one by default",
+            )
+            .channel_types(vec![ChannelType::Text, ChannelType::News]),
+        )
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let channel_id = command
+        .data
+        .options()
+        .iter()
+        .find_map(|option| match option.value {
+            ResolvedValue::Channel(channel) if option.name == "channel" => Some(channel.id),
+            _ => None,
+        })
+        .unwrap_or(command.channel_id);
+
+    // Summarizing can take a while, longer than Discord waits for a reply.
+    command.defer_ephemeral(&ctx.http).await?;
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    commands
+        .ingest_tx
+        .send(IngestEvent::SummarizeNow {
+            channel_id,
+            reply: reply_tx,
+        })
+        .await?;
+    let reply = match reply_rx.await {
+        Ok(Ok(Some(summary))) => format!("**Summary of <#{channel_id}>**\n\n{summary}"),
+        Ok(Ok(None)) => format!("There are no new messages to summarize in <#{channel_id}>."),
+        Ok(Err(e)) => format!("Could not summarize <#{channel_id}>, it will be retried later: {e}"),
+        Err(_) => format!("Could not summarize <#{channel_id}>."),
+    };
+    respond_deferred(ctx, command, &reply, true).await?;
+    Ok(())
+}
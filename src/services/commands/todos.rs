@@ -0,0 +1,143 @@
+use chrono_tz::Tz;
+use serenity::all::{
+    ChannelType, CommandInteraction, CommandOptionType, Context, CreateCommand,
+    CreateCommandOption, ResolvedValue,
+};
+
+use crate::db::{self, ActionItemFilter, ActionItemStatus};
+
+use super::{bool_option, integer_option, respond, Commands};
+
+pub const NAME: &str = "todos";
+
+/// Most action items listed in a single reply.
+const MAX_LISTED: usize = 50;
+
+pub fn register() -> CreateCommand {
+    let id_option = || {
+        CreateCommandOption::new(CommandOptionType::Integer, "id", "ID of the action item")
+            .required(true)
+            .min_int_value(1)
+    };
+    CreateCommand::new(NAME)
+        .description("Track the action items found in this server's discussions")
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "list",
+                "List the open action items of this server",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Channel,
+                    "channel",
+                    "Only list the action items of a channel",
+                )
+                .channel_types(vec![ChannelType::Text, ChannelType::News]),
+            )
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "resolved",
+                "List resolved action items instead",
+            )),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "resolve",
+                "Mark an action item as done",
+            )
+            .add_sub_option(id_option()),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "reopen",
+                "Mark a resolved action item as open again",
+            )
+            .add_sub_option(id_option()),
+        )
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let guild_id = command.guild_id.map(|id| id.get() as i64);
+    let options = command.data.options();
+    let Some((subcommand, options)) = options.into_iter().find_map(|option| match option.value {
+        ResolvedValue::SubCommand(options) => Some((option.name, options)),
+        _ => None,
+    }) else {
+        respond(ctx, command, "Pick what to do with the action items.", true).await?;
+        return Ok(());
+    };
+
+    let reply = match subcommand {
+        "resolve" | "reopen" => {
+            let id = integer_option(&options, "id").unwrap_or_default();
+            let status = if subcommand == "resolve" {
+                ActionItemStatus::Resolved
+            } else {
+                ActionItemStatus::Open
+            };
+            match db::set_action_item_status(&commands.db, id, guild_id, status).await? {
+                true if status == ActionItemStatus::Resolved => {
+                    format!("Marked action item #{id} as resolved.")
+                }
+                true => format!("Reopened action item #{id}."),
+                false => format!("There is no action item #{id} in this server."),
+            }
+        }
+        _ => {
+            let resolved = bool_option(&options, "resolved").unwrap_or(false);
+            let channel_id = options.iter().find_map(|option| match option.value {
+                ResolvedValue::Channel(channel) if option.name == "channel" => {
+                    Some(channel.id.get() as i64)
+                }
+                _ => None,
+            });
+            let filter = ActionItemFilter {
+                guild_id,
+                channel_id,
+                status: Some(if resolved {
+                    ActionItemStatus::Resolved
+                } else {
+                    ActionItemStatus::Open
+                }),
+            };
+            let items = db::fetch_action_items(&commands.db, &filter).await?;
+            render_items(&items, resolved, commands.timezone)
+        }
+    };
+    respond(ctx, command, &reply, true).await?;
+    Ok(())
+}
+
+fn render_items(items: &[db::TrackedActionItem], resolved: bool, timezone: Tz) -> String {
+    let status = if resolved { "resolved" } else { "open" };
+    if items.is_empty() {
+        return format!("There are no {status} action items.");
+    }
+    let mut reply = format!("**{} {status} action items**\n", items.len());
+    for item in items.iter().take(MAX_LISTED) {
+        reply.push_str(&format!("\n`#{}` {}", item.id, item.description));
+        if let Some(assignee) = &item.assignee {
+            reply.push_str(&format!(" ({assignee})"));
+        }
+        if let Some(channel_id) = item.channel_id {
+            reply.push_str(&format!(" in <#{channel_id}>"));
+        }
+        let created = item.created_at.with_timezone(&timezone);
+        reply.push_str(&format!(", {}", created.format("%b %-d")));
+    }
+    if items.len() > MAX_LISTED {
+        reply.push_str(&format!(
+            "\n\n...and {} more, see the `/action_items` API for the full list.",
+            items.len() - MAX_LISTED
+        ));
+    }
+    reply
+}
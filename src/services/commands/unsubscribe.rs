@@ -0,0 +1,68 @@
+use serenity::all::{
+    ChannelType, CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+};
+
+use crate::db;
+
+use super::subscribe::{channel_option, describe};
+use super::{respond, Commands};
+
+pub const NAME: &str = "unsubscribe";
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("Stop getting this server's daily digests in your DMs")
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "channel",
+                "Only stop getting the summaries of this channel",
+            )
+            .channel_types(vec![ChannelType::Text, ChannelType::News]),
+        )
+}
+
+pub async fn run(
+    commands: &Commands,
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> eyre::Result<()> {
+    let Some(guild_id) = command.guild_id else {
+        respond(ctx, command, "Unsubscribe from a server.", true).await?;
+        return Ok(());
+    };
+    let user_id = command.user.id.get() as i64;
+    let guild_id = guild_id.get() as i64;
+    let options = command.data.options();
+
+    let subscription = db::fetch_digest_subscription(&commands.db, user_id, guild_id).await?;
+    let reply = match (subscription, channel_option(&options)) {
+        (None, _) => "You are not subscribed to this server's daily digests.".to_string(),
+        (Some(mut subscription), Some(channel_id)) => {
+            if !subscription.channel_ids.contains(&channel_id) {
+                format!("You do not get the summaries of <#{channel_id}> on their own.")
+            } else {
+                subscription.channel_ids.retain(|id| *id != channel_id);
+                // No channels left would mean every channel.
+                if subscription.channel_ids.is_empty() {
+                    db::delete_digest_subscription(&commands.db, user_id, guild_id).await?;
+                    "You no longer follow any channel, and will not get daily digests anymore."
+                        .to_string()
+                } else {
+                    db::save_digest_subscription(&commands.db, &subscription).await?;
+                    format!(
+                        "You will get this server's daily digests in your DMs {}.",
+                        describe(&subscription, commands.timezone)
+                    )
+                }
+            }
+        }
+        (Some(_), None) => {
+            db::delete_digest_subscription(&commands.db, user_id, guild_id).await?;
+            "You will no longer get this server's daily digests in your DMs.".to_string()
+        }
+    };
+    respond(ctx, command, &reply, true).await?;
+    Ok(())
+}
@@ -1,77 +1,1008 @@
-use crate::{db, gpt};
+use crate::config::{ChannelPriority, Verbosity, WebhookEvent};
+use crate::db::{self, ContentKind, RollupTier};
+use crate::gpt::{Embedder, LlmPaused, Summarizer};
+use crate::names::DiscordNames;
+use crate::prompts::{PromptVars, Prompts};
+use crate::schedule::{start_of_day, Schedule};
+use crate::services::changes::{changes_section, compare_digests};
+use crate::services::channel_groups::ChannelGroups;
+use crate::services::citations::{numbered_sources, sources_section, CITATIONS_FORMAT};
+use crate::services::embeddings::embed_content;
+use crate::services::events::{EventBus, EventKind, ServiceHealth};
+use crate::services::github::{repository_activity_section, GithubActivity, RepoActivity};
+use crate::services::glossary::new_terms_section;
+use crate::services::highlights::highlights_section;
+use crate::services::links::shared_links_section;
+use crate::services::questions::open_questions_section;
+use crate::services::reactions::{most_reacted_section, MAX_REACTED, REACTIONS_EMPHASIS};
+use crate::services::scheduled_events::{
+    concluded_events_section, upcoming_events_section, EventRecap, ScheduledEvents, UpcomingEvent,
+};
+use crate::services::sinks::Sinks;
+use crate::services::stats::{compute_stats, stats_section};
+use crate::services::topics::{
+    count_topics, recurring_topics_section, MAX_RECURRING, MIN_RECURRING_DIGESTS,
+};
+use crate::services::webhooks::Webhooks;
+use crate::templates::DigestTemplates;
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serenity::all::GuildId;
 use sqlx::sqlite::SqlitePool;
-use std::{sync::Arc, time::Duration};
-use tokio::time::interval;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use tokio::time::{interval, sleep};
 use tracing::{error, info};
 
-pub struct DailyRecapService {
+/// Maximum number of characters allowed in a single Discord message.
+pub const DISCORD_MESSAGE_LIMIT: usize = 2000;
+
+/// Appended to the digest instructions to keep digests to a single paragraph.
+const TLDR_LENGTH: &str = "Keep the digest to a single short paragraph covering only the most important discussions and decisions, without headings or lists.";
+/// Appended to the digest instructions to have the model write a detailed report.
+const DETAILED_LENGTH: &str = "Write a detailed report rather than a brief digest: cover each discussion in depth, with its context, the arguments made, what was decided and what is left to do, under a heading per topic.";
+/// Marks the summaries of high-priority channels in digest prompts.
+const PRIORITY_MARKER: &str = "[High priority]";
+/// Appended to the digest instructions when some summaries are marked high priority.
+const PRIORITY_EMPHASIS: &str = "Summaries marked [High priority] come from the most important channels. Cover them first and in more detail than the others, and leave the marker out of the digest.";
+/// Instructions for the TL;DR of a digest.
+const TLDR_INSTRUCTIONS: &str = "Condense the following digest of a Discord server into a TL;DR: a single paragraph of at most three sentences with the most important discussions and decisions. Reply with the paragraph only.";
+
+/// The time range a scheduled digest covers.
+#[derive(Clone, Copy)]
+struct CoverageWindow {
+    from: Option<DateTime<Utc>>,
+    to: DateTime<Utc>,
+}
+
+/// Periodically rolls up the summaries or digests of the tier below into digests of
+/// one rollup tier, per guild.
+pub struct RecapService {
     db: Arc<SqlitePool>,
-    interval: Duration,
+    tier: RollupTier,
+    schedule: Schedule,
+    /// Timezone digest periods are reported in.
+    timezone: Tz,
+    /// Whether each digest covers exactly one calendar day in `timezone`.
+    calendar_days: bool,
+    summarizer: Arc<dyn Summarizer>,
+    /// Embeds each digest as soon as it is stored, when set.
+    embedder: Option<Arc<dyn Embedder>>,
+    /// When set, digests list the questions that went unanswered for this long.
+    question_answer_window: Option<Duration>,
+    /// Whether digests cite their summaries and link to a message of each.
+    citations: bool,
+    /// Whether digests quote the messages highlighted since the previous one.
+    highlights: bool,
+    /// Whether digests list the links shared since the previous one.
+    shared_links: bool,
+    /// Whether digests end with who took part in the period they cover.
+    participant_stats: bool,
+    /// Whether weekly digests mention the topics that came up on several days.
+    recurring_topics: bool,
+    /// Whether daily digests point out what changed since the previous one.
+    changes: bool,
+    /// Whether weekly digests list the terms added to the glossary since the previous one.
+    new_terms: bool,
+    /// How long the digests written by the model are.
+    verbosity: Verbosity,
+    /// Whether a one-paragraph TL;DR of each digest is stored along with it.
+    tldr: bool,
+    /// Priority of the channels that are not of normal priority.
+    channel_priorities: HashMap<i64, ChannelPriority>,
+    /// Writes a digest of each channel group along with each daily digest, when set.
+    channel_groups: Option<ChannelGroups>,
+    /// Lists the scheduled events coming up within this long and summarizes those that
+    /// concluded in each digest, when set.
+    scheduled_events: Option<(ScheduledEvents, Duration)>,
+    /// Lists the activity of GitHub repositories in each digest, when set.
+    github: Option<GithubActivity>,
+    /// Notified of every new daily digest, when set.
+    webhooks: Option<Webhooks>,
+    /// Delivered every new digest, when set.
+    sinks: Option<Sinks>,
+    /// Notified of every new digest, when set.
+    events: Option<EventBus>,
+    health: Option<ServiceHealth>,
+    prompts: Prompts,
+    /// Looks up the guild names used in prompts, when set.
+    names: Option<DiscordNames>,
+    templates: Arc<DigestTemplates>,
 }
 
-impl DailyRecapService {
-    pub fn new(db: Arc<SqlitePool>, interval_seconds: u64) -> Self {
+impl RecapService {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        tier: RollupTier,
+        schedule: Schedule,
+        timezone: Tz,
+        summarizer: Arc<dyn Summarizer>,
+    ) -> Self {
         Self {
             db,
-            interval: Duration::from_secs(interval_seconds),
+            tier,
+            schedule,
+            timezone,
+            calendar_days: false,
+            summarizer,
+            embedder: None,
+            question_answer_window: None,
+            citations: false,
+            highlights: false,
+            shared_links: false,
+            participant_stats: false,
+            recurring_topics: false,
+            changes: false,
+            new_terms: false,
+            verbosity: Verbosity::default(),
+            tldr: false,
+            channel_priorities: HashMap::new(),
+            channel_groups: None,
+            scheduled_events: None,
+            github: None,
+            webhooks: None,
+            sinks: None,
+            events: None,
+            health: None,
+            prompts: Prompts::default(),
+            names: None,
+            templates: Arc::new(DigestTemplates::builtin(timezone)),
         }
     }
 
+    /// Broadcasts each new digest, and every failure to produce one, to live
+    /// subscribers.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        let service = format!("{} recap", self.tier.name());
+        self.health = Some(ServiceHealth::new(events.clone(), service));
+        self.events = Some(events);
+        self
+    }
+
+    /// Rolls up with the configured prompt templates, filled in with the name of each
+    /// guild.
+    pub fn with_prompts(mut self, prompts: Prompts, names: DiscordNames) -> Self {
+        self.prompts = prompts;
+        self.names = Some(names);
+        self
+    }
+
+    /// Posts digests rendered with the configured templates rather than the built-in
+    /// ones.
+    pub fn with_templates(mut self, templates: Arc<DigestTemplates>) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Sends each new daily digest to the configured webhooks.
+    pub fn with_webhooks(mut self, webhooks: Webhooks) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Delivers each new digest to the sinks that want it.
+    pub fn with_sinks(mut self, sinks: Sinks) -> Self {
+        self.sinks = Some(sinks);
+        self
+    }
+
+    /// Has the model cite the summaries each digest is written from, and appends links
+    /// to the conversations it cites.
+    pub fn with_citations(mut self) -> Self {
+        self.citations = true;
+        self
+    }
+
+    /// Appends the messages highlighted since the previous digest to each digest.
+    pub fn with_highlights(mut self) -> Self {
+        self.highlights = true;
+        self
+    }
+
+    /// Appends the links shared since the previous digest to each digest.
+    pub fn with_shared_links(mut self) -> Self {
+        self.shared_links = true;
+        self
+    }
+
+    /// Appends message counts, active members and the busiest hour of each channel to
+    /// each digest, and stores them along with it.
+    pub fn with_participant_stats(mut self) -> Self {
+        self.participant_stats = true;
+        self
+    }
+
+    /// Mentions the topics that came up in several of the daily digests each weekly
+    /// digest rolls up.
+    pub fn with_recurring_topics(mut self) -> Self {
+        self.recurring_topics = true;
+        self
+    }
+
+    /// Compares each digest to the previous one of its guild, appending the new topics,
+    /// resolved issues and continuing discussions to it.
+    pub fn with_changes(mut self) -> Self {
+        self.changes = true;
+        self
+    }
+
+    /// Lists the terms added to the glossary since the previous weekly digest in each
+    /// weekly digest.
+    pub fn with_new_terms(mut self) -> Self {
+        self.new_terms = true;
+        self
+    }
+
+    /// Has the model write digests of the given length.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Writes a one-paragraph TL;DR of each digest, and stores it along with it.
+    pub fn with_tldr(mut self) -> Self {
+        self.tldr = true;
+        self
+    }
+
+    /// Puts the summaries of high-priority channels first in digest prompts, marked for
+    /// the model to give them more weight, and those of low-priority channels last.
+    pub fn with_channel_priorities(mut self, priorities: HashMap<i64, ChannelPriority>) -> Self {
+        self.channel_priorities = priorities;
+        self
+    }
+
+    /// Writes a digest of the summaries of each channel group along with each digest,
+    /// sectioned by channel.
+    pub fn with_channel_groups(mut self, groups: ChannelGroups) -> Self {
+        self.channel_groups = Some(groups);
+        self
+    }
+
+    /// Appends the guild's events starting within `upcoming` and those that concluded
+    /// since the previous digest to each digest.
+    pub fn with_scheduled_events(mut self, events: ScheduledEvents, upcoming: Duration) -> Self {
+        self.scheduled_events = Some((events, upcoming));
+        self
+    }
+
+    /// Appends the pull requests, issues and releases opened in the configured GitHub
+    /// repositories during the digest's period to each digest.
+    pub fn with_github(mut self, github: GithubActivity) -> Self {
+        self.github = Some(github);
+        self
+    }
+
+    /// Appends the questions nobody answered within `answer_window` to each digest.
+    pub fn with_open_questions(mut self, answer_window: Duration) -> Self {
+        self.question_answer_window = Some(answer_window);
+        self
+    }
+
+    /// Embeds each digest right after storing it, rather than leaving it to the
+    /// embedding service.
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// Makes each digest cover exactly one calendar day in the reporting timezone,
+    /// waiting for a day to be over before rolling it up.
+    pub fn with_calendar_days(mut self) -> Self {
+        self.calendar_days = true;
+        self
+    }
+
     pub async fn run(&mut self) {
-        let mut interval_timer = interval(self.interval);
-
-        loop {
-            interval_timer.tick().await;
-            // Perform your task here
-            info!("Running daily recap of summaries...");
-
-            // Here, we should decide whether to fetch all summaries or only those after the last recap.
-            let last_recap: Option<(i32, NaiveDateTime)> =
-                sqlx::query_as::<_, (i32, NaiveDateTime)>(
-                    "SELECT id, timestamp FROM daily_digests ORDER BY timestamp DESC LIMIT 1",
-                )
-                .fetch_optional(&*self.db)
-                .await
-                .unwrap(); // Handle this error properly in production code
-
-            let summaries = match last_recap {
-                Some((_, last_timestamp)) => sqlx::query_as!(
-                    db::Summary,
-                    "SELECT * FROM summaries WHERE timestamp >= ? ORDER BY timestamp ASC",
-                    last_timestamp,
-                )
-                .fetch_all(&*self.db)
-                .await
-                .unwrap(),
-                None => sqlx::query_as!(db::Summary, "SELECT * FROM summaries")
-                    .fetch_all(&*self.db)
-                    .await
-                    .unwrap(),
+        match &self.schedule {
+            Schedule::Interval(period) => {
+                let mut interval_timer = interval(*period);
+                loop {
+                    interval_timer.tick().await;
+                    self.recap(None).await;
+                }
+            }
+            Schedule::Cron(cron) => loop {
+                let run_at = match cron.next_after(Utc::now()) {
+                    Ok(run_at) => run_at,
+                    Err(e) => {
+                        error!(
+                            "Could not compute the next {} recap time: {e}",
+                            self.tier.name()
+                        );
+                        return;
+                    }
+                };
+                sleep((run_at - Utc::now()).to_std().unwrap_or_default()).await;
+                let window = CoverageWindow {
+                    from: cron.previous_before(run_at),
+                    to: run_at,
+                };
+                self.recap(Some(window)).await;
+            },
+        }
+    }
+
+    /// Rolls up every source that has not been rolled up yet, regardless of the
+    /// schedule.
+    pub async fn recap_pending(&self) {
+        self.recap(None).await;
+    }
+
+    /// Rolls up the sources that end on `day` in the reporting timezone into a digest
+    /// per guild, whether or not the day is over, and returns the IDs of the digests
+    /// written.
+    pub async fn recap_day(&self, day: NaiveDate) -> Vec<i64> {
+        let tier = self.tier.name();
+        let sources = match db::fetch_rollup_sources(&self.db, self.tier, None).await {
+            Ok(sources) => sources,
+            Err(e) => {
+                error!("Could not fetch sources for the {tier} recap of {day}: {e}");
+                return vec![];
+            }
+        };
+        let mut sources_by_guild: BTreeMap<Option<i64>, Vec<db::RollupSource>> = BTreeMap::new();
+        for source in sources {
+            if self.source_day(&source) == day {
+                sources_by_guild
+                    .entry(source.guild_id)
+                    .or_default()
+                    .push(source);
+            }
+        }
+        if sources_by_guild.is_empty() {
+            info!("Nothing of {day} to roll up into a {tier} digest");
+        }
+        let window = CoverageWindow {
+            from: Some(start_of_day(day, self.timezone)),
+            to: start_of_day(day + Duration::days(1), self.timezone),
+        };
+        let mut digest_ids = vec![];
+        for (guild_id, sources) in sources_by_guild {
+            digest_ids.extend(self.recap_guild(guild_id, sources, Some(window)).await);
+        }
+        digest_ids
+    }
+
+    /// The day a source ends on in the reporting timezone.
+    fn source_day(&self, source: &db::RollupSource) -> NaiveDate {
+        source
+            .covers_to
+            .unwrap_or(source.timestamp)
+            .with_timezone(&self.timezone)
+            .date_naive()
+    }
+
+    async fn recap(&self, window: Option<CoverageWindow>) {
+        let tier = self.tier.name();
+        info!("Running {tier} recap...");
+
+        // Every guild gets its own digest of the sources that have not been rolled up
+        // yet. Scheduled digests leave out sources created after their window.
+        let before = window.map(|window| window.to);
+        let sources = match db::fetch_rollup_sources(&self.db, self.tier, before).await {
+            Ok(sources) => sources,
+            Err(e) => {
+                error!("Could not fetch sources for the {tier} recap: {e}");
+                self.report_failure(&format!("Could not fetch sources: {e}"));
+                return;
+            }
+        };
+        if sources.is_empty() {
+            info!("Nothing to roll up into a {tier} digest");
+            return;
+        }
+        if let Some(answer_window) = self.question_answer_window {
+            if let Err(e) = db::mark_answered_questions(&self.db, answer_window.num_seconds()).await
+            {
+                error!("Could not check which questions were answered: {e}");
+            }
+        }
+        // With calendar days, sources are further split by the day they end on, and only
+        // days that are over get a digest.
+        let today = Utc::now().with_timezone(&self.timezone).date_naive();
+        let mut sources_by_guild: BTreeMap<
+            (Option<i64>, Option<NaiveDate>),
+            Vec<db::RollupSource>,
+        > = BTreeMap::new();
+        for source in sources {
+            let day = self.calendar_days.then(|| self.source_day(&source));
+            sources_by_guild
+                .entry((source.guild_id, day))
+                .or_default()
+                .push(source);
+        }
+        for ((guild_id, day), sources) in sources_by_guild {
+            let window = match day {
+                Some(day) if day >= today => continue,
+                Some(day) => Some(CoverageWindow {
+                    from: Some(start_of_day(day, self.timezone)),
+                    to: start_of_day(day + Duration::days(1), self.timezone),
+                }),
+                None => window,
+            };
+            self.recap_guild(guild_id, sources, window).await;
+        }
+    }
+
+    /// Rolls up the sources of a guild into a digest, and returns its ID once stored.
+    async fn recap_guild(
+        &self,
+        guild_id: Option<i64>,
+        mut sources: Vec<db::RollupSource>,
+        window: Option<CoverageWindow>,
+    ) -> Option<i64> {
+        let tier = self.tier.name();
+        info!(
+            "Rolling up {} sources into a {tier} digest for guild {guild_id:?}",
+            sources.len()
+        );
+        let source_ids: Vec<i64> = sources.iter().map(|s| s.id).collect();
+
+        let mut instructions_extra = vec![];
+        let priority = |source: &db::RollupSource| {
+            source
+                .channel_id
+                .and_then(|channel_id| self.channel_priorities.get(&channel_id))
+                .copied()
+                .unwrap_or_default()
+        };
+        sources.sort_by_key(|source| std::cmp::Reverse(priority(source)));
+        let source_texts: Vec<String> = sources
+            .iter()
+            .map(|source| match priority(source) {
+                ChannelPriority::High => format!("{PRIORITY_MARKER} {}", source.text),
+                _ => source.text.clone(),
+            })
+            .collect();
+        if sources
+            .iter()
+            .any(|source| priority(source) == ChannelPriority::High)
+        {
+            instructions_extra.push(PRIORITY_EMPHASIS);
+        }
+        match self.verbosity {
+            Verbosity::Tldr => instructions_extra.push(TLDR_LENGTH),
+            Verbosity::Standard => {}
+            Verbosity::Detailed => instructions_extra.push(DETAILED_LENGTH),
+        }
+        let mut sources_content = if self.citations {
+            instructions_extra.push(CITATIONS_FORMAT);
+            numbered_sources(&source_texts)
+        } else {
+            source_texts.join(" ")
+        };
+        let mut digest = db::NewDigest::from_sources(String::new(), &sources);
+        if let Some(window) = window {
+            // Sources left over from missed runs can start before the window.
+            digest.covers_from = match (digest.covers_from, window.from) {
+                (Some(covers_from), Some(window_from)) => Some(covers_from.min(window_from)),
+                (covers_from, window_from) => window_from.or(covers_from),
+            };
+            digest.covers_to = Some(window.to);
+        }
+        let reacted = self.most_reacted(guild_id, &digest).await;
+        if let Some(section) = most_reacted_section(&reacted) {
+            sources_content = format!("{sources_content}\n\n{section}");
+            instructions_extra.push(REACTIONS_EMPHASIS);
+        }
+        let format_time = |time: Option<DateTime<Utc>>| {
+            time.map(|time| {
+                time.with_timezone(&self.timezone)
+                    .format("%Y-%m-%d %H:%M %Z")
+                    .to_string()
+            })
+            .unwrap_or_default()
+        };
+        let vars = PromptVars::lookup(
+            self.names.as_ref(),
+            guild_id.map(|id| GuildId::new(id as u64)),
+            None,
+            format_time(digest.covers_from),
+            format_time(digest.covers_to),
+        )
+        .await;
+        let mut instructions = self.prompts.digest(&vars, &sources_content);
+        for extra in instructions_extra {
+            instructions = format!("{instructions}\n\n{extra}");
+        }
+        digest.text = match self
+            .summarizer
+            .summarize(&instructions, &sources_content)
+            .await
+        {
+            Ok(txt) => txt,
+            // The sources are rolled up on the next run after calls resume.
+            Err(e) if e.downcast_ref::<LlmPaused>().is_some() => {
+                info!("Postponing the {tier} digest for guild {guild_id:?}: {e}");
+                return None;
+            }
+            Err(e) => {
+                error!("Could not summarize {tier} digest for guild {guild_id:?}: {e}");
+                self.report_failure(&format!("Could not summarize digest: {e}"));
+                return None;
+            }
+        };
+        info!(
+            "Obtained a summarized {tier} digest for guild {guild_id:?}: {}",
+            digest.text
+        );
+        digest.tldr = self.tldr(guild_id, &digest.text).await;
+        if self.citations {
+            let citations = self.citations(&sources).await;
+            if let Some(section) = sources_section(&digest.text, &citations) {
+                digest.text = format!("{}\n\n{section}", digest.text);
+            }
+        }
+        let changes = self.changes(guild_id, &digest.text).await;
+        if let Some(section) = changes
+            .as_ref()
+            .and_then(|(_, changes)| changes_section(changes))
+        {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        if let Some(section) = recurring_topics_section(&self.recurring_topics(&source_ids).await) {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        let new_terms = self.new_terms(guild_id, window).await;
+        if let Some(section) = new_terms_section(&new_terms) {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        let highlights = self.highlights(guild_id, window).await;
+        if let Some(section) = highlights_section(&highlights, self.timezone) {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        let questions = self.open_questions(guild_id, window).await;
+        if let Some(section) = open_questions_section(&questions, self.timezone) {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        let links = self.shared_links(guild_id, window).await;
+        if let Some(section) = shared_links_section(&links, self.timezone) {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        let (upcoming, concluded) = self.scheduled_events(guild_id, window).await;
+        if let Some(section) = upcoming_events_section(&upcoming, self.timezone) {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        if let Some(section) = concluded_events_section(&concluded, self.timezone) {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        let activity = self.repository_activity(guild_id, &digest).await;
+        if let Some(section) = repository_activity_section(&activity) {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        let stats = self.participant_stats(guild_id, &digest).await;
+        if let Some(section) = stats
+            .as_ref()
+            .and_then(|stats| stats_section(stats, self.timezone))
+        {
+            digest.text = format!("{}\n\n{section}", digest.text);
+        }
+        digest.stats = stats;
+        let digest_text = digest.text.clone();
+        let message_count = digest.message_count;
+        let covers = digest.covers_from.zip(digest.covers_to);
+        let digest_id =
+            match db::insert_digest(&self.db, self.tier, digest, source_ids.clone()).await {
+                Ok(digest_id) => digest_id,
+                Err(e) => {
+                    error!("Could not insert summarized {tier} digest into DB: {e}");
+                    self.report_failure(&format!("Could not store digest: {e}"));
+                    return None;
+                }
             };
+        info!("Saved {tier} digest for guild {guild_id:?} to DB");
+        if let Some(health) = &self.health {
+            health.succeeded();
+        }
+        let question_ids: Vec<i64> = questions.iter().map(|question| question.id).collect();
+        if let Err(e) = db::link_questions_to_digest(&self.db, &question_ids, digest_id).await {
+            error!("Could not record the open questions listed in digest {digest_id}: {e}");
+        }
+        let highlight_ids: Vec<i64> = highlights.iter().map(|highlight| highlight.id).collect();
+        if let Err(e) = db::link_highlights_to_digest(&self.db, &highlight_ids, digest_id).await {
+            error!("Could not record the highlights quoted in digest {digest_id}: {e}");
+        }
+        if let RollupTier::Daily = self.tier {
+            self.record_topics(digest_id, guild_id, &source_ids).await;
+        }
+        if let Some(groups) = &self.channel_groups {
+            groups.write_digests(digest_id, guild_id, &sources).await;
+        }
+        if let Some((previous_digest_id, changes)) = &changes {
+            if let Err(e) =
+                db::insert_digest_changes(&self.db, digest_id, *previous_digest_id, changes).await
+            {
+                error!("Could not store the changes of digest {digest_id}: {e}");
+            }
+        }
+        let term_ids: Vec<i64> = new_terms.iter().map(|term| term.id).collect();
+        if let Err(e) = db::link_glossary_terms_to_digest(&self.db, &term_ids, digest_id).await {
+            error!("Could not record the glossary terms listed in digest {digest_id}: {e}");
+        }
+        let event_ids: Vec<i64> = concluded.iter().map(|recap| recap.event.event_id).collect();
+        if let Err(e) = db::link_scheduled_events_to_digest(&self.db, &event_ids, digest_id).await {
+            error!("Could not record the events summarized in digest {digest_id}: {e}");
+        }
+        let link_ids: Vec<i64> = links.iter().map(|link| link.id).collect();
+        if let Err(e) = db::link_shared_links_to_digest(&self.db, &link_ids, digest_id).await {
+            error!("Could not record the links listed in digest {digest_id}: {e}");
+        }
+        self.publish_digest(digest_id).await;
+        if let Some(embedder) = &self.embedder {
+            embed_content(
+                &self.db,
+                embedder.as_ref(),
+                ContentKind::digest_of(self.tier),
+                digest_id,
+                &digest_text,
+            )
+            .await;
+        }
+
+        if let Some(sinks) = &self.sinks {
+            let view = self.templates.view(
+                self.tier,
+                Some(digest_id),
+                guild_id,
+                &digest_text,
+                message_count,
+                covers,
+            );
+            sinks.deliver(self.tier, &view).await;
+        }
+        Some(digest_id)
+    }
 
-            if summaries.is_empty() {
-                info!("No summaries to recap");
-                continue;
+    fn report_failure(&self, message: &str) {
+        if let Some(health) = &self.health {
+            health.failed(message);
+        }
+    }
+
+    /// Sends a newly stored digest to webhooks, for daily digests, and to live
+    /// subscribers.
+    async fn publish_digest(&self, digest_id: i64) {
+        if let (Some(webhooks), RollupTier::Daily) = (&self.webhooks, self.tier) {
+            match db::fetch_daily_digest(&self.db, digest_id).await {
+                Ok(Some(stored)) => webhooks.publish(WebhookEvent::DailyDigest, &stored).await,
+                Ok(None) => {}
+                Err(e) => error!("Could not fetch digest {digest_id} for webhooks: {e}"),
+            }
+        }
+        if let Some(events) = &self.events {
+            match db::fetch_digest(&self.db, self.tier, digest_id).await {
+                Ok(Some(stored)) => events.publish(
+                    EventKind::digest_of(self.tier),
+                    stored.guild_id,
+                    stored.channel_id,
+                    &stored,
+                ),
+                Ok(None) => {}
+                Err(e) => error!("Could not fetch digest {digest_id} for events: {e}"),
             }
-            let summary_ids: Vec<i64> = summaries.iter().map(|s| s.id).collect();
+        }
+    }
 
-            let summaries_content: Vec<String> = summaries.into_iter().map(|s| s.text).collect();
-            let summaries_content = summaries_content.join(" ");
-            let digest = match gpt::summarize(&summaries_content).await {
-                Ok(txt) => txt,
+    /// Fetches the questions of a guild that went unanswered for the whole answer window
+    /// and were asked before the end of the digest's window.
+    async fn open_questions(
+        &self,
+        guild_id: Option<i64>,
+        window: Option<CoverageWindow>,
+    ) -> Vec<db::UnansweredQuestion> {
+        let Some(answer_window) = self.question_answer_window else {
+            return vec![];
+        };
+        let unanswered_since = Utc::now() - answer_window;
+        let before = window.map_or(unanswered_since, |window| window.to.min(unanswered_since));
+        db::fetch_unlisted_unanswered_questions(&self.db, guild_id, before)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Could not fetch open questions for guild {guild_id:?}: {e}");
+                vec![]
+            })
+    }
+
+    /// Fetches the messages of a guild that got the most reactions in the period a daily
+    /// digest covers. Digests of other tiers are written from daily digests, which
+    /// already emphasize them.
+    async fn most_reacted(
+        &self,
+        guild_id: Option<i64>,
+        digest: &db::NewDigest,
+    ) -> Vec<db::ReactedMessage> {
+        let (RollupTier::Daily, Some(from), Some(to)) =
+            (self.tier, digest.covers_from, digest.covers_to)
+        else {
+            return vec![];
+        };
+        db::fetch_most_reacted_messages(&self.db, guild_id, from, to, MAX_REACTED)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Could not fetch the most reacted messages for guild {guild_id:?}: {e}");
+                vec![]
+            })
+    }
+
+    /// Stores the topics of a new daily digest, gathered from its summaries.
+    async fn record_topics(&self, digest_id: i64, guild_id: Option<i64>, summary_ids: &[i64]) {
+        let topics = match db::fetch_summary_topics(&self.db, summary_ids).await {
+            Ok(topics) => count_topics(&topics),
+            Err(e) => {
+                error!("Could not fetch the topics of the summaries of digest {digest_id}: {e}");
+                return;
+            }
+        };
+        if let Err(e) = db::insert_digest_topics(&self.db, digest_id, guild_id, &topics).await {
+            error!("Could not record the topics of digest {digest_id}: {e}");
+        }
+    }
+
+    /// Fetches the topics that came up in several of the daily digests a weekly digest
+    /// rolls up.
+    async fn recurring_topics(&self, daily_digest_ids: &[i64]) -> Vec<db::RecurringTopic> {
+        if !self.recurring_topics {
+            return vec![];
+        }
+        db::fetch_recurring_topics(
+            &self.db,
+            daily_digest_ids,
+            MIN_RECURRING_DIGESTS,
+            MAX_RECURRING,
+        )
+        .await
+        .unwrap_or_else(|e| {
+            error!("Could not fetch the recurring topics of a weekly digest: {e}");
+            vec![]
+        })
+    }
+
+    /// Computes the participant statistics of the period a daily digest covers.
+    async fn participant_stats(
+        &self,
+        guild_id: Option<i64>,
+        digest: &db::NewDigest,
+    ) -> Option<db::DigestStats> {
+        let (true, Some(from), Some(to)) =
+            (self.participant_stats, digest.covers_from, digest.covers_to)
+        else {
+            return None;
+        };
+        match db::fetch_message_activity(&self.db, guild_id, from, to).await {
+            Ok(messages) => Some(compute_stats(&messages, self.timezone)),
+            Err(e) => {
+                error!("Could not fetch the activity of guild {guild_id:?}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Fetches the message each summary links to, in the order of the summaries.
+    async fn citations(&self, sources: &[db::RollupSource]) -> Vec<Option<db::Citation>> {
+        let mut citations = vec![];
+        for source in sources {
+            let citation = db::fetch_summary_citation(&self.db, source.id)
+                .await
+                .unwrap_or_else(|e| {
+                    error!(
+                        "Could not fetch the message to cite for summary {}: {e}",
+                        source.id
+                    );
+                    None
+                });
+            citations.push(citation);
+        }
+        citations
+    }
+
+    /// Fetches the messages of a guild sent before the end of the digest's window that
+    /// were highlighted and no digest quotes yet.
+    async fn highlights(
+        &self,
+        guild_id: Option<i64>,
+        window: Option<CoverageWindow>,
+    ) -> Vec<db::Highlight> {
+        if !self.highlights {
+            return vec![];
+        }
+        let before = window.map_or_else(Utc::now, |window| window.to);
+        db::fetch_unlisted_highlights(&self.db, guild_id, before)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Could not fetch highlights for guild {guild_id:?}: {e}");
+                vec![]
+            })
+    }
+
+    /// Compares the text of a new daily digest to the previous one of its guild, returning
+    /// the ID of the previous digest along with what changed.
+    /// Writes the TL;DR of a digest, when enabled. Digests already kept to a single
+    /// paragraph are their own TL;DR.
+    async fn tldr(&self, guild_id: Option<i64>, text: &str) -> Option<String> {
+        if !self.tldr {
+            return None;
+        }
+        if self.verbosity == Verbosity::Tldr {
+            return Some(text.to_string());
+        }
+        match self.summarizer.complete(TLDR_INSTRUCTIONS, text).await {
+            Ok(tldr) if !tldr.trim().is_empty() => Some(tldr.trim().to_string()),
+            Ok(_) => None,
+            Err(e) => {
+                error!(
+                    "Could not write the TL;DR of the {} digest for guild {guild_id:?}: {e}",
+                    self.tier.name()
+                );
+                None
+            }
+        }
+    }
+
+    async fn changes(&self, guild_id: Option<i64>, text: &str) -> Option<(i64, db::DigestChanges)> {
+        if !self.changes {
+            return None;
+        }
+        let (previous_id, previous_text) =
+            match db::fetch_latest_daily_digest_text(&self.db, guild_id).await {
+                Ok(previous) => previous?,
                 Err(e) => {
-                    error!("Could not summarize daily digest: {e}");
-                    continue;
+                    error!("Could not fetch the previous digest of guild {guild_id:?}: {e}");
+                    return None;
                 }
             };
-            info!("Obtained a summarized daily digest: {digest}");
-            if let Err(e) = db::insert_daily_digest(&self.db, digest, summary_ids).await {
-                error!("Could not insert summarized daily digest into DB: {e}");
-                continue;
+        match compare_digests(self.summarizer.as_ref(), &previous_text, text).await {
+            Ok(changes) => Some((previous_id, changes)),
+            Err(e) => {
+                error!(
+                    "Could not compare the digest of guild {guild_id:?} to the previous one: {e}"
+                );
+                None
+            }
+        }
+    }
+
+    /// Fetches the terms of a guild added to the glossary before the end of the digest's
+    /// window that no weekly digest lists yet.
+    async fn new_terms(
+        &self,
+        guild_id: Option<i64>,
+        window: Option<CoverageWindow>,
+    ) -> Vec<db::GlossaryTerm> {
+        if !self.new_terms {
+            return vec![];
+        }
+        let before = window.map_or_else(Utc::now, |window| window.to);
+        db::fetch_unlisted_glossary_terms(&self.db, guild_id, before)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Could not fetch the new glossary terms of guild {guild_id:?}: {e}");
+                vec![]
+            })
+    }
+
+    /// Fetches the events of a guild coming up, and those that concluded before the end
+    /// of the digest's window that no digest summarized yet.
+    async fn scheduled_events(
+        &self,
+        guild_id: Option<i64>,
+        window: Option<CoverageWindow>,
+    ) -> (Vec<UpcomingEvent>, Vec<EventRecap>) {
+        let (Some((events, upcoming)), Some(guild_id)) = (&self.scheduled_events, guild_id) else {
+            return (vec![], vec![]);
+        };
+        let before = window.map_or_else(Utc::now, |window| window.to);
+        (
+            events
+                .upcoming(GuildId::new(guild_id as u64), *upcoming)
+                .await,
+            events.concluded(guild_id, before).await,
+        )
+    }
+
+    /// Fetches what was opened in the GitHub repositories listed in a guild's digests
+    /// during the digest's period, or the last day when it has no start.
+    async fn repository_activity(
+        &self,
+        guild_id: Option<i64>,
+        digest: &db::NewDigest,
+    ) -> Vec<RepoActivity> {
+        let Some(github) = self
+            .github
+            .as_ref()
+            .filter(|github| github.covers(guild_id))
+        else {
+            return vec![];
+        };
+        let to = digest.covers_to.unwrap_or_else(Utc::now);
+        let from = digest.covers_from.unwrap_or_else(|| to - Duration::days(1));
+        github.fetch(from, to).await
+    }
+
+    /// Fetches the links of a guild shared before the end of the digest's window that
+    /// no digest lists yet.
+    async fn shared_links(
+        &self,
+        guild_id: Option<i64>,
+        window: Option<CoverageWindow>,
+    ) -> Vec<db::SharedLink> {
+        if !self.shared_links {
+            return vec![];
+        }
+        let before = window.map_or_else(Utc::now, |window| window.to);
+        db::fetch_unlisted_shared_links(&self.db, guild_id, before)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Could not fetch shared links for guild {guild_id:?}: {e}");
+                vec![]
+            })
+    }
+}
+
+/// The summaries of a daily digest that cover any of `channel_ids`, under a heading per
+/// channel, along with how many messages they summarize. `None` when none of the
+/// channels were summarized.
+pub fn channel_summaries(digest: &db::DailyDigest, channel_ids: &[i64]) -> Option<(String, i64)> {
+    let mut text = String::new();
+    let mut message_count = 0;
+    for channel_id in channel_ids {
+        let summaries: Vec<&db::Summary> = digest
+            .summaries
+            .iter()
+            .filter(|summary| summary.channel_id == Some(*channel_id))
+            .collect();
+        if summaries.is_empty() {
+            continue;
+        }
+        text.push_str(&format!("**<#{channel_id}>**\n"));
+        for summary in summaries {
+            text.push_str(&format!("{}\n\n", summary.text));
+            message_count += summary.message_count;
+        }
+    }
+    (!text.is_empty()).then_some((text, message_count))
+}
+
+/// Splits text into chunks of at most `limit` characters, breaking between lines
+/// where possible, then between words, and only splitting words that are too long.
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    let mut current_len = 0;
+    let mut push = |piece: &str, current: &mut String, current_len: &mut usize| {
+        let piece_len = piece.chars().count();
+        if *current_len + piece_len > limit && !current.is_empty() {
+            chunks.push(std::mem::take(current));
+            *current_len = 0;
+        }
+        current.push_str(piece);
+        *current_len += piece_len;
+    };
+    for line in text.split_inclusive('\n') {
+        if line.chars().count() <= limit {
+            push(line, &mut current, &mut current_len);
+            continue;
+        }
+        for word in line.split_inclusive(' ') {
+            let chars: Vec<char> = word.chars().collect();
+            for piece in chars.chunks(limit) {
+                push(
+                    &piece.iter().collect::<String>(),
+                    &mut current,
+                    &mut current_len,
+                );
             }
-            info!("Saved daily digest to DB");
         }
     }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+        .into_iter()
+        .map(|chunk| chunk.trim_end().to_string())
+        .filter(|chunk| !chunk.is_empty())
+        .collect()
 }
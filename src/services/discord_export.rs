@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use eyre::{bail, eyre, WrapErr};
+use serde::Deserialize;
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use super::digests::RecapService;
+use super::discord_handler::{MessageIntake, ReactionChange, ThreadInfo};
+use super::message_listener::MessageLogService;
+use super::sources::{IngestEvent, IngestedAttachment, IngestedMessage};
+use super::summarizer::SummarizerService;
+
+/// A channel exported by DiscordChatExporter as JSON.
+#[derive(Deserialize)]
+struct ChatExport {
+    guild: ChatExportGuild,
+    channel: ChatExportChannel,
+    messages: Vec<ChatExportMessage>,
+}
+
+#[derive(Deserialize)]
+struct ChatExportGuild {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChatExportChannel {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    /// The parent channel, for threads.
+    category_id: Option<String>,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChatExportMessage {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    timestamp: String,
+    content: String,
+    author: ChatExportUser,
+    #[serde(default)]
+    attachments: Vec<ChatExportAttachment>,
+    #[serde(default)]
+    reactions: Vec<ChatExportReaction>,
+    #[serde(default)]
+    mentions: Vec<ChatExportUser>,
+    reference: Option<ChatExportReference>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChatExportUser {
+    id: String,
+    name: String,
+    nickname: Option<String>,
+    #[serde(default)]
+    is_bot: bool,
+}
+
+impl ChatExportUser {
+    fn display_name(&self) -> &str {
+        self.nickname.as_deref().unwrap_or(&self.name)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChatExportAttachment {
+    url: String,
+    file_name: String,
+    #[serde(default)]
+    file_size_bytes: u64,
+}
+
+#[derive(Deserialize)]
+struct ChatExportReaction {
+    count: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChatExportReference {
+    message_id: Option<String>,
+}
+
+/// A message of the `messages/c<id>/messages.json` files of Discord's data package,
+/// which only holds the messages of the account that requested it.
+#[derive(Deserialize)]
+struct PackageMessage {
+    #[serde(rename = "ID")]
+    id: serde_json::Value,
+    #[serde(rename = "Timestamp")]
+    timestamp: String,
+    #[serde(rename = "Contents")]
+    contents: String,
+}
+
+/// The `channel.json` file next to each `messages.json` of the data package.
+#[derive(Deserialize)]
+struct PackageChannel {
+    id: String,
+    guild: Option<PackageGuild>,
+}
+
+#[derive(Deserialize)]
+struct PackageGuild {
+    id: String,
+}
+
+/// The `account/user.json` file of the data package.
+#[derive(Deserialize)]
+struct PackageUser {
+    id: serde_json::Value,
+    username: String,
+}
+
+/// A message of an export, along with what the intake needs to decide whether to log
+/// it.
+pub struct ExportedMessage {
+    pub message: IngestedMessage,
+    pub bot: bool,
+    /// Names of the users the message mentions.
+    pub mentions: HashMap<UserId, String>,
+    pub reactions: i64,
+}
+
+/// Parses a channel exported by DiscordChatExporter as JSON. System messages, such as
+/// members joining, are left out.
+pub fn parse_chat_export(json: &str) -> eyre::Result<Vec<ExportedMessage>> {
+    let export: ChatExport =
+        serde_json::from_str(json).wrap_err("Not a DiscordChatExporter JSON export")?;
+    let guild_id = parse_id(&export.guild.id).map(GuildId::new);
+    let channel_id = ChannelId::new(
+        parse_id(&export.channel.id).ok_or_else(|| eyre!("Export has no channel ID"))?,
+    );
+    let thread = export
+        .channel
+        .kind
+        .ends_with("Thread")
+        .then(|| export.channel.category_id.as_deref().and_then(parse_id))
+        .flatten()
+        .map(|parent_id| ThreadInfo {
+            id: channel_id,
+            parent_id: ChannelId::new(parent_id),
+            name: export.channel.name.clone(),
+        });
+    let mut messages = vec![];
+    for msg in export.messages {
+        if !matches!(msg.kind.as_str(), "Default" | "Reply") {
+            continue;
+        }
+        let (Some(message_id), Some(author_id)) = (parse_id(&msg.id), parse_id(&msg.author.id))
+        else {
+            continue;
+        };
+        let mentions = msg
+            .mentions
+            .iter()
+            .filter_map(|user| {
+                let id = parse_id(&user.id)?;
+                Some((UserId::new(id), user.display_name().to_string()))
+            })
+            .collect();
+        let message = IngestedMessage {
+            message_id: MessageId::new(message_id),
+            guild_id,
+            channel_id,
+            author_id: UserId::new(author_id),
+            author: msg.author.display_name().to_string(),
+            content: msg.content,
+            timestamp: parse_timestamp(&msg.timestamp)?,
+            reply_to: msg
+                .reference
+                .and_then(|reference| reference.message_id)
+                .and_then(|id| parse_id(&id))
+                .map(MessageId::new),
+            thread: thread.clone(),
+            attachments: msg
+                .attachments
+                .into_iter()
+                .map(|attachment| IngestedAttachment {
+                    filename: attachment.file_name,
+                    url: attachment.url,
+                    content_type: None,
+                    size: attachment.file_size_bytes,
+                })
+                .collect(),
+        };
+        messages.push(ExportedMessage {
+            message,
+            bot: msg.author.is_bot,
+            mentions,
+            reactions: msg.reactions.iter().map(|reaction| reaction.count).sum(),
+        });
+    }
+    Ok(messages)
+}
+
+/// Parses a `messages.json` file of Discord's data package, using the `channel.json`
+/// file next to it and the package's `account/user.json` for who wrote the messages.
+pub fn parse_package_messages(path: &Path, json: &str) -> eyre::Result<Vec<ExportedMessage>> {
+    let package_messages: Vec<PackageMessage> =
+        serde_json::from_str(json).wrap_err("Not a data package messages.json file")?;
+    let channel_dir = path.parent().unwrap_or(Path::new("."));
+    let channel: PackageChannel = read_json(&channel_dir.join("channel.json"))?;
+    let user_path = channel_dir.join("../../account/user.json");
+    let user: PackageUser = read_json(&user_path)?;
+    let guild_id = channel
+        .guild
+        .and_then(|guild| parse_id(&guild.id))
+        .map(GuildId::new);
+    let channel_id = ChannelId::new(
+        parse_id(&channel.id).ok_or_else(|| eyre!("channel.json has no channel ID"))?,
+    );
+    let author_id = json_id(&user.id)
+        .map(UserId::new)
+        .ok_or_else(|| eyre!("{} has no user ID", user_path.display()))?;
+    let mut messages = vec![];
+    for msg in package_messages {
+        let Some(message_id) = json_id(&msg.id) else {
+            continue;
+        };
+        if msg.contents.trim().is_empty() {
+            continue;
+        }
+        let message = IngestedMessage {
+            message_id: MessageId::new(message_id),
+            guild_id,
+            channel_id,
+            author_id,
+            author: user.username.clone(),
+            content: msg.contents,
+            timestamp: parse_timestamp(&msg.timestamp)?,
+            reply_to: None,
+            thread: None,
+            attachments: vec![],
+        };
+        messages.push(ExportedMessage {
+            message,
+            bot: false,
+            mentions: HashMap::new(),
+            reactions: 0,
+        });
+    }
+    Ok(messages)
+}
+
+/// Reads the messages of export files, and of every export file in the given
+/// directories and their subdirectories.
+pub fn read_exports(paths: &[PathBuf]) -> eyre::Result<Vec<ExportedMessage>> {
+    let mut files = vec![];
+    for path in paths {
+        collect_export_files(path, &mut files)?;
+    }
+    let mut messages = vec![];
+    for file in files {
+        let json = fs::read_to_string(&file)
+            .wrap_err_with(|| format!("Could not read {}", file.display()))?;
+        let parsed = if json.trim_start().starts_with('[') {
+            parse_package_messages(&file, &json)
+        } else {
+            parse_chat_export(&json)
+        };
+        let parsed = parsed.wrap_err_with(|| format!("Could not import {}", file.display()))?;
+        info!("Read {} messages from {}", parsed.len(), file.display());
+        messages.extend(parsed);
+    }
+    Ok(messages)
+}
+
+fn collect_export_files(path: &Path, files: &mut Vec<PathBuf>) -> eyre::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)
+        .wrap_err_with(|| format!("Could not list {}", path.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let is_json = entry
+            .extension()
+            .is_some_and(|extension| extension == "json");
+        // Metadata files of the data package hold no messages.
+        let is_metadata = entry
+            .file_name()
+            .is_some_and(|name| name == "channel.json" || name == "index.json");
+        if entry.is_dir() {
+            collect_export_files(&entry, files)?;
+        } else if is_json && !is_metadata {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Turns the messages the intake accepts into ingest events, oldest first. The
+/// messages of each channel, or thread, are backfilled a day at a time in `timezone`,
+/// so that every summary covers a single day and lands in that day's digest.
+pub fn replay_events(
+    messages: Vec<ExportedMessage>,
+    intake: &MessageIntake,
+    timezone: Tz,
+) -> Vec<IngestEvent> {
+    let mut days: BTreeMap<(NaiveDate, ChannelId), Vec<IngestedMessage>> = BTreeMap::new();
+    let mut reactions = vec![];
+    for exported in messages {
+        let ExportedMessage {
+            message,
+            bot,
+            mentions,
+            reactions: reaction_count,
+        } = exported;
+        let Some(message) =
+            intake.accept_exported(message, bot, |user_id| mentions.get(&user_id).cloned())
+        else {
+            continue;
+        };
+        if reaction_count > 0 {
+            reactions.push((message.message_id, reaction_count));
+        }
+        let day = message.timestamp.with_timezone(&timezone).date_naive();
+        // Threads are summarized along with their parent channel.
+        let channel_id = message
+            .thread
+            .as_ref()
+            .map_or(message.channel_id, |thread| thread.parent_id);
+        days.entry((day, channel_id)).or_default().push(message);
+    }
+    let mut events = vec![];
+    for ((_, channel_id), mut messages) in days {
+        messages.sort_by_key(|msg| (msg.timestamp, msg.message_id));
+        events.push(IngestEvent::Backfilled {
+            channel_id,
+            messages,
+        });
+    }
+    // Reactions are counted once every message is logged, which may be after their
+    // summary was written, but always before the digests are.
+    events.extend(
+        reactions
+            .into_iter()
+            .map(|(message_id, count)| IngestEvent::Reactions {
+                message_id,
+                change: ReactionChange::Total(count),
+            }),
+    );
+    events
+}
+
+/// Runs `events` through the message log and the summarizer until every message is
+/// summarized, then rolls the summaries up into digests. The message log must be the
+/// only sender of summarize requests, and `ingest_tx` the only sender of its events.
+pub async fn replay(
+    events: Vec<IngestEvent>,
+    ingest_tx: Sender<IngestEvent>,
+    mut message_log: MessageLogService,
+    mut summarizer: SummarizerService,
+    recap: RecapService,
+) {
+    let feed = async move {
+        for event in events {
+            if ingest_tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    };
+    // Once every event is fed and the message log is dropped, the summarizer stops
+    // after summarizing the last batch.
+    let log = async move {
+        message_log.run(CancellationToken::new()).await;
+    };
+    tokio::join!(feed, log, summarizer.run());
+    recap.recap_pending().await;
+}
+
+/// Parses a snowflake, which exports write as a string. `0` is not a valid ID.
+fn parse_id(id: &str) -> Option<u64> {
+    id.parse().ok().filter(|id| *id != 0)
+}
+
+/// Parses an ID the data package writes as either a number or a string.
+fn json_id(id: &serde_json::Value) -> Option<u64> {
+    match id {
+        serde_json::Value::Number(number) => number.as_u64().filter(|id| *id != 0),
+        serde_json::Value::String(id) => parse_id(id),
+        _ => None,
+    }
+}
+
+/// Parses the timestamps of exports, such as `2024-05-01T09:30:00.123+00:00` or the
+/// data package's `2024-05-01 09:30:00.123000+00:00`.
+fn parse_timestamp(timestamp: &str) -> eyre::Result<DateTime<Utc>> {
+    let parsed = DateTime::parse_from_rfc3339(timestamp)
+        .or_else(|_| DateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f%:z"));
+    match parsed {
+        Ok(parsed) => Ok(parsed.with_timezone(&Utc)),
+        Err(e) => bail!("Invalid timestamp {timestamp:?}: {e}"),
+    }
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> eyre::Result<T> {
+    let json =
+        fs::read_to_string(path).wrap_err_with(|| format!("Could not read {}", path.display()))?;
+    serde_json::from_str(&json).wrap_err_with(|| format!("Could not parse {}", path.display()))
+}
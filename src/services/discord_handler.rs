@@ -1,43 +1,541 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 use axum::async_trait;
 use serenity::{
-    all::{ChannelId, Message, Ready},
+    all::{
+        Channel, ChannelId, Guild, GuildChannel, GuildId, Interaction, Message, MessageId,
+        MessageUpdateEvent, PartialGuildChannel, Reaction, Ready, ScheduledEvent, UserId,
+    },
     client::{Context, EventHandler},
 };
 use tokio::sync::mpsc::Sender;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-pub enum DiscordMessage {
-    Received(Message),
+use super::backfill::Backfiller;
+use super::commands::Commands;
+use super::highlights::HighlightEmoji;
+use super::mentions::MentionResolver;
+use super::privacy::{OptOuts, Pseudonyms};
+use super::scheduled_events::ScheduledEvents;
+use super::sources::{IngestEvent, IngestedMessage};
+
+pub enum ReactionChange {
+    Added,
+    Removed,
+    /// The message has this many reactions in total.
+    Total(i64),
+}
+
+/// A thread, or a post in a forum channel. Its messages are logged as part of its parent
+/// channel, as a conversation of their own.
+#[derive(Clone)]
+pub struct ThreadInfo {
+    pub id: ChannelId,
+    pub parent_id: ChannelId,
+    pub name: String,
+}
+
+impl ThreadInfo {
+    /// The thread a channel is, if it is one.
+    fn from_channel(channel: &GuildChannel) -> Option<Self> {
+        channel.thread_metadata?;
+        Some(Self {
+            id: channel.id,
+            parent_id: channel.parent_id?,
+            name: channel.name.clone(),
+        })
+    }
+}
+
+/// Channels whose messages are forwarded for logging.
+pub enum AllowedChannels {
+    All,
+    Only(HashSet<ChannelId>),
+}
+
+impl AllowedChannels {
+    pub fn contains(&self, channel_id: &ChannelId) -> bool {
+        match self {
+            AllowedChannels::All => true,
+            AllowedChannels::Only(channels) => channels.contains(channel_id),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, AllowedChannels::Only(channels) if channels.is_empty())
+    }
+}
+
+/// Decides which channels to listen to in each guild. Guilds with their own
+/// configuration use it, every other guild falls back to the default channels.
+pub struct ChannelFilter {
+    default_channels: AllowedChannels,
+    guild_channels: HashMap<GuildId, AllowedChannels>,
+}
+
+impl ChannelFilter {
+    pub fn new(
+        default_channels: AllowedChannels,
+        guild_channels: HashMap<GuildId, AllowedChannels>,
+    ) -> Self {
+        Self {
+            default_channels,
+            guild_channels,
+        }
+    }
+
+    pub fn allows(&self, guild_id: Option<GuildId>, channel_id: &ChannelId) -> bool {
+        guild_id
+            .and_then(|guild_id| self.guild_channels.get(&guild_id))
+            .unwrap_or(&self.default_channels)
+            .contains(channel_id)
+    }
+}
+
+/// Decides whose messages are logged, so that bots, webhooks, blocked users and members
+/// who opted out do not end up in summaries.
+pub struct AuthorFilter {
+    ignore_bots: bool,
+    ignore_webhooks: bool,
+    ignored_users: HashSet<UserId>,
+    opt_outs: OptOuts,
+}
+
+impl AuthorFilter {
+    pub fn new(ignore_bots: bool, ignore_webhooks: bool, ignored_users: HashSet<UserId>) -> Self {
+        Self {
+            ignore_bots,
+            ignore_webhooks,
+            ignored_users,
+            opt_outs: OptOuts::default(),
+        }
+    }
+
+    /// Also skips the messages of the members who opted out.
+    pub fn with_opt_outs(mut self, opt_outs: OptOuts) -> Self {
+        self.opt_outs = opt_outs;
+        self
+    }
+
+    /// Whether to log a message. The bot's own messages, `own_id`, are always skipped.
+    pub fn allows(&self, msg: &Message, own_id: UserId) -> bool {
+        // Webhook messages are sent under a bot author, so they are only told apart by
+        // their webhook ID.
+        msg.author.id != own_id
+            && self.allows_author(msg.author.id, msg.author.bot, msg.webhook_id.is_some())
+    }
+
+    /// Whether to log a message of `author_id`, a bot when `bot` is set, sent through a
+    /// webhook when `webhook` is set.
+    pub fn allows_author(&self, author_id: UserId, bot: bool, webhook: bool) -> bool {
+        if self.ignored_users.contains(&author_id) || self.opt_outs.contains(author_id) {
+            return false;
+        }
+        if webhook {
+            return !self.ignore_webhooks;
+        }
+        !(self.ignore_bots && bot)
+    }
+}
+
+/// Decides which messages are logged and prepares them for it, for messages received
+/// live and fetched from channel history alike.
+pub struct MessageIntake {
+    channel_filter: ChannelFilter,
+    author_filter: AuthorFilter,
+    mentions: MentionResolver,
+    /// Whether each channel messages were received in is a thread, and which.
+    threads: Mutex<HashMap<ChannelId, Option<ThreadInfo>>>,
+}
+
+impl MessageIntake {
+    pub fn new(channel_filter: ChannelFilter, author_filter: AuthorFilter) -> Self {
+        Self {
+            channel_filter,
+            author_filter,
+            mentions: MentionResolver::new(None),
+            threads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Mentions users by pseudonym, for when messages are stored under pseudonyms.
+    pub fn with_pseudonyms(mut self, pseudonyms: Pseudonyms) -> Self {
+        self.mentions = MentionResolver::new(Some(pseudonyms));
+        self
+    }
+
+    pub fn allows_channel(&self, guild_id: Option<GuildId>, channel_id: &ChannelId) -> bool {
+        self.channel_filter.allows(guild_id, channel_id)
+    }
+
+    /// The message ready to be logged, or `None` if it should not be logged.
+    pub async fn accept(&self, ctx: &Context, mut msg: Message) -> Option<IngestedMessage> {
+        let thread = self.thread(ctx, &msg).await;
+        let channel_id = thread
+            .as_ref()
+            .map_or(msg.channel_id, |thread| thread.parent_id);
+        if !self.channel_filter.allows(msg.guild_id, &channel_id) {
+            return None;
+        }
+        let own_id = ctx.cache.current_user().id;
+        if !self.author_filter.allows(&msg, own_id) {
+            return None;
+        }
+        msg.content = self
+            .mentions
+            .resolve(&msg.content, msg.guild_id, &msg.mentions, &ctx.cache);
+        Some(IngestedMessage::from_discord(msg, thread))
+    }
+
+    /// A message of a Discord export ready to be logged, or `None` if it should not be
+    /// logged. Its mentions are resolved with `user_name`, since there is no cache of
+    /// the guild to look them up in. Exports do not tell which messages came from
+    /// webhooks, so these are filtered like any other bot's.
+    pub fn accept_exported(
+        &self,
+        mut msg: IngestedMessage,
+        bot: bool,
+        user_name: impl Fn(UserId) -> Option<String>,
+    ) -> Option<IngestedMessage> {
+        let channel_id = msg
+            .thread
+            .as_ref()
+            .map_or(msg.channel_id, |thread| thread.parent_id);
+        if !self.channel_filter.allows(msg.guild_id, &channel_id)
+            || !self.author_filter.allows_author(msg.author_id, bot, false)
+        {
+            return None;
+        }
+        msg.content = self
+            .mentions
+            .resolve_with(&msg.content, user_name, |_| None, |_| None);
+        Some(msg)
+    }
+
+    /// The thread a message was posted in, if any. Channels are looked up once, from
+    /// the cache when it has them.
+    async fn thread(&self, ctx: &Context, msg: &Message) -> Option<ThreadInfo> {
+        msg.guild_id?;
+        if let Some(thread) = self.known_threads().get(&msg.channel_id) {
+            return thread.clone();
+        }
+        let thread = match msg.channel_id.to_channel(ctx).await {
+            Ok(Channel::Guild(channel)) => ThreadInfo::from_channel(&channel),
+            Ok(_) => None,
+            Err(e) => {
+                warn!(
+                    "Could not look up channel {}, logging its messages as is: {e}",
+                    msg.channel_id
+                );
+                return None;
+            }
+        };
+        self.known_threads().insert(msg.channel_id, thread.clone());
+        thread
+    }
+
+    fn known_threads(&self) -> MutexGuard<'_, HashMap<ChannelId, Option<ThreadInfo>>> {
+        self.threads.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 pub struct Handler {
-    tx: Sender<DiscordMessage>,
-    allowed_channels: HashSet<ChannelId>,
+    tx: Sender<IngestEvent>,
+    intake: Arc<MessageIntake>,
+    commands: Commands,
+    /// Backfills this many days of history in every listened to channel of the guilds
+    /// the bot joins, when set.
+    backfill_on_join: Option<(Backfiller, u32)>,
+    /// Recovers the messages missed while disconnected, going back at most this many
+    /// days, when set.
+    gap_recovery: Option<(Backfiller, u32)>,
+    highlight_emoji: HighlightEmoji,
+    /// Stores the scheduled events of guilds as they change, when set.
+    scheduled_events: Option<ScheduledEvents>,
 }
 
 impl Handler {
-    pub fn new(tx: Sender<DiscordMessage>, allowed_channels: HashSet<ChannelId>) -> Self {
+    pub fn new(tx: Sender<IngestEvent>, intake: Arc<MessageIntake>, commands: Commands) -> Self {
         Self {
             tx,
-            allowed_channels,
+            intake,
+            commands,
+            backfill_on_join: None,
+            gap_recovery: None,
+            highlight_emoji: HighlightEmoji::default(),
+            scheduled_events: None,
+        }
+    }
+
+    /// Keeps track of the scheduled events of guilds as they are created, start, end
+    /// and get canceled.
+    pub fn with_scheduled_events(mut self, events: ScheduledEvents) -> Self {
+        self.scheduled_events = Some(events);
+        self
+    }
+
+    /// Backfills `days` of history when the bot joins a guild, so that it does not start
+    /// from an empty log.
+    pub fn with_backfill_on_join(mut self, backfiller: Backfiller, days: u32) -> Self {
+        self.backfill_on_join = Some((backfiller, days));
+        self
+    }
+
+    /// Fetches the messages posted while the bot was disconnected whenever it connects,
+    /// going back at most `max_days` days.
+    pub fn with_gap_recovery(mut self, backfiller: Backfiller, max_days: u32) -> Self {
+        self.gap_recovery = Some((backfiller, max_days));
+        self
+    }
+
+    /// Highlights messages that members react to with any of `emoji`, to quote them in
+    /// daily digests.
+    pub fn with_highlight_emoji(mut self, emoji: HighlightEmoji) -> Self {
+        self.highlight_emoji = emoji;
+        self
+    }
+
+    /// Whether a member other than the bot reacted to a message with a highlight emoji.
+    fn is_highlighted(&self, msg: &Message) -> bool {
+        msg.reactions.iter().any(|reaction| {
+            self.highlight_emoji.matches(&reaction.reaction_type)
+                && reaction.count > u64::from(reaction.me)
+        })
+    }
+
+    async fn forward_highlighted(&self, message_id: MessageId, highlighted: bool) {
+        let highlight = IngestEvent::Highlighted {
+            message_id,
+            highlighted,
+        };
+        if let Err(e) = self.tx.send(highlight).await {
+            error!("Could not send highlight tx over channel: {e}");
+        }
+    }
+
+    async fn forward_reactions(&self, message_id: MessageId, change: ReactionChange) {
+        let reactions = IngestEvent::Reactions { message_id, change };
+        if let Err(e) = self.tx.send(reactions).await {
+            error!("Could not send reactions tx over channel: {e}");
+        }
+    }
+
+    async fn forward_deleted(&self, message_ids: Vec<MessageId>) {
+        if let Err(e) = self.tx.send(IngestEvent::Deleted { message_ids }).await {
+            error!("Could not send deleted messages tx over channel: {e}");
         }
     }
 }
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn message(&self, _: Context, msg: Message) {
-        if !self.allowed_channels.contains(&msg.channel_id) {
+    async fn message(&self, ctx: Context, msg: Message) {
+        let Some(msg) = self.intake.accept(&ctx, msg).await else {
             return;
-        }
-        if let Err(e) = self.tx.send(DiscordMessage::Received(msg)).await {
+        };
+        let received = IngestEvent::Received(Box::new(msg));
+        if let Err(e) = self.tx.send(received).await {
             error!("Could not send received message tx over channel: {e}");
         }
     }
 
-    async fn ready(&self, _: Context, ready: Ready) {
+    /// Forwards new content of edited messages. Only messages that were logged are
+    /// updated, so they need no filtering here.
+    async fn message_update(
+        &self,
+        ctx: Context,
+        _: Option<Message>,
+        _: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        // Updates without content, such as links getting their embeds, are not edits.
+        let Some(content) = event.content else {
+            return;
+        };
+        let mentions = event.mentions.unwrap_or_default();
+        let edited = IngestEvent::Edited {
+            message_id: event.id,
+            content: self
+                .intake
+                .mentions
+                .resolve(&content, event.guild_id, &mentions, &ctx.cache),
+        };
+        if let Err(e) = self.tx.send(edited).await {
+            error!("Could not send edited message tx over channel: {e}");
+        }
+    }
+
+    async fn message_delete(
+        &self,
+        _: Context,
+        _: ChannelId,
+        deleted_message_id: MessageId,
+        _: Option<GuildId>,
+    ) {
+        self.forward_deleted(vec![deleted_message_id]).await;
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        _: Context,
+        _: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        _: Option<GuildId>,
+    ) {
+        self.forward_deleted(multiple_deleted_messages_ids).await;
+    }
+
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        if reaction.user_id == Some(ctx.cache.current_user().id) {
+            return;
+        }
+        self.forward_reactions(reaction.message_id, ReactionChange::Added)
+            .await;
+        if self.highlight_emoji.matches(&reaction.emoji) {
+            self.forward_highlighted(reaction.message_id, true).await;
+        }
+    }
+
+    async fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        if reaction.user_id == Some(ctx.cache.current_user().id) {
+            return;
+        }
+        self.forward_reactions(reaction.message_id, ReactionChange::Removed)
+            .await;
+        if self.highlight_emoji.matches(&reaction.emoji) {
+            // Other members may still have the message highlighted.
+            match reaction.message(&ctx.http).await {
+                Ok(msg) if self.is_highlighted(&msg) => {}
+                Ok(_) => self.forward_highlighted(reaction.message_id, false).await,
+                Err(e) => warn!(
+                    "Could not check whether message {} is still highlighted: {e}",
+                    reaction.message_id
+                ),
+            }
+        }
+    }
+
+    async fn reaction_remove_all(&self, _: Context, _: ChannelId, message_id: MessageId) {
+        self.forward_reactions(message_id, ReactionChange::Total(0))
+            .await;
+        self.forward_highlighted(message_id, false).await;
+    }
+
+    /// Moderators removing every reaction of one emoji do not say how many there were,
+    /// so the message's remaining reactions are counted again.
+    async fn reaction_remove_emoji(&self, ctx: Context, reaction: Reaction) {
+        let msg = match reaction.message(&ctx.http).await {
+            Ok(msg) => msg,
+            Err(e) => {
+                warn!(
+                    "Could not count the reactions of message {}: {e}",
+                    reaction.message_id
+                );
+                return;
+            }
+        };
+        let total = msg
+            .reactions
+            .iter()
+            .map(|reaction| reaction.count as i64 - i64::from(reaction.me))
+            .sum();
+        self.forward_reactions(msg.id, ReactionChange::Total(total))
+            .await;
+        if self.highlight_emoji.matches(&reaction.emoji) {
+            self.forward_highlighted(msg.id, self.is_highlighted(&msg))
+                .await;
+        }
+    }
+
+    /// Joins the threads and forum posts created in listened to channels, as private
+    /// threads only send their messages to members.
+    async fn thread_create(&self, ctx: Context, thread: GuildChannel) {
+        let Some(info) = ThreadInfo::from_channel(&thread) else {
+            return;
+        };
+        if !self
+            .intake
+            .allows_channel(Some(thread.guild_id), &info.parent_id)
+        {
+            return;
+        }
+        self.intake.known_threads().insert(thread.id, Some(info));
+        if let Err(e) = thread.id.join_thread(&ctx.http).await {
+            warn!("Could not join thread {}: {e}", thread.id);
+        }
+    }
+
+    /// Keeps the titles of renamed threads up to date.
+    async fn thread_update(&self, _: Context, _: Option<GuildChannel>, new: GuildChannel) {
+        if let Some(info) = ThreadInfo::from_channel(&new) {
+            self.intake.known_threads().insert(new.id, Some(info));
+        }
+    }
+
+    async fn thread_delete(
+        &self,
+        _: Context,
+        thread: PartialGuildChannel,
+        _: Option<GuildChannel>,
+    ) {
+        self.intake.known_threads().remove(&thread.id);
+    }
+
+    async fn guild_create(&self, ctx: Context, guild: Guild, is_new: Option<bool>) {
+        let Some((backfiller, days)) = &self.backfill_on_join else {
+            return;
+        };
+        if is_new != Some(true) {
+            return;
+        }
+        info!(
+            "Joined guild {}, backfilling {days} days of history",
+            guild.id
+        );
+        match backfiller.backfill_guild(&ctx, guild.id, *days).await {
+            Ok(count) => info!("Backfilled {count} messages of guild {}", guild.id),
+            Err(e) => error!("Could not backfill guild {}: {e}", guild.id),
+        }
+    }
+
+    async fn guild_scheduled_event_create(&self, _: Context, event: ScheduledEvent) {
+        if let Some(events) = &self.scheduled_events {
+            events.record(&event).await;
+        }
+    }
+
+    async fn guild_scheduled_event_update(&self, _: Context, event: ScheduledEvent) {
+        if let Some(events) = &self.scheduled_events {
+            events.record(&event).await;
+        }
+    }
+
+    async fn guild_scheduled_event_delete(&self, _: Context, event: ScheduledEvent) {
+        if let Some(events) = &self.scheduled_events {
+            events.canceled(&event).await;
+        }
+    }
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
         info!("{} is connected!", ready.user.name);
+        if let Err(e) = self.commands.register(&ctx.http).await {
+            error!("Could not register slash commands: {e}");
+        }
+        // Sessions that are resumed replay the events they missed, but a new session
+        // starts from scratch.
+        if let Some((backfiller, max_days)) = &self.gap_recovery {
+            match backfiller.recover_gaps(&ctx, *max_days).await {
+                Ok(0) => {}
+                Ok(count) => info!("Recovered {count} messages missed while disconnected"),
+                Err(e) => error!("Could not recover messages missed while disconnected: {e}"),
+            }
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Command(command) = interaction {
+            self.commands.handle(&ctx, &command).await;
+        }
     }
 }
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use eyre::{bail, eyre, WrapErr};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use sqlx::SqlitePool;
+use tracing::{error, warn};
+
+use crate::config::{EmailConfig, SmtpSecurity};
+use crate::db::{self, RollupTier};
+use crate::templates::{DigestTarget, DigestTemplates, DigestView};
+
+use super::sinks::DigestSink;
+
+/// Emails digests to a list of recipients over SMTP, recording whether each of them was
+/// sent each daily digest.
+#[derive(Clone)]
+pub struct EmailSender {
+    db: Arc<SqlitePool>,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    recipients: Arc<Vec<Mailbox>>,
+}
+
+impl EmailSender {
+    /// Creates a sender emailing `recipients` through the server of the config, or
+    /// `None` if there are no recipients.
+    pub fn from_config(
+        db: Arc<SqlitePool>,
+        config: &EmailConfig,
+        recipients: &[String],
+    ) -> eyre::Result<Option<Self>> {
+        if recipients.is_empty() {
+            return Ok(None);
+        }
+        let Some(host) = config.smtp_host.as_deref() else {
+            bail!("email.smtp_host must be set to email digests");
+        };
+        let from = config
+            .from
+            .as_deref()
+            .ok_or_else(|| eyre!("email.from must be set to email digests"))?
+            .parse::<Mailbox>()
+            .wrap_err("Invalid email.from address")?;
+        let recipients = recipients
+            .iter()
+            .map(|recipient| {
+                recipient
+                    .parse::<Mailbox>()
+                    .wrap_err_with(|| format!("Invalid email recipient {recipient:?}"))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        let mut builder = match config.security {
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        if let Some(port) = config.smtp_port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = &config.username {
+            let password = config.password().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        Ok(Some(Self {
+            db,
+            transport: builder.build(),
+            from,
+            recipients: Arc::new(recipients),
+        }))
+    }
+
+    async fn send(
+        &self,
+        recipient: &Mailbox,
+        subject: &str,
+        plain: &str,
+        html: &str,
+    ) -> eyre::Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(recipient.clone())
+            .subject(subject)
+            .multipart(MultiPart::alternative_plain_html(
+                plain.to_string(),
+                html.to_string(),
+            ))?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DigestSink for EmailSender {
+    fn name(&self) -> String {
+        format!("{} email recipients", self.recipients.len())
+    }
+
+    /// Emails a digest to every recipient, rendered with the email templates and with
+    /// the Discord rendering as its plain text alternative. Whether each email was sent
+    /// is recorded for daily digests.
+    async fn deliver(&self, templates: &DigestTemplates, digest: &DigestView) -> eyre::Result<()> {
+        let subject = templates.render(DigestTarget::EmailSubject, digest);
+        let html = templates.render(DigestTarget::Email, digest);
+        let plain = templates.render(DigestTarget::Discord, digest);
+        let mut failed = 0;
+        for recipient in self.recipients.iter() {
+            let error = match self.send(recipient, &subject, &plain, &html).await {
+                Ok(()) => None,
+                Err(e) => {
+                    warn!("Could not email digest to {recipient}: {e:#}");
+                    failed += 1;
+                    Some(format!("{e:#}"))
+                }
+            };
+            let Some(digest_id) = digest
+                .id
+                .filter(|_| digest.tier == RollupTier::Daily.name())
+            else {
+                continue;
+            };
+            if let Err(e) = db::insert_email_delivery(
+                &self.db,
+                digest_id,
+                &recipient.to_string(),
+                error.as_deref(),
+            )
+            .await
+            {
+                error!("Could not record the email of digest {digest_id} to {recipient}: {e}");
+            }
+        }
+        if failed > 0 {
+            bail!("{failed} of {} emails were not sent", self.recipients.len());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::time::interval;
+use tracing::{error, info};
+
+use crate::db::{self, ContentKind};
+use crate::gpt::{cosine_similarity, Embedder};
+
+/// How many summaries and digests to embed per database round trip.
+const EMBED_BATCH_SIZE: i64 = 50;
+
+/// Periodically embeds the summaries and digests that have no embedding from the
+/// configured model yet, such as those stored before embeddings were enabled or whose
+/// embedding failed when they were stored.
+pub struct EmbeddingService {
+    db: Arc<SqlitePool>,
+    embedder: Arc<dyn Embedder>,
+    interval: Duration,
+}
+
+impl EmbeddingService {
+    pub fn new(db: Arc<SqlitePool>, embedder: Arc<dyn Embedder>, interval_seconds: u64) -> Self {
+        Self {
+            db,
+            embedder,
+            interval: Duration::from_secs(interval_seconds),
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval_timer = interval(self.interval);
+        loop {
+            interval_timer.tick().await;
+            self.embed_missing().await;
+        }
+    }
+
+    async fn embed_missing(&self) {
+        let model = self.embedder.model();
+        loop {
+            let batch = match db::fetch_unembedded_content(&self.db, model, EMBED_BATCH_SIZE).await
+            {
+                Ok(batch) => batch,
+                Err(e) => {
+                    error!("Could not fetch content to embed: {e}");
+                    return;
+                }
+            };
+            if batch.is_empty() {
+                return;
+            }
+            info!("Embedding {} summaries and digests", batch.len());
+            for content in &batch {
+                let Some(kind) = ContentKind::parse(&content.kind) else {
+                    continue;
+                };
+                let vector = match self.embedder.embed(&content.text).await {
+                    Ok(vector) => vector,
+                    Err(e) => {
+                        // Try again on the next run rather than hammering a failing API.
+                        error!("Could not embed {} {}: {e}", content.kind, content.id);
+                        return;
+                    }
+                };
+                if let Err(e) =
+                    db::upsert_embedding(&self.db, kind, content.id, model, &vector).await
+                {
+                    error!(
+                        "Could not store embedding of {} {}: {e}",
+                        content.kind, content.id
+                    );
+                    return;
+                }
+            }
+            if (batch.len() as i64) < EMBED_BATCH_SIZE {
+                return;
+            }
+        }
+    }
+}
+
+/// Embeds a summary or digest right after it is stored. Failures are only logged, as
+/// the embedding service picks up whatever is missing an embedding on its next run.
+pub async fn embed_content(
+    db: &SqlitePool,
+    embedder: &dyn Embedder,
+    kind: ContentKind,
+    id: i64,
+    text: &str,
+) {
+    let vector = match embedder.embed(text).await {
+        Ok(vector) => vector,
+        Err(e) => {
+            error!("Could not embed {} {id}: {e}", kind.as_str());
+            return;
+        }
+    };
+    if let Err(e) = db::upsert_embedding(db, kind, id, embedder.model(), &vector).await {
+        error!("Could not store embedding of {} {id}: {e}", kind.as_str());
+    }
+}
+
+/// A stored summary or digest matching a search, with its cosine similarity to it.
+#[derive(Serialize)]
+pub struct SearchResult {
+    pub kind: ContentKind,
+    pub id: i64,
+    pub text: String,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub covers_from: Option<DateTime<Utc>>,
+    pub covers_to: Option<DateTime<Utc>>,
+    pub score: f32,
+}
+
+/// Finds the `limit` summaries and digests of a guild closest in meaning to `query`,
+/// most similar first.
+pub async fn search(
+    db: &SqlitePool,
+    embedder: &dyn Embedder,
+    query: &str,
+    guild_id: Option<i64>,
+    limit: usize,
+) -> eyre::Result<Vec<SearchResult>> {
+    let query = embedder.embed(query).await?;
+    let candidates = db::fetch_embedded_content(db, embedder.model(), guild_id).await?;
+    let mut results: Vec<SearchResult> = candidates
+        .into_iter()
+        .filter_map(|content| {
+            let score = cosine_similarity(&query, &content.vector());
+            Some(SearchResult {
+                kind: ContentKind::parse(&content.kind)?,
+                id: content.id,
+                text: content.text,
+                guild_id: content.guild_id,
+                channel_id: content.channel_id,
+                covers_from: content.covers_from,
+                covers_to: content.covers_to,
+                score,
+            })
+        })
+        .collect();
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results.truncate(limit);
+    Ok(results)
+}
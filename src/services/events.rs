@@ -0,0 +1,157 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::error;
+
+use crate::db::{ContentFilter, RollupTier};
+
+/// How many events a slow subscriber can fall behind before it starts missing some.
+const EVENT_BUFFER: usize = 64;
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Summary,
+    DailyDigest,
+    WeeklyDigest,
+    MonthlyDigest,
+    Status,
+}
+
+impl EventKind {
+    pub fn digest_of(tier: RollupTier) -> Self {
+        match tier {
+            RollupTier::Daily => EventKind::DailyDigest,
+            RollupTier::Weekly => EventKind::WeeklyDigest,
+            RollupTier::Monthly => EventKind::MonthlyDigest,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Summary => "summary",
+            EventKind::DailyDigest => "daily_digest",
+            EventKind::WeeklyDigest => "weekly_digest",
+            EventKind::MonthlyDigest => "monthly_digest",
+            EventKind::Status => "status",
+        }
+    }
+}
+
+/// A newly created summary or digest, as returned by the HTTP API, or a change in the
+/// health of a service.
+#[derive(Clone, Serialize)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub data: serde_json::Value,
+}
+
+impl Event {
+    /// Whether a subscriber only interested in a guild or channel should get the event.
+    /// Status events concern every subscriber.
+    pub fn matches(&self, filter: &ContentFilter) -> bool {
+        if let EventKind::Status = self.kind {
+            return true;
+        }
+        filter.guild_id.is_none_or(|id| self.guild_id == Some(id))
+            && filter
+                .channel_id
+                .is_none_or(|id| self.channel_id == Some(id))
+    }
+}
+
+#[derive(Serialize)]
+struct ServiceStatus<'a> {
+    service: &'a str,
+    healthy: bool,
+    message: &'a str,
+}
+
+/// Broadcasts newly created summaries and digests to live subscribers, such as the
+/// `/events` stream and `/ws` clients.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        Self { tx }
+    }
+
+    /// Sends an event to every current subscriber. Events published while nobody is
+    /// subscribed are dropped.
+    pub fn publish<T: Serialize>(
+        &self,
+        kind: EventKind,
+        guild_id: Option<i64>,
+        channel_id: Option<i64>,
+        data: &T,
+    ) {
+        let data = match serde_json::to_value(data) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Could not serialize {} event: {e}", kind.as_str());
+                return;
+            }
+        };
+        let _ = self.tx.send(Event {
+            kind,
+            guild_id,
+            channel_id,
+            data,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Publishes a status event every time a service fails, and once when it recovers.
+pub struct ServiceHealth {
+    events: EventBus,
+    service: String,
+    failing: AtomicBool,
+}
+
+impl ServiceHealth {
+    pub fn new(events: EventBus, service: impl Into<String>) -> Self {
+        Self {
+            events,
+            service: service.into(),
+            failing: AtomicBool::new(false),
+        }
+    }
+
+    pub fn failed(&self, message: &str) {
+        self.failing.store(true, Ordering::Relaxed);
+        self.publish(false, message);
+    }
+
+    pub fn succeeded(&self) {
+        if self.failing.swap(false, Ordering::Relaxed) {
+            self.publish(true, "Recovered");
+        }
+    }
+
+    fn publish(&self, healthy: bool, message: &str) {
+        let status = ServiceStatus {
+            service: &self.service,
+            healthy,
+            message,
+        };
+        self.events.publish(EventKind::Status, None, None, &status);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use eyre::{eyre, WrapErr};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tokio::time::interval;
+use tracing::{error, info};
+
+use crate::db::{self, FaqSource, FaqUpdate};
+use crate::gpt::Summarizer;
+
+/// Instructions asking the model which questions of the summaries belong in the FAQ.
+const FAQ_INSTRUCTIONS: &str = r#"You maintain the FAQ of a Discord community, so that members can find answers without asking again. You are given the current FAQ, each question with its ID, followed by summaries of recent discussions. Find the questions members asked in the summaries that got an answer, and that others are likely to ask too. Leave out questions that were not answered and those only about one person's situation. When a question is already in the FAQ, give its ID and its answer updated with what the summaries add. Reply with a single JSON object and nothing else, in this format:
+{
+  "questions": [
+    {
+      "id": ID of the question in the FAQ, or null for a new question,
+      "question": "the question, phrased as a member would ask it",
+      "answer": "the answer, in one to three sentences"
+    }
+  ]
+}"#;
+
+/// How many summaries are mined per run.
+const MINE_BATCH_SIZE: i64 = 200;
+/// How many summaries are sent to the model at once.
+const SUMMARIES_PER_REQUEST: usize = 25;
+/// Most questions of the current FAQ sent to the model along with the summaries.
+const MAX_KNOWN_QUESTIONS: usize = 200;
+
+#[derive(Deserialize)]
+struct FaqReply {
+    #[serde(default)]
+    questions: Vec<FaqReplyEntry>,
+}
+
+#[derive(Deserialize)]
+struct FaqReplyEntry {
+    id: Option<i64>,
+    question: String,
+    answer: String,
+}
+
+impl FaqReply {
+    /// Parses the model's reply, tolerating Markdown code fences or text around the
+    /// JSON object.
+    fn parse(reply: &str) -> eyre::Result<Vec<FaqUpdate>> {
+        let start = reply.find('{');
+        let end = reply.rfind('}');
+        let json = match start.zip(end) {
+            Some((start, end)) if start < end => &reply[start..=end],
+            _ => return Err(eyre!("Reply does not contain a JSON object")),
+        };
+        let parsed: Self = serde_json::from_str(json).wrap_err("Reply is not a valid FAQ")?;
+        Ok(parsed
+            .questions
+            .into_iter()
+            .map(|entry| FaqUpdate {
+                id: entry.id,
+                question: entry.question.trim().to_string(),
+                answer: entry.answer.trim().to_string(),
+            })
+            .filter(|update| !update.question.is_empty() && !update.answer.is_empty())
+            .collect())
+    }
+}
+
+/// Periodically mines the summaries stored since its previous run for the questions
+/// members ask and the answers they get, and keeps the FAQ of each guild up to date
+/// with them.
+pub struct FaqService {
+    db: Arc<SqlitePool>,
+    summarizer: Arc<dyn Summarizer>,
+    interval: Duration,
+}
+
+impl FaqService {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        summarizer: Arc<dyn Summarizer>,
+        interval_seconds: u64,
+    ) -> Self {
+        Self {
+            db,
+            summarizer,
+            interval: Duration::from_secs(interval_seconds),
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval_timer = interval(self.interval);
+        loop {
+            interval_timer.tick().await;
+            let sources = match db::fetch_unmined_faq_sources(&self.db, MINE_BATCH_SIZE).await {
+                Ok(sources) => sources,
+                Err(e) => {
+                    error!("Could not fetch summaries to mine for the FAQ: {e}");
+                    continue;
+                }
+            };
+            let mut sources_by_guild: BTreeMap<Option<i64>, Vec<FaqSource>> = BTreeMap::new();
+            for source in sources {
+                sources_by_guild
+                    .entry(source.guild_id)
+                    .or_default()
+                    .push(source);
+            }
+            for (guild_id, sources) in sources_by_guild {
+                for batch in sources.chunks(SUMMARIES_PER_REQUEST) {
+                    if let Err(e) = self.mine(guild_id, batch).await {
+                        error!(
+                            "Could not mine the summaries of guild {guild_id:?} for the FAQ: {e:#}"
+                        );
+                        // The rest of the guild's summaries would be mined out of order.
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds the questions found in a batch of a guild's summaries to its FAQ.
+    async fn mine(&self, guild_id: Option<i64>, sources: &[FaqSource]) -> eyre::Result<()> {
+        let mut known = db::fetch_guild_faq(&self.db, guild_id).await?;
+        known.sort_by_key(|entry| std::cmp::Reverse(entry.asked_count));
+        let mut text = String::from("Current FAQ:");
+        if known.is_empty() {
+            text.push_str("\n(empty)");
+        }
+        for entry in known.iter().take(MAX_KNOWN_QUESTIONS) {
+            text.push_str(&format!(
+                "\n[{}] Q: {}\nA: {}",
+                entry.id, entry.question, entry.answer
+            ));
+        }
+        text.push_str("\n\nSummaries:");
+        for source in sources {
+            text.push_str(&format!("\n\n{}", source.text));
+        }
+        let reply = self.summarizer.complete(FAQ_INSTRUCTIONS, &text).await?;
+        let updates = FaqReply::parse(&reply)?;
+        let summary_ids: Vec<i64> = sources.iter().map(|source| source.id).collect();
+        db::apply_faq_updates(&self.db, guild_id, &updates, &summary_ids).await?;
+        info!(
+            "Mined {} summaries of guild {guild_id:?} into {} FAQ questions",
+            sources.len(),
+            updates.len()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use eyre::{bail, eyre};
+use reqwest::header::{ACCEPT, USER_AGENT};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serenity::all::GuildId;
+use tracing::warn;
+
+use crate::config::GithubConfig;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const PAGE_SIZE: u32 = 100;
+/// Pages of issues fetched per repository, newest first, which is plenty for a day.
+const MAX_PAGES: u32 = 5;
+/// Most pull requests, issues or releases listed per repository.
+const MAX_LISTED: usize = 10;
+
+/// An issue or pull request, which the issues API lists together.
+#[derive(Deserialize)]
+struct Issue {
+    number: u64,
+    title: String,
+    html_url: String,
+    user: Option<Account>,
+    created_at: DateTime<Utc>,
+    /// Only set on pull requests.
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    name: Option<String>,
+    tag_name: String,
+    html_url: String,
+    author: Option<Account>,
+    published_at: Option<DateTime<Utc>>,
+    draft: bool,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    login: String,
+}
+
+/// A pull request, issue or release of a repository.
+pub struct ActivityItem {
+    pub title: String,
+    pub url: String,
+    pub author: Option<String>,
+    /// The number of pull requests and issues.
+    pub number: Option<u64>,
+}
+
+/// What was opened or published in a repository during a digest's period.
+pub struct RepoActivity {
+    pub repo: String,
+    pub pull_requests: Vec<ActivityItem>,
+    pub issues: Vec<ActivityItem>,
+    pub releases: Vec<ActivityItem>,
+}
+
+impl RepoActivity {
+    fn is_empty(&self) -> bool {
+        self.pull_requests.is_empty() && self.issues.is_empty() && self.releases.is_empty()
+    }
+}
+
+/// Fetches the pull requests, issues and releases of the configured repositories
+/// through the GitHub REST API, for the "Repository activity" section of daily digests.
+#[derive(Clone)]
+pub struct GithubActivity {
+    client: reqwest::Client,
+    api_url: Arc<String>,
+    token: Option<Arc<String>>,
+    repos: Arc<Vec<String>>,
+    guilds: Arc<Vec<GuildId>>,
+}
+
+impl GithubActivity {
+    /// Creates a client for the repositories of the config, or `None` if there are
+    /// none.
+    pub fn from_config(config: &GithubConfig) -> eyre::Result<Option<Self>> {
+        if config.repos.is_empty() {
+            return Ok(None);
+        }
+        for repo in &config.repos {
+            let valid = repo.split_once('/').is_some_and(|(owner, name)| {
+                !owner.is_empty() && !name.is_empty() && !name.contains('/')
+            });
+            if !valid {
+                bail!("invalid GitHub repository {repo:?}: expected owner/name");
+            }
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Ok(Some(Self {
+            client,
+            api_url: Arc::new(config.api_url.trim_end_matches('/').to_string()),
+            token: config.token().map(Arc::new),
+            repos: Arc::new(config.repos.clone()),
+            guilds: Arc::new(config.guild_ids()?),
+        }))
+    }
+
+    /// Whether the digests of a guild list the activity.
+    pub fn covers(&self, guild_id: Option<i64>) -> bool {
+        self.guilds.is_empty()
+            || guild_id.is_some_and(|guild_id| {
+                self.guilds
+                    .iter()
+                    .any(|guild| guild.get() as i64 == guild_id)
+            })
+    }
+
+    /// The activity of every repository from `from` to `to`. Repositories that could
+    /// not be fetched are left out.
+    pub async fn fetch(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<RepoActivity> {
+        let mut activity = vec![];
+        for repo in self.repos.iter() {
+            match self.repo_activity(repo, from, to).await {
+                Ok(repo_activity) => activity.push(repo_activity),
+                Err(e) => warn!("Could not fetch the activity of GitHub repository {repo}: {e:#}"),
+            }
+        }
+        activity
+    }
+
+    async fn repo_activity(
+        &self,
+        repo: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> eyre::Result<RepoActivity> {
+        let mut activity = RepoActivity {
+            repo: repo.to_string(),
+            pull_requests: vec![],
+            issues: vec![],
+            releases: vec![],
+        };
+        for issue in self.opened_issues(repo, from, to).await? {
+            let item = ActivityItem {
+                title: issue.title,
+                url: issue.html_url,
+                author: issue.user.map(|user| user.login),
+                number: Some(issue.number),
+            };
+            match issue.pull_request {
+                Some(_) => activity.pull_requests.push(item),
+                None => activity.issues.push(item),
+            }
+        }
+        let releases: Vec<Release> = self
+            .get(
+                &format!("repos/{repo}/releases"),
+                &[("per_page", PAGE_SIZE.to_string())],
+            )
+            .await?;
+        activity.releases = releases
+            .into_iter()
+            .filter(|release| {
+                !release.draft
+                    && release
+                        .published_at
+                        .is_some_and(|published_at| published_at >= from && published_at < to)
+            })
+            .map(|release| ActivityItem {
+                title: release
+                    .name
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or(release.tag_name),
+                url: release.html_url,
+                author: release.author.map(|author| author.login),
+                number: None,
+            })
+            .collect();
+        Ok(activity)
+    }
+
+    /// The issues and pull requests opened from `from` to `to`, oldest first.
+    async fn opened_issues(
+        &self,
+        repo: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> eyre::Result<Vec<Issue>> {
+        let mut opened = vec![];
+        for page in 1..=MAX_PAGES {
+            // Issues opened since `from` were also updated since then, which the API
+            // can filter on.
+            let issues: Vec<Issue> = self
+                .get(
+                    &format!("repos/{repo}/issues"),
+                    &[
+                        ("state", "all".to_string()),
+                        ("sort", "created".to_string()),
+                        ("direction", "desc".to_string()),
+                        ("since", from.to_rfc3339()),
+                        ("per_page", PAGE_SIZE.to_string()),
+                        ("page", page.to_string()),
+                    ],
+                )
+                .await?;
+            let last_page = issues.len() < PAGE_SIZE as usize
+                || issues.last().is_some_and(|issue| issue.created_at < from);
+            opened.extend(
+                issues
+                    .into_iter()
+                    .filter(|issue| issue.created_at >= from && issue.created_at < to),
+            );
+            if last_page {
+                break;
+            }
+        }
+        opened.reverse();
+        Ok(opened)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> eyre::Result<T> {
+        let mut request = self
+            .client
+            .get(format!("{}/{path}", self.api_url))
+            .query(query)
+            .header(ACCEPT, "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            // The API rejects requests without a user agent.
+            .header(USER_AGENT, "daily-discord-summarizer");
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token.as_str());
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("{path} responded with {status}");
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| eyre!("unexpected response to {path}: {e}"))
+    }
+}
+
+/// Renders the "Repository activity" section appended to daily digests, or `None` when
+/// nothing was opened or published.
+pub fn repository_activity_section(activity: &[RepoActivity]) -> Option<String> {
+    let mut section = String::from("**Repository activity**");
+    let mut listed = false;
+    for repo in activity.iter().filter(|repo| !repo.is_empty()) {
+        listed = true;
+        section.push_str(&format!("\n__{}__", repo.repo));
+        for (kind, items) in [
+            ("Pull request", &repo.pull_requests),
+            ("Issue", &repo.issues),
+            ("Release", &repo.releases),
+        ] {
+            for item in items.iter().take(MAX_LISTED) {
+                // Wrapping the URL in <> keeps Discord from embedding every link.
+                let mut line = match item.number {
+                    Some(number) => {
+                        format!("\n- {kind} [#{number} {}](<{}>)", item.title, item.url)
+                    }
+                    None => format!("\n- {kind} [{}](<{}>)", item.title, item.url),
+                };
+                if let Some(author) = &item.author {
+                    line.push_str(&format!(" by {author}"));
+                }
+                section.push_str(&line);
+            }
+            if items.len() > MAX_LISTED {
+                section.push_str(&format!(
+                    "\n- ...and {} more {}s",
+                    items.len() - MAX_LISTED,
+                    kind.to_lowercase()
+                ));
+            }
+        }
+    }
+    listed.then_some(section)
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use eyre::{eyre, WrapErr};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tokio::time::interval;
+use tracing::{error, info};
+
+use crate::db::{self, GlossarySource, GlossaryTerm, GlossaryUpdate};
+use crate::gpt::Summarizer;
+
+/// Instructions asking the model for the jargon of the summaries.
+const GLOSSARY_INSTRUCTIONS: &str = r#"You maintain the glossary of a Discord community, so that newcomers can follow its discussions. You are given the terms already in the glossary, followed by summaries of recent discussions. Find the jargon, acronyms, code names and community-specific terms the summaries use as if everyone knew them, leaving out common words and general technical terms any newcomer would know. Define each in one sentence from how the summaries use it. Only include a term already in the glossary when the summaries change what it means. Reply with a single JSON object and nothing else, in this format:
+{
+  "terms": [
+    {"term": "the term, as written in the summaries", "definition": "what it means in this community"}
+  ]
+}"#;
+
+/// How many summaries are mined per run.
+const MINE_BATCH_SIZE: i64 = 200;
+/// How many summaries are sent to the model at once.
+const SUMMARIES_PER_REQUEST: usize = 25;
+/// Most terms of the current glossary sent to the model along with the summaries.
+const MAX_KNOWN_TERMS: usize = 300;
+/// Most new terms listed in a weekly digest.
+const MAX_LISTED: usize = 15;
+
+#[derive(Deserialize)]
+struct GlossaryReply {
+    #[serde(default)]
+    terms: Vec<GlossaryReplyTerm>,
+}
+
+#[derive(Deserialize)]
+struct GlossaryReplyTerm {
+    term: String,
+    definition: String,
+}
+
+impl GlossaryReply {
+    /// Parses the model's reply, tolerating Markdown code fences or text around the
+    /// JSON object.
+    fn parse(reply: &str) -> eyre::Result<Vec<GlossaryUpdate>> {
+        let start = reply.find('{');
+        let end = reply.rfind('}');
+        let json = match start.zip(end) {
+            Some((start, end)) if start < end => &reply[start..=end],
+            _ => return Err(eyre!("Reply does not contain a JSON object")),
+        };
+        let parsed: Self = serde_json::from_str(json).wrap_err("Reply is not a valid glossary")?;
+        Ok(parsed
+            .terms
+            .into_iter()
+            .map(|term| GlossaryUpdate {
+                term: term.term.trim().to_string(),
+                definition: term.definition.trim().to_string(),
+            })
+            .filter(|update| !update.term.is_empty() && !update.definition.is_empty())
+            .collect())
+    }
+}
+
+/// Periodically mines the summaries stored since its previous run for the jargon and
+/// acronyms of each guild, and keeps its glossary up to date with their definitions.
+pub struct GlossaryService {
+    db: Arc<SqlitePool>,
+    summarizer: Arc<dyn Summarizer>,
+    interval: Duration,
+}
+
+impl GlossaryService {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        summarizer: Arc<dyn Summarizer>,
+        interval_seconds: u64,
+    ) -> Self {
+        Self {
+            db,
+            summarizer,
+            interval: Duration::from_secs(interval_seconds),
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval_timer = interval(self.interval);
+        loop {
+            interval_timer.tick().await;
+            let sources = match db::fetch_unmined_glossary_sources(&self.db, MINE_BATCH_SIZE).await
+            {
+                Ok(sources) => sources,
+                Err(e) => {
+                    error!("Could not fetch summaries to mine for the glossary: {e}");
+                    continue;
+                }
+            };
+            let mut sources_by_guild: BTreeMap<Option<i64>, Vec<GlossarySource>> = BTreeMap::new();
+            for source in sources {
+                sources_by_guild
+                    .entry(source.guild_id)
+                    .or_default()
+                    .push(source);
+            }
+            for (guild_id, sources) in sources_by_guild {
+                for batch in sources.chunks(SUMMARIES_PER_REQUEST) {
+                    if let Err(e) = self.mine(guild_id, batch).await {
+                        error!(
+                            "Could not mine the summaries of guild {guild_id:?} for the glossary: {e:#}"
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds the terms found in a batch of a guild's summaries to its glossary.
+    async fn mine(&self, guild_id: Option<i64>, sources: &[GlossarySource]) -> eyre::Result<()> {
+        let known = db::fetch_guild_glossary(&self.db, guild_id).await?;
+        let mut text = String::from("Glossary:");
+        if known.is_empty() {
+            text.push_str("\n(empty)");
+        }
+        for term in known.iter().take(MAX_KNOWN_TERMS) {
+            text.push_str(&format!("\n- {}: {}", term.term, term.definition));
+        }
+        text.push_str("\n\nSummaries:");
+        for source in sources {
+            text.push_str(&format!("\n\n{}", source.text));
+        }
+        let reply = self
+            .summarizer
+            .complete(GLOSSARY_INSTRUCTIONS, &text)
+            .await?;
+        let updates = GlossaryReply::parse(&reply)?;
+        let summary_ids: Vec<i64> = sources.iter().map(|source| source.id).collect();
+        db::apply_glossary_updates(&self.db, guild_id, &updates, &summary_ids).await?;
+        info!(
+            "Mined {} summaries of guild {guild_id:?} into {} glossary terms",
+            sources.len(),
+            updates.len()
+        );
+        Ok(())
+    }
+}
+
+/// Renders the "New terms this week" section of weekly digests, or `None` when no term
+/// was added to the glossary.
+pub fn new_terms_section(terms: &[GlossaryTerm]) -> Option<String> {
+    if terms.is_empty() {
+        return None;
+    }
+    let mut section = String::from("**New terms this week**");
+    for term in terms.iter().take(MAX_LISTED) {
+        section.push_str(&format!("\n- **{}**: {}", term.term, term.definition));
+    }
+    if terms.len() > MAX_LISTED {
+        section.push_str(&format!("\n- ...and {} more", terms.len() - MAX_LISTED));
+    }
+    Some(section)
+}
@@ -0,0 +1,88 @@
+use chrono_tz::Tz;
+use serenity::all::ReactionType;
+
+use crate::db::Highlight;
+
+/// Most highlights quoted in a digest.
+const MAX_LISTED: usize = 10;
+/// Highlights longer than this are cut short.
+const MAX_HIGHLIGHT_CHARS: usize = 500;
+
+/// The emoji members react with to highlight a message.
+#[derive(Clone, Default)]
+pub struct HighlightEmoji {
+    emoji: Vec<String>,
+}
+
+impl HighlightEmoji {
+    /// Unicode emoji, or the names of custom emoji, with or without colons.
+    pub fn new(emoji: &[String]) -> Self {
+        Self {
+            emoji: emoji
+                .iter()
+                .map(|emoji| normalize(emoji.trim_matches(':')))
+                .collect(),
+        }
+    }
+
+    pub fn matches(&self, reaction: &ReactionType) -> bool {
+        let name = match reaction {
+            ReactionType::Unicode(emoji) => normalize(emoji),
+            ReactionType::Custom {
+                name: Some(name), ..
+            } => name.clone(),
+            _ => return false,
+        };
+        self.emoji.contains(&name)
+    }
+}
+
+/// Drops the variation selector some clients add to emoji such as ⭐️.
+fn normalize(emoji: &str) -> String {
+    emoji.replace('\u{FE0F}', "")
+}
+
+/// Link opening a message in Discord.
+pub fn jump_link(guild_id: Option<i64>, channel_id: i64, message_id: i64) -> String {
+    let guild = guild_id.map_or_else(|| "@me".to_string(), |id| id.to_string());
+    format!("https://discord.com/channels/{guild}/{channel_id}/{message_id}")
+}
+
+/// Renders the "Highlights" section quoting the highlighted messages in daily digests,
+/// or `None` when nothing was highlighted.
+pub fn highlights_section(highlights: &[Highlight], timezone: Tz) -> Option<String> {
+    if highlights.is_empty() {
+        return None;
+    }
+    let mut section = String::from("**Highlights**");
+    for highlight in highlights.iter().take(MAX_LISTED) {
+        let content = match highlight.content.char_indices().nth(MAX_HIGHLIGHT_CHARS) {
+            Some((end, _)) => format!("{}...", &highlight.content[..end]),
+            None => highlight.content.clone(),
+        };
+        section.push('\n');
+        for line in content.lines() {
+            section.push_str(&format!("\n> {line}"));
+        }
+        // Messages of threads are stored under their parent channel, but only open
+        // from the thread.
+        let channel_id = highlight.thread_id.unwrap_or(highlight.channel_id);
+        section.push_str(&format!(
+            "\n— {} in <#{}>, {} ([jump](<{}>))",
+            highlight.author,
+            highlight.channel_id,
+            highlight
+                .timestamp
+                .with_timezone(&timezone)
+                .format("%b %-d, %H:%M"),
+            jump_link(highlight.guild_id, channel_id, highlight.message_id)
+        ));
+    }
+    if highlights.len() > MAX_LISTED {
+        section.push_str(&format!(
+            "\n\n...and {} more",
+            highlights.len() - MAX_LISTED
+        ));
+    }
+    Some(section)
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::config::IngestConfig;
+use crate::db;
+
+use super::sources::{IngestEvent, IngestedMessage};
+
+/// Most messages in a single request to `POST /ingest`.
+pub const MAX_INGEST_BATCH: usize = 1000;
+
+/// Discord snowflakes will not reach the top bit before 2084, so IDs made up for
+/// ingested messages, channels and authors have it set to never collide with them.
+const SYNTHETIC_ID_BIT: u64 = 1 << 63;
+
+/// A message of another system, such as a forum post or a support ticket, posted to
+/// `POST /ingest`.
+#[derive(Deserialize)]
+pub struct IngestPost {
+    pub author: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    /// Name of the channel, thread or queue the message belongs to, which it is
+    /// batched and summarized with.
+    pub channel: String,
+}
+
+#[derive(Serialize)]
+pub struct IngestReport {
+    pub accepted: usize,
+}
+
+#[derive(Debug)]
+pub enum IngestError {
+    /// A message has no author or channel.
+    Invalid,
+    /// The message log service stopped.
+    Closed,
+    Database,
+}
+
+/// Feeds messages posted to `POST /ingest` to the message log, as if they came from a
+/// message source. Each channel name is given an ID of its own, whose name is stored
+/// so that prompts can refer to the channel by it.
+#[derive(Clone)]
+pub struct HttpIngest {
+    db: Arc<SqlitePool>,
+    ingest_tx: mpsc::Sender<IngestEvent>,
+    guild_id: Option<GuildId>,
+}
+
+impl HttpIngest {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        ingest_tx: mpsc::Sender<IngestEvent>,
+        config: &IngestConfig,
+    ) -> eyre::Result<Self> {
+        Ok(Self {
+            db,
+            ingest_tx,
+            guild_id: config.guild_id()?,
+        })
+    }
+
+    /// Sends a batch of messages to the message log. Nothing is sent unless every
+    /// message is valid. Messages posted again are only logged once, as their ID is
+    /// derived from what they are made of.
+    pub async fn ingest(&self, posts: Vec<IngestPost>) -> Result<IngestReport, IngestError> {
+        if posts
+            .iter()
+            .any(|post| post.author.trim().is_empty() || post.channel.trim().is_empty())
+        {
+            return Err(IngestError::Invalid);
+        }
+        let channels: BTreeMap<String, ChannelId> = posts
+            .iter()
+            .map(|post| {
+                let name = post.channel.trim();
+                (
+                    name.to_string(),
+                    ChannelId::new(synthetic_id(&["channel", name])),
+                )
+            })
+            .collect();
+        for (name, channel_id) in &channels {
+            if let Err(e) = db::upsert_ingest_channel(&self.db, channel_id.get() as i64, name).await
+            {
+                error!("Could not store the name of ingested channel {name:?}: {e}");
+                return Err(IngestError::Database);
+            }
+        }
+
+        let accepted = posts.len();
+        for post in posts {
+            let channel = post.channel.trim();
+            let author = post.author.trim();
+            let message = IngestedMessage {
+                message_id: MessageId::new(synthetic_id(&[
+                    "message",
+                    channel,
+                    author,
+                    &post.timestamp.to_rfc3339(),
+                    &post.content,
+                ])),
+                guild_id: self.guild_id,
+                channel_id: channels[channel],
+                author_id: UserId::new(synthetic_id(&["author", author])),
+                author: author.to_string(),
+                content: post.content,
+                timestamp: post.timestamp,
+                reply_to: None,
+                thread: None,
+                attachments: vec![],
+            };
+            self.ingest_tx
+                .send(IngestEvent::Received(Box::new(message)))
+                .await
+                .map_err(|_| IngestError::Closed)?;
+        }
+        Ok(IngestReport { accepted })
+    }
+}
+
+/// Whether an ID was made up for an ingested message, channel or author rather than
+/// given by Discord.
+pub fn is_synthetic(id: u64) -> bool {
+    id & SYNTHETIC_ID_BIT != 0
+}
+
+/// Stable 64-bit FNV-1a hash of `parts`, with the top bit set.
+pub fn synthetic_id(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        // Parts are separated by a byte that cannot appear in UTF-8 text.
+        for byte in part.bytes().chain([0xff]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash | SYNTHETIC_ID_BIT
+}
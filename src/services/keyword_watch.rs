@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use eyre::{bail, WrapErr};
+use regex::Regex;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tracing::{error, info};
+
+use crate::config::{KeywordWatchConfig, WebhookEvent};
+use crate::db;
+
+use super::alerts::Alerts;
+use super::highlights::jump_link;
+
+/// Longest a quoted message gets in alerts posted to Discord.
+const MAX_QUOTE_CHARS: usize = 300;
+
+struct WatchRule {
+    name: String,
+    pattern: Regex,
+}
+
+/// A logged message that matched watch rules.
+pub struct WatchedMessage {
+    /// Row ID of the message.
+    pub id: i64,
+    pub message_id: i64,
+    pub guild_id: Option<i64>,
+    /// The parent channel for messages of threads.
+    pub channel_id: i64,
+    pub thread_id: Option<i64>,
+    pub author: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// What webhooks are sent about a message matching watch rules.
+#[derive(Serialize)]
+pub struct KeywordMatch {
+    /// Names of the rules the message matched.
+    pub rules: Vec<String>,
+    pub guild_id: Option<i64>,
+    pub channel_id: i64,
+    pub thread_id: Option<i64>,
+    pub message_id: i64,
+    pub author: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    pub link: String,
+    /// The messages posted just before it, oldest first.
+    pub context: Vec<db::ContextMessage>,
+}
+
+/// Alerts about messages mentioning watched keywords as soon as they are logged,
+/// without waiting for the next digest.
+#[derive(Clone)]
+pub struct KeywordWatch {
+    db: Arc<SqlitePool>,
+    alerts: Alerts,
+    rules: Arc<Vec<WatchRule>>,
+    context_messages: i64,
+}
+
+impl KeywordWatch {
+    pub fn from_config(
+        db: Arc<SqlitePool>,
+        alerts: Alerts,
+        config: &KeywordWatchConfig,
+    ) -> eyre::Result<Self> {
+        let mut rules = vec![];
+        for rule in &config.rules {
+            let mut alternatives: Vec<String> = rule
+                .keywords
+                .iter()
+                .map(|keyword| format!(r"(?i:\b{}\b)", regex::escape(keyword.trim())))
+                .collect();
+            alternatives.extend(rule.pattern.iter().map(|pattern| format!("(?:{pattern})")));
+            if alternatives.is_empty() {
+                bail!(
+                    "keyword_watch rule {:?} has neither keywords nor a pattern",
+                    rule.name
+                );
+            }
+            let pattern = Regex::new(&alternatives.join("|"))
+                .wrap_err_with(|| format!("Invalid keyword_watch rule {:?}", rule.name))?;
+            rules.push(WatchRule {
+                name: rule.name.clone(),
+                pattern,
+            });
+        }
+        Ok(Self {
+            db,
+            alerts,
+            rules: Arc::new(rules),
+            context_messages: config.context_messages.max(0),
+        })
+    }
+
+    /// Names of the rules `content` matches.
+    pub fn matching_rules(&self, content: &str) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(content))
+            .map(|rule| rule.name.clone())
+            .collect()
+    }
+
+    /// Alerts about a message that matched `rules`, along with the messages before it.
+    pub async fn alert(&self, rules: Vec<String>, message: WatchedMessage) {
+        let context = match db::fetch_messages_before(
+            &self.db,
+            message.channel_id,
+            message.thread_id,
+            message.id,
+            self.context_messages,
+        )
+        .await
+        {
+            Ok(context) => context,
+            Err(e) => {
+                error!(
+                    "Could not load the messages before message {}: {e}",
+                    message.message_id
+                );
+                vec![]
+            }
+        };
+        let link_channel_id = message.thread_id.unwrap_or(message.channel_id);
+        let keyword_match = KeywordMatch {
+            rules,
+            guild_id: message.guild_id,
+            channel_id: message.channel_id,
+            thread_id: message.thread_id,
+            message_id: message.message_id,
+            author: message.author,
+            content: message.content,
+            timestamp: message.timestamp,
+            link: jump_link(message.guild_id, link_channel_id, message.message_id),
+            context,
+        };
+
+        let mut text = format!(
+            "**Watched keywords ({}) in <#{link_channel_id}>** ([jump](<{}>))",
+            keyword_match.rules.join(", "),
+            keyword_match.link
+        );
+        for earlier in &keyword_match.context {
+            text.push_str(&format!(
+                "\n> {}: {}",
+                earlier.author,
+                quote(&earlier.content)
+            ));
+        }
+        text.push_str(&format!(
+            "\n> **{}**: {}",
+            keyword_match.author,
+            quote(&keyword_match.content)
+        ));
+        info!(
+            "Message {} matched keyword watch rules {}",
+            keyword_match.message_id,
+            keyword_match.rules.join(", ")
+        );
+        self.alerts
+            .send(WebhookEvent::KeywordMatch, &text, &keyword_match)
+            .await;
+    }
+}
+
+/// Content of a message on a single line, shortened to fit in an alert.
+fn quote(content: &str) -> String {
+    let line = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(MAX_QUOTE_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line,
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use sqlx::SqlitePool;
+use tracing::{error, info};
+
+use crate::db::{self, KbArticle, NewKbArticle, TopicSummary};
+use crate::gpt::Summarizer;
+
+/// Instructions for writing an article from the summaries mentioning a topic.
+const KB_ARTICLE_INSTRUCTIONS: &str = "You write knowledge base articles for a Discord community from summaries of its discussions, oldest first. Write a single consolidated article about the given topic in Markdown: what it is, what was decided about it and why, how to do the things members asked about, and what is still unresolved. When later discussions changed earlier decisions, describe the latest state and mention what changed. Only use what the summaries say, and leave out what is unrelated to the topic. Start with a title line beginning with \"# \".";
+
+/// Most summaries an article is written from, the most recent ones.
+const MAX_SOURCES: i64 = 60;
+/// Topics longer than this are rejected.
+const MAX_TOPIC_CHARS: usize = 100;
+
+#[derive(Debug)]
+pub enum KbError {
+    /// The topic is empty or too long.
+    Invalid,
+    /// No summary mentions the topic.
+    NoSources,
+    /// The model could not write the article.
+    Llm,
+    Database,
+}
+
+/// Writes knowledge base articles on demand from every summary mentioning a topic, and
+/// stores them along with the summaries and digests they come from.
+#[derive(Clone)]
+pub struct KnowledgeBase {
+    db: Arc<SqlitePool>,
+    summarizer: Arc<dyn Summarizer>,
+}
+
+impl KnowledgeBase {
+    pub fn new(db: Arc<SqlitePool>, summarizer: Arc<dyn Summarizer>) -> Self {
+        Self { db, summarizer }
+    }
+
+    /// Writes and stores an article about `topic` from the summaries of a guild when
+    /// given, or of every guild.
+    pub async fn generate(&self, topic: &str, guild_id: Option<i64>) -> Result<KbArticle, KbError> {
+        let topic = topic.trim();
+        if topic.is_empty() || topic.chars().count() > MAX_TOPIC_CHARS {
+            return Err(KbError::Invalid);
+        }
+        let sources = db::fetch_topic_summaries(&self.db, topic, guild_id, MAX_SOURCES)
+            .await
+            .map_err(|e| {
+                error!("Could not fetch the summaries mentioning {topic:?}: {e}");
+                KbError::Database
+            })?;
+        if sources.is_empty() {
+            return Err(KbError::NoSources);
+        }
+        info!(
+            "Writing a knowledge base article about {topic:?} from {} summaries",
+            sources.len()
+        );
+        let reply = self
+            .summarizer
+            .complete(KB_ARTICLE_INSTRUCTIONS, &article_request(topic, &sources))
+            .await
+            .map_err(|e| {
+                error!("Could not write a knowledge base article about {topic:?}: {e}");
+                KbError::Llm
+            })?;
+        let (title, content) = split_title(&reply, topic);
+        if content.is_empty() {
+            error!("The knowledge base article about {topic:?} came back empty");
+            return Err(KbError::Llm);
+        }
+        let new_article = NewKbArticle {
+            guild_id,
+            topic,
+            title: &title,
+            content: &content,
+            sources: &sources,
+        };
+        let stored = match db::insert_kb_article(&self.db, &new_article).await {
+            Ok(id) => db::fetch_kb_article(&self.db, id).await,
+            Err(e) => Err(e),
+        };
+        match stored {
+            Ok(Some(article)) => Ok(article),
+            Ok(None) => Err(KbError::Database),
+            Err(e) => {
+                error!("Could not store the knowledge base article about {topic:?}: {e}");
+                Err(KbError::Database)
+            }
+        }
+    }
+}
+
+fn article_request(topic: &str, sources: &[TopicSummary]) -> String {
+    let mut text = format!("Topic: {topic}\n\nSummaries:");
+    for source in sources {
+        text.push_str(&format!("\n\n[{}", source.timestamp.format("%Y-%m-%d")));
+        if let Some(channel_id) = source.channel_id {
+            text.push_str(&format!(", channel {channel_id}"));
+        }
+        text.push_str(&format!("]\n{}", source.text.trim()));
+    }
+    text
+}
+
+/// Splits the title line off the model's article, falling back to the topic when it
+/// wrote none.
+fn split_title(reply: &str, topic: &str) -> (String, String) {
+    let reply = reply.trim();
+    match reply.split_once('\n') {
+        Some((first, rest)) if first.starts_with('#') => (
+            first.trim_start_matches('#').trim().to_string(),
+            rest.trim().to_string(),
+        ),
+        _ => (topic.to_string(), reply.to_string()),
+    }
+}
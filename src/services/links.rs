@@ -0,0 +1,252 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono_tz::Tz;
+use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+use sqlx::SqlitePool;
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::db::{self, SharedLink};
+
+/// How many links to fetch the pages of per run.
+const FETCH_BATCH_SIZE: i64 = 20;
+/// Only the beginning of a page is read, which is where its metadata lives.
+const MAX_PAGE_BYTES: usize = 256 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Titles and descriptions longer than this are cut short.
+const MAX_METADATA_CHARS: usize = 300;
+/// Most links listed in the links section of a digest.
+const MAX_LISTED: usize = 25;
+
+/// Finds the http and https URLs in a message, in the order they appear and without
+/// duplicates.
+pub fn extract_urls(content: &str) -> Vec<String> {
+    let mut urls: Vec<String> = vec![];
+    for word in content.split_whitespace() {
+        let Some(start) = word.find("https://").or_else(|| word.find("http://")) else {
+            continue;
+        };
+        // Discord users wrap links in <> to hide their embed, and Markdown links end
+        // their target with a parenthesis.
+        let url = word[start..]
+            .split(['<', '>', '"', '`', '|'])
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '*', '_']);
+        let url = if url.ends_with(')') && !url.contains('(') {
+            url.trim_end_matches(')')
+        } else {
+            url
+        };
+        if url.len() > "https://".len() && !urls.iter().any(|seen| seen == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+/// Periodically fetches the pages of newly shared links to store their title and
+/// description.
+pub struct LinkPreviewService {
+    db: Arc<SqlitePool>,
+    client: reqwest::Client,
+    interval: Duration,
+}
+
+impl LinkPreviewService {
+    pub fn new(db: Arc<SqlitePool>, interval_seconds: u64) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            db,
+            client,
+            interval: Duration::from_secs(interval_seconds),
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval_timer = interval(self.interval);
+        loop {
+            interval_timer.tick().await;
+            let links = match db::fetch_links_without_metadata(&self.db, FETCH_BATCH_SIZE).await {
+                Ok(links) => links,
+                Err(e) => {
+                    error!("Could not fetch links without metadata: {e}");
+                    continue;
+                }
+            };
+            for link in links {
+                let metadata = match self.fetch_metadata(&link.url).await {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        warn!("Could not fetch the page of {}: {e}", link.url);
+                        PageMetadata::default()
+                    }
+                };
+                if let Err(e) = db::update_link_metadata(
+                    &self.db,
+                    link.id,
+                    metadata.title.as_deref(),
+                    metadata.description.as_deref(),
+                )
+                .await
+                {
+                    error!("Could not store the metadata of {}: {e}", link.url);
+                }
+            }
+        }
+    }
+
+    async fn fetch_metadata(&self, url: &str) -> eyre::Result<PageMetadata> {
+        let mut response = self
+            .client
+            .get(url)
+            .header(USER_AGENT, "daily-discord-summarizer link preview")
+            .send()
+            .await?
+            .error_for_status()?;
+        let is_html = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("html"));
+        if !is_html {
+            return Ok(PageMetadata::default());
+        }
+        let mut page = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            page.extend_from_slice(&chunk);
+            if page.len() >= MAX_PAGE_BYTES {
+                break;
+            }
+        }
+        let metadata = PageMetadata::parse(&String::from_utf8_lossy(&page));
+        info!("Fetched metadata of {url}: {:?}", metadata.title);
+        Ok(metadata)
+    }
+}
+
+/// The title and description of a web page.
+#[derive(Default)]
+struct PageMetadata {
+    title: Option<String>,
+    description: Option<String>,
+}
+
+impl PageMetadata {
+    /// Reads the Open Graph title and description of a page, falling back to its
+    /// `<title>` and description meta tag.
+    fn parse(html: &str) -> Self {
+        let lowercase = html.to_ascii_lowercase();
+        let mut og_title = None;
+        let mut og_description = None;
+        let mut description = None;
+        let mut rest = 0;
+        while let Some(start) = lowercase[rest..].find("<meta") {
+            let start = rest + start;
+            let end = lowercase[start..]
+                .find('>')
+                .map_or(html.len(), |end| start + end);
+            let tag = &html[start..end];
+            let key = attribute(tag, "property").or_else(|| attribute(tag, "name"));
+            let content = attribute(tag, "content");
+            match (key.as_deref(), content) {
+                (Some("og:title"), Some(content)) => og_title = Some(content),
+                (Some("og:description"), Some(content)) => og_description = Some(content),
+                (Some("description"), Some(content)) => description = Some(content),
+                _ => {}
+            }
+            rest = end;
+        }
+        let title = lowercase.find("<title").and_then(|start| {
+            let start = start + lowercase[start..].find('>')? + 1;
+            let end = start + lowercase[start..].find("</title")?;
+            Some(html[start..end].to_string())
+        });
+        Self {
+            title: clean(og_title.or(title)),
+            description: clean(og_description.or(description)),
+        }
+    }
+}
+
+/// Reads the value of an attribute of an HTML tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lowercase = tag.to_ascii_lowercase();
+    let mut rest = 0;
+    while let Some(found) = lowercase[rest..].find(name) {
+        let start = rest + found;
+        rest = start + name.len();
+        let preceded_by_space = lowercase[..start].ends_with(char::is_whitespace);
+        let value = lowercase[rest..].trim_start();
+        if !preceded_by_space || !value.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - value.len() + 1;
+        let value = tag[value_start..].trim_start();
+        let (quote, value) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => (Some(quote), &value[1..]),
+            _ => (None, value),
+        };
+        let end = match quote {
+            Some(quote) => value.find(quote),
+            None => value.find(|c: char| c.is_whitespace() || c == '/'),
+        };
+        return Some(value[..end.unwrap_or(value.len())].to_string());
+    }
+    None
+}
+
+/// Decodes common HTML entities, collapses whitespace and shortens long values.
+fn clean(value: Option<String>) -> Option<String> {
+    let value = value?
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    if value.is_empty() {
+        return None;
+    }
+    Some(match value.char_indices().nth(MAX_METADATA_CHARS) {
+        Some((end, _)) => format!("{}...", &value[..end]),
+        None => value,
+    })
+}
+
+/// Renders the "Links shared today" section appended to daily digests, or `None` when
+/// no links were shared.
+pub fn shared_links_section(links: &[SharedLink], timezone: Tz) -> Option<String> {
+    if links.is_empty() {
+        return None;
+    }
+    let mut section = String::from("**Links shared today**");
+    for link in links.iter().take(MAX_LISTED) {
+        // Wrapping the URL in <> keeps Discord from embedding every link.
+        let mut line = match &link.title {
+            Some(title) => format!("\n- [{title}](<{}>)", link.url),
+            None => format!("\n- <{}>", link.url),
+        };
+        line.push_str(&format!(
+            ", shared by {} in <#{}>, {}",
+            link.author,
+            link.channel_id,
+            link.shared_at
+                .with_timezone(&timezone)
+                .format("%b %-d, %H:%M")
+        ));
+        if let Some(description) = &link.description {
+            line.push_str(&format!(": {description}"));
+        }
+        section.push_str(&line);
+    }
+    if links.len() > MAX_LISTED {
+        section.push_str(&format!("\n- ...and {} more", links.len() - MAX_LISTED));
+    }
+    Some(section)
+}
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::async_trait;
+use chrono::Utc;
+use eyre::{bail, eyre, WrapErr};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::MatrixConfig;
+use crate::templates::{markdown_html, DigestTarget, DigestTemplates, DigestView};
+
+use super::digests::split_message;
+use super::sinks::DigestSink;
+
+/// Longest text sent in a single Matrix message, keeping the event along with its HTML
+/// well under the 65 KB limit of homeservers.
+const MATRIX_MESSAGE_LIMIT: usize = 16_000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    errcode: String,
+    error: Option<String>,
+}
+
+/// Posts digests to a Matrix room through the client-server API, as the user the
+/// access token belongs to.
+#[derive(Clone)]
+pub struct MatrixSender {
+    client: reqwest::Client,
+    homeserver_url: Arc<Url>,
+    access_token: Arc<String>,
+    room_id: Arc<String>,
+}
+
+impl MatrixSender {
+    /// Creates a sender posting to `room_id` as the account of the config.
+    pub fn from_config(config: &MatrixConfig, room_id: &str) -> eyre::Result<Self> {
+        let homeserver_url = config
+            .homeserver_url
+            .as_deref()
+            .ok_or_else(|| eyre!("matrix.homeserver_url must be set to post digests to Matrix"))?;
+        let homeserver_url =
+            Url::parse(homeserver_url).wrap_err("Invalid matrix.homeserver_url")?;
+        if homeserver_url.cannot_be_a_base() {
+            bail!("Invalid matrix.homeserver_url {homeserver_url}");
+        }
+        let Some(access_token) = config.access_token() else {
+            bail!("matrix.access_token or the MATRIX_ACCESS_TOKEN env var must be set to post digests to Matrix");
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Ok(Self {
+            client,
+            homeserver_url: Arc::new(homeserver_url),
+            access_token: Arc::new(access_token),
+            room_id: Arc::new(room_id.to_string()),
+        })
+    }
+
+    async fn send(&self, transaction_id: &str, text: &str) -> eyre::Result<()> {
+        let mut url = (*self.homeserver_url).clone();
+        url.path_segments_mut()
+            .map_err(|_| eyre!("homeserver URL cannot have a path"))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                self.room_id.as_str(),
+                "send",
+                "m.room.message",
+                transaction_id,
+            ]);
+        let response = self
+            .client
+            .put(url)
+            .bearer_auth(self.access_token.as_str())
+            .json(&json!({
+                "msgtype": "m.text",
+                "body": text,
+                "format": "org.matrix.custom.html",
+                "formatted_body": markdown_html(text),
+            }))
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        match response.json::<ErrorResponse>().await {
+            Ok(error) => bail!(
+                "{status}: {} {}",
+                error.errcode,
+                error.error.as_deref().unwrap_or_default()
+            ),
+            Err(_) => bail!("{status}"),
+        }
+    }
+}
+
+#[async_trait]
+impl DigestSink for MatrixSender {
+    fn name(&self) -> String {
+        format!("Matrix room {}", self.room_id)
+    }
+
+    /// Posts a digest rendered with the Discord template, with an HTML version of each
+    /// message for clients that display formatting.
+    async fn deliver(&self, templates: &DigestTemplates, digest: &DigestView) -> eyre::Result<()> {
+        let content = templates.render(DigestTarget::Discord, digest);
+        for (index, chunk) in split_message(&content, MATRIX_MESSAGE_LIMIT)
+            .iter()
+            .enumerate()
+        {
+            // Homeservers ignore messages sent again with the same transaction ID, so
+            // it must differ for every message, even of the same digest.
+            let transaction_id = format!(
+                "digest-{}-{index}",
+                Utc::now().timestamp_nanos_opt().unwrap_or_default()
+            );
+            self.send(&transaction_id, chunk).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::DateTime;
+use regex::{Captures, Regex};
+use serenity::all::{ChannelId, GuildId, RoleId, User, UserId};
+use serenity::cache::Cache;
+
+use super::privacy::Pseudonyms;
+
+/// Replaces the markup Discord uses for mentions, custom emoji and timestamps with
+/// readable text, so that the model sees `@alice in #general` rather than
+/// `<@123> in <#456>`.
+pub struct MentionResolver {
+    markup: Regex,
+    /// Users are mentioned by pseudonym when set, as their messages are stored under it.
+    pseudonyms: Option<Pseudonyms>,
+}
+
+impl MentionResolver {
+    pub fn new(pseudonyms: Option<Pseudonyms>) -> Self {
+        Self {
+            markup: Regex::new(
+                r"<(?:@!?(?P<user>\d+)|@&(?P<role>\d+)|#(?P<channel>\d+)|a?:(?P<emoji>\w+):\d+|t:(?P<time>-?\d+)(?::[tTdDfFR])?)>",
+            )
+            .expect("the mention pattern is valid"),
+            pseudonyms,
+        }
+    }
+
+    /// The content of a message with its markup resolved using the users it mentions
+    /// and what the cache knows of its guild. Mentions of anything that cannot be found
+    /// keep a generic name.
+    pub fn resolve(
+        &self,
+        content: &str,
+        guild_id: Option<GuildId>,
+        mentions: &[User],
+        cache: &Cache,
+    ) -> String {
+        self.resolve_with(
+            content,
+            |user_id| Some(self.user_name(guild_id, mentions, cache, user_id)),
+            |role_id| {
+                let guild = cache.guild(guild_id?)?;
+                let role = guild.roles.get(&role_id)?;
+                Some(role.name.clone())
+            },
+            |channel_id| {
+                let guild = cache.guild(guild_id?)?;
+                let channel = guild.channels.get(&channel_id)?;
+                Some(channel.name.clone())
+            },
+        )
+    }
+
+    /// The content of a message with its markup resolved using the given lookups of
+    /// user, role and channel names, such as for messages of an export. Users are
+    /// mentioned by pseudonym when set.
+    pub fn resolve_with(
+        &self,
+        content: &str,
+        user_name: impl Fn(UserId) -> Option<String>,
+        role_name: impl Fn(RoleId) -> Option<String>,
+        channel_name: impl Fn(ChannelId) -> Option<String>,
+    ) -> String {
+        self.markup
+            .replace_all(content, |captures: &Captures| {
+                let id = |name| {
+                    let id = captures.name(name)?.as_str().parse::<u64>().ok()?;
+                    (id != 0).then_some(id)
+                };
+                if let Some(user_id) = id("user") {
+                    let user_id = UserId::new(user_id);
+                    let name = match &self.pseudonyms {
+                        Some(pseudonyms) => Some(pseudonyms.pseudonym(user_id)),
+                        None => user_name(user_id),
+                    };
+                    format!("@{}", name.as_deref().unwrap_or("user"))
+                } else if let Some(role_id) = id("role") {
+                    let name = role_name(RoleId::new(role_id));
+                    format!("@{}", name.as_deref().unwrap_or("role"))
+                } else if let Some(channel_id) = id("channel") {
+                    let name = channel_name(ChannelId::new(channel_id));
+                    format!("#{}", name.as_deref().unwrap_or("channel"))
+                } else if let Some(emoji) = captures.name("emoji") {
+                    format!(":{}:", emoji.as_str())
+                } else {
+                    captures
+                        .name("time")
+                        .and_then(|time| time.as_str().parse().ok())
+                        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+                        .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+                        .unwrap_or_default()
+                }
+            })
+            .into_owned()
+    }
+
+    /// The nickname of a mentioned user in the guild, falling back to their global
+    /// display name and username.
+    fn user_name(
+        &self,
+        guild_id: Option<GuildId>,
+        mentions: &[User],
+        cache: &Cache,
+        user_id: UserId,
+    ) -> String {
+        let nick = guild_id
+            .and_then(|guild_id| cache.guild(guild_id))
+            .and_then(|guild| guild.members.get(&user_id)?.nick.clone());
+        if let Some(nick) = nick {
+            return nick;
+        }
+        let user = mentions.iter().find(|user| user.id == user_id);
+        match user {
+            Some(user) => user
+                .global_name
+                .clone()
+                .unwrap_or_else(|| user.name.clone()),
+            None => cache
+                .user(user_id)
+                .map(|user| user.name.clone())
+                .unwrap_or_else(|| "user".to_string()),
+        }
+    }
+}
@@ -1,127 +1,731 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::Write,
+    collections::HashMap,
     path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
+use serenity::all::{ChannelId, GuildId};
+use sqlx::SqlitePool;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
-use tracing::{error, info, warn};
+use tokio::sync::Semaphore;
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
-use super::{discord_handler::DiscordMessage, summarizer::SummarizeRequest};
+use crate::config::DeletedMessagePolicy;
+use crate::db;
+use crate::gpt::{ImageDescriber, LlmPause, TokenCounter};
+use crate::redaction::Redactor;
+
+use super::{
+    discord_handler::ReactionChange,
+    ingest::synthetic_id,
+    keyword_watch::{KeywordWatch, WatchedMessage},
+    links::extract_urls,
+    message_logs::{find_message_logs, parse_message_log},
+    privacy::Pseudonyms,
+    questions::is_question,
+    sources::{IngestEvent, IngestedAttachment, IngestedMessage},
+    summarizer::{render_attachment, SummarizeRequest},
+};
+
+/// How often to check for channels that are due an idle flush.
+const IDLE_FLUSH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many images are described at the same time, at most.
+const MAX_CONCURRENT_DESCRIPTIONS: usize = 4;
+
+/// The unsummarized messages of a single channel, waiting to fill up a batch.
+struct ChannelLog {
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    token_count: usize,
+    /// Row ID of the newest stored message, if any are waiting to be summarized.
+    last_message_id: Option<i64>,
+    last_flush: Instant,
+}
+
+impl ChannelLog {
+    fn new(guild_id: Option<GuildId>, channel_id: ChannelId) -> Self {
+        Self {
+            guild_id,
+            channel_id,
+            token_count: 0,
+            last_message_id: None,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// Closes the current batch of messages and returns a request to summarize it,
+    /// unless there is nothing to summarize.
+    fn flush(&mut self) -> Option<SummarizeRequest> {
+        let up_to_message_id = self.last_message_id.take()?;
+        self.token_count = 0;
+        self.last_flush = Instant::now();
+        Some(SummarizeRequest::Messages {
+            guild_id: self.guild_id,
+            channel_id: self.channel_id,
+            up_to_message_id,
+            reply: None,
+        })
+    }
+}
+
+/// When a channel is summarized, if not like every other channel.
+#[derive(Clone, Copy, Default)]
+pub struct ChannelSchedule {
+    pub summary_tokens_threshold: Option<usize>,
+    pub summarize_after: Option<Duration>,
+}
 
 pub struct MessageLogService {
+    db: Arc<SqlitePool>,
     summarize_tx: Sender<SummarizeRequest>,
-    discord_rx: Receiver<DiscordMessage>,
-    message_log_path: PathBuf,
-    log_file_index: usize,
-    curr_file_token_count: usize,
-    message_log: File,
+    ingest_rx: Receiver<IngestEvent>,
+    token_counter: Arc<dyn TokenCounter>,
+    channel_logs: HashMap<ChannelId, ChannelLog>,
     summary_tokens_threshold: usize,
+    summarize_after: Option<Duration>,
+    /// Overrides the threshold and idle period of some channels.
+    channel_schedules: HashMap<ChannelId, ChannelSchedule>,
+    /// Replaces the names of authors before they are stored, when set.
+    pseudonyms: Option<Pseudonyms>,
+    redactor: Redactor,
+    deleted_messages: DeletedMessagePolicy,
+    /// Describes the images attached to messages, when set.
+    image_describer: Option<Arc<dyn ImageDescriber>>,
+    /// Bounds how many images are described at the same time.
+    descriptions: Arc<Semaphore>,
+    /// Alerts about new messages mentioning watched keywords, when set.
+    keyword_watch: Option<KeywordWatch>,
+    /// Holds off idle flushes while LLM calls are paused, when set.
+    pause: Option<LlmPause>,
+    /// Whether LLM calls were paused at the previous idle flush check.
+    was_paused: bool,
+    /// Where message logs of older versions are imported from, when set.
+    message_log_directory: Option<PathBuf>,
 }
 
 impl MessageLogService {
     pub fn new(
-        message_log_path: PathBuf,
+        db: Arc<SqlitePool>,
         summarize_tx: Sender<SummarizeRequest>,
-        discord_rx: Receiver<DiscordMessage>,
+        ingest_rx: Receiver<IngestEvent>,
+        token_counter: Arc<dyn TokenCounter>,
         summary_tokens_threshold: usize,
+        summarize_after_seconds: Option<u64>,
     ) -> Self {
-        let log_file_index: usize = find_last_log_file_index(&message_log_path).unwrap_or(0);
-        info!("{}", log_file_index);
-        let fpath = message_log_path.join(format!("messages_{log_file_index}.txt"));
-        let message_log = OpenOptions::new()
-            .append(true) // Set to append mode
-            .create(true) // Create file if it does not exist
-            .open(&fpath) // Specify the file path
-            .expect("Unable to open messages log");
-
-        let curr_file_token_count = crate::gpt::estimate_token_count(fpath)
-            .expect("Could not estimate token count of file on init");
         Self {
+            db,
             summarize_tx,
-            discord_rx,
-            message_log_path,
-            log_file_index,
-            curr_file_token_count,
-            message_log,
+            ingest_rx,
+            token_counter,
+            channel_logs: HashMap::new(),
             summary_tokens_threshold,
+            summarize_after: summarize_after_seconds.map(Duration::from_secs),
+            channel_schedules: HashMap::new(),
+            pseudonyms: None,
+            redactor: Redactor::default(),
+            deleted_messages: DeletedMessagePolicy::default(),
+            image_describer: None,
+            descriptions: Arc::new(Semaphore::new(MAX_CONCURRENT_DESCRIPTIONS)),
+            keyword_watch: None,
+            pause: None,
+            was_paused: false,
+            message_log_directory: None,
         }
     }
 
-    pub async fn run(&mut self) {
-        while let Some(data) = self.discord_rx.recv().await {
-            match data {
-                DiscordMessage::Received(msg) => {
-                    // Check if the file has reached the critical mass, then figure out what we need to do:
-                    // Have we reached the max tokens we want in our request? If so, then increase the log file index
-                    // and emit a summarize request.
-                    let incoming_token_count =
-                        msg.content.chars().count() / crate::gpt::CHARS_PER_TOKEN;
-                    if self.curr_file_token_count + incoming_token_count
-                        > self.summary_tokens_threshold
-                    {
-                        warn!("File has overflowed the allowed token count, creating new file");
-                        let log_file_index = self.log_file_index + 1;
-                        let fpath = self
-                            .message_log_path
-                            .join(format!("messages_{log_file_index}.txt"));
-                        let message_log = OpenOptions::new()
-                            .append(true)
-                            .create(true)
-                            .open(fpath)
-                            .expect("Unable to open messages log"); // TODO: Handle panic.
-
-                        // Send a request to summarize the previous, full file.
-                        self.summarize_tx
-                            .send(SummarizeRequest::FileWithIndex(self.log_file_index))
-                            .await
-                            .unwrap(); // TODO: Handle panic.
-
-                        self.message_log = message_log;
-                        self.log_file_index = log_file_index;
-                        self.curr_file_token_count = 0;
+    /// Summarizes some channels at their own token threshold or idle period, such as a
+    /// busy support channel every hour.
+    pub fn with_channel_schedules(
+        mut self,
+        channel_schedules: HashMap<ChannelId, ChannelSchedule>,
+    ) -> Self {
+        self.channel_schedules = channel_schedules;
+        self
+    }
+
+    /// Only flushes channels that reach their token threshold while LLM calls are
+    /// paused, then catches up by summarizing every channel once they resume.
+    pub fn with_pause(mut self, pause: LlmPause) -> Self {
+        self.pause = Some(pause);
+        self
+    }
+
+    /// Scrubs secrets and personal information out of messages before storing them.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Decides what happens to logged messages that are deleted on Discord.
+    pub fn with_deleted_messages(mut self, policy: DeletedMessagePolicy) -> Self {
+        self.deleted_messages = policy;
+        self
+    }
+
+    /// Describes the images attached to messages with a vision model, so that their
+    /// descriptions are part of the transcript. Messages are stored right away and their
+    /// images described in the background, so that a slow vision API does not hold up
+    /// storing the messages after them.
+    pub fn with_image_describer(mut self, image_describer: Arc<dyn ImageDescriber>) -> Self {
+        self.image_describer = Some(image_describer);
+        self
+    }
+
+    /// Alerts about new messages that match the watch rules. Backfilled history is not
+    /// alerted about.
+    pub fn with_keyword_watch(mut self, keyword_watch: KeywordWatch) -> Self {
+        self.keyword_watch = Some(keyword_watch);
+        self
+    }
+
+    /// Stores messages under pseudonyms instead of the names of their authors, so that
+    /// the LLM never sees who said what. The database still keeps the Discord ID of each
+    /// author, which erasing a member's data relies on, but never sends it to the LLM.
+    pub fn with_pseudonyms(mut self, pseudonyms: Pseudonyms) -> Self {
+        self.pseudonyms = Some(pseudonyms);
+        self
+    }
+
+    /// Imports the message logs that versions of the bot that logged messages to files
+    /// left in the directory, before picking up unsummarized messages.
+    pub fn with_message_log_directory(mut self, message_log_directory: PathBuf) -> Self {
+        self.message_log_directory = Some(message_log_directory);
+        self
+    }
+
+    /// Stores and batches incoming messages until `shutdown` is cancelled, then stores
+    /// the messages still queued and sends every channel's batch to be summarized.
+    pub async fn run(&mut self, shutdown: CancellationToken) {
+        self.import_message_logs().await;
+        self.restore_channel_logs().await;
+
+        let idle_periods: Vec<Duration> = self
+            .summarize_after
+            .into_iter()
+            .chain(
+                self.channel_schedules
+                    .values()
+                    .filter_map(|schedule| schedule.summarize_after),
+            )
+            .collect();
+        let idle_flush = !idle_periods.is_empty() || self.pause.is_some();
+        let check_interval = idle_periods
+            .into_iter()
+            .fold(IDLE_FLUSH_CHECK_INTERVAL, Duration::min);
+        let mut idle_flush_timer = interval(check_interval);
+        loop {
+            tokio::select! {
+                data = self.ingest_rx.recv() => match data {
+                    Some(data) => self.handle_message(data).await,
+                    None => break,
+                },
+                _ = idle_flush_timer.tick(), if idle_flush => {
+                    self.flush_idle_logs().await;
+                }
+                _ = shutdown.cancelled() => break,
+            }
+        }
+
+        self.ingest_rx.close();
+        while let Some(data) = self.ingest_rx.recv().await {
+            self.handle_message(data).await;
+        }
+        self.flush_all_logs().await;
+    }
+
+    /// Stores the messages of the log files an older version left behind, deleting each
+    /// file once imported. Their messages are then picked up like any other unsummarized
+    /// messages, and the pending summarizations of the files point at them.
+    async fn import_message_logs(&mut self) {
+        let Some(message_log_directory) = &self.message_log_directory else {
+            return;
+        };
+        let logs = match find_message_logs(message_log_directory) {
+            Ok(logs) => logs,
+            Err(e) => {
+                error!(
+                    "Could not look for message logs to import in {}: {e}",
+                    message_log_directory.display()
+                );
+                return;
+            }
+        };
+        if !logs.is_empty() {
+            info!(
+                "Importing {} message logs left by an older version",
+                logs.len()
+            );
+        }
+        for log in logs {
+            let contents = match std::fs::read_to_string(&log.path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    error!("Could not read message log {}: {e}", log.path.display());
+                    continue;
+                }
+            };
+            let lines = parse_message_log(&contents);
+            let rows: Vec<_> = lines
+                .iter()
+                .enumerate()
+                .map(|(position, line)| {
+                    let content = self.redactor.redact(&line.content);
+                    let author = match &self.pseudonyms {
+                        Some(pseudonyms) => pseudonyms.pseudonym_for_name(&line.author),
+                        None => line.author.clone(),
+                    };
+                    // Message logs did not keep Discord IDs, so imported messages are
+                    // given IDs made up from where they were logged.
+                    let message_id = synthetic_id(&[
+                        "message log",
+                        &log.channel_id.to_string(),
+                        &log.index.to_string(),
+                        &position.to_string(),
+                    ]);
+                    (
+                        message_id as i64,
+                        self.token_counter.count_tokens(&content) as i64,
+                        author,
+                        content,
+                    )
+                })
+                .collect();
+            let messages = rows
+                .iter()
+                .zip(&lines)
+                .map(
+                    |((message_id, token_count, author, content), line)| db::NewMessage {
+                        message_id: *message_id,
+                        guild_id: log.guild_id.map(|id| id.get() as i64),
+                        channel_id: log.channel_id.get() as i64,
+                        // Message logs only kept the names of authors.
+                        author_id: None,
+                        author,
+                        content,
+                        timestamp: line.timestamp,
+                        token_count: *token_count,
+                        reply_to_message_id: None,
+                        thread_id: None,
+                        thread_name: None,
+                    },
+                )
+                .collect();
+            let channel_id = log.channel_id.get() as i64;
+            if let Err(e) =
+                db::import_message_log(&self.db, channel_id, log.index as i64, messages).await
+            {
+                error!("Could not import message log {}: {e}", log.path.display());
+                continue;
+            }
+            info!(
+                "Imported {} messages from message log {}",
+                lines.len(),
+                log.path.display()
+            );
+            if let Err(e) = std::fs::remove_file(&log.path) {
+                error!(
+                    "Could not delete imported message log {}: {e}",
+                    log.path.display()
+                );
+            }
+        }
+    }
+
+    /// Picks up the messages a previous run stored but did not get to summarize,
+    /// summarizing right away the channels that already have a full batch.
+    async fn restore_channel_logs(&mut self) {
+        let channels = match db::fetch_unsummarized_channels(&self.db).await {
+            Ok(channels) => channels,
+            Err(e) => {
+                error!("Could not load unsummarized messages from a previous run: {e}");
+                return;
+            }
+        };
+        for channel in channels {
+            let channel_id = ChannelId::new(channel.channel_id as u64);
+            let guild_id = channel.guild_id.map(|id| GuildId::new(id as u64));
+            let mut channel_log = ChannelLog::new(guild_id, channel_id);
+            channel_log.token_count = channel.token_count as usize;
+            channel_log.last_message_id = Some(channel.last_message_id);
+            info!(
+                "Restored unsummarized messages for channel {channel_id} with total token count of {}",
+                channel_log.token_count
+            );
+            if channel_log.token_count > self.summary_tokens_threshold(channel_id) {
+                if let Some(request) = channel_log.flush() {
+                    request_summary(&self.summarize_tx, request).await;
+                }
+            }
+            self.channel_logs.insert(channel_id, channel_log);
+        }
+    }
+
+    async fn handle_message(&mut self, data: IngestEvent) {
+        match data {
+            IngestEvent::Received(msg) => {
+                self.store_message(&msg, true).await;
+            }
+            IngestEvent::Reactions { message_id, change } => {
+                let id = message_id.get() as i64;
+                let result = match change {
+                    ReactionChange::Added => db::add_message_reactions(&self.db, id, 1).await,
+                    ReactionChange::Removed => db::add_message_reactions(&self.db, id, -1).await,
+                    ReactionChange::Total(count) => {
+                        db::set_message_reactions(&self.db, id, count).await
                     }
+                };
+                if let Err(e) = result {
+                    error!("Could not update the reactions of message {message_id}: {e}");
+                }
+            }
+            IngestEvent::Highlighted {
+                message_id,
+                highlighted,
+            } => {
+                let id = message_id.get() as i64;
+                let result = if highlighted {
+                    db::insert_highlight(&self.db, id).await
+                } else {
+                    db::delete_unlisted_highlight(&self.db, id).await
+                };
+                if let Err(e) = result {
+                    error!("Could not update the highlight of message {message_id}: {e}");
+                }
+            }
+            IngestEvent::Backfilled {
+                channel_id,
+                messages,
+            } => {
+                // History is older than the messages waiting in the channel's batch, so
+                // it is summarized in batches of its own.
+                self.flush_channel(channel_id).await;
+                for msg in messages {
+                    self.store_message(&msg, false).await;
+                }
+                self.flush_channel(channel_id).await;
+            }
+            IngestEvent::Edited {
+                message_id,
+                content,
+            } => {
+                // The batch's token count is left as is, it is only an estimate of when
+                // to summarize.
+                let content = self.redactor.redact(&content);
+                let token_count = self.token_counter.count_tokens(&content) as i64;
+                match db::update_message_content(
+                    &self.db,
+                    message_id.get() as i64,
+                    &content,
+                    token_count,
+                )
+                .await
+                {
+                    Ok(true) => info!("Updated edited message {message_id}"),
+                    Ok(false) => {}
+                    Err(e) => error!("Could not update edited message {message_id}: {e}"),
+                }
+            }
+            IngestEvent::Deleted { message_ids } => {
+                let keep_marker = matches!(self.deleted_messages, DeletedMessagePolicy::Mark);
+                for message_id in message_ids {
+                    match db::delete_message(&self.db, message_id.get() as i64, keep_marker).await {
+                        Ok(true) => info!("Removed deleted message {message_id}"),
+                        Ok(false) => {}
+                        Err(e) => error!("Could not remove deleted message {message_id}: {e}"),
+                    }
+                }
+            }
+            IngestEvent::SummarizeNow { channel_id, reply } => {
+                let request = self
+                    .channel_logs
+                    .get_mut(&channel_id)
+                    .and_then(ChannelLog::flush);
+                match request {
+                    Some(request) => {
+                        info!("Summarizing messages for channel {channel_id} on demand");
+                        request_summary(&self.summarize_tx, request.with_reply(reply)).await;
+                    }
+                    None => {
+                        let _ = reply.send(Ok(None));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stores a message in the batch of its channel, or of the parent channel of its
+    /// thread, first sending the batch to be summarized if the message overflows it.
+    /// `live` messages were just posted, rather than backfilled.
+    async fn store_message(&mut self, msg: &IngestedMessage, live: bool) {
+        // Threads are summarized along with their parent channel.
+        let thread = &msg.thread;
+        let channel_id = thread
+            .as_ref()
+            .map_or(msg.channel_id, |thread| thread.parent_id);
+        let content = self.redactor.redact(&msg.content);
+        let incoming_token_count = self.token_counter.count_tokens(&content)
+            + msg
+                .attachments
+                .iter()
+                .map(|attachment| {
+                    let logged = db::LoggedAttachment {
+                        filename: attachment.filename.clone(),
+                        description: None,
+                    };
+                    self.token_counter.count_tokens(&render_attachment(&logged))
+                })
+                .sum::<usize>();
+        let timestamp = msg.timestamp;
+        let author = match &self.pseudonyms {
+            Some(pseudonyms) => pseudonyms.pseudonym(msg.author_id),
+            None => msg.author.clone(),
+        };
+        let new_message = db::NewMessage {
+            message_id: msg.message_id.get() as i64,
+            guild_id: msg.guild_id.map(|id| id.get() as i64),
+            channel_id: channel_id.get() as i64,
+            author_id: Some(msg.author_id.get() as i64),
+            author: &author,
+            content: &content,
+            timestamp,
+            token_count: incoming_token_count as i64,
+            reply_to_message_id: msg.reply_to.map(|reply_to| reply_to.get() as i64),
+            thread_id: thread.as_ref().map(|thread| thread.id.get() as i64),
+            thread_name: thread.as_ref().map(|thread| thread.name.as_str()),
+        };
+        let id = match db::insert_message(&self.db, new_message).await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                debug!("Message {} is already logged, skipping it", msg.message_id);
+                return;
+            }
+            Err(e) => {
+                error!("Could not store message with content: {content} in DB: {e}");
+                return;
+            }
+        };
+
+        let mut images = vec![];
+        for attachment in &msg.attachments {
+            let new_attachment = db::NewAttachment {
+                message_id: id,
+                filename: &attachment.filename,
+                url: &attachment.url,
+                content_type: attachment.content_type.as_deref(),
+                size: attachment.size as i64,
+                description: None,
+            };
+            match db::insert_attachment(&self.db, new_attachment).await {
+                Ok(attachment_id) if self.is_describable(attachment) => {
+                    images.push((attachment_id, attachment.clone()));
+                }
+                Ok(_) => {}
+                Err(e) => error!(
+                    "Could not store attachment {} of message {id}: {e}",
+                    attachment.filename
+                ),
+            }
+        }
+        self.describe_images(images);
+
+        // Check if the batch has reached the critical mass, then figure out what we need to do:
+        // Have we reached the max tokens we want in our request? If so, emit a summarize request
+        // for the messages so far and start a new batch. The batch ends before this message,
+        // as it is not its last message yet.
+        let summary_tokens_threshold = self.summary_tokens_threshold(channel_id);
+        let channel_log = self
+            .channel_logs
+            .entry(channel_id)
+            .or_insert_with(|| ChannelLog::new(msg.guild_id, channel_id));
+        if channel_log.token_count + incoming_token_count > summary_tokens_threshold {
+            warn!("Messages for channel {channel_id} have overflowed the allowed token count, starting a new batch");
+            if let Some(request) = channel_log.flush() {
+                request_summary(&self.summarize_tx, request).await;
+            }
+        }
+        if is_question(&content) {
+            if let Err(e) = db::insert_question(&self.db, id).await {
+                error!("Could not record message {id} as a question: {e}");
+            }
+        }
+        for url in extract_urls(&content) {
+            let link = db::NewSharedLink {
+                message_id: id,
+                guild_id: msg.guild_id.map(|id| id.get() as i64),
+                channel_id: channel_id.get() as i64,
+                author: &author,
+                url: &url,
+                shared_at: timestamp,
+            };
+            if let Err(e) = db::insert_shared_link(&self.db, link).await {
+                error!("Could not store link {url} shared in message {id}: {e}");
+            }
+        }
+        if let Some(keyword_watch) = self.keyword_watch.as_ref().filter(|_| live) {
+            let rules = keyword_watch.matching_rules(&content);
+            if !rules.is_empty() {
+                let message = WatchedMessage {
+                    id,
+                    message_id: msg.message_id.get() as i64,
+                    guild_id: msg.guild_id.map(|id| id.get() as i64),
+                    channel_id: channel_id.get() as i64,
+                    thread_id: thread.as_ref().map(|thread| thread.id.get() as i64),
+                    author: author.clone(),
+                    content: content.to_string(),
+                    timestamp,
+                };
+                // Alerting waits on Discord and webhooks, which should not hold up
+                // logging the messages that follow.
+                let keyword_watch = keyword_watch.clone();
+                tokio::spawn(async move { keyword_watch.alert(rules, message).await });
+            }
+        }
+        channel_log.token_count += incoming_token_count;
+        channel_log.last_message_id = Some(id);
+        info!(
+            "Processed message, channel {channel_id} has total token count of {}",
+            channel_log.token_count
+        );
+    }
+
+    /// How many tokens of a channel's messages make a batch.
+    fn summary_tokens_threshold(&self, channel_id: ChannelId) -> usize {
+        self.channel_schedules
+            .get(&channel_id)
+            .and_then(|schedule| schedule.summary_tokens_threshold)
+            .unwrap_or(self.summary_tokens_threshold)
+    }
+
+    /// Sends the batch of a channel to be summarized, if it has any messages.
+    async fn flush_channel(&mut self, channel_id: ChannelId) {
+        let request = self
+            .channel_logs
+            .get_mut(&channel_id)
+            .and_then(ChannelLog::flush);
+        if let Some(request) = request {
+            request_summary(&self.summarize_tx, request).await;
+        }
+    }
+
+    /// Whether an attachment is an image small enough to go through the image describer.
+    fn is_describable(&self, attachment: &IngestedAttachment) -> bool {
+        let Some(describer) = &self.image_describer else {
+            return false;
+        };
+        attachment
+            .content_type
+            .as_deref()
+            .is_some_and(|content_type| content_type.starts_with("image/"))
+            && attachment.size <= describer.max_image_bytes()
+    }
 
-                    let timestamp = msg.timestamp;
-                    let content = msg.content;
-                    let author = msg.author.name;
-                    if let Err(e) = writeln!(
-                        self.message_log,
-                        "timestamp: {timestamp}, author: {author}, content: {content}"
-                    ) {
-                        error!("Could not write message with content: {content} to log file: {e}");
+    /// Describes stored images in the background, adding their descriptions to their
+    /// attachments. A batch summarized before its images are described goes without
+    /// their descriptions.
+    fn describe_images(&self, images: Vec<(i64, IngestedAttachment)>) {
+        let Some(describer) = self.image_describer.clone().filter(|_| !images.is_empty()) else {
+            return;
+        };
+        let db = self.db.clone();
+        let redactor = self.redactor.clone();
+        let descriptions = self.descriptions.clone();
+        tokio::spawn(async move {
+            let Ok(_permit) = descriptions.acquire().await else {
+                return;
+            };
+            for (attachment_id, image) in images {
+                let content_type = image.content_type.as_deref().unwrap_or_default();
+                let description = match describer.describe(&image.url, content_type).await {
+                    // Images can show secrets as well as text can.
+                    Ok(text) => redactor.redact(&text).into_owned(),
+                    Err(e) => {
+                        warn!("Could not describe image {}: {e}", image.filename);
                         continue;
                     }
-                    self.curr_file_token_count += incoming_token_count;
-                    info!(
-                        "Processed message, file has total token count of {}",
-                        self.curr_file_token_count
+                };
+                if let Err(e) =
+                    db::set_attachment_description(&db, attachment_id, &description).await
+                {
+                    error!(
+                        "Could not store the description of image {}: {e}",
+                        image.filename
                     );
                 }
             }
+        });
+    }
+
+    /// Emits summarize requests for every channel with unsummarized messages.
+    async fn flush_all_logs(&mut self) {
+        for channel_log in self.channel_logs.values_mut() {
+            let Some(request) = channel_log.flush() else {
+                continue;
+            };
+            info!(
+                "Flushing messages for channel {} before shutting down",
+                channel_log.channel_id
+            );
+            request_summary(&self.summarize_tx, request).await;
+        }
+    }
+
+    /// Emits summarize requests for channels that have unsummarized messages but have
+    /// not been flushed within the configured idle period, so quiet channels still get
+    /// summaries. Right after LLM calls resume, every channel with unsummarized
+    /// messages is flushed.
+    async fn flush_idle_logs(&mut self) {
+        let mut catch_up = false;
+        if let Some(pause) = &self.pause {
+            let paused = pause.is_paused();
+            catch_up = self.was_paused && !paused;
+            self.was_paused = paused;
+            if paused {
+                return;
+            }
+            if catch_up {
+                info!("LLM calls resumed, summarizing the messages logged while they were paused");
+            }
+        }
+        for channel_log in self.channel_logs.values_mut() {
+            if catch_up {
+                if let Some(request) = channel_log.flush() {
+                    request_summary(&self.summarize_tx, request).await;
+                }
+                continue;
+            }
+            let summarize_after = self
+                .channel_schedules
+                .get(&channel_log.channel_id)
+                .and_then(|schedule| schedule.summarize_after)
+                .or(self.summarize_after);
+            let Some(summarize_after) = summarize_after else {
+                continue;
+            };
+            if channel_log.last_flush.elapsed() < summarize_after {
+                continue;
+            }
+            let Some(request) = channel_log.flush() else {
+                continue;
+            };
+            info!(
+                "Flushing messages for channel {} after {}s without a summary",
+                channel_log.channel_id,
+                summarize_after.as_secs()
+            );
+            request_summary(&self.summarize_tx, request).await;
         }
     }
 }
 
-fn find_last_log_file_index(dirpath: &PathBuf) -> Option<usize> {
-    std::fs::read_dir(dirpath)
-        .expect("Directory containing message logs not found")
-        .filter_map(|entry| {
-            entry.ok().and_then(|e| {
-                e.path().file_name().and_then(|name| {
-                    name.to_str().and_then(|s| {
-                        if s.starts_with("messages_") && s.ends_with(".txt") {
-                            s.trim_start_matches("messages_")
-                                .trim_end_matches(".txt")
-                                .parse::<usize>()
-                                .ok()
-                        } else {
-                            None
-                        }
-                    })
-                })
-            })
-        })
-        .max()
+/// Sends a batch of messages to be summarized. Should the summarizer be gone, the
+/// messages stay stored and are summarized after the next start.
+async fn request_summary(summarize_tx: &Sender<SummarizeRequest>, request: SummarizeRequest) {
+    if let Err(e) = summarize_tx.send(request).await {
+        error!("Could not send summarize request, its messages will be summarized on the next start: {e}");
+    }
 }
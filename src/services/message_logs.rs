@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serenity::all::{ChannelId, GuildId};
+
+/// A message log file left behind by a version of the bot that logged messages to
+/// files, one subdirectory per guild and channel.
+pub struct MessageLogFile {
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub index: usize,
+    pub path: PathBuf,
+}
+
+/// A message read back out of a message log.
+pub struct LoggedLine {
+    pub timestamp: DateTime<Utc>,
+    pub author: String,
+    pub content: String,
+}
+
+/// Finds the message log files in the directory they were kept in, ordered by channel
+/// and then in the order they were written. Only summarized logs were deleted, so
+/// every file found holds messages that were never summarized.
+pub fn find_message_logs(message_log_path: &Path) -> std::io::Result<Vec<MessageLogFile>> {
+    let mut logs = vec![];
+    if !message_log_path.is_dir() {
+        return Ok(logs);
+    }
+    for guild_entry in std::fs::read_dir(message_log_path)? {
+        let guild_dir = guild_entry?.path();
+        let Some(guild_name) = guild_dir.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let guild_id = match guild_name {
+            "no_guild" => None,
+            name => match name.parse::<u64>() {
+                Ok(id) if id != 0 => Some(GuildId::new(id)),
+                _ => continue,
+            },
+        };
+        if !guild_dir.is_dir() {
+            continue;
+        }
+        for channel_entry in std::fs::read_dir(&guild_dir)? {
+            let channel_dir = channel_entry?.path();
+            let channel_id = match channel_dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse::<u64>().ok())
+            {
+                Some(id) if id != 0 && channel_dir.is_dir() => ChannelId::new(id),
+                _ => continue,
+            };
+            for file_entry in std::fs::read_dir(&channel_dir)? {
+                let path = file_entry?.path();
+                let index = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_prefix("messages_"))
+                    .and_then(|name| name.strip_suffix(".txt"))
+                    .and_then(|index| index.parse::<usize>().ok());
+                if let Some(index) = index {
+                    logs.push(MessageLogFile {
+                        guild_id,
+                        channel_id,
+                        index,
+                        path,
+                    });
+                }
+            }
+        }
+    }
+    logs.sort_by_key(|log| (log.channel_id, log.index));
+    Ok(logs)
+}
+
+/// Reads the messages back out of the contents of a message log, which has a line per
+/// message. Lines that do not start a message continue the content of the one before.
+pub fn parse_message_log(contents: &str) -> Vec<LoggedLine> {
+    let mut messages: Vec<LoggedLine> = vec![];
+    for line in contents.lines() {
+        let message = line.strip_prefix("timestamp: ").and_then(|rest| {
+            let (timestamp, rest) = rest.split_once(", author: ")?;
+            let (author, content) = rest.split_once(", content: ")?;
+            let timestamp = DateTime::parse_from_rfc3339(timestamp).ok()?;
+            Some(LoggedLine {
+                timestamp: timestamp.with_timezone(&Utc),
+                author: author.to_string(),
+                content: content.to_string(),
+            })
+        });
+        match (message, messages.last_mut()) {
+            (Some(message), _) => messages.push(message),
+            (None, Some(previous)) => {
+                previous.content.push('\n');
+                previous.content.push_str(line);
+            }
+            (None, None) => {}
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_spanning_several_lines_are_read_back_whole() {
+        let contents = "timestamp: 2024-01-02T10:00:00Z, author: alice, content: hello\n\
+            timestamp: 2024-01-02T10:01:30.250Z, author: bob, content: first line\n\
+            second line\n";
+        let messages = parse_message_log(contents);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].author, "alice");
+        assert_eq!(messages[0].content, "hello");
+        assert_eq!(messages[1].author, "bob");
+        assert_eq!(messages[1].content, "first line\nsecond line");
+        assert_eq!(
+            messages[1].timestamp,
+            DateTime::parse_from_rfc3339("2024-01-02T10:01:30.250Z").unwrap()
+        );
+    }
+
+    #[test]
+    fn log_files_are_found_in_the_order_they_were_written() {
+        let dir = std::env::temp_dir().join(format!("message-logs-{}", std::process::id()));
+        let channel_dir = dir.join("1").join("2");
+        std::fs::create_dir_all(&channel_dir).unwrap();
+        for name in ["messages_10.txt", "messages_2.txt", "notes.txt"] {
+            std::fs::write(channel_dir.join(name), "").unwrap();
+        }
+        let logs = find_message_logs(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let indices: Vec<usize> = logs.iter().map(|log| log.index).collect();
+        assert_eq!(indices, [2, 10]);
+        assert_eq!(logs[0].guild_id, Some(GuildId::new(1)));
+        assert_eq!(logs[0].channel_id, ChannelId::new(2));
+    }
+}
@@ -1,4 +1,43 @@
+pub mod alerts;
+pub mod anomalies;
+pub mod backfill;
+pub mod changes;
+pub mod channel_groups;
+pub mod citations;
+pub mod commands;
 pub mod digests;
+pub mod discord_export;
 pub mod discord_handler;
+pub mod email;
+pub mod embeddings;
+pub mod events;
+pub mod faq;
+pub mod github;
+pub mod glossary;
+pub mod highlights;
+pub mod ingest;
+pub mod keyword_watch;
+pub mod knowledge_base;
+pub mod links;
+pub mod matrix;
+pub mod mentions;
 pub mod message_listener;
+pub mod message_logs;
+pub mod pending;
+pub mod privacy;
+pub mod prompt_reload;
+pub mod questions;
+pub mod reactions;
+pub mod scheduled_events;
+pub mod sinks;
+pub mod sources;
+pub mod standup;
+pub mod stats;
+pub mod subscriptions;
 pub mod summarizer;
+pub mod telegram;
+pub mod topics;
+pub mod verification;
+#[cfg(feature = "voice")]
+pub mod voice;
+pub mod webhooks;
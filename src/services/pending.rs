@@ -0,0 +1,67 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::all::{ChannelId, GuildId};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc::Sender;
+use tokio::time::interval;
+use tracing::{error, info};
+
+use crate::db;
+
+use super::summarizer::SummarizeRequest;
+
+/// Periodically re-sends summarize requests that previously failed and were
+/// persisted in the `pending_summaries` table.
+pub struct PendingSummaryService {
+    db: Arc<SqlitePool>,
+    summarize_tx: Sender<SummarizeRequest>,
+    interval: Duration,
+}
+
+impl PendingSummaryService {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        summarize_tx: Sender<SummarizeRequest>,
+        interval_seconds: u64,
+    ) -> Self {
+        Self {
+            db,
+            summarize_tx,
+            interval: Duration::from_secs(interval_seconds),
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval_timer = interval(self.interval);
+        loop {
+            interval_timer.tick().await;
+            let due =
+                match db::claim_due_pending_summaries(&self.db, self.interval.as_secs() as i64)
+                    .await
+                {
+                    Ok(due) => due,
+                    Err(e) => {
+                        error!("Could not fetch pending summaries: {e}");
+                        continue;
+                    }
+                };
+            for pending in due {
+                info!(
+                    "Retrying summarization of messages up to {} for channel {} (attempt {})",
+                    pending.up_to_message_id,
+                    pending.channel_id,
+                    pending.attempts + 1
+                );
+                let request = SummarizeRequest::Messages {
+                    guild_id: pending.guild_id.map(|id| GuildId::new(id as u64)),
+                    channel_id: ChannelId::new(pending.channel_id as u64),
+                    up_to_message_id: pending.up_to_message_id,
+                    reply: None,
+                };
+                if let Err(e) = self.summarize_tx.send(request).await {
+                    error!("Could not send pending summarize request over channel: {e}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serenity::all::{ChannelId, GuildId, UserId};
+use serenity::http::Http;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use tracing::{error, info, warn};
+
+use crate::db;
+use crate::gpt::{StructuredSummary, Summarizer};
+use crate::names::DiscordNames;
+use crate::prompts::{format_prompt_time, PromptVars, Prompts};
+
+use super::summarizer::{message_contents, summarize_channel_messages};
+
+/// Text of summaries whose messages were all deleted, or that could not be written
+/// again without the deleted ones.
+const ERASED_SUMMARY: &str = "This summary was removed at the request of a member.";
+
+/// The members who opted out of logging, shared by the Discord handler, the /optout
+/// command and the HTTP API.
+#[derive(Clone, Default)]
+pub struct OptOuts {
+    users: Arc<RwLock<HashSet<UserId>>>,
+}
+
+impl OptOuts {
+    pub async fn load(db: &SqlitePool) -> eyre::Result<Self> {
+        let users = db::fetch_opted_out_users(db)
+            .await?
+            .into_iter()
+            .map(|id| UserId::new(id as u64))
+            .collect();
+        Ok(Self {
+            users: Arc::new(RwLock::new(users)),
+        })
+    }
+
+    pub fn contains(&self, user_id: UserId) -> bool {
+        self.users
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(&user_id)
+    }
+
+    async fn insert(&self, db: &SqlitePool, user_id: UserId) -> eyre::Result<()> {
+        db::insert_opted_out_user(db, user_id.get() as i64).await?;
+        self.users
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(user_id);
+        Ok(())
+    }
+}
+
+/// Stable pseudonyms replacing the names of authors, such as `member-1a2b3c4d`, derived
+/// from their Discord ID with a keyed hash.
+#[derive(Clone)]
+pub struct Pseudonyms {
+    key: Vec<u8>,
+}
+
+impl Pseudonyms {
+    pub fn new(key: Option<&str>) -> Self {
+        if key.is_none() {
+            warn!("No privacy.pseudonym_key is configured, pseudonyms can be traced back to members by hashing their IDs");
+        }
+        Self {
+            key: key.unwrap_or_default().as_bytes().to_vec(),
+        }
+    }
+
+    pub fn pseudonym(&self, user_id: UserId) -> String {
+        self.pseudonym_of(&user_id.get().to_be_bytes())
+    }
+
+    /// The pseudonym of a member only known by name, as in the message logs of older
+    /// versions of the bot.
+    pub fn pseudonym_for_name(&self, name: &str) -> String {
+        self.pseudonym_of(name.as_bytes())
+    }
+
+    fn pseudonym_of(&self, bytes: &[u8]) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes keys of any length");
+        mac.update(bytes);
+        let hash = hex::encode(mac.finalize().into_bytes());
+        format!("member-{}", &hash[..8])
+    }
+}
+
+/// What erasing a member's data removed.
+#[derive(Serialize)]
+pub struct ErasureReport {
+    pub messages_deleted: u64,
+    /// Summaries written again from the messages left.
+    pub summaries_rewritten: usize,
+    /// Summaries replaced by a notice, because none of their messages were left or
+    /// they could not be written again.
+    pub summaries_removed: usize,
+}
+
+/// Opts members out of logging and erases what was stored of them.
+#[derive(Clone)]
+pub struct DataEraser {
+    db: Arc<SqlitePool>,
+    http: Arc<Http>,
+    opt_outs: OptOuts,
+    summarizer: Arc<dyn Summarizer>,
+    prompts: Prompts,
+    /// Looks up the guild and channel names used in prompts, when set.
+    names: Option<DiscordNames>,
+    /// Summarizers of the models prompt profiles use.
+    models: Arc<HashMap<String, Arc<dyn Summarizer>>>,
+}
+
+impl DataEraser {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        http: Arc<Http>,
+        opt_outs: OptOuts,
+        summarizer: Arc<dyn Summarizer>,
+    ) -> Self {
+        Self {
+            db,
+            http,
+            opt_outs,
+            summarizer,
+            prompts: Prompts::default(),
+            names: None,
+            models: Arc::default(),
+        }
+    }
+
+    /// Writes summaries again with the configured prompt templates and profiles.
+    pub fn with_prompts(
+        mut self,
+        prompts: Prompts,
+        names: DiscordNames,
+        models: Arc<HashMap<String, Arc<dyn Summarizer>>>,
+    ) -> Self {
+        self.prompts = prompts;
+        self.names = Some(names);
+        self.models = models;
+        self
+    }
+
+    /// Stops logging a member's messages, deletes those already stored and writes the
+    /// summaries they went into again without them. Digests already produced are left
+    /// as they are.
+    pub async fn erase(&self, user_id: UserId) -> eyre::Result<ErasureReport> {
+        self.opt_outs.insert(&self.db, user_id).await?;
+        // Messages stored before author IDs were recorded only have the author's name.
+        let author = match user_id.to_user(&self.http).await {
+            Ok(user) => Some(user.name),
+            Err(e) => {
+                warn!("Could not look up user {user_id}, only deleting the messages stored with their ID: {e}");
+                None
+            }
+        };
+        let deleted =
+            db::delete_user_messages(&self.db, user_id.get() as i64, author.as_deref()).await?;
+        info!(
+            "Deleted {} messages of user {user_id}, rewriting {} summaries",
+            deleted.count,
+            deleted.summary_ids.len()
+        );
+        let mut report = ErasureReport {
+            messages_deleted: deleted.count,
+            summaries_rewritten: 0,
+            summaries_removed: 0,
+        };
+        for summary_id in deleted.summary_ids {
+            if self.rewrite_summary(summary_id).await? {
+                report.summaries_rewritten += 1;
+            } else {
+                report.summaries_removed += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Writes a summary again from the messages it has left, or replaces it with a
+    /// notice when there are none or summarizing fails. Returns whether it was written
+    /// again.
+    async fn rewrite_summary(&self, summary_id: i64) -> eyre::Result<bool> {
+        let messages = db::fetch_summary_messages(&self.db, summary_id).await?;
+        let Some(first) = messages.first() else {
+            self.remove_summary(summary_id).await?;
+            return Ok(false);
+        };
+        let guild_id = first.guild_id.map(|id| GuildId::new(id as u64));
+        let channel_id = ChannelId::new(first.channel_id as u64);
+        let covers_from = messages.iter().map(|msg| msg.timestamp).min();
+        let covers_to = messages.iter().map(|msg| msg.timestamp).max();
+        let vars = PromptVars::lookup(
+            self.names.as_ref(),
+            guild_id,
+            Some(channel_id),
+            format_prompt_time(covers_from),
+            format_prompt_time(covers_to),
+        )
+        .await;
+        let prompt = self
+            .prompts
+            .summary(channel_id, &vars, &message_contents(&messages));
+        let summary =
+            match summarize_channel_messages(&self.summarizer, &self.models, &prompt, &messages)
+                .await
+            {
+                Ok(summary) => summary,
+                Err(e) => {
+                    error!("Could not rewrite summary {summary_id}, removing it instead: {e}");
+                    self.remove_summary(summary_id).await?;
+                    return Ok(false);
+                }
+            };
+        let new_summary = db::NewSummary {
+            guild_id: first.guild_id,
+            channel_id: first.channel_id,
+            summary: &summary,
+            message_count: messages.len() as i64,
+            covers_from,
+            covers_to,
+        };
+        db::replace_summary(&self.db, summary_id, new_summary).await?;
+        Ok(true)
+    }
+
+    async fn remove_summary(&self, summary_id: i64) -> eyre::Result<()> {
+        let notice = StructuredSummary::unstructured(ERASED_SUMMARY.to_string());
+        // The notice has no action items, the only use of the guild and channel.
+        let new_summary = db::NewSummary {
+            guild_id: None,
+            channel_id: 0,
+            summary: &notice,
+            message_count: 0,
+            covers_from: None,
+            covers_to: None,
+        };
+        db::replace_summary(&self.db, summary_id, new_summary).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fs, time::Duration, time::SystemTime};
+
+use tokio::time::interval;
+use tracing::{error, info};
+
+use crate::config::{AppConfig, PromptsConfig};
+use crate::prompts::Prompts;
+
+/// How often the config and template files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reloads the prompt templates whenever the config file or a template file changes,
+/// so prompts can be tuned without restarting.
+pub struct PromptReloadService {
+    prompts: Prompts,
+    config_path: String,
+    config: PromptsConfig,
+    /// Modification times of the watched files when they were last loaded.
+    modified: Vec<Option<SystemTime>>,
+}
+
+impl PromptReloadService {
+    pub fn new(prompts: Prompts, config_path: &str, config: PromptsConfig) -> Self {
+        let mut service = Self {
+            prompts,
+            config_path: config_path.to_string(),
+            config,
+            modified: vec![],
+        };
+        service.modified = service.modification_times();
+        service
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval_timer = interval(POLL_INTERVAL);
+        loop {
+            interval_timer.tick().await;
+            let modified = self.modification_times();
+            if modified == self.modified {
+                continue;
+            }
+            self.modified = modified;
+            let config = match AppConfig::load_from_file(&self.config_path) {
+                Ok(config) => config.prompts,
+                Err(e) => {
+                    error!("Could not reload prompt templates, keeping the current ones: {e}");
+                    continue;
+                }
+            };
+            match self.prompts.reload(&config) {
+                Ok(()) => info!("Reloaded prompt templates"),
+                Err(e) => {
+                    error!("Could not reload prompt templates, keeping the current ones: {e:#}")
+                }
+            }
+            // The template files to watch may have changed along with the config.
+            self.config = config;
+            self.modified = self.modification_times();
+        }
+    }
+
+    fn modification_times(&self) -> Vec<Option<SystemTime>> {
+        let profile_files = self
+            .config
+            .profiles
+            .values()
+            .map(|profile| profile.summary_file.as_deref());
+        [
+            Some(self.config_path.as_str()),
+            self.config.summary_file.as_deref(),
+            self.config.digest_file.as_deref(),
+        ]
+        .into_iter()
+        .chain(profile_files)
+        .flatten()
+        .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .collect()
+    }
+}
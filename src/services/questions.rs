@@ -0,0 +1,80 @@
+use chrono_tz::Tz;
+
+use crate::db::UnansweredQuestion;
+
+/// Most questions listed in the open questions section of a digest.
+const MAX_LISTED: usize = 15;
+/// Questions longer than this are cut short in the open questions section.
+const MAX_QUESTION_CHARS: usize = 200;
+
+/// Whether a message asks a question: some sentence of it ends with a question mark,
+/// outside of quotes, code and links.
+pub fn is_question(content: &str) -> bool {
+    let mut in_code_block = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block || line.starts_with('>') {
+            continue;
+        }
+        let prose: Vec<&str> = line
+            .split('`')
+            .step_by(2)
+            .flat_map(str::split_whitespace)
+            .filter(|word| !word.starts_with("http://") && !word.starts_with("https://"))
+            .collect();
+        let words = prose
+            .iter()
+            .filter(|word| word.chars().any(char::is_alphabetic))
+            .count();
+        let asks = prose.iter().any(|word| {
+            let word = word.trim_end_matches(['!', '.', ')', '"', '\'', '*', '_']);
+            word.ends_with('?')
+                && word
+                    .trim_end_matches('?')
+                    .chars()
+                    .last()
+                    .is_some_and(char::is_alphanumeric)
+        });
+        if asks && words >= 2 {
+            return true;
+        }
+    }
+    false
+}
+
+/// Renders the "Open questions" section appended to daily digests, or `None` when
+/// there is nothing to follow up on.
+pub fn open_questions_section(questions: &[UnansweredQuestion], timezone: Tz) -> Option<String> {
+    if questions.is_empty() {
+        return None;
+    }
+    let mut section = String::from("**Open questions**");
+    for question in questions.iter().take(MAX_LISTED) {
+        let content = question
+            .content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let content = match content.char_indices().nth(MAX_QUESTION_CHARS) {
+            Some((end, _)) => format!("{}...", &content[..end]),
+            None => content,
+        };
+        section.push_str(&format!(
+            "\n- {content} ({} in <#{}>, {})",
+            question.author,
+            question.channel_id,
+            question
+                .asked_at
+                .with_timezone(&timezone)
+                .format("%b %-d, %H:%M")
+        ));
+    }
+    if questions.len() > MAX_LISTED {
+        section.push_str(&format!("\n- ...and {} more", questions.len() - MAX_LISTED));
+    }
+    Some(section)
+}
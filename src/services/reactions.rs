@@ -0,0 +1,34 @@
+use crate::db::ReactedMessage;
+
+/// Most messages listed to the model as the most reacted of a daily digest's period.
+pub const MAX_REACTED: i64 = 5;
+/// Messages longer than this are cut short in the list.
+const MAX_MESSAGE_CHARS: usize = 300;
+
+/// Appended to the digest instructions when the most reacted messages are listed.
+pub const REACTIONS_EMPHASIS: &str = "The messages members reacted to the most are listed after the summaries. Emphasize what they are about.";
+
+/// Lists the messages members reacted to the most after the summaries a daily digest is
+/// written from, or `None` when nobody reacted to anything.
+pub fn most_reacted_section(messages: &[ReactedMessage]) -> Option<String> {
+    if messages.is_empty() {
+        return None;
+    }
+    let mut section = String::from("Messages members reacted to the most:");
+    for message in messages {
+        let content = message
+            .content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let content = match content.char_indices().nth(MAX_MESSAGE_CHARS) {
+            Some((end, _)) => format!("{}...", &content[..end]),
+            None => content,
+        };
+        section.push_str(&format!(
+            "\n- {} in channel {}: \"{content}\" ({} reactions)",
+            message.author, message.channel_id, message.reaction_count
+        ));
+    }
+    Some(section)
+}
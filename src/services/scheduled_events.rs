@@ -0,0 +1,256 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serenity::all::{GuildId, ScheduledEvent, ScheduledEventStatus};
+use serenity::http::Http;
+use sqlx::SqlitePool;
+use tracing::error;
+
+use crate::db::{self, ConcludedEvent, NewScheduledEvent};
+
+/// Most upcoming or concluded events listed in a digest.
+const MAX_LISTED: usize = 10;
+/// Summaries of concluded events longer than this are cut short.
+const MAX_SUMMARY_CHARS: usize = 400;
+
+/// An event that has yet to start or is happening now.
+pub struct UpcomingEvent {
+    pub event_id: u64,
+    pub guild_id: u64,
+    pub channel_id: Option<u64>,
+    pub name: String,
+    pub location: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub active: bool,
+    pub user_count: Option<u64>,
+}
+
+/// A concluded event along with what was said in its channel while it was on.
+pub struct EventRecap {
+    pub event: ConcludedEvent,
+    pub summary: Option<String>,
+}
+
+/// Keeps track of the scheduled events of guilds, from the gateway events the bot
+/// receives and the REST API, so that daily digests can list the upcoming ones and
+/// summarize those that concluded.
+#[derive(Clone)]
+pub struct ScheduledEvents {
+    db: Arc<SqlitePool>,
+    http: Arc<Http>,
+}
+
+impl ScheduledEvents {
+    pub fn new(db: Arc<SqlitePool>, http: Arc<Http>) -> Self {
+        Self { db, http }
+    }
+
+    /// Stores the latest state of an event.
+    pub async fn record(&self, event: &ScheduledEvent) {
+        let Some(status) = status(event.status) else {
+            return;
+        };
+        let new_event = NewScheduledEvent {
+            event_id: event.id.get() as i64,
+            guild_id: event.guild_id.get() as i64,
+            channel_id: event.channel_id.map(|id| id.get() as i64),
+            name: event.name.clone(),
+            description: event.description.clone().filter(|d| !d.is_empty()),
+            location: event
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.location.clone()),
+            starts_at: timestamp(event.start_time),
+            ends_at: event.end_time.map(timestamp),
+            status,
+            user_count: event.user_count.map(|count| count as i64),
+        };
+        if let Err(e) = db::upsert_scheduled_event(&self.db, &new_event).await {
+            error!("Could not store scheduled event {}: {e}", event.id);
+        }
+    }
+
+    /// Records that an event was deleted, which is how Discord cancels them.
+    pub async fn canceled(&self, event: &ScheduledEvent) {
+        if let Err(e) = db::cancel_scheduled_event(&self.db, event.id.get() as i64).await {
+            error!("Could not cancel scheduled event {}: {e}", event.id);
+        }
+    }
+
+    /// Fetches the events of a guild happening now or starting within `ahead`, soonest
+    /// first, storing their latest state along the way.
+    pub async fn upcoming(&self, guild_id: GuildId, ahead: Duration) -> Vec<UpcomingEvent> {
+        let events = match guild_id.scheduled_events(&self.http, true).await {
+            Ok(events) => events,
+            Err(e) => {
+                error!("Could not fetch the scheduled events of guild {guild_id}: {e}");
+                return vec![];
+            }
+        };
+        let until = Utc::now() + ahead;
+        let mut upcoming = vec![];
+        for event in events {
+            self.record(&event).await;
+            let active = match event.status {
+                ScheduledEventStatus::Active => true,
+                ScheduledEventStatus::Scheduled => false,
+                _ => continue,
+            };
+            let starts_at = timestamp(event.start_time);
+            if !active && starts_at > until {
+                continue;
+            }
+            upcoming.push(UpcomingEvent {
+                event_id: event.id.get(),
+                guild_id: guild_id.get(),
+                channel_id: event.channel_id.map(|id| id.get()),
+                name: event.name,
+                location: event.metadata.and_then(|metadata| metadata.location),
+                starts_at,
+                active,
+                user_count: event.user_count,
+            });
+        }
+        upcoming.sort_by_key(|event| event.starts_at);
+        upcoming
+    }
+
+    /// Fetches the events of a guild that concluded before `before` and were not
+    /// summarized yet, each with the summaries of its channel while it was on.
+    pub async fn concluded(&self, guild_id: i64, before: DateTime<Utc>) -> Vec<EventRecap> {
+        let events = match db::fetch_unlisted_concluded_events(&self.db, guild_id, before).await {
+            Ok(events) => events,
+            Err(e) => {
+                error!("Could not fetch the concluded events of guild {guild_id}: {e}");
+                return vec![];
+            }
+        };
+        let mut recaps = vec![];
+        for event in events {
+            let summary = match event.channel_id {
+                Some(channel_id) => db::fetch_channel_summary_texts(
+                    &self.db,
+                    channel_id,
+                    event.started_at,
+                    event.concluded_at,
+                )
+                .await
+                .unwrap_or_else(|e| {
+                    error!(
+                        "Could not fetch the summaries of event {}: {e}",
+                        event.event_id
+                    );
+                    vec![]
+                })
+                .into_iter()
+                .next(),
+                None => None,
+            };
+            recaps.push(EventRecap { event, summary });
+        }
+        recaps
+    }
+}
+
+fn status(status: ScheduledEventStatus) -> Option<db::ScheduledEventStatus> {
+    Some(match status {
+        ScheduledEventStatus::Scheduled => db::ScheduledEventStatus::Scheduled,
+        ScheduledEventStatus::Active => db::ScheduledEventStatus::Active,
+        ScheduledEventStatus::Completed => db::ScheduledEventStatus::Completed,
+        ScheduledEventStatus::Canceled => db::ScheduledEventStatus::Canceled,
+        _ => return None,
+    })
+}
+
+fn timestamp(timestamp: serenity::all::Timestamp) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp.unix_timestamp(), 0).unwrap_or_default()
+}
+
+/// Link opening an event in Discord.
+fn event_link(guild_id: u64, event_id: u64) -> String {
+    format!("https://discord.com/events/{guild_id}/{event_id}")
+}
+
+/// Where an event takes place: its channel, or its location for external events.
+fn venue(channel_id: Option<u64>, location: Option<&str>) -> String {
+    match (channel_id, location) {
+        (Some(channel_id), _) => format!(" in <#{channel_id}>"),
+        (None, Some(location)) => format!(" at {location}"),
+        (None, None) => String::new(),
+    }
+}
+
+/// Renders the "Upcoming events" section of daily digests, or `None` when no event is
+/// coming up.
+pub fn upcoming_events_section(events: &[UpcomingEvent], timezone: Tz) -> Option<String> {
+    if events.is_empty() {
+        return None;
+    }
+    let mut section = String::from("**Upcoming events**");
+    for event in events.iter().take(MAX_LISTED) {
+        let when = if event.active {
+            "happening now".to_string()
+        } else {
+            event
+                .starts_at
+                .with_timezone(&timezone)
+                .format("%a %b %-d, %H:%M")
+                .to_string()
+        };
+        section.push_str(&format!(
+            "\n- [{}](<{}>){}, {when}",
+            event.name,
+            event_link(event.guild_id, event.event_id),
+            venue(event.channel_id, event.location.as_deref())
+        ));
+        if let Some(count) = event.user_count.filter(|count| *count > 0) {
+            section.push_str(&format!(" ({count} interested)"));
+        }
+    }
+    if events.len() > MAX_LISTED {
+        section.push_str(&format!("\n- ...and {} more", events.len() - MAX_LISTED));
+    }
+    Some(section)
+}
+
+/// Renders the "Concluded events" section of daily digests, with the summary of what was
+/// said in each event's channel while it was on, or its description otherwise. `None`
+/// when no event concluded.
+pub fn concluded_events_section(recaps: &[EventRecap], timezone: Tz) -> Option<String> {
+    if recaps.is_empty() {
+        return None;
+    }
+    let mut section = String::from("**Concluded events**");
+    for recap in recaps.iter().take(MAX_LISTED) {
+        let event = &recap.event;
+        section.push_str(&format!(
+            "\n- **{}**{}, {} to {}",
+            event.name,
+            venue(
+                event.channel_id.map(|id| id as u64),
+                event.location.as_deref()
+            ),
+            event
+                .started_at
+                .with_timezone(&timezone)
+                .format("%b %-d, %H:%M"),
+            event.concluded_at.with_timezone(&timezone).format("%H:%M")
+        ));
+        if let Some(count) = event.user_count.filter(|count| *count > 0) {
+            section.push_str(&format!(" ({count} interested)"));
+        }
+        if let Some(text) = recap.summary.as_ref().or(event.description.as_ref()) {
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let text = match text.char_indices().nth(MAX_SUMMARY_CHARS) {
+                Some((end, _)) => format!("{}...", &text[..end]),
+                None => text,
+            };
+            section.push_str(&format!(": {text}"));
+        }
+    }
+    if recaps.len() > MAX_LISTED {
+        section.push_str(&format!("\n- ...and {} more", recaps.len() - MAX_LISTED));
+    }
+    Some(section)
+}
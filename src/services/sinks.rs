@@ -0,0 +1,344 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::async_trait;
+use chrono::Utc;
+use eyre::{bail, eyre};
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use serenity::all::{ChannelId, GuildId};
+use serenity::http::Http;
+use sqlx::SqlitePool;
+use tracing::{error, info, warn};
+
+use crate::config::{AppConfig, SinkConfig, SinkKind};
+use crate::db::{self, RollupTier};
+use crate::templates::{DigestTarget, DigestTemplates, DigestView};
+
+use super::digests::{channel_summaries, split_message, DISCORD_MESSAGE_LIMIT};
+use super::email::EmailSender;
+use super::matrix::MatrixSender;
+use super::telegram::TelegramSender;
+use super::webhooks::{sign, SIGNATURE_HEADER};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Somewhere digests are delivered to once they are stored.
+#[async_trait]
+pub trait DigestSink: Send + Sync {
+    /// What the sink is called in logs, such as "Discord channel 123".
+    fn name(&self) -> String;
+
+    async fn deliver(&self, templates: &DigestTemplates, digest: &DigestView) -> eyre::Result<()>;
+}
+
+/// Which digests a sink gets.
+#[derive(Default)]
+pub struct SinkFilter {
+    /// Every guild when empty.
+    pub guilds: Vec<GuildId>,
+    /// When set, only the summaries of these channels are delivered, out of daily
+    /// digests.
+    pub channels: Option<Vec<ChannelId>>,
+    /// The channel group `channels` come from, whose digest is delivered instead of
+    /// the summaries when one was written.
+    pub group: Option<String>,
+    /// Every tier when empty.
+    pub tiers: Vec<RollupTier>,
+}
+
+impl SinkFilter {
+    fn wants(&self, tier: RollupTier, guild_id: Option<i64>) -> bool {
+        let guild_matches = self.guilds.is_empty()
+            || guild_id.is_some_and(|guild_id| {
+                self.guilds
+                    .iter()
+                    .any(|guild| guild.get() as i64 == guild_id)
+            });
+        let tier_matches = self.tiers.is_empty() || self.tiers.contains(&tier);
+        let channels_match = self.channels.is_none() || tier == RollupTier::Daily;
+        guild_matches && tier_matches && channels_match
+    }
+}
+
+/// Every configured sink, along with the digests each of them gets.
+#[derive(Clone)]
+pub struct Sinks {
+    db: Arc<SqlitePool>,
+    templates: Arc<DigestTemplates>,
+    sinks: Arc<Vec<(Box<dyn DigestSink>, SinkFilter)>>,
+}
+
+impl Sinks {
+    /// Builds the sinks of `[[sinks]]`, along with those of the sections that predate
+    /// them: the digest channels of `[[discord.guilds]]`, which get the digests of their
+    /// guild, the recipients of `[email]`, who get daily digests, and the chat and room
+    /// of `[telegram]` and `[matrix]`, which get every digest.
+    pub fn from_config(
+        db: Arc<SqlitePool>,
+        templates: Arc<DigestTemplates>,
+        http: Arc<Http>,
+        config: &AppConfig,
+    ) -> eyre::Result<Self> {
+        let mut sinks: Vec<(Box<dyn DigestSink>, SinkFilter)> = vec![];
+        for (guild_id, channel_id) in config.discord.digest_channels()? {
+            sinks.push((
+                Box::new(DiscordSink::new(http.clone(), channel_id)),
+                SinkFilter {
+                    guilds: vec![guild_id],
+                    ..SinkFilter::default()
+                },
+            ));
+        }
+        if let Some(email) =
+            EmailSender::from_config(db.clone(), &config.email, &config.email.recipients)?
+        {
+            sinks.push((
+                Box::new(email),
+                SinkFilter {
+                    tiers: vec![RollupTier::Daily],
+                    ..SinkFilter::default()
+                },
+            ));
+        }
+        if let Some(chat_id) = &config.telegram.chat_id {
+            let telegram = TelegramSender::from_config(&config.telegram, chat_id)?;
+            sinks.push((Box::new(telegram), SinkFilter::default()));
+        }
+        if let Some(room_id) = &config.matrix.room_id {
+            let matrix = MatrixSender::from_config(&config.matrix, room_id)?;
+            sinks.push((Box::new(matrix), SinkFilter::default()));
+        }
+        for sink in &config.sinks {
+            sinks.push((
+                build_sink(&db, &http, config, sink)?,
+                SinkFilter {
+                    guilds: sink.guild_ids()?,
+                    channels: sink.channel_ids(&config.channel_groups)?,
+                    group: sink.channel_group.clone(),
+                    tiers: sink.tiers.clone(),
+                },
+            ));
+        }
+        Ok(Self {
+            db,
+            templates,
+            sinks: Arc::new(sinks),
+        })
+    }
+
+    /// Delivers a newly stored digest to every sink that wants it, one after the other.
+    /// Failures are only logged, as one sink failing should not keep the others from
+    /// getting the digest.
+    pub async fn deliver(&self, tier: RollupTier, digest: &DigestView<'_>) {
+        let Some(digest_id) = digest.id else {
+            return;
+        };
+        // Loaded for the first sink limited to a channel group.
+        let mut stored: Option<Option<db::DailyDigest>> = None;
+        for (sink, filter) in self.sinks.iter() {
+            if !filter.wants(tier, digest.guild_id) {
+                continue;
+            }
+            let group_digest = match &filter.group {
+                Some(group) => self.group_digest(digest_id, group).await,
+                None => None,
+            };
+            let result = match (&filter.channels, group_digest) {
+                (None, _) => sink.deliver(&self.templates, digest).await,
+                (Some(_), Some(group_digest)) => {
+                    let digest = DigestView {
+                        title: format!("{} ({})", digest.title, group_digest.group_name),
+                        text: &group_digest.text,
+                        message_count: group_digest.message_count,
+                        ..digest.clone()
+                    };
+                    sink.deliver(&self.templates, &digest).await
+                }
+                (Some(channels), None) => {
+                    if stored.is_none() {
+                        stored = Some(match db::fetch_daily_digest(&self.db, digest_id).await {
+                            Ok(stored) => stored,
+                            Err(e) => {
+                                error!("Could not fetch digest {digest_id} for sinks: {e}");
+                                None
+                            }
+                        });
+                    }
+                    let Some(Some(stored)) = &stored else {
+                        continue;
+                    };
+                    let channel_ids: Vec<i64> = channels
+                        .iter()
+                        .map(|channel| channel.get() as i64)
+                        .collect();
+                    let Some((text, message_count)) = channel_summaries(stored, &channel_ids)
+                    else {
+                        continue;
+                    };
+                    let digest = DigestView {
+                        text: &text,
+                        message_count,
+                        ..digest.clone()
+                    };
+                    sink.deliver(&self.templates, &digest).await
+                }
+            };
+            match result {
+                Ok(()) => info!(
+                    "Delivered {} digest {digest_id} to {}",
+                    tier.name(),
+                    sink.name()
+                ),
+                Err(e) => warn!(
+                    "Could not deliver {} digest {digest_id} to {}: {e:#}",
+                    tier.name(),
+                    sink.name()
+                ),
+            }
+        }
+    }
+
+    /// The digest of a channel group written along with a daily digest, if any.
+    async fn group_digest(&self, digest_id: i64, group: &str) -> Option<db::GroupDigest> {
+        db::fetch_daily_group_digest(&self.db, digest_id, group)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Could not fetch the digest of channel group {group:?} for digest {digest_id}: {e}");
+                None
+            })
+    }
+}
+
+fn build_sink(
+    db: &Arc<SqlitePool>,
+    http: &Arc<Http>,
+    config: &AppConfig,
+    sink: &SinkConfig,
+) -> eyre::Result<Box<dyn DigestSink>> {
+    Ok(match &sink.kind {
+        SinkKind::Discord { .. } => {
+            let channel_id = sink
+                .kind
+                .discord_channel()?
+                .ok_or_else(|| eyre!("Discord sink has no channel"))?;
+            Box::new(DiscordSink::new(http.clone(), channel_id))
+        }
+        SinkKind::Webhook { url, secret } => Box::new(WebhookSink::new(url, secret.clone())),
+        SinkKind::Email { recipients } => {
+            match EmailSender::from_config(db.clone(), &config.email, recipients)? {
+                Some(email) => Box::new(email),
+                None => bail!("email sinks must list at least one recipient"),
+            }
+        }
+        SinkKind::Telegram { chat_id } => {
+            Box::new(TelegramSender::from_config(&config.telegram, chat_id)?)
+        }
+        SinkKind::Matrix { room_id } => {
+            Box::new(MatrixSender::from_config(&config.matrix, room_id)?)
+        }
+        SinkKind::Stdout => Box::new(StdoutSink),
+    })
+}
+
+/// Posts digests to a Discord channel, rendered with the Discord template.
+pub struct DiscordSink {
+    http: Arc<Http>,
+    channel_id: ChannelId,
+}
+
+impl DiscordSink {
+    pub fn new(http: Arc<Http>, channel_id: ChannelId) -> Self {
+        Self { http, channel_id }
+    }
+}
+
+#[async_trait]
+impl DigestSink for DiscordSink {
+    fn name(&self) -> String {
+        format!("Discord channel {}", self.channel_id)
+    }
+
+    async fn deliver(&self, templates: &DigestTemplates, digest: &DigestView) -> eyre::Result<()> {
+        let content = templates.render(DigestTarget::Discord, digest);
+        for chunk in split_message(&content, DISCORD_MESSAGE_LIMIT) {
+            self.channel_id.say(&self.http, chunk).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Body of the requests of webhook sinks.
+#[derive(Serialize)]
+struct SinkPayload<'a> {
+    sent_at: chrono::DateTime<Utc>,
+    digest: &'a DigestView<'a>,
+    /// The digest rendered with the API template.
+    rendered: String,
+}
+
+/// POSTs digests to a URL as JSON. Unlike `[[webhooks]]`, failed requests are not
+/// retried.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str, secret: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            url: url.to_string(),
+            secret,
+        }
+    }
+}
+
+#[async_trait]
+impl DigestSink for WebhookSink {
+    fn name(&self) -> String {
+        format!("webhook {}", self.url)
+    }
+
+    async fn deliver(&self, templates: &DigestTemplates, digest: &DigestView) -> eyre::Result<()> {
+        let payload = SinkPayload {
+            sent_at: Utc::now(),
+            digest,
+            rendered: templates.render(DigestTarget::Api, digest),
+        };
+        let body = serde_json::to_string(&payload)?;
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(secret) = &self.secret {
+            let signature = sign(secret, &body).map_err(|e| eyre!("invalid secret: {e}"))?;
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        let response = request.body(body).send().await?;
+        if !response.status().is_success() {
+            bail!("responded with {}", response.status());
+        }
+        Ok(())
+    }
+}
+
+/// Prints digests to standard output, rendered with the Discord template.
+pub struct StdoutSink;
+
+#[async_trait]
+impl DigestSink for StdoutSink {
+    fn name(&self) -> String {
+        "stdout".to_string()
+    }
+
+    async fn deliver(&self, templates: &DigestTemplates, digest: &DigestView) -> eyre::Result<()> {
+        println!("{}\n", templates.render(DigestTarget::Discord, digest));
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serenity::all::{ChannelId, GuildId, Message, MessageId, UserId};
+use serenity::Client;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Result;
+
+use super::discord_handler::{ReactionChange, ThreadInfo};
+use super::summarizer::SummaryReply;
+
+/// Something messages are ingested from, such as Discord. Sources are given a sender of
+/// [`IngestEvent`]s when they are created, and send what they receive to the message
+/// log, which stores and summarizes messages the same way whatever their source.
+#[async_trait]
+pub trait MessageSource: Send + 'static {
+    /// What the source is called in logs and service statuses.
+    fn name(&self) -> String;
+
+    /// Receives messages until the source stops or `shutdown` is cancelled. Called
+    /// again whenever it fails.
+    async fn run(&mut self, shutdown: CancellationToken) -> Result<()>;
+}
+
+/// What message sources send to the message log.
+pub enum IngestEvent {
+    Received(Box<IngestedMessage>),
+    /// A logged message was edited, its content is replaced unless it was already
+    /// summarized.
+    Edited {
+        message_id: MessageId,
+        content: String,
+    },
+    /// Logged messages were deleted.
+    Deleted {
+        message_ids: Vec<MessageId>,
+    },
+    /// The reactions on a logged message changed.
+    Reactions {
+        message_id: MessageId,
+        change: ReactionChange,
+    },
+    /// A member highlighted a logged message, or it lost its last highlight.
+    Highlighted {
+        message_id: MessageId,
+        highlighted: bool,
+    },
+    /// Messages fetched from the history of a channel, oldest first.
+    Backfilled {
+        channel_id: ChannelId,
+        messages: Vec<IngestedMessage>,
+    },
+    /// Summarize the messages collected so far in a channel without waiting for the
+    /// batch to fill up.
+    SummarizeNow {
+        channel_id: ChannelId,
+        reply: SummaryReply,
+    },
+}
+
+/// A message ready to be logged, whatever its source. Sources other than Discord
+/// make up IDs that do not collide with Discord's.
+pub struct IngestedMessage {
+    pub message_id: MessageId,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    pub author: String,
+    /// Content with mentions already readable, such as `@alice`.
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    pub reply_to: Option<MessageId>,
+    /// The thread the message was posted in, for messages of threads and forum posts.
+    pub thread: Option<ThreadInfo>,
+    pub attachments: Vec<IngestedAttachment>,
+}
+
+#[derive(Clone)]
+pub struct IngestedAttachment {
+    pub filename: String,
+    pub url: String,
+    pub content_type: Option<String>,
+    pub size: u64,
+}
+
+impl IngestedMessage {
+    pub fn from_discord(msg: Message, thread: Option<ThreadInfo>) -> Self {
+        Self {
+            message_id: msg.id,
+            guild_id: msg.guild_id,
+            channel_id: msg.channel_id,
+            author_id: msg.author.id,
+            author: msg.author.name,
+            content: msg.content,
+            timestamp: DateTime::from_timestamp(msg.timestamp.unix_timestamp(), 0)
+                .unwrap_or_default(),
+            reply_to: msg.referenced_message.map(|reply_to| reply_to.id),
+            thread,
+            attachments: msg
+                .attachments
+                .into_iter()
+                .map(|attachment| IngestedAttachment {
+                    filename: attachment.filename,
+                    url: attachment.url,
+                    content_type: attachment.content_type,
+                    size: u64::from(attachment.size),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Messages received from Discord through the bot's gateway connection, which also
+/// serves slash commands.
+pub struct DiscordSource {
+    client: Client,
+}
+
+impl DiscordSource {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl MessageSource for DiscordSource {
+    fn name(&self) -> String {
+        "discord".to_string()
+    }
+
+    /// Shutting down is left to the shard manager, which is stopped before anything
+    /// else so that the message log gets the last messages.
+    async fn run(&mut self, _: CancellationToken) -> Result<()> {
+        // The Serenity crate Will automatically attempt to reconnect, and will perform
+        // exponential backoff until it reconnects.
+        self.client.start().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use eyre::{eyre, WrapErr};
+use serde::Deserialize;
+use serenity::all::{ChannelId, GetMessages, Message, MessageId};
+use serenity::http::Http;
+use sqlx::SqlitePool;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+use crate::config::StandupConfig;
+use crate::db::{self, OpenStandup, StandupEntry};
+use crate::gpt::Summarizer;
+use crate::schedule::CronSchedule;
+
+use super::digests::{split_message, DISCORD_MESSAGE_LIMIT};
+
+/// Instructions asking the model to sort standup replies by person.
+const STANDUP_INSTRUCTIONS: &str = r#"You summarize the replies to a team's daily standup. For each person who replied, list in short items what they did yesterday, what they are doing today and what is blocking them, leaving a list empty when they did not say. Reply with a single JSON object and nothing else, in this format:
+{
+  "entries": [
+    {
+      "person": "name of the person, as in the replies",
+      "yesterday": ["..."],
+      "today": ["..."],
+      "blockers": ["..."]
+    }
+  ]
+}"#;
+
+const PAGE_SIZE: u8 = 100;
+
+#[derive(Deserialize)]
+struct StandupReply {
+    #[serde(default)]
+    entries: Vec<StandupEntry>,
+}
+
+impl StandupReply {
+    /// Parses the model's reply, tolerating Markdown code fences or text around the
+    /// JSON object.
+    fn parse(reply: &str) -> eyre::Result<Vec<StandupEntry>> {
+        let start = reply.find('{');
+        let end = reply.rfind('}');
+        let json = match start.zip(end) {
+            Some((start, end)) if start < end => &reply[start..=end],
+            _ => return Err(eyre!("Reply does not contain a JSON object")),
+        };
+        let parsed: Self =
+            serde_json::from_str(json).wrap_err("Reply is not a valid standup summary")?;
+        let mut entries = parsed.entries;
+        for entry in &mut entries {
+            entry.person = entry.person.trim().to_string();
+            // Models sometimes pad lists with blank entries.
+            for list in [&mut entry.yesterday, &mut entry.today, &mut entry.blockers] {
+                list.retain(|item| !item.trim().is_empty());
+            }
+        }
+        entries.retain(|entry| !entry.person.is_empty());
+        Ok(entries)
+    }
+}
+
+/// Asks a channel for standup updates on a schedule, collects the replies posted
+/// within a window, then posts and stores a summary of what each person did yesterday,
+/// is doing today and is blocked by. Standups are kept apart from digests.
+pub struct StandupService {
+    db: Arc<SqlitePool>,
+    http: Arc<Http>,
+    summarizer: Arc<dyn Summarizer>,
+    channel_id: ChannelId,
+    schedule: CronSchedule,
+    /// How long replies are collected for.
+    window: Duration,
+    prompt: String,
+    /// Timezone standup dates are reported in.
+    timezone: Tz,
+}
+
+impl StandupService {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        http: Arc<Http>,
+        summarizer: Arc<dyn Summarizer>,
+        channel_id: ChannelId,
+        config: &StandupConfig,
+        timezone: Tz,
+    ) -> eyre::Result<Self> {
+        Ok(Self {
+            db,
+            http,
+            summarizer,
+            channel_id,
+            schedule: CronSchedule::new(&config.schedule, timezone)
+                .wrap_err("Invalid standup schedule")?,
+            window: Duration::minutes(config.window_minutes as i64),
+            prompt: config.prompt.clone(),
+            timezone,
+        })
+    }
+
+    pub async fn run(&mut self) {
+        loop {
+            let now = Utc::now();
+            // Standups still open when the bot restarts are closed on time all the same.
+            let open = match db::fetch_open_standups(&self.db).await {
+                Ok(open) => open,
+                Err(e) => {
+                    error!("Could not fetch open standups: {e}");
+                    vec![]
+                }
+            };
+            for standup in open.iter().filter(|standup| standup.closes_at <= now) {
+                if let Err(e) = self.close(standup).await {
+                    error!("Could not summarize standup {}: {e:#}", standup.id);
+                }
+            }
+
+            let ask_at = match self.schedule.next_after(now) {
+                Ok(ask_at) => ask_at,
+                Err(e) => {
+                    error!("Could not compute the next standup time: {e}");
+                    return;
+                }
+            };
+            // Standups that failed to close are retried on the next wake up, rather
+            // than right away.
+            let wake_at = open
+                .iter()
+                .map(|standup| standup.closes_at)
+                .filter(|closes_at| *closes_at > now)
+                .fold(ask_at, DateTime::min);
+            sleep((wake_at - Utc::now()).to_std().unwrap_or_default()).await;
+            if wake_at == ask_at {
+                if let Err(e) = self.open(ask_at).await {
+                    error!("Could not start standup: {e:#}");
+                }
+            }
+        }
+    }
+
+    /// Asks the channel for updates.
+    async fn open(&self, opened_at: DateTime<Utc>) -> eyre::Result<()> {
+        info!("Starting standup in channel {}", self.channel_id);
+        let guild_id = self
+            .channel_id
+            .to_channel(&self.http)
+            .await?
+            .guild()
+            .map(|channel| channel.guild_id.get() as i64);
+        let prompt = self.channel_id.say(&self.http, &self.prompt).await?;
+        db::insert_standup(
+            &self.db,
+            guild_id,
+            self.channel_id.get() as i64,
+            prompt.id.get() as i64,
+            opened_at,
+            opened_at + self.window,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Summarizes the replies to a standup and posts the summary.
+    async fn close(&self, standup: &OpenStandup) -> eyre::Result<()> {
+        let channel_id = ChannelId::new(standup.channel_id as u64);
+        let replies = self.replies(channel_id, standup).await?;
+        info!(
+            "Summarizing standup {} from {} replies",
+            standup.id,
+            replies.len()
+        );
+        let entries = if replies.is_empty() {
+            vec![]
+        } else {
+            let transcript = replies
+                .iter()
+                .map(|msg| {
+                    let name = msg.author.global_name.as_ref().unwrap_or(&msg.author.name);
+                    format!("{name}: {}", msg.content)
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let reply = self
+                .summarizer
+                .complete(STANDUP_INSTRUCTIONS, &transcript)
+                .await?;
+            StandupReply::parse(&reply)?
+        };
+        let summary = standup_summary(&entries, standup.opened_at.with_timezone(&self.timezone));
+        for chunk in split_message(&summary, DISCORD_MESSAGE_LIMIT) {
+            channel_id.say(&self.http, chunk).await?;
+        }
+        db::complete_standup(
+            &self.db,
+            standup.id,
+            replies.len() as i64,
+            &entries,
+            &summary,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// The messages members posted after the prompt and before the standup closed,
+    /// oldest first.
+    async fn replies(
+        &self,
+        channel_id: ChannelId,
+        standup: &OpenStandup,
+    ) -> eyre::Result<Vec<Message>> {
+        let closes_at = standup.closes_at.timestamp();
+        let mut after = MessageId::new(standup.prompt_message_id as u64);
+        let mut replies = vec![];
+        loop {
+            let request = GetMessages::new().after(after).limit(PAGE_SIZE);
+            let mut page = channel_id.messages(&self.http, request).await?;
+            page.sort_by_key(|msg| msg.id);
+            let Some(newest) = page.last() else {
+                break;
+            };
+            after = newest.id;
+            let done =
+                page.len() < PAGE_SIZE as usize || newest.timestamp.unix_timestamp() > closes_at;
+            replies.extend(page.into_iter().filter(|msg| {
+                !msg.author.bot
+                    && !msg.content.trim().is_empty()
+                    && msg.timestamp.unix_timestamp() <= closes_at
+            }));
+            if done {
+                break;
+            }
+        }
+        Ok(replies)
+    }
+}
+
+/// Renders what each person said in a standup.
+fn standup_summary(entries: &[StandupEntry], date: DateTime<Tz>) -> String {
+    let mut summary = format!("**Standup, {}**", date.format("%a %b %-d"));
+    if entries.is_empty() {
+        summary.push_str("\nNobody posted an update.");
+        return summary;
+    }
+    for entry in entries {
+        summary.push_str(&format!("\n__{}__", entry.person));
+        for (label, items) in [
+            ("Yesterday", &entry.yesterday),
+            ("Today", &entry.today),
+            ("Blockers", &entry.blockers),
+        ] {
+            if !items.is_empty() {
+                summary.push_str(&format!("\n- {label}: {}", items.join("; ")));
+            }
+        }
+        if entry.blockers.is_empty() {
+            summary.push_str("\n- Blockers: none");
+        }
+    }
+    summary
+}
@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Timelike;
+use chrono_tz::Tz;
+
+use crate::db::{ChannelStats, Contributor, DigestStats, MessageActivity};
+
+/// Most contributors listed per channel.
+const MAX_CONTRIBUTORS: usize = 3;
+
+/// Counts the messages, members and busiest hour of a period, overall and per channel.
+pub fn compute_stats(messages: &[MessageActivity], timezone: Tz) -> DigestStats {
+    let mut by_channel: HashMap<i64, Vec<&MessageActivity>> = HashMap::new();
+    for message in messages {
+        by_channel
+            .entry(message.channel_id)
+            .or_default()
+            .push(message);
+    }
+    let mut channels: Vec<ChannelStats> = by_channel
+        .into_iter()
+        .map(|(channel_id, messages)| {
+            let mut counts: HashMap<&str, i64> = HashMap::new();
+            for message in &messages {
+                *counts.entry(message.author.as_str()).or_default() += 1;
+            }
+            let mut top_contributors: Vec<Contributor> = counts
+                .into_iter()
+                .map(|(author, message_count)| Contributor {
+                    author: author.to_string(),
+                    message_count,
+                })
+                .collect();
+            top_contributors.sort_by(|a, b| {
+                b.message_count
+                    .cmp(&a.message_count)
+                    .then_with(|| a.author.cmp(&b.author))
+            });
+            let active_users = top_contributors.len() as i64;
+            top_contributors.truncate(MAX_CONTRIBUTORS);
+            ChannelStats {
+                channel_id,
+                message_count: messages.len() as i64,
+                active_users,
+                busiest_hour: busiest_hour(messages.iter().copied(), timezone),
+                top_contributors,
+            }
+        })
+        .collect();
+    channels.sort_by(|a, b| {
+        b.message_count
+            .cmp(&a.message_count)
+            .then_with(|| a.channel_id.cmp(&b.channel_id))
+    });
+    let active_users = messages
+        .iter()
+        .map(|message| message.author.as_str())
+        .collect::<HashSet<_>>()
+        .len() as i64;
+    DigestStats {
+        message_count: messages.len() as i64,
+        active_users,
+        busiest_hour: busiest_hour(messages.iter(), timezone),
+        channels,
+    }
+}
+
+/// The hour of the day most of the messages were sent in, the earliest on ties.
+fn busiest_hour<'a>(
+    messages: impl Iterator<Item = &'a MessageActivity>,
+    timezone: Tz,
+) -> Option<u32> {
+    let mut counts = [0usize; 24];
+    for message in messages {
+        counts[message.timestamp.with_timezone(&timezone).hour() as usize] += 1;
+    }
+    let (hour, count) = counts
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, count)| **count)?;
+    (*count > 0).then_some(hour as u32)
+}
+
+/// Renders the "Activity" section of daily digests, or `None` when nobody posted.
+pub fn stats_section(stats: &DigestStats, timezone: Tz) -> Option<String> {
+    if stats.message_count == 0 {
+        return None;
+    }
+    let mut section = format!(
+        "**Activity**\n{} from {}",
+        plural(stats.message_count, "message"),
+        plural(stats.active_users, "member")
+    );
+    if let Some(hour) = stats.busiest_hour {
+        section.push_str(&format!(", busiest at {hour:02}:00 {}", timezone.name()));
+    }
+    for channel in &stats.channels {
+        section.push_str(&format!(
+            "\n- <#{}>: {} from {}",
+            channel.channel_id,
+            plural(channel.message_count, "message"),
+            plural(channel.active_users, "member")
+        ));
+        if let Some(hour) = channel.busiest_hour {
+            section.push_str(&format!(", busiest at {hour:02}:00"));
+        }
+        let contributors: Vec<String> = channel
+            .top_contributors
+            .iter()
+            .map(|contributor| format!("{} ({})", contributor.author, contributor.message_count))
+            .collect();
+        if !contributors.is_empty() {
+            section.push_str(&format!(". Top contributors: {}", contributors.join(", ")));
+        }
+    }
+    Some(section)
+}
+
+fn plural(count: i64, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use eyre::eyre;
+use serenity::all::UserId;
+use serenity::http::Http;
+use sqlx::SqlitePool;
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::db::{self, RollupTier};
+use crate::templates::{DigestTarget, DigestTemplates};
+
+use super::digests::{channel_summaries, split_message, DISCORD_MESSAGE_LIMIT};
+
+/// How often to check for digests due to subscribers.
+const DELIVERY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sends daily digests to the members subscribed to them in their DMs, at the hour of
+/// the day each of them chose.
+pub struct SubscriptionService {
+    db: Arc<SqlitePool>,
+    http: Arc<Http>,
+    templates: Arc<DigestTemplates>,
+    /// Timezone delivery hours are in.
+    timezone: Tz,
+}
+
+impl SubscriptionService {
+    pub fn new(
+        db: Arc<SqlitePool>,
+        http: Arc<Http>,
+        templates: Arc<DigestTemplates>,
+        timezone: Tz,
+    ) -> Self {
+        Self {
+            db,
+            http,
+            templates,
+            timezone,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval_timer = interval(DELIVERY_CHECK_INTERVAL);
+        loop {
+            interval_timer.tick().await;
+            self.deliver_due().await;
+        }
+    }
+
+    /// Sends every subscriber whose delivery time came the newest digest of their guild.
+    /// Only the newest one is sent, older ones they missed are skipped.
+    async fn deliver_due(&self) {
+        let deliveries = match db::fetch_pending_deliveries(&self.db).await {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                error!("Could not fetch digest subscriptions: {e}");
+                return;
+            }
+        };
+        let now = Utc::now();
+        for delivery in deliveries {
+            let subscription = &delivery.subscription;
+            let due = delivery_time(
+                delivery.digest_timestamp,
+                subscription.delivery_hour,
+                self.timezone,
+            );
+            if due > now {
+                continue;
+            }
+            // Members who cannot be sent DMs are not retried, so that they are not
+            // tried every minute until the next digest.
+            if let Err(e) = self.deliver(&delivery).await {
+                warn!(
+                    "Could not send digest {} to subscriber {}: {e:#}",
+                    delivery.digest_id, subscription.user_id
+                );
+            }
+            if let Err(e) = db::set_subscription_last_digest(
+                &self.db,
+                subscription.user_id,
+                subscription.guild_id,
+                delivery.digest_id,
+            )
+            .await
+            {
+                error!(
+                    "Could not record the delivery of digest {} to subscriber {}: {e}",
+                    delivery.digest_id, subscription.user_id
+                );
+            }
+        }
+    }
+
+    async fn deliver(&self, delivery: &db::PendingDelivery) -> eyre::Result<()> {
+        let Some(content) = self.render(delivery).await? else {
+            return Ok(());
+        };
+        let user_id = UserId::new(delivery.subscription.user_id as u64);
+        let channel = user_id.create_dm_channel(&self.http).await?;
+        for chunk in split_message(&content, DISCORD_MESSAGE_LIMIT) {
+            channel.say(&self.http, chunk).await?;
+        }
+        info!("Sent digest {} to subscriber {user_id}", delivery.digest_id);
+        Ok(())
+    }
+
+    /// The digest as a subscriber gets it: whole, or only the summaries of the channels
+    /// they follow. `None` when none of their channels were summarized.
+    async fn render(&self, delivery: &db::PendingDelivery) -> eyre::Result<Option<String>> {
+        let digest = db::fetch_daily_digest(&self.db, delivery.digest_id)
+            .await?
+            .ok_or_else(|| eyre!("digest {} no longer exists", delivery.digest_id))?;
+        let channel_ids = &delivery.subscription.channel_ids;
+        let (text, message_count) = if channel_ids.is_empty() {
+            (digest.text.clone(), digest.message_count)
+        } else {
+            match channel_summaries(&digest, channel_ids) {
+                Some(channel_summaries) => channel_summaries,
+                None => return Ok(None),
+            }
+        };
+        let view = self.templates.view(
+            RollupTier::Daily,
+            Some(digest.id),
+            digest.guild_id,
+            &text,
+            message_count,
+            digest.covers_from.zip(digest.covers_to),
+        );
+        Ok(Some(self.templates.render(DigestTarget::Discord, &view)))
+    }
+}
+
+/// When a digest produced at `produced` is sent to a subscriber: at the next
+/// `delivery_hour` in `timezone`, or right away during that hour or without one.
+fn delivery_time(
+    produced: DateTime<Utc>,
+    delivery_hour: Option<i64>,
+    timezone: Tz,
+) -> DateTime<Utc> {
+    let Some(hour) = delivery_hour.and_then(|hour| u32::try_from(hour).ok()) else {
+        return produced;
+    };
+    let local = produced.with_timezone(&timezone);
+    if local.hour() == hour {
+        return produced;
+    }
+    let mut day = local.date_naive();
+    if local.hour() > hour {
+        day += ChronoDuration::days(1);
+    }
+    day.and_hms_opt(hour, 0, 0)
+        .and_then(|time| timezone.from_local_datetime(&time).earliest())
+        .map_or(produced, |time| time.with_timezone(&Utc))
+}
@@ -1,70 +1,578 @@
-use std::{path::PathBuf, sync::Arc};
+use std::collections::HashMap;
+use std::sync::Arc;
 
+use chrono::Utc;
+use eyre::WrapErr;
+use serenity::all::{ChannelId, GuildId};
 use sqlx::SqlitePool;
-use tokio::sync::mpsc::Receiver;
-use tracing::{error, info};
+use tokio::sync::{mpsc::Receiver, oneshot};
+use tracing::{error, info, warn};
+
+use crate::config::{SummaryFormat, VerificationConfig, WebhookEvent};
+use crate::db::{self, ContentKind, LoggedAttachment, LoggedMessage};
+use crate::gpt::{
+    analyze_sentiment, BudgetExceeded, Embedder, LlmPaused, StructuredSummary, Summarizer,
+    TokenCounter,
+};
+use crate::names::DiscordNames;
+use crate::prompts::{format_prompt_time, PromptVars, Prompts, SummaryPrompt};
+
+use super::embeddings::embed_content;
+use super::events::{EventBus, EventKind, ServiceHealth};
+use super::verification::{grounding_instructions, SummaryVerifier, Verification};
+use super::webhooks::Webhooks;
+
+/// Receives the summary produced for a request, rendered as Markdown, or `None` if
+/// there was nothing left to summarize.
+pub type SummaryReply = oneshot::Sender<eyre::Result<Option<String>>>;
 
 pub enum SummarizeRequest {
-    FileWithIndex(usize),
+    /// Summarize the unsummarized messages of a channel, up to and including the
+    /// stored message with the given row ID.
+    Messages {
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        up_to_message_id: i64,
+        reply: Option<SummaryReply>,
+    },
+}
+
+impl SummarizeRequest {
+    /// Reports the outcome of the request back through `reply` once it is done.
+    pub fn with_reply(self, reply: SummaryReply) -> Self {
+        match self {
+            SummarizeRequest::Messages {
+                guild_id,
+                channel_id,
+                up_to_message_id,
+                ..
+            } => SummarizeRequest::Messages {
+                guild_id,
+                channel_id,
+                up_to_message_id,
+                reply: Some(reply),
+            },
+        }
+    }
+}
+
+/// Characters of a replied to message quoted in the transcript.
+const REPLY_QUOTE_CHARS: usize = 80;
+
+/// Renders stored messages into the transcript that is sent to the model. Replies name
+/// the author of the message they reply to and quote its start, so that the model can
+/// follow who answered whom. Messages of threads come last, grouped under the title of
+/// their thread.
+fn render_transcript(messages: &[LoggedMessage]) -> String {
+    let (channel, threaded): (Vec<_>, Vec<_>) =
+        messages.iter().partition(|msg| msg.thread_id.is_none());
+    let mut transcript: String = channel.into_iter().map(render_message).collect();
+    // Threads in the order of their first message.
+    let mut threads: Vec<(Option<i64>, Vec<&LoggedMessage>)> = vec![];
+    for msg in threaded {
+        match threads.iter_mut().find(|(id, _)| *id == msg.thread_id) {
+            Some((_, thread)) => thread.push(msg),
+            None => threads.push((msg.thread_id, vec![msg])),
+        }
+    }
+    for (_, thread) in threads {
+        // Threads can be renamed, the latest message has the current title.
+        let name = thread
+            .last()
+            .and_then(|msg| msg.thread_name.as_deref())
+            .unwrap_or("untitled");
+        transcript.push_str(&format!("\nthread: \"{name}\"\n"));
+        transcript.extend(thread.into_iter().map(render_message));
+    }
+    transcript
+}
+
+fn render_message(msg: &LoggedMessage) -> String {
+    let reply = match (&msg.reply_to_author, &msg.reply_to_content) {
+        // Deleted messages keep no content to quote.
+        (Some(author), Some(content)) if content.is_empty() => {
+            format!(" (replying to {author})")
+        }
+        (Some(author), Some(content)) => {
+            let mut quote: String = content.chars().take(REPLY_QUOTE_CHARS).collect();
+            if quote.len() < content.len() {
+                quote.push_str("...");
+            }
+            format!(" (replying to {author}: \"{quote}\")")
+        }
+        _ => String::new(),
+    };
+    if msg.deleted {
+        return format!(
+            "timestamp: {}, author: {}{reply}, content: (deleted message)\n",
+            msg.timestamp, msg.author
+        );
+    }
+    let attachments: String = msg.attachments.iter().map(render_attachment).collect();
+    let reactions = match msg.reaction_count {
+        0 => String::new(),
+        count => format!(", reactions: {count}"),
+    };
+    format!(
+        "timestamp: {}, author: {}{reply}, content: {}{attachments}{reactions}\n",
+        msg.timestamp, msg.author, msg.content
+    )
+}
+
+/// How an attachment is shown after the content of its message.
+pub fn render_attachment(attachment: &LoggedAttachment) -> String {
+    match &attachment.description {
+        Some(description) => format!(" [image {}: {description}]", attachment.filename),
+        None => format!(" [attachment: {}]", attachment.filename),
+    }
+}
+
+/// The text of the messages alone, to detect the language they are written in.
+pub fn message_contents(messages: &[LoggedMessage]) -> String {
+    messages
+        .iter()
+        .map(|msg| msg.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Appended to the instructions when some of the messages were posted in threads.
+const THREADS_FORMAT: &str = "Messages posted in threads and forum posts are listed after the others, under the title of their thread. Summarize each thread in a section of its own, titled after it.";
+
+/// Appended to the instructions when members reacted to some of the messages.
+const REACTIONS_FORMAT: &str = "Messages members reacted to list how many reactions they got. Give more weight to the most reacted messages, as they are what members engaged with the most.";
+
+/// Appended to the instructions of profiles using the bullet list format.
+const BULLETS_FORMAT: &str =
+    "Reply with a concise Markdown bullet list of the key points and nothing else.";
+
+/// Summarizes a channel's messages following its prompt, using the summarizer of the
+/// prompt's model when there is one in `models`.
+pub async fn summarize_channel_messages(
+    summarizer: &Arc<dyn Summarizer>,
+    models: &HashMap<String, Arc<dyn Summarizer>>,
+    prompt: &SummaryPrompt,
+    messages: &[LoggedMessage],
+) -> eyre::Result<StructuredSummary> {
+    let mut prompt = prompt.clone();
+    if messages.iter().any(|msg| msg.thread_id.is_some()) {
+        prompt.instructions = format!("{}\n\n{THREADS_FORMAT}", prompt.instructions);
+    }
+    if messages.iter().any(|msg| msg.reaction_count > 0) {
+        prompt.instructions = format!("{}\n\n{REACTIONS_FORMAT}", prompt.instructions);
+    }
+    summarize_transcript(summarizer, models, &prompt, &render_transcript(messages)).await
+}
+
+/// Summarizes a transcript following a prompt, using the summarizer of the prompt's
+/// model when there is one in `models`.
+pub async fn summarize_transcript(
+    summarizer: &Arc<dyn Summarizer>,
+    models: &HashMap<String, Arc<dyn Summarizer>>,
+    prompt: &SummaryPrompt,
+    transcript: &str,
+) -> eyre::Result<StructuredSummary> {
+    let instructions = &prompt.instructions;
+    let summarizer = match &prompt.model {
+        Some(model) => models.get(model).unwrap_or_else(|| {
+            warn!("Model {model} was added to a prompt profile after starting, using the default model until a restart");
+            summarizer
+        }),
+        None => summarizer,
+    };
+    let reply = match prompt.format {
+        SummaryFormat::Structured => {
+            return summarizer
+                .summarize_structured(instructions, transcript)
+                .await;
+        }
+        SummaryFormat::Prose => summarizer.summarize(instructions, transcript).await?,
+        SummaryFormat::Bullets => {
+            let instructions = format!("{instructions}\n\n{BULLETS_FORMAT}");
+            summarizer.summarize(&instructions, transcript).await?
+        }
+    };
+    let mut summary = StructuredSummary::unstructured(reply.trim().to_string());
+    summary.backend = summarizer.backend();
+    Ok(summary)
 }
 
 pub struct SummarizerService {
     summarize_rx: Receiver<SummarizeRequest>,
-    message_log_path: PathBuf,
     db: Arc<SqlitePool>,
+    summarizer: Arc<dyn Summarizer>,
+    embedder: Arc<dyn Embedder>,
+    token_counter: Arc<dyn TokenCounter>,
+    pending_retry_seconds: i64,
+    webhooks: Webhooks,
+    /// Notified of every new summary, when set.
+    events: Option<EventBus>,
+    health: Option<ServiceHealth>,
+    prompts: Prompts,
+    /// Looks up the guild and channel names used in prompts, when set.
+    names: Option<DiscordNames>,
+    /// Summarizers of the models prompt profiles use.
+    models: Arc<HashMap<String, Arc<dyn Summarizer>>>,
+    /// Scores the sentiment of each summarized batch of messages, when set.
+    sentiment: Option<Arc<dyn Summarizer>>,
+    /// Checks the people and channels summaries mention against their messages, when
+    /// set.
+    verifier: Option<SummaryVerifier>,
+    verification: VerificationConfig,
 }
 
 impl SummarizerService {
     pub fn new(
-        message_log_path: PathBuf,
         summarize_rx: Receiver<SummarizeRequest>,
         db: Arc<SqlitePool>,
+        summarizer: Arc<dyn Summarizer>,
+        embedder: Arc<dyn Embedder>,
+        token_counter: Arc<dyn TokenCounter>,
+        pending_retry_seconds: u64,
+        webhooks: Webhooks,
     ) -> Self {
         Self {
-            message_log_path,
             summarize_rx,
             db,
+            summarizer,
+            embedder,
+            token_counter,
+            pending_retry_seconds: pending_retry_seconds as i64,
+            webhooks,
+            events: None,
+            health: None,
+            prompts: Prompts::default(),
+            names: None,
+            models: Arc::default(),
+            sentiment: None,
+            verifier: None,
+            verification: VerificationConfig::default(),
         }
     }
+
+    /// Broadcasts each new summary, and every failure to summarize, to live subscribers.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.health = Some(ServiceHealth::new(events.clone(), "summarizer"));
+        self.events = Some(events);
+        self
+    }
+
+    /// Summarizes with the configured prompt templates and profiles, filled in with the
+    /// names of each channel and its guild. `models` holds the summarizers of the
+    /// models profiles use.
+    pub fn with_prompts(
+        mut self,
+        prompts: Prompts,
+        names: DiscordNames,
+        models: Arc<HashMap<String, Arc<dyn Summarizer>>>,
+    ) -> Self {
+        self.prompts = prompts;
+        self.names = Some(names);
+        self.models = models;
+        self
+    }
+
+    /// Stores the sentiment and tone of each summarized batch of messages along with its
+    /// summary, as scored by `summarizer`.
+    pub fn with_sentiment(mut self, summarizer: Arc<dyn Summarizer>) -> Self {
+        self.sentiment = Some(summarizer);
+        self
+    }
+
+    /// Verifies that the people and channels each summary mentions appear in its
+    /// messages, writing it again when too many do not, and stores its confidence.
+    pub fn with_verification(mut self, config: VerificationConfig) -> Self {
+        self.verifier = Some(SummaryVerifier::default());
+        self.verification = config;
+        self
+    }
+
     pub async fn run(&mut self) {
         while let Some(data) = self.summarize_rx.recv().await {
             match data {
-                SummarizeRequest::FileWithIndex(log_file_index) => {
-                    info!("Summarizing contents of message log file with index {log_file_index}");
-                    let fpath = self
-                        .message_log_path
-                        .join(format!("messages_{log_file_index}.txt"));
-                    let file_contents = match std::fs::read_to_string(&fpath) {
-                        Ok(f) => f,
-                        Err(e) => {
-                            error!("Could not read file to summarize: {e}");
-                            continue;
-                        }
-                    };
-                    let summary = match crate::gpt::summarize(&file_contents).await {
-                        Ok(txt) => txt,
-                        Err(e) => {
-                            error!("Could not summarize message log: {e}");
-                            continue;
-                        }
-                    };
-                    info!("Summary: {summary}");
-
-                    // Save the summary to the DB.
-                    if let Err(e) = crate::db::insert_summary(&self.db, &summary).await {
-                        error!("Could not insert summary to DB: {e}, contents: {summary}");
-                        continue;
+                SummarizeRequest::Messages {
+                    guild_id,
+                    channel_id,
+                    up_to_message_id,
+                    reply,
+                } => {
+                    let outcome = self
+                        .summarize_messages(guild_id, channel_id, up_to_message_id)
+                        .await;
+                    if let Some(reply) = reply {
+                        // Whoever asked may have stopped waiting, which is fine.
+                        let _ = reply.send(outcome);
                     }
-                    info!("Wrote the summary to the DB");
+                }
+            }
+        }
+    }
 
-                    // Delete the file with index that it came from.
-                    if let Err(e) = std::fs::remove_file(&fpath) {
-                        error!("Could not delete file at path: {e}");
+    /// Summarizes a batch of messages, persisting it for a later retry if that fails.
+    async fn summarize_messages(
+        &self,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        up_to_message_id: i64,
+    ) -> eyre::Result<Option<String>> {
+        info!("Summarizing messages up to {up_to_message_id} for channel {channel_id}");
+        let outcome = self
+            .try_summarize_messages(guild_id, channel_id, up_to_message_id)
+            .await;
+        match &outcome {
+            Ok(_) => {
+                self.clear_pending(channel_id, up_to_message_id).await;
+                if let Some(health) = &self.health {
+                    health.succeeded();
+                }
+            }
+            Err(e) => {
+                // Batches over budget are queued until the budget resets, which is not a
+                // failure of the service.
+                let paused = e.downcast_ref::<LlmPaused>();
+                let retry_after_seconds = match (e.downcast_ref::<BudgetExceeded>(), paused) {
+                    (Some(budget), _) => {
+                        info!("Queueing messages up to {up_to_message_id} for channel {channel_id}: {budget}");
+                        (budget.resets_at - Utc::now()).num_seconds().max(1)
+                    }
+                    // Paused batches are not a failure either, and are retried once
+                    // calls resume.
+                    (None, Some(paused)) => {
+                        info!("Queueing messages up to {up_to_message_id} for channel {channel_id}: {paused}");
+                        paused
+                            .resumes_at
+                            .map_or(self.pending_retry_seconds, |resumes_at| {
+                                (resumes_at - Utc::now()).num_seconds().max(1)
+                            })
                     }
+                    (None, None) => {
+                        error!("{e:#}");
+                        if let Some(health) = &self.health {
+                            health.failed(&format!("{e:#}"));
+                        }
+                        self.pending_retry_seconds
+                    }
+                };
+                self.defer(
+                    guild_id,
+                    channel_id,
+                    up_to_message_id,
+                    &format!("{e:#}"),
+                    retry_after_seconds,
+                )
+                .await;
+            }
+        }
+        outcome
+    }
 
-                    info!("Deleted summarized messages log file at path: {:?}", fpath);
+    async fn try_summarize_messages(
+        &self,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        up_to_message_id: i64,
+    ) -> eyre::Result<Option<String>> {
+        let messages =
+            db::fetch_unsummarized_messages(&self.db, channel_id.get() as i64, up_to_message_id)
+                .await
+                .wrap_err("Could not fetch messages to summarize")?;
+        if messages.is_empty() {
+            // A later batch of the channel already covered these messages.
+            info!(
+                "Messages up to {up_to_message_id} for channel {channel_id} are already summarized"
+            );
+            return Ok(None);
+        }
+        info!(
+            "Summarizing {} messages for channel {channel_id} totalling {} tokens",
+            messages.len(),
+            self.token_counter
+                .count_tokens(&render_transcript(&messages))
+        );
+        let covers_from = messages.iter().map(|msg| msg.timestamp).min();
+        let covers_to = messages.iter().map(|msg| msg.timestamp).max();
+        let vars = PromptVars::lookup(
+            self.names.as_ref(),
+            guild_id,
+            Some(channel_id),
+            format_prompt_time(covers_from),
+            format_prompt_time(covers_to),
+        )
+        .await;
+        let prompt = self
+            .prompts
+            .summary(channel_id, &vars, &message_contents(&messages));
+        let summary =
+            summarize_channel_messages(&self.summarizer, &self.models, &prompt, &messages)
+                .await
+                .wrap_err("Could not summarize messages")?;
+        let (summary, verification) = self
+            .verify_summary(summary, &prompt, &messages, &vars.channel)
+            .await;
+        info!("Summary: {summary:?}");
+
+        // Save the summary to the DB, marking its messages as summarized.
+        let new_summary = db::NewSummary {
+            guild_id: guild_id.map(|id| id.get() as i64),
+            channel_id: channel_id.get() as i64,
+            summary: &summary,
+            message_count: messages.len() as i64,
+            covers_from,
+            covers_to,
+        };
+        let summary_id = db::insert_summary(&self.db, new_summary, up_to_message_id)
+            .await
+            .wrap_err_with(|| {
+                format!(
+                    "Could not insert summary to DB, contents: {}",
+                    summary.summary
+                )
+            })?;
+        info!("Wrote the summary to the DB");
+        if let Some(verification) = &verification {
+            if let Err(e) = db::set_summary_confidence(
+                &self.db,
+                summary_id,
+                verification.confidence,
+                &verification.unverified,
+            )
+            .await
+            {
+                error!("Could not store the confidence of summary {summary_id}: {e}");
+            }
+        }
+        if let Some(summarizer) = &self.sentiment {
+            // Summaries are kept without a sentiment when it cannot be scored.
+            match analyze_sentiment(summarizer.as_ref(), &render_transcript(&messages)).await {
+                Ok(sentiment) => {
+                    if let Err(e) =
+                        db::set_summary_sentiment(&self.db, summary_id, &sentiment).await
+                    {
+                        error!("Could not store the sentiment of summary {summary_id}: {e}");
+                    }
                 }
+                Err(e) => warn!("Could not score the sentiment of summary {summary_id}: {e:#}"),
+            }
+        }
+        embed_content(
+            &self.db,
+            self.embedder.as_ref(),
+            ContentKind::Summary,
+            summary_id,
+            &summary.summary,
+        )
+        .await;
+        match db::fetch_summary(&self.db, summary_id).await {
+            Ok(Some(stored)) => {
+                self.webhooks.publish(WebhookEvent::Summary, &stored).await;
+                if let Some(events) = &self.events {
+                    events.publish(
+                        EventKind::Summary,
+                        stored.guild_id,
+                        stored.channel_id,
+                        &stored,
+                    );
+                }
+            }
+            Ok(None) => {}
+            Err(e) => error!("Could not fetch summary {summary_id} to publish: {e}"),
+        }
+        Ok(Some(summary.render()))
+    }
+
+    /// Checks the people and channels a summary mentions against its messages, when
+    /// summaries are verified. A summary that is not confident enough is written again
+    /// once, keeping whichever attempt is more confident.
+    async fn verify_summary(
+        &self,
+        summary: StructuredSummary,
+        prompt: &SummaryPrompt,
+        messages: &[LoggedMessage],
+        channel: &str,
+    ) -> (StructuredSummary, Option<Verification>) {
+        let Some(verifier) = &self.verifier else {
+            return (summary, None);
+        };
+        let verification = verifier.verify(&summary, messages, channel);
+        if verification.confidence >= self.verification.min_confidence {
+            return (summary, Some(verification));
+        }
+        warn!(
+            "Summary of channel {channel} mentions {} that do not appear in its messages, confidence {:.2}",
+            verification.unverified.join(", "),
+            verification.confidence
+        );
+        if !self.verification.regenerate {
+            return (summary, Some(verification));
+        }
+        let grounded = SummaryPrompt {
+            instructions: format!(
+                "{}\n\n{}",
+                prompt.instructions,
+                grounding_instructions(&verification.unverified)
+            ),
+            model: prompt.model.clone(),
+            format: prompt.format,
+        };
+        let retried = match summarize_channel_messages(
+            &self.summarizer,
+            &self.models,
+            &grounded,
+            messages,
+        )
+        .await
+        {
+            Ok(retried) => retried,
+            Err(e) => {
+                warn!("Could not summarize channel {channel} again, keeping the unverified summary: {e:#}");
+                return (summary, Some(verification));
             }
+        };
+        let retried_verification = verifier.verify(&retried, messages, channel);
+        info!(
+            "Summarized channel {channel} again, confidence went from {:.2} to {:.2}",
+            verification.confidence, retried_verification.confidence
+        );
+        if retried_verification.confidence >= verification.confidence {
+            (retried, Some(retried_verification))
+        } else {
+            (summary, Some(verification))
+        }
+    }
+
+    /// Persists a failed summarization so the pending summary service retries it after
+    /// `retry_after_seconds`.
+    async fn defer(
+        &self,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        up_to_message_id: i64,
+        error: &str,
+        retry_after_seconds: i64,
+    ) {
+        if let Err(e) = db::upsert_pending_summary(
+            &self.db,
+            guild_id.map(|id| id.get() as i64),
+            channel_id.get() as i64,
+            up_to_message_id,
+            error,
+            retry_after_seconds,
+        )
+        .await
+        {
+            error!("Could not persist failed summarization of messages up to {up_to_message_id} for channel {channel_id}: {e}");
+        }
+    }
+
+    async fn clear_pending(&self, channel_id: ChannelId, up_to_message_id: i64) {
+        if let Err(e) = db::delete_pending_summary_for_batch(
+            &self.db,
+            channel_id.get() as i64,
+            up_to_message_id,
+        )
+        .await
+        {
+            error!("Could not clear pending summarization of messages up to {up_to_message_id} for channel {channel_id}: {e}");
         }
     }
 }
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::async_trait;
+use eyre::{bail, eyre};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::TelegramConfig;
+use crate::templates::{markdown_link, DigestTarget, DigestTemplates, DigestView};
+
+use super::digests::split_message;
+use super::sinks::DigestSink;
+
+const API_BASE: &str = "https://api.telegram.org";
+/// Longest text of a single Telegram message, counted after formatting is parsed.
+const TELEGRAM_MESSAGE_LIMIT: usize = 4096;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Characters that must be escaped everywhere in MarkdownV2 text.
+const MARKDOWN_V2_SPECIAL: &str = "_*[]()~`>#+-=|{}.!\\";
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    description: Option<String>,
+}
+
+/// Pushes digests to a Telegram group or channel through a bot.
+#[derive(Clone)]
+pub struct TelegramSender {
+    client: reqwest::Client,
+    send_message_url: Arc<String>,
+    chat_id: Arc<String>,
+}
+
+impl TelegramSender {
+    /// Creates a sender pushing to `chat_id` through the bot of the config.
+    pub fn from_config(config: &TelegramConfig, chat_id: &str) -> eyre::Result<Self> {
+        let Some(token) = config.bot_token() else {
+            bail!("telegram.bot_token or the TELEGRAM_BOT_TOKEN env var must be set to push digests to Telegram");
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Ok(Self {
+            client,
+            send_message_url: Arc::new(format!("{API_BASE}/bot{token}/sendMessage")),
+            chat_id: Arc::new(chat_id.to_string()),
+        })
+    }
+
+    async fn send(&self, text: &str) -> eyre::Result<()> {
+        let response = self
+            .client
+            .post(self.send_message_url.as_str())
+            .json(&json!({
+                "chat_id": self.chat_id.as_str(),
+                "text": text,
+                "parse_mode": "MarkdownV2",
+                "disable_web_page_preview": true,
+            }))
+            .send()
+            .await?;
+        let status = response.status();
+        let body: ApiResponse = response
+            .json()
+            .await
+            .map_err(|e| eyre!("unexpected response with status {status}: {e}"))?;
+        if !body.ok {
+            bail!(
+                "{status}: {}",
+                body.description.as_deref().unwrap_or("no description")
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DigestSink for TelegramSender {
+    fn name(&self) -> String {
+        format!("Telegram chat {}", self.chat_id)
+    }
+
+    /// Pushes a digest rendered with the Discord template, split into as many messages
+    /// as it takes.
+    async fn deliver(&self, templates: &DigestTemplates, digest: &DigestView) -> eyre::Result<()> {
+        let content = templates.render(DigestTarget::Discord, digest);
+        for chunk in split_message(&content, TELEGRAM_MESSAGE_LIMIT) {
+            self.send(&markdown_v2(&chunk)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts the Markdown digests are written in to Telegram's MarkdownV2: `**bold**`
+/// becomes `*bold*`, `[label](url)` links are kept and everything else is escaped.
+fn markdown_v2(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut bold = false;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**") {
+            out.push('*');
+            bold = !bold;
+            rest = after;
+        } else if let Some((label, url, after)) = markdown_link(rest) {
+            out.push('[');
+            escape_into(&mut out, label, MARKDOWN_V2_SPECIAL);
+            out.push_str("](");
+            escape_into(&mut out, url, ")\\");
+            out.push(')');
+            rest = after;
+        } else {
+            escape_into(&mut out, &rest[..c.len_utf8()], MARKDOWN_V2_SPECIAL);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if bold {
+        out.push('*');
+    }
+    out
+}
+
+fn escape_into(out: &mut String, text: &str, special: &str) {
+    for c in text.chars() {
+        if special.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::db::RecurringTopic;
+
+/// Topics of fewer daily digests are not mentioned as recurring in weekly digests.
+pub const MIN_RECURRING_DIGESTS: i64 = 2;
+/// Most recurring topics mentioned in a weekly digest.
+pub const MAX_RECURRING: i64 = 10;
+
+/// Lowercases a topic and drops surrounding punctuation and extra whitespace, so that
+/// "Release planning." and "release  planning" are counted as one topic.
+pub fn normalize_topic(topic: &str) -> String {
+    topic
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Counts how many summaries discussed each topic, given the topics of every summary.
+pub fn count_topics(summary_topics: &[Vec<String>]) -> Vec<(String, i64)> {
+    let mut counts: BTreeMap<String, i64> = BTreeMap::new();
+    for topics in summary_topics {
+        let labels: HashSet<String> = topics
+            .iter()
+            .map(|topic| normalize_topic(topic))
+            .filter(|label| !label.is_empty())
+            .collect();
+        for label in labels {
+            *counts.entry(label).or_default() += 1;
+        }
+    }
+    counts.into_iter().collect()
+}
+
+/// Renders the note on the topics that came up on several days of a weekly digest, or
+/// `None` when no topic did.
+pub fn recurring_topics_section(topics: &[RecurringTopic]) -> Option<String> {
+    if topics.is_empty() {
+        return None;
+    }
+    let topics: Vec<String> = topics
+        .iter()
+        .map(|topic| format!("{} ({} days)", topic.label, topic.digest_count))
+        .collect();
+    Some(format!(
+        "**Recurring topics this week**\n{}",
+        topics.join(", ")
+    ))
+}
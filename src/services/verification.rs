@@ -0,0 +1,256 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use crate::db::LoggedMessage;
+use crate::gpt::StructuredSummary;
+
+/// How well a summary's mentions of people and channels are backed by its messages.
+pub struct Verification {
+    /// Share of the people and channels the summary mentions that appear in its
+    /// messages, 1 when it mentions none.
+    pub confidence: f64,
+    /// Mentions that do not appear in the messages, such as `@bob` or `#general`.
+    pub unverified: Vec<String>,
+}
+
+/// The people and channels that appear in a batch of messages, lowercased.
+#[derive(Default)]
+struct KnownNames {
+    people: HashSet<String>,
+    channels: HashSet<String>,
+}
+
+/// Whether `name` is one of `names`, or a word of one of them, so that a summary
+/// calling `Jane Doe` by her first name checks out.
+fn contains_name(names: &HashSet<String>, name: &str) -> bool {
+    let name = name.to_lowercase();
+    names.contains(&name)
+        || names
+            .iter()
+            .any(|known| known.split_whitespace().any(|word| word == name))
+}
+
+/// A channel or thread name as summaries mention it, with dashes for spaces.
+fn channel_slug(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase()
+}
+
+/// Checks that the people and channels a summary mentions appear in the messages it
+/// summarizes, to catch summaries that make them up.
+pub struct SummaryVerifier {
+    /// `@name` mentions, as stored messages and summaries write them.
+    person: Regex,
+    /// `#name` mentions of channels.
+    channel: Regex,
+    /// Pseudonyms replacing the names of authors, which summaries use as they are.
+    pseudonym: Regex,
+}
+
+impl Default for SummaryVerifier {
+    fn default() -> Self {
+        Self {
+            person: Regex::new(r"(?:^|[^\w@])@(\w(?:[\w.-]*\w)?)")
+                .expect("the person mention pattern is valid"),
+            channel: Regex::new(r"(?:^|[^\w&#])#([A-Za-z0-9_][\w-]*)")
+                .expect("the channel mention pattern is valid"),
+            pseudonym: Regex::new(r"\bmember-[0-9a-f]{8}\b")
+                .expect("the pseudonym pattern is valid"),
+        }
+    }
+}
+
+impl SummaryVerifier {
+    /// Verifies a summary of `messages`, posted in the channel named `channel`.
+    pub fn verify(
+        &self,
+        summary: &StructuredSummary,
+        messages: &[LoggedMessage],
+        channel: &str,
+    ) -> Verification {
+        let known = self.known_names(messages, channel);
+        let text = [summary.summary.as_str()]
+            .into_iter()
+            .chain(summary.topics.iter().map(String::as_str))
+            .chain(summary.decisions.iter().map(String::as_str))
+            .chain(summary.open_questions.iter().map(String::as_str))
+            .chain(
+                summary
+                    .action_items
+                    .iter()
+                    .map(|item| item.description.as_str()),
+            )
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // Each reference along with whether it checks out, once per name.
+        let mut references: Vec<(String, bool)> = vec![];
+        let mut add = |reference: String, verified: bool| {
+            if !references
+                .iter()
+                .any(|(seen, _)| seen.eq_ignore_ascii_case(&reference))
+            {
+                references.push((reference, verified));
+            }
+        };
+        for captures in self.person.captures_iter(&text) {
+            let name = &captures[1];
+            add(format!("@{name}"), contains_name(&known.people, name));
+        }
+        for pseudonym in self.pseudonym.find_iter(&text) {
+            let name = pseudonym.as_str();
+            add(name.to_string(), contains_name(&known.people, name));
+        }
+        for captures in self.channel.captures_iter(&text) {
+            let name = &captures[1];
+            // Issue and pull request numbers are not channels.
+            if name.chars().all(|c| c.is_ascii_digit()) {
+                continue;
+            }
+            add(format!("#{name}"), contains_name(&known.channels, name));
+        }
+        for owner in summary
+            .action_items
+            .iter()
+            .filter_map(|item| item.owner.as_deref())
+        {
+            let handle = owner.trim().starts_with('@');
+            let owner = owner.trim().trim_start_matches('@');
+            if owner.is_empty() {
+                continue;
+            }
+            // Owners can be several people, such as "alice and bob".
+            let verified = contains_name(&known.people, owner)
+                || owner
+                    .split(|c: char| !(c.is_alphanumeric() || "._-".contains(c)))
+                    .any(|word| !word.is_empty() && contains_name(&known.people, word));
+            // Owners such as "everyone", "the team" or "TBD" name nobody in particular,
+            // so only handles are taken for people when they match no one.
+            if !verified && !handle {
+                continue;
+            }
+            add(owner.to_string(), verified);
+        }
+
+        let verified = references.iter().filter(|(_, verified)| *verified).count();
+        let confidence = if references.is_empty() {
+            1.0
+        } else {
+            verified as f64 / references.len() as f64
+        };
+        Verification {
+            confidence,
+            unverified: references
+                .into_iter()
+                .filter(|(_, verified)| !verified)
+                .map(|(reference, _)| reference)
+                .collect(),
+        }
+    }
+
+    /// Authors, the people they reply to and mention, and the channels and threads the
+    /// messages were posted in or mention.
+    fn known_names(&self, messages: &[LoggedMessage], channel: &str) -> KnownNames {
+        let mut known = KnownNames::default();
+        known.channels.insert(channel_slug(channel));
+        for msg in messages {
+            known.people.insert(msg.author.to_lowercase());
+            if let Some(author) = &msg.reply_to_author {
+                known.people.insert(author.to_lowercase());
+            }
+            if let Some(thread) = &msg.thread_name {
+                known.channels.insert(channel_slug(thread));
+            }
+            for captures in self.person.captures_iter(&msg.content) {
+                known.people.insert(captures[1].to_lowercase());
+            }
+            for captures in self.channel.captures_iter(&msg.content) {
+                known.channels.insert(captures[1].to_lowercase());
+            }
+        }
+        known
+    }
+}
+
+/// Appended to the instructions when summarizing again after a summary mentioned
+/// people or channels that are not in its messages.
+pub fn grounding_instructions(unverified: &[String]) -> String {
+    format!(
+        "Only mention people and channels that appear in the messages, by the names the messages use. An earlier summary of these messages mentioned {}, which do not appear in them.",
+        unverified.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::gpt::ActionItem;
+
+    fn message(author: &str, content: &str) -> LoggedMessage {
+        LoggedMessage {
+            id: 1,
+            message_id: 1,
+            guild_id: None,
+            channel_id: 1,
+            author: author.to_string(),
+            content: content.to_string(),
+            timestamp: Utc::now(),
+            summary_id: None,
+            token_count: 0,
+            thread_id: None,
+            thread_name: None,
+            attachments: Json(vec![]),
+            reaction_count: 0,
+            deleted: false,
+            reply_to_author: None,
+            reply_to_content: None,
+        }
+    }
+
+    fn summary(text: &str, owners: &[&str]) -> StructuredSummary {
+        StructuredSummary {
+            summary: text.to_string(),
+            action_items: owners
+                .iter()
+                .map(|owner| ActionItem {
+                    description: "Ship it".to_string(),
+                    owner: Some(owner.to_string()),
+                })
+                .collect(),
+            ..StructuredSummary::default()
+        }
+    }
+
+    #[test]
+    fn mentions_missing_from_the_messages_are_unverified() {
+        let messages = [message("alice", "ask @bob in #releases")];
+        let verification = SummaryVerifier::default().verify(
+            &summary("@alice and @bob moved to #releases, @carol in #random", &[]),
+            &messages,
+            "general",
+        );
+        assert_eq!(verification.unverified, ["@carol", "#random"]);
+        assert_eq!(verification.confidence, 3.0 / 5.0);
+    }
+
+    #[test]
+    fn owners_naming_nobody_in_particular_are_not_checked() {
+        let messages = [message("Alice Smith", "who takes this?")];
+        let verification = SummaryVerifier::default().verify(
+            &summary(
+                "Release planning",
+                &["alice", "everyone", "the team", "TBD", "@dave"],
+            ),
+            &messages,
+            "general",
+        );
+        assert_eq!(verification.unverified, ["dave"]);
+        assert_eq!(verification.confidence, 0.5);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use eyre::{bail, WrapErr};
+use serenity::all::{ChannelId, Context, GuildId};
+use songbird::driver::{Channels, DecodeMode, SampleRate};
+use songbird::events::{CoreEvent, Event, EventContext, EventHandler};
+use songbird::Config;
+
+/// Audio is decoded to what Whisper resamples recordings to anyway, which keeps
+/// recordings small.
+const SAMPLE_RATE: u32 = 16_000;
+
+/// Configures the voice client to decode what members say in the mono 16 kHz audio
+/// recordings are made of.
+pub fn voice_config() -> Config {
+    Config::default()
+        .decode_mode(DecodeMode::Decode)
+        .decode_channels(Channels::Mono)
+        .decode_sample_rate(SampleRate::Hz16000)
+}
+
+/// A meeting being recorded in a voice channel.
+struct ActiveRecording {
+    channel_id: ChannelId,
+    title: String,
+    started_at: DateTime<Utc>,
+    audio: Arc<Mutex<Audio>>,
+}
+
+/// What was said in a meeting, with the moments nobody spoke left out.
+#[derive(Default)]
+struct Audio {
+    samples: Vec<i16>,
+    /// Whether recording stopped early, once the recording reached its size limit.
+    truncated: bool,
+}
+
+/// A meeting whose recording stopped, ready to be transcribed.
+pub struct FinishedRecording {
+    pub channel_id: ChannelId,
+    pub title: String,
+    pub started_at: DateTime<Utc>,
+    /// The recording as a WAV file.
+    pub wav: Vec<u8>,
+    /// Whether the end of the meeting is missing, as the recording reached its size
+    /// limit.
+    pub truncated: bool,
+}
+
+/// Joins voice channels to record meetings, at most one per guild at a time.
+#[derive(Clone)]
+pub struct VoiceRecorder {
+    recordings: Arc<Mutex<HashMap<GuildId, ActiveRecording>>>,
+    /// Recordings stop growing once their WAV file reaches this many bytes.
+    max_audio_bytes: u64,
+}
+
+impl VoiceRecorder {
+    pub fn new(max_audio_bytes: u64) -> Self {
+        Self {
+            recordings: Arc::default(),
+            max_audio_bytes,
+        }
+    }
+
+    /// Joins the voice channel and records what members say in it until `stop` is
+    /// called for its guild.
+    pub async fn start(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        title: &str,
+    ) -> eyre::Result<()> {
+        if self.lock().contains_key(&guild_id) {
+            bail!("a meeting is already being recorded in this server");
+        }
+        let Some(manager) = songbird::get(ctx).await else {
+            bail!("the voice client is not set up");
+        };
+        let call = manager
+            .join(guild_id, channel_id)
+            .await
+            .wrap_err("could not join the voice channel")?;
+        let audio = Arc::new(Mutex::new(Audio::default()));
+        let max_samples = (self.max_audio_bytes.saturating_sub(WAV_HEADER_BYTES) / 2) as usize;
+        call.lock().await.add_global_event(
+            CoreEvent::VoiceTick.into(),
+            Receiver {
+                audio: audio.clone(),
+                max_samples,
+            },
+        );
+        self.lock().insert(
+            guild_id,
+            ActiveRecording {
+                channel_id,
+                title: title.to_string(),
+                started_at: Utc::now(),
+                audio,
+            },
+        );
+        Ok(())
+    }
+
+    /// Leaves the voice channel of the guild, and returns what was recorded in it, or
+    /// `None` when no meeting is being recorded there.
+    pub async fn stop(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+    ) -> eyre::Result<Option<FinishedRecording>> {
+        let Some(recording) = self.lock().remove(&guild_id) else {
+            return Ok(None);
+        };
+        if let Some(manager) = songbird::get(ctx).await {
+            manager
+                .remove(guild_id)
+                .await
+                .wrap_err("could not leave the voice channel")?;
+        }
+        let audio = std::mem::take(
+            &mut *recording
+                .audio
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        Ok(Some(FinishedRecording {
+            channel_id: recording.channel_id,
+            title: recording.title,
+            started_at: recording.started_at,
+            wav: wav(&audio.samples),
+            truncated: audio.truncated,
+        }))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<GuildId, ActiveRecording>> {
+        self.recordings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Mixes what every member says during each 20 ms tick into the recording.
+struct Receiver {
+    audio: Arc<Mutex<Audio>>,
+    max_samples: usize,
+}
+
+#[async_trait]
+impl EventHandler for Receiver {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::VoiceTick(tick) = ctx else {
+            return None;
+        };
+        let mut mixed: Vec<i32> = vec![];
+        for samples in tick
+            .speaking
+            .values()
+            .filter_map(|voice| voice.decoded_voice.as_ref())
+        {
+            if mixed.len() < samples.len() {
+                mixed.resize(samples.len(), 0);
+            }
+            for (mixed, sample) in mixed.iter_mut().zip(samples) {
+                *mixed += i32::from(*sample);
+            }
+        }
+        // Ticks when nobody speaks are left out, so that recordings only grow while
+        // members talk.
+        if mixed.is_empty() {
+            return None;
+        }
+        let mut audio = self.audio.lock().unwrap_or_else(PoisonError::into_inner);
+        if audio.samples.len() + mixed.len() > self.max_samples {
+            audio.truncated = true;
+            return None;
+        }
+        audio.samples.extend(
+            mixed
+                .into_iter()
+                .map(|sample| sample.clamp(i16::MIN.into(), i16::MAX.into()) as i16),
+        );
+        None
+    }
+}
+
+const WAV_HEADER_BYTES: u64 = 44;
+
+/// Encodes mono 16-bit samples as a WAV file.
+fn wav(samples: &[i16]) -> Vec<u8> {
+    let data_bytes = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(WAV_HEADER_BYTES as usize + data_bytes as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_bytes).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    // The format chunk: 16 bytes long, uncompressed, mono.
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    // Bytes per second and per sample, and bits per sample.
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_bytes.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
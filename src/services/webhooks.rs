@@ -0,0 +1,218 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use hmac::{digest::InvalidLength, Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use tokio::{sync::Notify, time::sleep};
+use tracing::{error, info, warn};
+
+use crate::config::{WebhookConfig, WebhookEvent};
+use crate::db::{self, WebhookDelivery};
+
+/// How often due retries are looked for when no new event comes in.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How many deliveries are sent per run.
+const DELIVERY_BATCH_SIZE: i64 = 50;
+/// Attempts after which a delivery is given up on, about a day after the first one.
+const MAX_ATTEMPTS: i64 = 10;
+/// Delay before the first retry, doubled after every failed attempt.
+const INITIAL_RETRY_SECONDS: i64 = 60;
+const MAX_RETRY_SECONDS: i64 = 6 * 60 * 60;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Header holding the HMAC-SHA256 signature of the request body, as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "x-signature-256";
+const EVENT_HEADER: &str = "x-webhook-event";
+const DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+/// Body of every webhook request.
+#[derive(Serialize)]
+struct WebhookPayload<'a, T> {
+    event: WebhookEvent,
+    sent_at: chrono::DateTime<Utc>,
+    data: &'a T,
+}
+
+/// Queues events for delivery to the configured webhooks.
+#[derive(Clone)]
+pub struct Webhooks {
+    db: Arc<SqlitePool>,
+    webhooks: Arc<Vec<WebhookConfig>>,
+    notify: Arc<Notify>,
+}
+
+impl Webhooks {
+    pub fn new(db: Arc<SqlitePool>, webhooks: Vec<WebhookConfig>) -> Self {
+        Self {
+            db,
+            webhooks: Arc::new(webhooks),
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Queues an event for every webhook that wants it and wakes up the webhook service
+    /// to send it. Failures are only logged, as webhooks are not worth failing the
+    /// summary or digest over.
+    pub async fn publish<T: Serialize>(&self, event: WebhookEvent, data: &T) {
+        let urls: Vec<&str> = self
+            .webhooks
+            .iter()
+            .filter(|webhook| webhook.wants(event))
+            .map(|webhook| webhook.url.as_str())
+            .collect();
+        if urls.is_empty() {
+            return;
+        }
+        let payload = WebhookPayload {
+            event,
+            sent_at: Utc::now(),
+            data,
+        };
+        let payload = match serde_json::to_string(&payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!(
+                    "Could not serialize {} webhook payload: {e}",
+                    event.as_str()
+                );
+                return;
+            }
+        };
+        match db::insert_webhook_deliveries(&self.db, &urls, event.as_str(), &payload).await {
+            Ok(()) => self.notify.notify_one(),
+            Err(e) => error!("Could not queue {} webhook deliveries: {e}", event.as_str()),
+        }
+    }
+}
+
+/// Sends queued webhook deliveries as they come in, and retries failed ones with
+/// exponential backoff.
+pub struct WebhookService {
+    webhooks: Webhooks,
+    client: reqwest::Client,
+}
+
+impl WebhookService {
+    pub fn new(webhooks: Webhooks) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self { webhooks, client }
+    }
+
+    pub async fn run(&mut self) {
+        loop {
+            tokio::select! {
+                _ = self.webhooks.notify.notified() => {}
+                _ = sleep(POLL_INTERVAL) => {}
+            }
+            let due = match db::fetch_due_webhook_deliveries(&self.webhooks.db, DELIVERY_BATCH_SIZE)
+                .await
+            {
+                Ok(due) => due,
+                Err(e) => {
+                    error!("Could not fetch due webhook deliveries: {e}");
+                    continue;
+                }
+            };
+            for delivery in due {
+                self.deliver(delivery).await;
+            }
+        }
+    }
+
+    async fn deliver(&self, delivery: WebhookDelivery) {
+        let db = &self.webhooks.db;
+        let Some(webhook) = self
+            .webhooks
+            .webhooks
+            .iter()
+            .find(|webhook| webhook.url == delivery.url)
+        else {
+            let error = "Webhook is no longer configured";
+            if let Err(e) = db::record_webhook_failure(db, delivery.id, error, None, None).await {
+                error!(
+                    "Could not record failure of webhook delivery {}: {e}",
+                    delivery.id
+                );
+            }
+            return;
+        };
+
+        let mut request = self
+            .client
+            .post(&delivery.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, delivery.id.to_string());
+        if let Some(secret) = &webhook.secret {
+            match sign(secret, &delivery.payload) {
+                Ok(signature) => request = request.header(SIGNATURE_HEADER, signature),
+                Err(e) => {
+                    let error = format!("Could not sign webhook: {e}");
+                    if let Err(e) =
+                        db::record_webhook_failure(db, delivery.id, &error, None, None).await
+                    {
+                        error!(
+                            "Could not record failure of webhook delivery {}: {e}",
+                            delivery.id
+                        );
+                    }
+                    return;
+                }
+            }
+        }
+        let result = request.body(delivery.payload.clone()).send().await;
+
+        let (error, response_status) = match result {
+            Ok(response) if response.status().is_success() => {
+                info!(
+                    "Delivered {} webhook {} to {}",
+                    delivery.event, delivery.id, delivery.url
+                );
+                let status = i64::from(response.status().as_u16());
+                if let Err(e) = db::mark_webhook_delivered(db, delivery.id, status).await {
+                    error!("Could not record webhook delivery {}: {e}", delivery.id);
+                }
+                return;
+            }
+            Ok(response) => (
+                format!("Webhook responded with {}", response.status()),
+                Some(i64::from(response.status().as_u16())),
+            ),
+            Err(e) => (format!("Could not send webhook: {e}"), None),
+        };
+        let attempts = delivery.attempts + 1;
+        let retry_after = (attempts < MAX_ATTEMPTS).then(|| {
+            INITIAL_RETRY_SECONDS
+                .saturating_mul(1 << (attempts - 1).min(20))
+                .min(MAX_RETRY_SECONDS)
+        });
+        warn!(
+            "Webhook delivery {} to {} failed (attempt {attempts}): {error}",
+            delivery.id, delivery.url
+        );
+        if let Err(e) =
+            db::record_webhook_failure(db, delivery.id, &error, response_status, retry_after).await
+        {
+            error!(
+                "Could not record failure of webhook delivery {}: {e}",
+                delivery.id
+            );
+        }
+    }
+}
+
+/// Signs a request body with HMAC-SHA256, formatted as `sha256=<hex digest>`.
+pub fn sign(secret: &str, body: &str) -> Result<String, InvalidLength> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+    mac.update(body.as_bytes());
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
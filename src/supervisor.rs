@@ -0,0 +1,163 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{
+    sync::{Mutex as AsyncMutex, OwnedMutexGuard},
+    task::{self, JoinError, JoinHandle},
+    time::sleep,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::error::Result;
+use crate::services::events::{EventBus, ServiceHealth};
+
+/// Delay before restarting a crashed service, doubled after every crash in a row.
+const INITIAL_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(5 * 60);
+/// A service that ran at least this long before crashing is restarted after the
+/// initial delay again, rather than the delay built up by earlier crashes.
+const HEALTHY_RUN: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceState {
+    Running,
+    /// Crashed or failed, and waiting to be restarted.
+    Restarting,
+    /// Returned, normally because the process is shutting down.
+    Stopped,
+}
+
+/// Health of a supervised service, as reported by `/status`.
+#[derive(Clone, Serialize)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub state: ServiceState,
+    pub started_at: DateTime<Utc>,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub last_crashed_at: Option<DateTime<Utc>>,
+}
+
+/// Runs each service in its own task and restarts it with a growing delay when it
+/// panics or fails, keeping track of the health of every service.
+#[derive(Clone)]
+pub struct Supervisor {
+    shutdown: CancellationToken,
+    events: EventBus,
+    statuses: Arc<Mutex<Vec<ServiceStatus>>>,
+}
+
+impl Supervisor {
+    pub fn new(shutdown: CancellationToken, events: EventBus) -> Self {
+        Self {
+            shutdown,
+            events,
+            statuses: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Supervises a service, running it through `run` until it returns. `run` is given
+    /// the service and the shutdown token, and is called again with the same service
+    /// whenever it panics or returns an error, unless the process is shutting down.
+    pub fn spawn<S, F, Fut>(&self, name: &str, service: S, run: F) -> JoinHandle<()>
+    where
+        S: Send + 'static,
+        F: Fn(OwnedMutexGuard<S>, CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let index = {
+            let mut statuses = self.lock_statuses();
+            statuses.push(ServiceStatus {
+                name: name.to_string(),
+                state: ServiceState::Running,
+                started_at: Utc::now(),
+                restarts: 0,
+                last_error: None,
+                last_crashed_at: None,
+            });
+            statuses.len() - 1
+        };
+        let supervisor = self.clone();
+        let health = ServiceHealth::new(self.events.clone(), name);
+        let name = name.to_string();
+        let service = Arc::new(AsyncMutex::new(service));
+        task::spawn(async move {
+            let mut restart_delay = INITIAL_RESTART_DELAY;
+            loop {
+                info!("Running {name} service");
+                let started = Instant::now();
+                let guard = service.clone().lock_owned().await;
+                let message = match task::spawn(run(guard, supervisor.shutdown.clone())).await {
+                    Ok(Ok(())) => {
+                        supervisor.update(index, |status| status.state = ServiceState::Stopped);
+                        return;
+                    }
+                    Ok(Err(e)) => e.to_string(),
+                    Err(e) => panic_message(e),
+                };
+                if started.elapsed() >= HEALTHY_RUN {
+                    restart_delay = INITIAL_RESTART_DELAY;
+                }
+                error!("The {name} service failed, restarting it in {restart_delay:?}: {message}");
+                health.failed(&format!("Crashed: {message}"));
+                supervisor.update(index, |status| {
+                    status.state = ServiceState::Restarting;
+                    status.last_error = Some(message.clone());
+                    status.last_crashed_at = Some(Utc::now());
+                });
+                tokio::select! {
+                    _ = sleep(restart_delay) => {}
+                    _ = supervisor.shutdown.cancelled() => {
+                        supervisor.update(index, |status| status.state = ServiceState::Stopped);
+                        return;
+                    }
+                }
+                restart_delay = (restart_delay * 2).min(MAX_RESTART_DELAY);
+                info!("Restarting the {name} service");
+                supervisor.update(index, |status| {
+                    status.state = ServiceState::Running;
+                    status.started_at = Utc::now();
+                    status.restarts += 1;
+                });
+            }
+        })
+    }
+
+    /// The health of every supervised service, in the order they were started.
+    pub fn statuses(&self) -> Vec<ServiceStatus> {
+        self.lock_statuses().clone()
+    }
+
+    fn update(&self, index: usize, update: impl FnOnce(&mut ServiceStatus)) {
+        if let Some(status) = self.lock_statuses().get_mut(index) {
+            update(status);
+        }
+    }
+
+    fn lock_statuses(&self) -> std::sync::MutexGuard<'_, Vec<ServiceStatus>> {
+        // Statuses are only ever replaced field by field, so they stay usable even if a
+        // panic happened while they were locked.
+        self.statuses.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn panic_message(e: JoinError) -> String {
+    if !e.is_panic() {
+        return e.to_string();
+    }
+    let panic = e.into_panic();
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panicked".to_string()
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fs;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use eyre::{bail, eyre, WrapErr};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::TemplatesConfig;
+use crate::db::RollupTier;
+
+/// How digests are rendered when no template is configured.
+pub const DEFAULT_DIGEST_TEMPLATE: &str = r#"**{{title}}**{{#if from}} ({{date from "%b %-d, %H:%M"}} {{t "to"}} {{date to "%b %-d, %H:%M"}} {{timezone}}){{/if}}
+
+{{text}}"#;
+
+/// How digests are rendered in the body of emails when no template is configured.
+pub const DEFAULT_EMAIL_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<body style="font-family: sans-serif; line-height: 1.5; max-width: 640px; margin: 0 auto">
+<h1 style="font-size: 20px">{{title}}</h1>
+{{#if from}}<p style="color: #666">{{date from "%b %-d, %H:%M"}} {{t "to"}} {{date to "%b %-d, %H:%M"}} {{timezone}}</p>
+{{/if}}{{html text}}
+</body>
+</html>"#;
+
+/// The subject of digest emails when no template is configured.
+pub const DEFAULT_EMAIL_SUBJECT_TEMPLATE: &str =
+    r#"{{title}}{{#if to}}, {{date to "%b %-d, %Y"}}{{/if}}"#;
+
+/// Strings templates can look up with `{{t "key"}}`, unless overridden in
+/// `[templates.strings]`.
+const DEFAULT_STRINGS: &[(&str, &str)] = &[
+    ("daily_digest", "Daily digest"),
+    ("weekly_digest", "Weekly digest"),
+    ("monthly_digest", "Monthly digest"),
+    ("to", "to"),
+];
+
+/// A template in a small subset of the Handlebars language: `{{value}}` for a value of
+/// the context, `{{#if value}}...{{else}}...{{/if}}`, `{{#each list}}...{{/each}}`
+/// where `{{this}}` and `{{@index}}` refer to the current item, `{{date value
+/// "format"}}` to format a timestamp in the reporting timezone, `{{t "key"}}` for a
+/// translatable string, `{{html value}}` to render Markdown text as HTML, and `{{!
+/// comments }}`.
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+enum Node {
+    Text(String),
+    Value(String),
+    Date {
+        path: String,
+        format: String,
+    },
+    Translate(String),
+    Html(String),
+    If {
+        path: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        path: String,
+        body: Vec<Node>,
+    },
+}
+
+/// What a template is rendered with besides its context.
+pub struct RenderEnv<'a> {
+    pub timezone: Tz,
+    pub strings: &'a HashMap<String, String>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> eyre::Result<Self> {
+        let mut tags = Tags { rest: source };
+        let (nodes, end) = parse_nodes(&mut tags)?;
+        if let Some(end) = end {
+            bail!("unexpected {{{{{end}}}}}");
+        }
+        Ok(Self { nodes })
+    }
+
+    pub fn render(&self, context: &Value, env: &RenderEnv) -> String {
+        let mut out = String::new();
+        let scopes = Scopes {
+            values: vec![context],
+            index: None,
+        };
+        render_nodes(&self.nodes, &scopes, env, &mut out);
+        out
+    }
+}
+
+/// Splits a template into text and the contents of `{{...}}` tags.
+struct Tags<'a> {
+    rest: &'a str,
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Tag(&'a str),
+}
+
+impl<'a> Tags<'a> {
+    fn next(&mut self) -> eyre::Result<Option<Piece<'a>>> {
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        let Some(start) = self.rest.find("{{") else {
+            let text = self.rest;
+            self.rest = "";
+            return Ok(Some(Piece::Text(text)));
+        };
+        if start > 0 {
+            let text = &self.rest[..start];
+            self.rest = &self.rest[start..];
+            return Ok(Some(Piece::Text(text)));
+        }
+        let end = self.rest[2..]
+            .find("}}")
+            .ok_or_else(|| eyre!("unclosed {{{{ in template"))?;
+        let tag = self.rest[2..2 + end].trim();
+        self.rest = &self.rest[2 + end + 2..];
+        Ok(Some(Piece::Tag(tag)))
+    }
+}
+
+/// Parses nodes until the end of the template or a closing tag, which is returned
+/// along with them.
+fn parse_nodes(tags: &mut Tags) -> eyre::Result<(Vec<Node>, Option<String>)> {
+    let mut nodes = vec![];
+    while let Some(piece) = tags.next()? {
+        let tag = match piece {
+            Piece::Text(text) => {
+                nodes.push(Node::Text(text.to_string()));
+                continue;
+            }
+            Piece::Tag(tag) => tag,
+        };
+        if tag.starts_with('!') {
+            continue;
+        }
+        if tag == "else" || tag.starts_with('/') {
+            return Ok((nodes, Some(tag.to_string())));
+        }
+        let args = split_args(tag)?;
+        let node = match args.as_slice() {
+            [block, path] if *block == "#if" => {
+                let (then, end) = parse_nodes(tags)?;
+                let otherwise = match end.as_deref() {
+                    Some("else") => match parse_nodes(tags)? {
+                        (otherwise, Some(end)) if end == "/if" => otherwise,
+                        _ => bail!("{{{{#if {path}}}}} is not closed with {{{{/if}}}}"),
+                    },
+                    Some("/if") => vec![],
+                    _ => bail!("{{{{#if {path}}}}} is not closed with {{{{/if}}}}"),
+                };
+                Node::If {
+                    path: path.to_string(),
+                    then,
+                    otherwise,
+                }
+            }
+            [block, path] if *block == "#each" => match parse_nodes(tags)? {
+                (body, Some(end)) if end == "/each" => Node::Each {
+                    path: path.to_string(),
+                    body,
+                },
+                _ => bail!("{{{{#each {path}}}}} is not closed with {{{{/each}}}}"),
+            },
+            [helper, path, format] if *helper == "date" => Node::Date {
+                path: path.to_string(),
+                format: format.to_string(),
+            },
+            [helper, key] if *helper == "t" => Node::Translate(key.to_string()),
+            [helper, path] if *helper == "html" => Node::Html(path.to_string()),
+            [path] if !path.starts_with('#') => Node::Value(path.to_string()),
+            _ => bail!("unsupported template tag {{{{{tag}}}}}"),
+        };
+        nodes.push(node);
+    }
+    Ok((nodes, None))
+}
+
+/// Splits a tag into words, keeping double quoted arguments whole.
+fn split_args(tag: &str) -> eyre::Result<Vec<&str>> {
+    let mut args = vec![];
+    let mut rest = tag.trim();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| eyre!("unclosed quote in {{{{{tag}}}}}"))?;
+            args.push(&quoted[..end]);
+            rest = quoted[end + 1..].trim_start();
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            args.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+    }
+    Ok(args)
+}
+
+/// The values names are looked up in, innermost last, and the index of the current
+/// `#each` item.
+struct Scopes<'a> {
+    values: Vec<&'a Value>,
+    index: Option<usize>,
+}
+
+impl Scopes<'_> {
+    fn lookup(&self, path: &str) -> Option<Value> {
+        match path {
+            "this" => return self.values.last().map(|value| (*value).clone()),
+            "@index" => return self.index.map(Value::from),
+            _ => {}
+        }
+        self.values.iter().rev().find_map(|scope| {
+            path.split('.')
+                .try_fold(*scope, |value, key| value.get(key))
+                .cloned()
+        })
+    }
+}
+
+fn render_nodes(nodes: &[Node], scopes: &Scopes, env: &RenderEnv, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Value(path) => match scopes.lookup(path) {
+                Some(Value::String(text)) => out.push_str(&text),
+                Some(Value::Null) | None => {}
+                Some(value) => out.push_str(&value.to_string()),
+            },
+            Node::Date { path, format } => {
+                let time = scopes
+                    .lookup(path)
+                    .and_then(|value| value.as_str().map(str::to_string))
+                    .and_then(|time| DateTime::parse_from_rfc3339(&time).ok());
+                if let Some(time) = time {
+                    out.push_str(&time.with_timezone(&env.timezone).format(format).to_string());
+                }
+            }
+            Node::Translate(key) => {
+                out.push_str(env.strings.get(key).map_or(key.as_str(), String::as_str))
+            }
+            Node::Html(path) => match scopes.lookup(path) {
+                Some(Value::String(text)) => out.push_str(&markdown_html(&text)),
+                Some(Value::Null) | None => {}
+                Some(value) => out.push_str(&escape_html(&value.to_string())),
+            },
+            Node::If {
+                path,
+                then,
+                otherwise,
+            } => {
+                let branch = if scopes.lookup(path).is_some_and(|value| is_truthy(&value)) {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(branch, scopes, env, out);
+            }
+            Node::Each { path, body } => {
+                let Some(Value::Array(items)) = scopes.lookup(path) else {
+                    continue;
+                };
+                for (index, item) in items.iter().enumerate() {
+                    let mut values = scopes.values.clone();
+                    values.push(item);
+                    let item_scopes = Scopes {
+                        values,
+                        index: Some(index),
+                    };
+                    render_nodes(body, &item_scopes, env, out);
+                }
+            }
+        }
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64() != Some(0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+/// Renders the Markdown digests are written in as HTML: paragraphs, bullet lists,
+/// `**bold**` and `[label](url)` links, escaping everything else.
+pub fn markdown_html(text: &str) -> String {
+    let mut out = String::new();
+    for block in text.split("\n\n") {
+        let lines: Vec<&str> = block
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            continue;
+        }
+        let items: Option<Vec<&str>> = lines.iter().map(|line| bullet_item(line)).collect();
+        if let Some(items) = items {
+            out.push_str("<ul>\n");
+            for item in items {
+                out.push_str(&format!("<li>{}</li>\n", inline_html(item)));
+            }
+            out.push_str("</ul>\n");
+        } else {
+            let lines: Vec<String> = lines.iter().map(|line| inline_html(line)).collect();
+            out.push_str(&format!("<p>{}</p>\n", lines.join("<br>\n")));
+        }
+    }
+    out
+}
+
+/// The text of a bullet list item, `None` for lines that are not one.
+fn bullet_item(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    ["- ", "* ", "• "]
+        .iter()
+        .find_map(|bullet| line.strip_prefix(bullet))
+}
+
+/// Renders the bold text and links of a line as HTML.
+fn inline_html(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    let mut bold = false;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**") {
+            out.push_str(if bold { "</strong>" } else { "<strong>" });
+            bold = !bold;
+            rest = after;
+        } else if let Some((label, url, after)) = markdown_link(rest) {
+            out.push_str(&format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(url),
+                escape_html(label)
+            ));
+            rest = after;
+        } else {
+            out.push_str(&escape_html(&rest[..c.len_utf8()]));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if bold {
+        out.push_str("</strong>");
+    }
+    out
+}
+
+/// The label, URL and the text after a `[label](url)` link starting `text`, where the
+/// URL may be wrapped in `<>` as Discord messages do to hide previews.
+pub fn markdown_link(text: &str) -> Option<(&str, &str, &str)> {
+    let (label, rest) = text.strip_prefix('[')?.split_once("](")?;
+    if label.contains(']') || label.contains('\n') {
+        return None;
+    }
+    let end = rest.find(')')?;
+    let url = rest[..end].trim_start_matches('<').trim_end_matches('>');
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return None;
+    }
+    Some((label, url, &rest[end + 1..]))
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Where a digest is rendered for.
+#[derive(Clone, Copy)]
+pub enum DigestTarget {
+    Discord,
+    Api,
+    /// The HTML body of emails.
+    Email,
+    EmailSubject,
+}
+
+/// A digest as templates see it.
+#[derive(Serialize, Clone)]
+pub struct DigestView<'a> {
+    pub id: Option<i64>,
+    /// `daily`, `weekly` or `monthly`.
+    pub tier: &'static str,
+    /// The tier's translatable title, such as "Daily digest".
+    pub title: String,
+    pub guild_id: Option<i64>,
+    pub text: &'a str,
+    pub message_count: i64,
+    /// Start and end of the period covered, only set when both are known.
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub timezone: &'static str,
+}
+
+/// The templates digests are rendered with for each target.
+pub struct DigestTemplates {
+    discord: Template,
+    api: Template,
+    email: Template,
+    email_subject: Template,
+    strings: HashMap<String, String>,
+    timezone: Tz,
+}
+
+impl DigestTemplates {
+    /// The default templates and strings.
+    pub fn builtin(timezone: Tz) -> Self {
+        Self::load(&TemplatesConfig::default(), timezone)
+            .expect("the built-in digest templates are valid")
+    }
+
+    pub fn load(config: &TemplatesConfig, timezone: Tz) -> eyre::Result<Self> {
+        let mut strings: HashMap<String, String> = DEFAULT_STRINGS
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        strings.extend(config.strings.clone());
+        Ok(Self {
+            discord: load_template(config.discord.as_deref(), config.discord_file.as_deref())
+                .wrap_err("Invalid templates.discord")?,
+            api: load_template(config.api.as_deref(), config.api_file.as_deref())
+                .wrap_err("Invalid templates.api")?,
+            email: load_template_or(
+                config.email.as_deref(),
+                config.email_file.as_deref(),
+                DEFAULT_EMAIL_TEMPLATE,
+            )
+            .wrap_err("Invalid templates.email")?,
+            email_subject: Template::parse(
+                config
+                    .email_subject
+                    .as_deref()
+                    .unwrap_or(DEFAULT_EMAIL_SUBJECT_TEMPLATE),
+            )
+            .wrap_err("Invalid templates.email_subject")?,
+            strings,
+            timezone,
+        })
+    }
+
+    /// Builds what templates see of a digest.
+    pub fn view<'a>(
+        &self,
+        tier: RollupTier,
+        id: Option<i64>,
+        guild_id: Option<i64>,
+        text: &'a str,
+        message_count: i64,
+        covers: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> DigestView<'a> {
+        let title_key = format!("{}_digest", tier.name());
+        DigestView {
+            id,
+            tier: tier.name(),
+            title: self.strings.get(&title_key).cloned().unwrap_or(title_key),
+            guild_id,
+            text,
+            message_count,
+            from: covers.map(|(from, _)| from),
+            to: covers.map(|(_, to)| to),
+            timezone: self.timezone.name(),
+        }
+    }
+
+    pub fn render(&self, target: DigestTarget, digest: &DigestView) -> String {
+        let template = match target {
+            DigestTarget::Discord => &self.discord,
+            DigestTarget::Api => &self.api,
+            DigestTarget::Email => &self.email,
+            DigestTarget::EmailSubject => &self.email_subject,
+        };
+        let context = serde_json::to_value(digest).unwrap_or_default();
+        let env = RenderEnv {
+            timezone: self.timezone,
+            strings: &self.strings,
+        };
+        template.render(&context, &env).trim().to_string()
+    }
+}
+
+/// Reads a template from its file if it has one, falling back to the inline template
+/// and then to the default one.
+fn load_template(inline: Option<&str>, file: Option<&str>) -> eyre::Result<Template> {
+    load_template_or(inline, file, DEFAULT_DIGEST_TEMPLATE)
+}
+
+fn load_template_or(
+    inline: Option<&str>,
+    file: Option<&str>,
+    default: &str,
+) -> eyre::Result<Template> {
+    let source = match file {
+        Some(path) => fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read digest template {path}"))?,
+        None => inline.unwrap_or(default).to_string(),
+    };
+    Template::parse(&source)
+}
@@ -0,0 +1,94 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::db::{self, ContentFilter, DateRange};
+use crate::gpt::{MockSummarizer, STRUCTURED_SUMMARY_FORMAT};
+use crate::services::discord_export::{parse_chat_export, replay_events};
+use crate::services::discord_handler::{
+    AllowedChannels, AuthorFilter, ChannelFilter, MessageIntake,
+};
+
+use super::run_pipeline;
+
+const CHANNEL_EXPORT: &str = r#"{
+  "guild": {"id": "1000", "name": "Test"},
+  "channel": {"id": "10", "type": "GuildTextChat", "categoryId": null, "category": "Text", "name": "general"},
+  "messages": [
+    {"id": "101", "type": "GuildMemberJoin", "timestamp": "2024-03-01T08:00:00.000+00:00", "content": "Joined the server.",
+     "author": {"id": "3", "name": "carol", "nickname": null, "isBot": false}},
+    {"id": "102", "type": "Default", "timestamp": "2024-03-01T09:00:00.000+00:00", "content": "When is the release?",
+     "author": {"id": "1", "name": "alice", "nickname": "Alice", "isBot": false},
+     "reactions": [{"emoji": {"name": "👍"}, "count": 3}]},
+    {"id": "103", "type": "Reply", "timestamp": "2024-03-01T09:05:00.000+00:00", "content": "Friday",
+     "author": {"id": "2", "name": "bob", "nickname": null, "isBot": false},
+     "reference": {"messageId": "102", "channelId": "10", "guildId": "1000"}},
+    {"id": "104", "type": "Default", "timestamp": "2024-03-02T10:00:00.000+00:00", "content": "<@2> the release notes are up",
+     "author": {"id": "1", "name": "alice", "nickname": "Alice", "isBot": false},
+     "mentions": [{"id": "2", "name": "bob", "nickname": "Bobby", "isBot": false}]},
+    {"id": "105", "type": "Default", "timestamp": "2024-03-02T10:01:00.000+00:00", "content": "Build passed",
+     "author": {"id": "9", "name": "ci", "nickname": null, "isBot": true}}
+  ]
+}"#;
+
+const THREAD_EXPORT: &str = r#"{
+  "guild": {"id": "1000", "name": "Test"},
+  "channel": {"id": "11", "type": "GuildPublicThread", "categoryId": "10", "category": "general", "name": "Release checklist"},
+  "messages": [
+    {"id": "106", "type": "Default", "timestamp": "2024-03-01T11:00:00.000+00:00", "content": "Docs are done",
+     "author": {"id": "2", "name": "bob", "nickname": null, "isBot": false}}
+  ]
+}"#;
+
+#[tokio::test]
+async fn chat_exports_are_imported_into_a_digest_per_day() {
+    let db = super::test_db().await;
+    let summary = r#"{"summary": "Release talk.", "topics": ["release"]}"#;
+    let summarizer = Arc::new(MockSummarizer::new().with_reply(STRUCTURED_SUMMARY_FORMAT, summary));
+    let mut messages = parse_chat_export(CHANNEL_EXPORT).unwrap();
+    messages.extend(parse_chat_export(THREAD_EXPORT).unwrap());
+    let intake = MessageIntake::new(
+        ChannelFilter::new(AllowedChannels::All, HashMap::new()),
+        AuthorFilter::new(true, true, HashSet::new()),
+    );
+    let events = replay_events(messages, &intake, chrono_tz::UTC);
+    run_pipeline(&db, summarizer.clone(), 10_000, events).await;
+
+    let mut summaries =
+        db::fetch_summaries(db.clone(), &ContentFilter::default(), &DateRange::default()).await;
+    summaries.sort_by_key(|summary| summary.covers_from);
+    assert_eq!(summaries.len(), 2);
+    // The thread is summarized along with its parent channel, and the bot is left out.
+    assert_eq!(summaries[0].message_count, 3);
+    assert_eq!(summaries[1].message_count, 1);
+    for summary in &summaries {
+        assert_eq!(summary.channel_id, Some(10));
+        assert_eq!(summary.guild_id, Some(1000));
+    }
+    assert_eq!(
+        summaries[0].covers_from.unwrap().to_rfc3339(),
+        "2024-03-01T09:00:00+00:00"
+    );
+
+    let requests = summarizer.requests();
+    let first_day = &requests[0].text;
+    assert!(first_day.contains("author: Alice, content: When is the release?"));
+    assert!(first_day.contains("author: bob (replying to Alice: \"When is the release?\")"));
+    assert!(first_day.contains("thread: \"Release checklist\""));
+    assert!(!first_day.contains("Joined the server."));
+    let second_day = &requests[1].text;
+    assert!(second_day.contains("content: @Bobby the release notes are up"));
+    assert!(!second_day.contains("Build passed"));
+
+    let mut digests =
+        db::fetch_daily_digests(&db, &ContentFilter::default(), &DateRange::default(), None)
+            .await
+            .unwrap();
+    digests.sort_by_key(|digest| digest.covers_from);
+    assert_eq!(digests.len(), 2);
+    assert_eq!(digests[0].message_count, 3);
+    assert_eq!(digests[1].message_count, 1);
+    assert_eq!(
+        digests[1].covers_from.unwrap().to_rfc3339(),
+        "2024-03-02T00:00:00+00:00"
+    );
+}
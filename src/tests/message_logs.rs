@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::db;
+use crate::gpt::{token_counter_for_model, TokenCounter};
+use crate::services::message_listener::MessageLogService;
+
+use super::GUILD_ID;
+
+#[tokio::test]
+async fn message_logs_of_older_versions_are_imported_with_their_pending_summaries() {
+    let db = super::test_db().await;
+    let log_dir = std::env::temp_dir().join(format!("imported-logs-{}", std::process::id()));
+    let channel_dir = log_dir.join(GUILD_ID.to_string()).join("10");
+    std::fs::create_dir_all(&channel_dir).unwrap();
+    std::fs::write(
+        channel_dir.join("messages_3.txt"),
+        "timestamp: 2024-01-01T09:00:00.000Z, author: alice, content: When is the release?\n\
+        timestamp: 2024-01-01T09:05:00.000Z, author: bob, content: Friday,\nif CI passes\n",
+    )
+    .unwrap();
+    std::fs::write(
+        channel_dir.join("messages_4.txt"),
+        "timestamp: 2024-01-01T10:00:00.000Z, author: alice, content: Thanks\n",
+    )
+    .unwrap();
+    // The summarization of the first log had failed before upgrading.
+    sqlx::query(
+        "INSERT INTO pending_message_logs (guild_id, channel_id, log_file_index, attempts, last_error, created_at)
+        VALUES (?, 10, 3, 2, 'rate limited', '2024-01-01 09:10:00')",
+    )
+    .bind(GUILD_ID as i64)
+    .execute(&*db)
+    .await
+    .unwrap();
+
+    let (summarize_tx, mut summarize_rx) = mpsc::channel(10);
+    let (_ingest_tx, ingest_rx) = mpsc::channel(10);
+    let token_counter: Arc<dyn TokenCounter> = token_counter_for_model("gpt-4").into();
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    MessageLogService::new(
+        db.clone(),
+        summarize_tx,
+        ingest_rx,
+        token_counter,
+        10_000,
+        None,
+    )
+    .with_message_log_directory(log_dir.clone())
+    .run(shutdown)
+    .await;
+    let logs_left = std::fs::read_dir(&channel_dir).unwrap().count();
+    std::fs::remove_dir_all(&log_dir).unwrap();
+    assert_eq!(logs_left, 0);
+
+    let messages = db::fetch_unsummarized_messages(&db, 10, i64::MAX)
+        .await
+        .unwrap();
+    let contents: Vec<&str> = messages.iter().map(|msg| msg.content.as_str()).collect();
+    assert_eq!(
+        contents,
+        ["When is the release?", "Friday,\nif CI passes", "Thanks"]
+    );
+    let pending = db::fetch_pending_summaries(db.clone()).await;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].up_to_message_id, messages[1].id);
+    assert_eq!(pending[0].attempts, 2);
+    assert_eq!(pending[0].last_error, "rate limited");
+    // Imported messages are summarized like any other messages left by a previous run.
+    assert!(summarize_rx.try_recv().is_ok());
+}
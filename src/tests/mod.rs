@@ -0,0 +1,105 @@
+//! End-to-end tests of the pipeline, run against an in-memory database with a mock
+//! LLM backend so that they are fast and deterministic.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+
+use crate::db::RollupTier;
+use crate::gpt::{token_counter_for_model, DryRunEmbedder, Summarizer, TokenCounter};
+use crate::schedule::Schedule;
+use crate::services::digests::RecapService;
+use crate::services::discord_export;
+use crate::services::message_listener::MessageLogService;
+use crate::services::sources::{IngestEvent, IngestedMessage};
+use crate::services::summarizer::SummarizerService;
+use crate::services::webhooks::Webhooks;
+
+mod import;
+mod message_logs;
+mod pipeline;
+
+/// Every test message is posted in this guild.
+const GUILD_ID: u64 = 1000;
+
+/// Creates an empty in-memory database with every migration applied.
+async fn test_db() -> Arc<SqlitePool> {
+    // Each connection to an in-memory database gets a database of its own, so the pool
+    // keeps a single connection open for the whole test.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(SqliteConnectOptions::from_str("sqlite::memory:").expect("valid URL"))
+        .await
+        .expect("in-memory database");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("migrations");
+    Arc::new(pool)
+}
+
+/// A message of the test guild, posted `minutes` after the start of 2024.
+fn message(id: u64, channel_id: u64, author: &str, content: &str, minutes: i64) -> IngestEvent {
+    let timestamp =
+        DateTime::<Utc>::from_timestamp(1_704_067_200 + minutes * 60, 0).expect("valid timestamp");
+    IngestEvent::Received(Box::new(IngestedMessage {
+        message_id: MessageId::new(id),
+        guild_id: Some(GuildId::new(GUILD_ID)),
+        channel_id: ChannelId::new(channel_id),
+        author_id: UserId::new(author.len() as u64),
+        author: author.to_string(),
+        content: content.to_string(),
+        timestamp,
+        reply_to: None,
+        thread: None,
+        attachments: vec![],
+    }))
+}
+
+/// Runs the message log, summarizer and daily recap services over `events`, as when
+/// importing an export, with a digest per calendar day in UTC. Channels are
+/// summarized once their batch goes over `summary_tokens_threshold`.
+async fn run_pipeline(
+    db: &Arc<SqlitePool>,
+    summarizer: Arc<dyn Summarizer>,
+    summary_tokens_threshold: usize,
+    events: Vec<IngestEvent>,
+) {
+    let (summarize_tx, summarize_rx) = mpsc::channel(100);
+    let (ingest_tx, ingest_rx) = mpsc::channel(100);
+    let token_counter: Arc<dyn TokenCounter> = token_counter_for_model("gpt-4").into();
+    let message_log = MessageLogService::new(
+        db.clone(),
+        summarize_tx,
+        ingest_rx,
+        token_counter.clone(),
+        summary_tokens_threshold,
+        None,
+    );
+    let summary_srv = SummarizerService::new(
+        summarize_rx,
+        db.clone(),
+        summarizer.clone(),
+        Arc::new(DryRunEmbedder),
+        token_counter,
+        60,
+        Webhooks::new(db.clone(), vec![]),
+    );
+    let recap_srv = RecapService::new(
+        db.clone(),
+        RollupTier::Daily,
+        Schedule::Interval(Duration::from_secs(86_400)),
+        chrono_tz::UTC,
+        summarizer,
+    )
+    .with_calendar_days();
+    discord_export::replay(events, ingest_tx, message_log, summary_srv, recap_srv).await;
+}
@@ -0,0 +1,278 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::db::{self, ContentFilter, DateRange, NewSummary, RollupTier};
+use crate::gpt::{
+    token_counter_for_model, ImageDescriber, MockSummarizer, StructuredSummary, TokenCounter,
+    STRUCTURED_SUMMARY_FORMAT, SYSTEM_PROMPT,
+};
+use crate::schedule::Schedule;
+use crate::services::digests::RecapService;
+use crate::services::message_listener::MessageLogService;
+use crate::services::sources::{IngestEvent, IngestedAttachment};
+
+use super::{message, run_pipeline, test_db, GUILD_ID};
+
+const SUMMARY_REPLY: &str = r#"{"summary": "The team agreed to ship the release on Friday.", "topics": ["release"], "decisions": ["Ship on Friday"], "action_items": [], "open_questions": [], "entities": []}"#;
+const DIGEST_REPLY: &str = "The release is on track for Friday.";
+
+/// Replies to summary requests with `SUMMARY_REPLY` and to digest requests, which do
+/// not ask for the structured format, with `DIGEST_REPLY`.
+fn mock_summarizer() -> MockSummarizer {
+    MockSummarizer::new()
+        .with_reply(STRUCTURED_SUMMARY_FORMAT, SUMMARY_REPLY)
+        .with_reply(SYSTEM_PROMPT, DIGEST_REPLY)
+}
+
+#[tokio::test]
+async fn messages_are_summarized_and_rolled_up_into_a_daily_digest() {
+    let db = test_db().await;
+    let summarizer = Arc::new(mock_summarizer());
+    let messages = vec![
+        message(1, 10, "alice", "Can we ship the release this week?", 0),
+        message(2, 10, "bob", "Friday works for me", 1),
+        message(3, 20, "carol", "The docs for the release are ready", 2),
+        message(4, 10, "alice", "Friday it is then", 3),
+        message(5, 20, "dave", "I will review them today", 4),
+    ];
+    run_pipeline(&db, summarizer.clone(), 10_000, messages).await;
+
+    let mut summaries =
+        db::fetch_summaries(db.clone(), &ContentFilter::default(), &DateRange::default()).await;
+    summaries.sort_by_key(|summary| summary.channel_id);
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].channel_id, Some(10));
+    assert_eq!(summaries[0].message_count, 3);
+    assert_eq!(summaries[1].channel_id, Some(20));
+    assert_eq!(summaries[1].message_count, 2);
+    for summary in &summaries {
+        assert_eq!(summary.guild_id, Some(GUILD_ID as i64));
+        assert_eq!(
+            summary.text,
+            "The team agreed to ship the release on Friday."
+        );
+        assert_eq!(summary.topics.0, vec!["release"]);
+        assert_eq!(summary.decisions.0, vec!["Ship on Friday"]);
+        assert_eq!(summary.backend.as_deref(), Some("mock"));
+    }
+    let unsummarized = db::fetch_unsummarized_channels(&db).await.unwrap();
+    assert!(unsummarized.is_empty());
+
+    let digests =
+        db::fetch_daily_digests(&db, &ContentFilter::default(), &DateRange::default(), None)
+            .await
+            .unwrap();
+    assert_eq!(digests.len(), 1);
+    let digest = &digests[0];
+    assert!(digest.text.starts_with(DIGEST_REPLY));
+    assert_eq!(digest.guild_id, Some(GUILD_ID as i64));
+    assert_eq!(digest.message_count, 5);
+    assert_eq!(digest.summaries.len(), 2);
+
+    // One request per channel, then one for the digest.
+    let requests = summarizer.requests();
+    assert_eq!(requests.len(), 3);
+    let transcripts: String = requests[..2]
+        .iter()
+        .map(|request| request.text.as_str())
+        .collect();
+    assert!(transcripts.contains("author: alice, content: Can we ship the release this week?"));
+    assert!(transcripts.contains("author: dave, content: I will review them today"));
+    assert!(!requests[2].instructions.contains(STRUCTURED_SUMMARY_FORMAT));
+    assert!(requests[2]
+        .text
+        .contains("The team agreed to ship the release on Friday."));
+}
+
+#[tokio::test]
+async fn overflowing_batches_are_summarized_separately() {
+    let db = test_db().await;
+    let summarizer = Arc::new(mock_summarizer());
+    let messages = (0..6)
+        .map(|i| {
+            let content =
+                format!("Status update number {i} on the migration of the billing service");
+            message(i + 1, 10, "alice", &content, i as i64)
+        })
+        .collect();
+    // Only a couple of messages fit in each batch.
+    run_pipeline(&db, summarizer.clone(), 30, messages).await;
+
+    let summaries =
+        db::fetch_summaries(db.clone(), &ContentFilter::default(), &DateRange::default()).await;
+    assert!(summaries.len() > 1);
+    let message_count: i64 = summaries.iter().map(|summary| summary.message_count).sum();
+    assert_eq!(message_count, 6);
+    let unsummarized = db::fetch_unsummarized_channels(&db).await.unwrap();
+    assert!(unsummarized.is_empty());
+
+    let digests =
+        db::fetch_daily_digests(&db, &ContentFilter::default(), &DateRange::default(), None)
+            .await
+            .unwrap();
+    assert_eq!(digests.len(), 1);
+    assert_eq!(digests[0].summaries.len(), summaries.len());
+    assert_eq!(digests[0].message_count, 6);
+}
+
+#[tokio::test]
+async fn failed_summaries_are_queued_for_retry() {
+    let db = test_db().await;
+    let summarizer = Arc::new(MockSummarizer::new().failing());
+    let messages = vec![
+        message(1, 10, "alice", "Is the build broken?", 0),
+        message(2, 10, "bob", "Yes, looking into it", 1),
+    ];
+    run_pipeline(&db, summarizer, 10_000, messages).await;
+
+    let summaries =
+        db::fetch_summaries(db.clone(), &ContentFilter::default(), &DateRange::default()).await;
+    assert!(summaries.is_empty());
+    let pending = db::fetch_pending_summaries(db.clone()).await;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].channel_id, 10);
+    assert!(pending[0].last_error.contains("Mock API is unavailable"));
+    // The messages stay stored, to be summarized once the retry succeeds.
+    let unsummarized = db::fetch_unsummarized_channels(&db).await.unwrap();
+    assert_eq!(unsummarized.len(), 1);
+    assert_eq!(unsummarized[0].channel_id, 10);
+
+    let digests =
+        db::fetch_daily_digests(&db, &ContentFilter::default(), &DateRange::default(), None)
+            .await
+            .unwrap();
+    assert!(digests.is_empty());
+}
+
+#[tokio::test]
+async fn a_single_day_is_rolled_up_on_demand() {
+    let db = test_db().await;
+    let summarizer = Arc::new(mock_summarizer());
+    let start_of_2024 = DateTime::<Utc>::from_timestamp(1_704_067_200, 0).unwrap();
+    for (day, channel_id) in [(0, 10), (1, 20)] {
+        let covers_from = start_of_2024 + chrono::Duration::days(day);
+        let summary = StructuredSummary::unstructured(format!("Day {day} in channel {channel_id}"));
+        let new_summary = NewSummary {
+            guild_id: Some(GUILD_ID as i64),
+            channel_id,
+            summary: &summary,
+            message_count: 2,
+            covers_from: Some(covers_from),
+            covers_to: Some(covers_from + chrono::Duration::hours(1)),
+        };
+        db::insert_summary(&db, new_summary, 0).await.unwrap();
+    }
+    let recap_srv = RecapService::new(
+        db.clone(),
+        RollupTier::Daily,
+        Schedule::Interval(Duration::from_secs(86_400)),
+        chrono_tz::UTC,
+        summarizer.clone(),
+    );
+
+    let day = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+    let digest_ids = recap_srv.recap_day(day).await;
+    assert_eq!(digest_ids.len(), 1);
+    let digest = db::fetch_daily_digest(&db, digest_ids[0])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(digest.text, DIGEST_REPLY);
+    assert_eq!(digest.summaries.len(), 1);
+    assert_eq!(digest.summaries[0].channel_id, Some(20));
+    assert_eq!(
+        digest.covers_from.unwrap().to_rfc3339(),
+        "2024-01-02T00:00:00+00:00"
+    );
+    // The other day is left for its own digest, and a day is only rolled up once.
+    assert!(recap_srv.recap_day(day).await.is_empty());
+    let pending = db::fetch_rollup_sources(&db, RollupTier::Daily, None)
+        .await
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].channel_id, Some(10));
+}
+
+/// Describes every image as a cat, once let through by `gate`.
+struct GatedDescriber {
+    gate: tokio::sync::Semaphore,
+}
+
+#[async_trait]
+impl ImageDescriber for GatedDescriber {
+    async fn describe(&self, _url: &str, _content_type: &str) -> eyre::Result<String> {
+        let _permit = self.gate.acquire().await?;
+        Ok("A cat".to_string())
+    }
+
+    fn max_image_bytes(&self) -> u64 {
+        1_000_000
+    }
+}
+
+#[tokio::test]
+async fn messages_are_stored_without_waiting_for_their_images_to_be_described() {
+    let db = test_db().await;
+    let describer = Arc::new(GatedDescriber {
+        gate: tokio::sync::Semaphore::new(0),
+    });
+    let (summarize_tx, _summarize_rx) = mpsc::channel(10);
+    let (ingest_tx, ingest_rx) = mpsc::channel(10);
+    let token_counter: Arc<dyn TokenCounter> = token_counter_for_model("gpt-4").into();
+    let mut message_log = MessageLogService::new(
+        db.clone(),
+        summarize_tx,
+        ingest_rx,
+        token_counter,
+        10_000,
+        None,
+    )
+    .with_image_describer(describer.clone());
+    let IngestEvent::Received(mut screenshot) = message(1, 10, "alice", "look", 0) else {
+        unreachable!()
+    };
+    screenshot.attachments.push(IngestedAttachment {
+        filename: "cat.png".to_string(),
+        url: "https://cdn.example.com/cat.png".to_string(),
+        content_type: Some("image/png".to_string()),
+        size: 1000,
+    });
+    ingest_tx
+        .send(IngestEvent::Received(screenshot))
+        .await
+        .unwrap();
+    ingest_tx
+        .send(message(2, 10, "bob", "cute", 1))
+        .await
+        .unwrap();
+    drop(ingest_tx);
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        message_log.run(CancellationToken::new()),
+    )
+    .await
+    .expect("storing messages does not wait on the vision API");
+    let messages = db::fetch_unsummarized_messages(&db, 10, i64::MAX)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].attachments[0].description, None);
+
+    describer.gate.add_permits(1);
+    for _ in 0..100 {
+        let messages = db::fetch_unsummarized_messages(&db, 10, i64::MAX)
+            .await
+            .unwrap();
+        if let Some(description) = &messages[0].attachments[0].description {
+            assert_eq!(description, "A cat");
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the image was never described");
+}